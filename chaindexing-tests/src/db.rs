@@ -17,6 +17,19 @@ pub fn setup() {
     });
 }
 
+/// Re-creates a database with the test database's name and the given suffix,
+/// dropping any left over by a previous run, and returns its URL
+pub fn fresh_database_url(db_name_suffix: &str) -> String {
+    let (db_name, db_raw_url) = get_db_name_and_raw_url(&database_url());
+    let fresh_db_name = format!("{db_name}_{db_name_suffix}");
+
+    let mut raw_conn = connect_to_database_url_or_panic(&db_raw_url);
+    drop_database(&fresh_db_name, &mut raw_conn);
+    create_database(&fresh_db_name, &mut raw_conn);
+
+    format!("{db_raw_url}/{fresh_db_name}")
+}
+
 fn connect() -> PgConnection {
     connect_to_database_url_or_panic(&database_url())
 }
@@ -43,6 +56,13 @@ fn create_database(db_name: &str, conn: &mut PgConnection) {
         .unwrap();
 }
 
+#[allow(clippy::uninlined_format_args)]
+fn drop_database(db_name: &str, conn: &mut PgConnection) {
+    diesel::sql_query(format!(r#"DROP DATABASE IF EXISTS "{}""#, db_name))
+        .execute(conn)
+        .unwrap();
+}
+
 #[allow(clippy::uninlined_format_args)]
 fn connect_to_database_url_or_panic(db_url: &str) -> PgConnection {
     PgConnection::establish(db_url).unwrap_or_else(|_| panic!("Error connecting to {}", db_url))
@@ -1,8 +1,10 @@
+mod configs;
 mod contracts;
 mod event_handlers;
 mod events;
 mod json_rpcs;
 
+pub use configs::*;
 pub use contracts::*;
 pub use event_handlers::*;
 pub use events::*;
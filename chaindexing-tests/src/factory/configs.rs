@@ -0,0 +1,12 @@
+use chaindexing::{Chain, Config, Contract};
+
+use crate::test_runner;
+
+pub fn config_with_contracts(contracts: Vec<Contract>) -> Config {
+    let chains = [(Chain::Mainnet, "http://localhost:8545".to_string())].into();
+
+    contracts.into_iter().fold(
+        Config::new(test_runner::new_repo(), chains),
+        |config, contract| config.add_contract(contract),
+    )
+}
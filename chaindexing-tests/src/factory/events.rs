@@ -6,7 +6,10 @@ use ethers::types::Block;
 use super::{transfer_log, BAYC_CONTRACT_ADDRESS};
 
 pub fn transfer_event_with_contract(contract: Contract) -> Event {
-    let contract_address = BAYC_CONTRACT_ADDRESS;
+    transfer_event_with_contract_address(contract, BAYC_CONTRACT_ADDRESS)
+}
+
+pub fn transfer_event_with_contract_address(contract: Contract, contract_address: &str) -> Event {
     let transfer_log = transfer_log(contract_address);
     let blocks_by_tx_hash = HashMap::from([(
         transfer_log.transaction_hash.clone().unwrap(),
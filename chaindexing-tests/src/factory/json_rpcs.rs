@@ -1,8 +1,9 @@
 use chaindexing::EventsIngesterJsonRpc;
 use ethers::providers::ProviderError;
-use ethers::types::{Block, Filter, Log, TxHash, U64};
+use ethers::types::{Block, Filter, Log, TransactionReceipt, TxHash, U64};
 
 use rand::seq::SliceRandom;
+use std::time::Duration;
 
 pub fn empty_json_rpc() -> impl EventsIngesterJsonRpc {
     #[derive(Clone)]
@@ -28,8 +29,394 @@ pub fn empty_json_rpc() -> impl EventsIngesterJsonRpc {
     return JsonRpc;
 }
 
-use ethers::types::{Bytes, H160, H256};
+pub fn json_rpc_with_max_block_range(max_block_range: u64) -> impl EventsIngesterJsonRpc {
+    #[derive(Clone)]
+    struct JsonRpc {
+        max_block_range: u64,
+    }
+    #[async_trait::async_trait]
+    impl EventsIngesterJsonRpc for JsonRpc {
+        async fn get_block_number(&self) -> Result<U64, ProviderError> {
+            Ok(U64::from(18115958))
+        }
+
+        async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, ProviderError> {
+            let from_block = filter.get_from_block().unwrap().as_u64();
+            let to_block = filter.get_to_block().unwrap().as_u64();
+
+            if to_block - from_block > self.max_block_range {
+                Err(ProviderError::CustomError(format!(
+                    "query exceeds max block range {}",
+                    self.max_block_range
+                )))
+            } else {
+                Ok(vec![])
+            }
+        }
+
+        async fn get_block(&self, block_number: U64) -> Result<Block<TxHash>, ProviderError> {
+            Ok(Block {
+                number: Some(block_number),
+                ..Default::default()
+            })
+        }
+    }
+
+    JsonRpc { max_block_range }
+}
+
+/// Fails every request, like a dead chain's endpoint. Counts requests.
+pub fn failing_json_rpc(requests_count: Arc<AtomicUsize>) -> impl EventsIngesterJsonRpc {
+    #[derive(Clone)]
+    struct JsonRpc {
+        requests_count: Arc<AtomicUsize>,
+    }
+    impl JsonRpc {
+        fn fail<T>(&self) -> Result<T, ProviderError> {
+            self.requests_count.fetch_add(1, Ordering::SeqCst);
+
+            Err(ProviderError::CustomError("connection refused".to_string()))
+        }
+    }
+    #[async_trait::async_trait]
+    impl EventsIngesterJsonRpc for JsonRpc {
+        async fn get_block_number(&self) -> Result<U64, ProviderError> {
+            self.fail()
+        }
+
+        async fn get_logs(&self, _filter: &Filter) -> Result<Vec<Log>, ProviderError> {
+            self.fail()
+        }
+
+        async fn get_block(&self, _block_number: U64) -> Result<Block<TxHash>, ProviderError> {
+            self.fail()
+        }
+
+        async fn get_transaction_receipt(
+            &self,
+            _tx_hash: TxHash,
+        ) -> Result<Option<TransactionReceipt>, ProviderError> {
+            self.fail()
+        }
+    }
+
+    JsonRpc { requests_count }
+}
+
+use ethers::types::{Bytes, ValueOrArray, H160, H256};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Returns a log at the start of the filter's range along with a stray one
+/// right past its end, like providers off by one at boundaries.
+pub fn json_rpc_with_stray_logs(
+    contract_address: &'static str,
+    current_block_number: u64,
+) -> impl EventsIngesterJsonRpc {
+    #[derive(Clone)]
+    struct JsonRpc {
+        contract_address: &'static str,
+        current_block_number: u64,
+    }
+    #[async_trait::async_trait]
+    impl EventsIngesterJsonRpc for JsonRpc {
+        async fn get_block_number(&self) -> Result<U64, ProviderError> {
+            Ok(U64::from(self.current_block_number))
+        }
+
+        async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, ProviderError> {
+            let mut log = transfer_log(self.contract_address);
+            log.block_number = filter.get_from_block();
+            log.log_index = Some(1.into());
+
+            let mut stray_log = transfer_log(self.contract_address);
+            stray_log.block_number = filter.get_to_block().map(|to_block| to_block + 1);
+            stray_log.log_index = Some(2.into());
+
+            Ok(vec![log, stray_log])
+        }
+
+        async fn get_block(&self, block_number: U64) -> Result<Block<TxHash>, ProviderError> {
+            Ok(Block {
+                number: Some(block_number),
+                ..Default::default()
+            })
+        }
+    }
+
+    JsonRpc {
+        contract_address,
+        current_block_number,
+    }
+}
+
+/// Serves a transfer log at `stale_block_number` for any block number range
+/// including it, like a provider node still on a reorged-out branch, while
+/// the canonical block at that number has another hash, or is not known at
+/// all unless `knows_stale_block_number`. Fetching logs by block hash errors,
+/// like nodes do for reorged-out blocks.
+pub fn json_rpc_with_stale_logs(
+    contract_address: &'static str,
+    current_block_number: u64,
+    stale_block_number: u64,
+    knows_stale_block_number: bool,
+) -> impl EventsIngesterJsonRpc {
+    #[derive(Clone)]
+    struct JsonRpc {
+        contract_address: &'static str,
+        current_block_number: u64,
+        stale_block_number: u64,
+        knows_stale_block_number: bool,
+    }
+    #[async_trait::async_trait]
+    impl EventsIngesterJsonRpc for JsonRpc {
+        async fn get_block_number(&self) -> Result<U64, ProviderError> {
+            Ok(U64::from(self.current_block_number))
+        }
+
+        async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, ProviderError> {
+            if let Some(block_hash) = filter.get_block_hash() {
+                return Err(ProviderError::CustomError(format!(
+                    "unknown block {block_hash:?}"
+                )));
+            }
+
+            let stale_block_number = U64::from(self.stale_block_number);
+            let from_block = filter.get_from_block().unwrap();
+            let to_block = filter.get_to_block().unwrap();
+
+            if from_block <= stale_block_number && stale_block_number <= to_block {
+                let mut stale_log = transfer_log(self.contract_address);
+                stale_log.block_number = Some(stale_block_number);
+
+                Ok(vec![stale_log])
+            } else {
+                Ok(vec![])
+            }
+        }
+
+        async fn get_block(&self, block_number: U64) -> Result<Block<TxHash>, ProviderError> {
+            Ok(Block {
+                number: Some(block_number),
+                hash: Some(H256::from_low_u64_be(block_number.as_u64())),
+                ..Default::default()
+            })
+        }
+
+        async fn get_block_hash(&self, block_number: U64) -> Result<Option<H256>, ProviderError> {
+            if block_number.as_u64() == self.stale_block_number && !self.knows_stale_block_number {
+                Ok(None)
+            } else {
+                Ok(self.get_block(block_number).await?.hash)
+            }
+        }
+    }
+
+    JsonRpc {
+        contract_address,
+        current_block_number,
+        stale_block_number,
+        knows_stale_block_number,
+    }
+}
+
+/// Serves a transfer log at the start of each filter's block range, only
+/// through `get_logs_batch`, recording how many filters each call multiplexes.
+pub fn json_rpc_with_batched_logs(
+    current_block_number: u64,
+    batched_filters_counts: Arc<Mutex<Vec<usize>>>,
+) -> impl EventsIngesterJsonRpc {
+    #[derive(Clone)]
+    struct JsonRpc {
+        current_block_number: u64,
+        batched_filters_counts: Arc<Mutex<Vec<usize>>>,
+    }
+    #[async_trait::async_trait]
+    impl EventsIngesterJsonRpc for JsonRpc {
+        async fn get_block_number(&self) -> Result<U64, ProviderError> {
+            Ok(U64::from(self.current_block_number))
+        }
+
+        async fn get_logs(&self, _filter: &Filter) -> Result<Vec<Log>, ProviderError> {
+            panic!("Logs should only get fetched in batches")
+        }
+
+        async fn get_logs_batch(
+            &self,
+            filters: &Vec<&Filter>,
+        ) -> Result<Vec<Vec<Log>>, ProviderError> {
+            self.batched_filters_counts.lock().unwrap().push(filters.len());
+
+            Ok(filters
+                .iter()
+                .map(|filter| {
+                    let addresses = match &filter.address {
+                        Some(ValueOrArray::Value(address)) => vec![*address],
+                        Some(ValueOrArray::Array(addresses)) => addresses.clone(),
+                        None => vec![],
+                    };
+
+                    addresses
+                        .iter()
+                        .map(|address| {
+                            let mut log = transfer_log(&format!("{address:?}"));
+                            log.block_number = filter.get_from_block();
+                            log
+                        })
+                        .collect()
+                })
+                .collect())
+        }
+
+        async fn get_block(&self, block_number: U64) -> Result<Block<TxHash>, ProviderError> {
+            Ok(Block {
+                number: Some(block_number),
+                ..Default::default()
+            })
+        }
+    }
+
+    JsonRpc {
+        current_block_number,
+        batched_filters_counts,
+    }
+}
+
+/// Serves the given logs of any block number range including them, e.g. to
+/// change the logs of already ingested blocks.
+pub fn json_rpc_with_served_logs(
+    current_block_number: u64,
+    logs: Vec<Log>,
+) -> impl EventsIngesterJsonRpc {
+    #[derive(Clone)]
+    struct JsonRpc {
+        current_block_number: u64,
+        logs: Vec<Log>,
+    }
+    #[async_trait::async_trait]
+    impl EventsIngesterJsonRpc for JsonRpc {
+        async fn get_block_number(&self) -> Result<U64, ProviderError> {
+            Ok(U64::from(self.current_block_number))
+        }
+
+        async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, ProviderError> {
+            let from_block = filter.get_from_block().unwrap();
+            let to_block = filter.get_to_block().unwrap();
+
+            Ok(self
+                .logs
+                .iter()
+                .filter(|log| {
+                    log.block_number.is_some_and(|block_number| {
+                        from_block <= block_number && block_number <= to_block
+                    })
+                })
+                .cloned()
+                .collect())
+        }
+
+        async fn get_block(&self, block_number: U64) -> Result<Block<TxHash>, ProviderError> {
+            Ok(Block {
+                number: Some(block_number),
+                ..Default::default()
+            })
+        }
+    }
+
+    JsonRpc {
+        current_block_number,
+        logs,
+    }
+}
+
+/// Serves the logs like `json_rpc_with_served_logs`, taking `delay` to
+/// respond to each `get_logs` and `get_block` call
+pub fn json_rpc_with_slow_served_logs(
+    current_block_number: u64,
+    logs: Vec<Log>,
+    delay: Duration,
+) -> impl EventsIngesterJsonRpc {
+    #[derive(Clone)]
+    struct JsonRpc<T: EventsIngesterJsonRpc> {
+        json_rpc: T,
+        delay: Duration,
+    }
+    #[async_trait::async_trait]
+    impl<T: EventsIngesterJsonRpc> EventsIngesterJsonRpc for JsonRpc<T> {
+        async fn get_block_number(&self) -> Result<U64, ProviderError> {
+            self.json_rpc.get_block_number().await
+        }
+
+        async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, ProviderError> {
+            tokio::time::sleep(self.delay).await;
+
+            self.json_rpc.get_logs(filter).await
+        }
+
+        async fn get_block(&self, block_number: U64) -> Result<Block<TxHash>, ProviderError> {
+            tokio::time::sleep(self.delay).await;
+
+            self.json_rpc.get_block(block_number).await
+        }
+
+        async fn get_transaction_receipt(
+            &self,
+            tx_hash: TxHash,
+        ) -> Result<Option<TransactionReceipt>, ProviderError> {
+            self.json_rpc.get_transaction_receipt(tx_hash).await
+        }
+    }
+
+    JsonRpc {
+        json_rpc: json_rpc_with_served_logs(current_block_number, logs),
+        delay,
+    }
+}
+
+/// Serves the logs like `json_rpc_with_served_logs`, except for the first
+/// `get_logs` call, which never responds. Counts `get_logs` calls.
+pub fn json_rpc_with_hanging_logs(
+    current_block_number: u64,
+    logs: Vec<Log>,
+    get_logs_calls_count: Arc<AtomicUsize>,
+) -> impl EventsIngesterJsonRpc {
+    #[derive(Clone)]
+    struct JsonRpc<T: EventsIngesterJsonRpc> {
+        json_rpc: T,
+        get_logs_calls_count: Arc<AtomicUsize>,
+    }
+    #[async_trait::async_trait]
+    impl<T: EventsIngesterJsonRpc> EventsIngesterJsonRpc for JsonRpc<T> {
+        async fn get_block_number(&self) -> Result<U64, ProviderError> {
+            self.json_rpc.get_block_number().await
+        }
+
+        async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, ProviderError> {
+            if self.get_logs_calls_count.fetch_add(1, Ordering::SeqCst) == 0 {
+                std::future::pending::<()>().await;
+            }
+
+            self.json_rpc.get_logs(filter).await
+        }
+
+        async fn get_block(&self, block_number: U64) -> Result<Block<TxHash>, ProviderError> {
+            self.json_rpc.get_block(block_number).await
+        }
+
+        async fn get_transaction_receipt(
+            &self,
+            tx_hash: TxHash,
+        ) -> Result<Option<TransactionReceipt>, ProviderError> {
+            self.json_rpc.get_transaction_receipt(tx_hash).await
+        }
+    }
+
+    JsonRpc {
+        json_rpc: json_rpc_with_served_logs(current_block_number, logs),
+        get_logs_calls_count,
+    }
+}
 
 pub fn transfer_log(contract_address: &str) -> Log {
     let log_index = *(1..800).collect::<Vec<_>>().choose(&mut rand::thread_rng()).unwrap();
@@ -83,8 +470,11 @@ macro_rules! json_rpc_with_logs {
                 Ok(U64::from($current_block_number))
             }
 
-            async fn get_logs(&self, _filter: &Filter) -> Result<Vec<Log>, ProviderError> {
-                Ok(vec![transfer_log($contract_address)])
+            async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, ProviderError> {
+                let mut transfer_log = transfer_log($contract_address);
+                transfer_log.block_number = filter.get_from_block();
+
+                Ok(vec![transfer_log])
             }
 
             async fn get_block(&self, block_number: U64) -> Result<Block<TxHash>, ProviderError> {
@@ -102,6 +492,11 @@ macro_rules! json_rpc_with_logs {
 #[macro_export]
 macro_rules! json_rpc_with_filter_stubber {
     ($contract_address:expr, $filter_stubber: expr) => {{
+        use crate::json_rpc_with_filter_stubber;
+
+        json_rpc_with_filter_stubber!($contract_address, 3, $filter_stubber)
+    }};
+    ($contract_address:expr, $current_block_number:expr, $filter_stubber: expr) => {{
         use chaindexing::EventsIngesterJsonRpc;
         use ethers::providers::ProviderError;
         use ethers::types::{Block, Filter, Log, TxHash, U64};
@@ -111,7 +506,7 @@ macro_rules! json_rpc_with_filter_stubber {
         #[async_trait::async_trait]
         impl EventsIngesterJsonRpc for JsonRpc {
             async fn get_block_number(&self) -> Result<U64, ProviderError> {
-                Ok(U64::from(3))
+                Ok(U64::from($current_block_number))
             }
 
             async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, ProviderError> {
@@ -162,3 +557,49 @@ macro_rules! json_rpc_with_empty_logs {
         JsonRpc
     }};
 }
+
+#[macro_export]
+macro_rules! json_rpc_with_reverted_transaction_logs {
+    ($contract_address:expr, $current_block_number:expr) => {{
+        use crate::factory::transfer_log;
+        use chaindexing::EventsIngesterJsonRpc;
+        use ethers::providers::ProviderError;
+        use ethers::types::{Block, Filter, Log, TransactionReceipt, TxHash, U64};
+
+        #[derive(Clone)]
+        struct JsonRpc;
+        #[async_trait::async_trait]
+        impl EventsIngesterJsonRpc for JsonRpc {
+            async fn get_block_number(&self) -> Result<U64, ProviderError> {
+                Ok(U64::from($current_block_number))
+            }
+
+            async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, ProviderError> {
+                let mut transfer_log = transfer_log($contract_address);
+                transfer_log.block_number = filter.get_from_block();
+
+                Ok(vec![transfer_log])
+            }
+
+            async fn get_block(&self, block_number: U64) -> Result<Block<TxHash>, ProviderError> {
+                Ok(Block {
+                    number: Some(block_number),
+                    ..Default::default()
+                })
+            }
+
+            async fn get_transaction_receipt(
+                &self,
+                tx_hash: TxHash,
+            ) -> Result<Option<TransactionReceipt>, ProviderError> {
+                Ok(Some(TransactionReceipt {
+                    transaction_hash: tx_hash,
+                    status: Some(U64::from(0)),
+                    ..Default::default()
+                }))
+            }
+        }
+
+        JsonRpc
+    }};
+}
@@ -1,6 +1,15 @@
+mod configs;
 mod contract_states;
+mod contracts;
+mod event_handlers;
+mod events;
 mod events_ingester;
+mod json_rpcs;
+mod pipelines;
+mod repos;
+mod tokens;
 
 pub async fn setup() {
     contract_states::setup().await;
+    tokens::setup().await;
 }
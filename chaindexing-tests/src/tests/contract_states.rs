@@ -1,6 +1,12 @@
 #[cfg(test)]
 mod tests {
-    use chaindexing::{ChaindexingRepo, EventContext, HasRawQueryClient};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use chaindexing::{
+        Chain, ChaindexingRepo, ContractStates, EventContext, HasRawQueryClient,
+        LoadsDataWithRawQuery, Migratable,
+    };
 
     use super::*;
     use crate::factory::{bayc_contract, transfer_event_with_contract};
@@ -84,6 +90,161 @@ mod tests {
         .await;
         assert_eq!(state, None);
     }
+
+    #[tokio::test]
+    pub async fn detects_migrations_edited_after_being_applied() {
+        let raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+        let migration = "CREATE TABLE IF NOT EXISTS checksummed_nft_states (
+            token_id INTEGER NOT NULL
+        )";
+        let edited_migration = "CREATE TABLE IF NOT EXISTS checksummed_nft_states (
+            token_id BIGINT NOT NULL
+        )";
+
+        ChaindexingRepo::migrate(&raw_query_client, vec![migration]).await;
+        let drifted_migrations =
+            ChaindexingRepo::get_drifted_migrations(&raw_query_client, &vec![migration]).await;
+        assert!(drifted_migrations.is_empty());
+
+        let drifted_migrations =
+            ChaindexingRepo::get_drifted_migrations(&raw_query_client, &vec![edited_migration])
+                .await;
+        assert_eq!(drifted_migrations, vec![edited_migration]);
+
+        // Re-creating the table applies the edited migration
+        ChaindexingRepo::migrate(
+            &raw_query_client,
+            vec!["DROP TABLE IF EXISTS checksummed_nft_states"],
+        )
+        .await;
+        let drifted_migrations =
+            ChaindexingRepo::get_drifted_migrations(&raw_query_client, &vec![edited_migration])
+                .await;
+        assert!(drifted_migrations.is_empty());
+    }
+
+    #[tokio::test]
+    pub async fn exposes_states_through_views_with_computed_columns() {
+        let bayc_contract = bayc_contract()
+            .add_state_migrations(NftStateMigrations)
+            .add_state_migrations(DoubledNftStateViewMigrations);
+        let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+        Chaindexing::run_migrations_for_contract_states(
+            &raw_query_client,
+            &vec![bayc_contract.clone()],
+        )
+        .await;
+        let raw_query_txn_client =
+            ChaindexingRepo::get_raw_query_txn_client(&mut raw_query_client).await;
+        let event_context = EventContext::new(
+            transfer_event_with_contract(bayc_contract),
+            &raw_query_txn_client,
+        );
+
+        NftState { token_id: 21 }.create(&event_context).await;
+
+        let doubled_nft_states: Vec<DoubledNftState> =
+            ChaindexingRepo::load_data_list_from_raw_query_with_txn_client(
+                &raw_query_txn_client,
+                "SELECT * FROM doubled_nft_states WHERE token_id = 21",
+            )
+            .await;
+        assert_eq!(
+            doubled_nft_states,
+            vec![DoubledNftState {
+                token_id: 21,
+                doubled_token_id: 42
+            }]
+        );
+    }
+
+    #[tokio::test]
+    pub async fn skips_identical_state_versions_when_deduplicating() {
+        let bayc_contract = bayc_contract().add_state_migrations(NftStateMigrations);
+        let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+        let raw_query_txn_client =
+            ChaindexingRepo::get_raw_query_txn_client(&mut raw_query_client).await;
+        let event_context = EventContext::new(
+            transfer_event_with_contract(bayc_contract),
+            &raw_query_txn_client,
+        )
+        .with_deduplicate_state_versions(true);
+
+        let new_state = NftState { token_id: 7 };
+        new_state.create(&event_context).await;
+        let updates = [("token_id".to_string(), "7".to_owned())];
+        new_state.update(updates.clone().into(), &event_context).await;
+        new_state.update(updates.into(), &event_context).await;
+
+        let state_versions: Vec<NftState> =
+            ChaindexingRepo::load_data_list_from_raw_query_with_txn_client(
+                &raw_query_txn_client,
+                "SELECT * FROM chaindexing_state_versions_for_nft_states WHERE token_id = 7",
+            )
+            .await;
+        assert_eq!(state_versions.len(), 1);
+    }
+
+    #[tokio::test]
+    pub async fn aggregates_states_across_chains_in_one_state_group() {
+        let bayc_contract = bayc_contract().add_state_migrations(TokenSupplyStateMigrations);
+        let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+        let raw_query_txn_client =
+            ChaindexingRepo::get_raw_query_txn_client(&mut raw_query_client).await;
+        let mainnet_event = transfer_event_with_contract(bayc_contract);
+        let mut polygon_event = mainnet_event.clone();
+        polygon_event.chain_id = Chain::Polygon as i32;
+        let mut next_polygon_event = polygon_event.clone();
+        next_polygon_event.block_number += 1;
+        let mainnet_context = EventContext::new(mainnet_event, &raw_query_txn_client);
+        let polygon_context = EventContext::new(polygon_event, &raw_query_txn_client);
+        let next_polygon_context = EventContext::new(next_polygon_event, &raw_query_txn_client);
+
+        for context in [&mainnet_context, &polygon_context, &next_polygon_context] {
+            mint_token(context).await;
+        }
+
+        let get_total_supply = || async {
+            TokenSupplyState::read_many(
+                [("token".to_owned(), "BAYC".to_owned())].into(),
+                &mainnet_context,
+            )
+            .await
+            .iter()
+            .map(|state| state.supply)
+            .sum::<i32>()
+        };
+        assert_eq!(get_total_supply().await, 3);
+
+        // Reorgs only backtrack their own chain's share
+        ContractStates::backtrack_states(
+            &vec![Arc::new(TokenSupplyStateMigrations)],
+            Chain::Polygon as i32,
+            next_polygon_context.event.block_number,
+            &raw_query_txn_client,
+        )
+        .await;
+        assert_eq!(get_total_supply().await, 2);
+    }
+
+    async fn mint_token(context: &EventContext<'_>) {
+        let filters: HashMap<_, _> = [("token".to_owned(), "BAYC".to_owned())].into();
+
+        match TokenSupplyState::read_one_on_chain(filters, context).await {
+            Some(state) => {
+                let updates = [("supply".to_owned(), (state.supply + 1).to_string())];
+                state.update(updates.into(), context).await;
+            }
+            None => {
+                let state = TokenSupplyState {
+                    token: "BAYC".to_owned(),
+                    chain_id: context.get_chain_id(),
+                    supply: 1,
+                };
+                state.create(context).await;
+            }
+        }
+    }
 }
 
 use chaindexing::{Chaindexing, ContractState, ContractStateMigrations, HasRawQueryClient};
@@ -111,8 +272,53 @@ impl ContractStateMigrations for NftStateMigrations {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct DoubledNftState {
+    token_id: i32,
+    doubled_token_id: i32,
+}
+struct DoubledNftStateViewMigrations;
+impl ContractStateMigrations for DoubledNftStateViewMigrations {
+    fn migrations(&self) -> Vec<&'static str> {
+        vec![]
+    }
+
+    fn view_migrations(&self) -> Vec<&'static str> {
+        vec![
+            "CREATE OR REPLACE VIEW doubled_nft_states AS
+        SELECT token_id, token_id * 2 AS doubled_token_id FROM nft_states",
+        ]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct TokenSupplyState {
+    token: String,
+    chain_id: i32,
+    supply: i32,
+}
+impl ContractState for TokenSupplyState {
+    fn table_name() -> &'static str {
+        "token_supply_states"
+    }
+}
+struct TokenSupplyStateMigrations;
+impl ContractStateMigrations for TokenSupplyStateMigrations {
+    fn migrations(&self) -> Vec<&'static str> {
+        vec![
+            "CREATE TABLE IF NOT EXISTS token_supply_states (
+        token TEXT NOT NULL,
+        chain_id INTEGER NOT NULL,
+        supply INTEGER NOT NULL,
+    )",
+        ]
+    }
+}
+
 pub async fn setup() {
-    let bayc_contract = bayc_contract().add_state_migrations(NftStateMigrations);
+    let bayc_contract = bayc_contract()
+        .add_state_migrations(NftStateMigrations)
+        .add_state_migrations(TokenSupplyStateMigrations);
     let raw_query_client = test_runner::new_repo().get_raw_query_client().await;
     Chaindexing::run_migrations_for_contract_states(&raw_query_client, &vec![bayc_contract]).await;
 }
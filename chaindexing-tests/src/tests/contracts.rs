@@ -0,0 +1,474 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    use chaindexing::{
+        AutoDetectStartBlock, Chain, Chaindexing, ChaindexingRepo, Contract, ContractAddressID,
+        DeploymentManifest, DeploymentManifestError, EventsIngester, EventsIngesterError,
+        FromLatestStartBlock, HandlerMismatch, PostgresRepo, Repo,
+    };
+    use ethers::types::Filter;
+
+    use crate::factory::{
+        bayc_contract, config_with_contracts, failing_json_rpc, json_rpc_with_served_logs,
+        transfer_log, TransferTestEventHandler, APPROCAL_EVENT_ABI, BAYC_CONTRACT_ADDRESS,
+        BAYC_CONTRACT_START_BLOCK_NUMBER, TRANSFER_EVENT_ABI,
+    };
+    use crate::{json_rpc_with_filter_stubber, json_rpc_with_logs, test_runner};
+
+    #[tokio::test]
+    pub async fn reports_contracts_with_their_saved_addresses_and_cursors() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            const DOODLES_CONTRACT_ADDRESS: &str = "0x8a90CAb2b38dba80c64b7734e58Ee1dB38B8992e";
+
+            let bayc_contract = bayc_contract().with_priority(2);
+            // Registered after setup, so never saved
+            let doodles_contract = Contract::new("Doodles")
+                .add_event(TRANSFER_EVENT_ABI, TransferTestEventHandler)
+                .add_address(DOODLES_CONTRACT_ADDRESS, &Chain::Polygon, 100);
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &vec![bayc_contract.clone()])
+                .await;
+            let bayc_contract_address = ChaindexingRepo::get_all_contract_addresses(&mut conn)
+                .await
+                .into_iter()
+                .find(|contract_address| {
+                    contract_address.address == BAYC_CONTRACT_ADDRESS.to_lowercase()
+                })
+                .unwrap();
+            let next_block_number_to_ingest_from = BAYC_CONTRACT_START_BLOCK_NUMBER as i64 + 50;
+            ChaindexingRepo::update_next_block_number_to_ingest_from(
+                &mut conn,
+                &bayc_contract_address,
+                next_block_number_to_ingest_from,
+            )
+            .await;
+
+            // Registered at runtime, so only known once saved
+            const REGISTERED_BAYC_CONTRACT_ADDRESS: &str =
+                "0x0000000000000000000000000000000000000001";
+            let registered_start_block_number = BAYC_CONTRACT_START_BLOCK_NUMBER as i64 + 10;
+            let config = config_with_contracts(vec![bayc_contract.clone(), doodles_contract]);
+            Chaindexing::register_contract_addresses(
+                &mut conn,
+                &config,
+                &bayc_contract,
+                &[REGISTERED_BAYC_CONTRACT_ADDRESS],
+                &Chain::Mainnet,
+                registered_start_block_number,
+            )
+            .await
+            .unwrap();
+
+            config.pause_chain(&Chain::Polygon);
+
+            let contracts_status = config.contracts_status_with_conn(&mut conn).await;
+            assert_eq!(contracts_status.len(), 2);
+
+            let bayc_status = &contracts_status[0];
+            assert_eq!(bayc_status.name, "BoredApeYachtClub");
+            assert_eq!(bayc_status.priority, 2);
+            assert_eq!(bayc_status.event_abis.len(), 2);
+            let bayc_address_status = bayc_status.addresses.first().unwrap();
+            assert_eq!(
+                bayc_address_status.address,
+                BAYC_CONTRACT_ADDRESS.to_lowercase()
+            );
+            assert_eq!(bayc_address_status.chain_id, Chain::Mainnet as i32);
+            assert_eq!(
+                bayc_address_status.start_block_number,
+                BAYC_CONTRACT_START_BLOCK_NUMBER as i64
+            );
+            assert!(bayc_address_status.enabled);
+            assert_eq!(
+                bayc_address_status.next_block_number_to_ingest_from,
+                Some(next_block_number_to_ingest_from)
+            );
+            assert_eq!(
+                bayc_address_status.next_block_number_to_handle_from,
+                Some(BAYC_CONTRACT_START_BLOCK_NUMBER as i64)
+            );
+
+            let registered_address_status = &bayc_status.addresses[1];
+            assert_eq!(
+                registered_address_status.address,
+                REGISTERED_BAYC_CONTRACT_ADDRESS
+            );
+            assert_eq!(
+                registered_address_status.start_block_number,
+                registered_start_block_number
+            );
+            assert_eq!(
+                registered_address_status.next_block_number_to_ingest_from,
+                Some(registered_start_block_number)
+            );
+
+            let doodles_address_status = contracts_status[1].addresses.first().unwrap();
+            assert_eq!(doodles_address_status.chain_id, Chain::Polygon as i32);
+            assert!(!doodles_address_status.enabled);
+            assert_eq!(
+                doodles_address_status.next_block_number_to_ingest_from,
+                None
+            );
+            assert_eq!(
+                doodles_address_status.next_block_number_to_handle_from,
+                None
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn gets_contract_addresses_by_id() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            Chaindexing::create_initial_contract_addresses(&mut conn, &vec![bayc_contract()]).await;
+            let contract_addresses = ChaindexingRepo::get_all_contract_addresses(&mut conn).await;
+            let bayc_contract_address = contract_addresses.first().unwrap();
+
+            let contract_address =
+                ChaindexingRepo::get_contract_address_by_id(&mut conn, bayc_contract_address.id())
+                    .await
+                    .unwrap();
+            assert_eq!(contract_address.id, bayc_contract_address.id);
+            assert_eq!(contract_address.address, bayc_contract_address.address);
+
+            let missing_contract_address = ChaindexingRepo::get_contract_address_by_id(
+                &mut conn,
+                ContractAddressID(bayc_contract_address.id + 1),
+            )
+            .await;
+            assert!(missing_contract_address.is_none());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn bulk_registers_contract_addresses_for_ingestion() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+
+            // More than a single insert's bind parameters allow
+            let addresses: Vec<_> = (1..=11_000).map(|i| format!("0x{i:040x}")).collect();
+            let mut addresses: Vec<_> = addresses.iter().map(|a| a.as_str()).collect();
+            addresses.push(addresses[0]);
+
+            let contract = bayc_contract();
+            let config = config_with_contracts(vec![contract.clone()])
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1);
+            Chaindexing::register_contract_addresses(
+                &mut conn,
+                &config,
+                &contract,
+                &addresses,
+                &Chain::Mainnet,
+                START_BLOCK_NUMBER as i64,
+            )
+            .await
+            .unwrap();
+
+            let contract_addresses = ChaindexingRepo::get_all_contract_addresses(&mut conn).await;
+            assert_eq!(contract_addresses.len(), 11_000);
+
+            let json_rpc = Arc::new(json_rpc_with_filter_stubber!(
+                BAYC_CONTRACT_ADDRESS,
+                START_BLOCK_NUMBER + 20,
+                |_filter: &Filter| {}
+            ));
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            let mut conn = conn.lock().await;
+            let contract_addresses = ChaindexingRepo::get_all_contract_addresses(&mut conn).await;
+            assert!(contract_addresses.iter().all(|contract_address| {
+                contract_address.next_block_number_to_ingest_from > START_BLOCK_NUMBER as i64
+            }));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn ingests_events_of_contract_addresses_registered_while_indexing() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            const DOODLES_CONTRACT_ADDRESS: &str = "0x8a90CAb2b38dba80c64b7734e58Ee1dB38B8992e";
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+
+            // Missing from the configured contract's addresses
+            let contract = bayc_contract();
+            let config = config_with_contracts(vec![contract.clone()])
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1);
+            Chaindexing::register_contract_addresses(
+                &mut conn,
+                &config,
+                &contract,
+                &[DOODLES_CONTRACT_ADDRESS],
+                &Chain::Mainnet,
+                START_BLOCK_NUMBER as i64,
+            )
+            .await
+            .unwrap();
+
+            let json_rpc = Arc::new(json_rpc_with_logs!(
+                DOODLES_CONTRACT_ADDRESS,
+                START_BLOCK_NUMBER + 20
+            ));
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            let mut conn = conn.lock().await;
+            let events = PostgresRepo::get_all_events(&mut conn).await;
+            assert_eq!(events.len(), 1);
+            let event = events.first().unwrap();
+            assert_eq!(
+                event.contract_address,
+                DOODLES_CONTRACT_ADDRESS.to_lowercase()
+            );
+            assert_eq!(event.contract_name, "BoredApeYachtClub");
+        })
+        .await;
+    }
+
+    #[test]
+    pub fn registers_contract_addresses_from_a_deployment_manifest() {
+        const DOODLES_CONTRACT_ADDRESS: &str = "0x8a90CAb2b38dba80c64b7734e58Ee1dB38B8992e";
+        let manifest = DeploymentManifest::from_json(&format!(
+            r#"{{
+                "mainnet": {{
+                    "BoredApeYachtClub": [
+                        {{ "address": "{BAYC_CONTRACT_ADDRESS}", "start_block_number": 12287507 }}
+                    ]
+                }},
+                "137": {{
+                    "Doodles": [
+                        {{ "address": "{DOODLES_CONTRACT_ADDRESS}", "start_block_number": 100 }}
+                    ]
+                }}
+            }}"#
+        ))
+        .unwrap();
+        let bayc_contract = Contract::new("BoredApeYachtClub")
+            .add_event(TRANSFER_EVENT_ABI, TransferTestEventHandler);
+        let doodles_contract =
+            Contract::new("Doodles").add_event(TRANSFER_EVENT_ABI, TransferTestEventHandler);
+
+        let config = config_with_contracts(vec![bayc_contract, doodles_contract])
+            .add_deployment_manifest(&manifest)
+            .unwrap();
+
+        let bayc_contract = config.contracts.first().unwrap();
+        assert_eq!(bayc_contract.addresses.len(), 1);
+        let bayc_contract_address = bayc_contract.addresses.first().unwrap();
+        assert_eq!(bayc_contract_address.chain_id, Chain::Mainnet as i32);
+        assert_eq!(
+            bayc_contract_address.get_address(),
+            BAYC_CONTRACT_ADDRESS.to_lowercase()
+        );
+        assert_eq!(bayc_contract_address.get_start_block_number(), 12287507);
+
+        let doodles_contract = config.contracts.last().unwrap();
+        assert_eq!(doodles_contract.addresses.len(), 1);
+        let doodles_contract_address = doodles_contract.addresses.first().unwrap();
+        assert_eq!(doodles_contract_address.chain_id, Chain::Polygon as i32);
+        assert_eq!(
+            doodles_contract_address.get_address(),
+            DOODLES_CONTRACT_ADDRESS.to_lowercase()
+        );
+        assert_eq!(doodles_contract_address.get_start_block_number(), 100);
+    }
+
+    #[test]
+    pub fn rejects_deployment_manifests_of_unconfigured_contracts() {
+        let manifest = DeploymentManifest::from_json(
+            r#"{ "mainnet": { "Doodles": [{ "address": "0x8a90CAb2b38dba80c64b7734e58Ee1dB38B8992e", "start_block_number": 100 }] } }"#,
+        )
+        .unwrap();
+
+        let result =
+            config_with_contracts(vec![bayc_contract()]).add_deployment_manifest(&manifest);
+
+        assert_eq!(
+            result.err(),
+            Some(DeploymentManifestError::UnknownContract(
+                "Doodles".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    pub fn validates_handlers_of_matching_contract_events() {
+        let config = config_with_contracts(vec![bayc_contract()]);
+
+        assert_eq!(config.validate_handlers(), Ok(()));
+    }
+
+    #[test]
+    pub fn reports_dead_handlers_of_events_decoded_with_another_abi() {
+        // Same topic as TRANSFER_EVENT_ABI, so only one of them decodes events
+        const RENAMED_TRANSFER_EVENT_ABI: &str =
+            "event Transfer(address indexed _from, address indexed _to, uint256 indexed _tokenId)";
+
+        let doodles_contract = Contract::new("Doodles")
+            .add_event(RENAMED_TRANSFER_EVENT_ABI, TransferTestEventHandler)
+            .add_address(
+                "0x8a90CAb2b38dba80c64b7734e58Ee1dB38B8992e",
+                &Chain::Mainnet,
+                100,
+            );
+        let config = config_with_contracts(vec![bayc_contract(), doodles_contract]);
+
+        assert_eq!(
+            config.validate_handlers(),
+            Err(vec![HandlerMismatch::DeadHandler {
+                contract_name: "BoredApeYachtClub".to_string(),
+                event_abi: TRANSFER_EVENT_ABI.to_string(),
+            }])
+        );
+    }
+
+    #[test]
+    pub fn reports_unhandled_contract_events() {
+        let abi_json = r#"[
+            {
+                "type": "event",
+                "name": "Transfer",
+                "anonymous": false,
+                "inputs": [
+                    { "name": "from", "type": "address", "indexed": true },
+                    { "name": "to", "type": "address", "indexed": true },
+                    { "name": "tokenId", "type": "uint256", "indexed": true }
+                ]
+            },
+            {
+                "type": "event",
+                "name": "ApprovalForAll",
+                "anonymous": false,
+                "inputs": [
+                    { "name": "owner", "type": "address", "indexed": true },
+                    { "name": "operator", "type": "address", "indexed": true },
+                    { "name": "approved", "type": "bool", "indexed": false }
+                ]
+            }
+        ]"#;
+        let contract = Contract::from_abi_json("BoredApeYachtClub", abi_json)
+            .add_event(TRANSFER_EVENT_ABI, TransferTestEventHandler)
+            .add_address(BAYC_CONTRACT_ADDRESS, &Chain::Mainnet, 17773490);
+        let config = config_with_contracts(vec![contract]);
+
+        assert_eq!(
+            config.validate_handlers(),
+            Err(vec![HandlerMismatch::UnhandledEvent {
+                contract_name: "BoredApeYachtClub".to_string(),
+                event_abi: APPROCAL_EVENT_ABI.to_string(),
+            }])
+        );
+    }
+
+    #[tokio::test]
+    pub async fn resolves_start_blocks_of_unregistered_contract_addresses() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let current_block_number = 18_200_000;
+            let first_log = transfer_log(BAYC_CONTRACT_ADDRESS);
+            let first_log_block_number = first_log.block_number.unwrap().as_u64() as i64;
+            let json_rpcs = HashMap::from([(
+                Chain::Mainnet,
+                json_rpc_with_served_logs(current_block_number, vec![first_log]),
+            )]);
+            let get_start_block_number =
+                |contracts: Vec<Contract>| contracts[0].addresses[0].get_start_block_number();
+            let config = config_with_contracts(vec![]);
+
+            let contracts = vec![bayc_contract()];
+            let resolved_contracts = Chaindexing::resolve_start_block_numbers(
+                &mut conn, &contracts, &json_rpcs, &config,
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                get_start_block_number(resolved_contracts),
+                BAYC_CONTRACT_START_BLOCK_NUMBER as i64
+            );
+
+            let contracts = vec![bayc_contract().with_start_block_strategy(FromLatestStartBlock)];
+            let resolved_contracts = Chaindexing::resolve_start_block_numbers(
+                &mut conn, &contracts, &json_rpcs, &config,
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                get_start_block_number(resolved_contracts),
+                current_block_number as i64
+            );
+
+            let contracts =
+                vec![bayc_contract().with_start_block_strategy(AutoDetectStartBlock::new(100_000))];
+            let resolved_contracts = Chaindexing::resolve_start_block_numbers(
+                &mut conn, &contracts, &json_rpcs, &config,
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                get_start_block_number(resolved_contracts),
+                first_log_block_number
+            );
+
+            // Registered addresses keep their start blocks
+            Chaindexing::create_initial_contract_addresses(&mut conn, &vec![bayc_contract()]).await;
+            let resolved_contracts = Chaindexing::resolve_start_block_numbers(
+                &mut conn, &contracts, &json_rpcs, &config,
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                get_start_block_number(resolved_contracts),
+                BAYC_CONTRACT_START_BLOCK_NUMBER as i64
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn fails_registering_contract_addresses_whose_start_blocks_cannot_be_resolved() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let requests_count = Arc::new(AtomicUsize::new(0));
+            let json_rpc = failing_json_rpc(requests_count.clone());
+
+            let contract = bayc_contract().with_start_block_strategy(FromLatestStartBlock);
+            let config = config_with_contracts(vec![contract.clone()]).with_max_json_rpc_retries(1);
+            let result = Chaindexing::register_contract_addresses_with_json_rpc(
+                &mut conn,
+                &config,
+                &contract,
+                &["0x0000000000000000000000000000000000000001"],
+                &Chain::Mainnet,
+                0,
+                json_rpc,
+            )
+            .await;
+
+            assert!(matches!(result, Err(EventsIngesterError::JsonRpcError(_))));
+            assert_eq!(requests_count.load(Ordering::SeqCst), 2);
+            assert!(ChaindexingRepo::get_all_contract_addresses(&mut conn).await.is_empty());
+        })
+        .await;
+    }
+}
@@ -1 +1,2015 @@
-// TODO: Event Handlers Test here
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::panic::AssertUnwindSafe;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex as StdMutex};
+    use std::time::{Duration, Instant};
+    use tokio::sync::Mutex;
+
+    use chaindexing::{
+        BlockNumber, Chain, Chaindexing, ChaindexingRepo, Config, Contract, ContractState,
+        ContractStateError, ContractStateMigrations, Event, EventContext, EventHandler, EventSink,
+        EventSinkError, EventSinkHandler, EventsIngester, ExecutesWithRawQuery, HandleEvents,
+        HasRawQueryClient, LoadsDataWithRawQuery, Metric, MetricKind, MetricLabels, PostgresRepo,
+        Repo, StateDriftKind, StateSnapshot, Streamable, SHADOW_STATE_SCHEMA, U256,
+    };
+    use ethers::abi::Token;
+    use ethers::types::H256;
+    use futures_util::{FutureExt, StreamExt};
+    use serde::{Deserialize, Serialize};
+
+    use crate::factory::{
+        bayc_contract, config_with_contracts, json_rpc_with_served_logs,
+        transfer_event_with_contract, transfer_event_with_contract_address, transfer_log,
+        TransferTestEventHandler, APPROCAL_EVENT_ABI, BAYC_CONTRACT_ADDRESS,
+        BAYC_CONTRACT_START_BLOCK_NUMBER, TRANSFER_EVENT_ABI,
+    };
+    use crate::test_runner;
+
+    static HANDLED_TRANSFER_EVENTS_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    struct CountingTransferEventHandler;
+
+    #[async_trait::async_trait]
+    impl EventHandler for CountingTransferEventHandler {
+        async fn handle_event<'a>(&self, _event_context: EventContext<'a>) {
+            HANDLED_TRANSFER_EVENTS_COUNT.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn halts_handling_events_beyond_ingested_range() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contract = Contract::new("BoredApeYachtClub")
+                .add_event(TRANSFER_EVENT_ABI, CountingTransferEventHandler)
+                .add_address(
+                    BAYC_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![contract.clone()];
+            let config = config_with_contracts(contracts.clone());
+
+            // Simulates a gap: the event was persisted, but the ingestion cursor
+            // never advanced past its block.
+            let transfer_event = transfer_event_with_contract(contract);
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            ChaindexingRepo::create_events(&mut conn, &vec![transfer_event.clone()]).await;
+
+            let conn = Arc::new(Mutex::new(conn));
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+
+            HandleEvents::run(conn.clone(), &config, &mut raw_query_client).await;
+            assert_eq!(HANDLED_TRANSFER_EVENTS_COUNT.load(Ordering::SeqCst), 0);
+
+            {
+                let mut conn = conn.lock().await;
+                let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+                let bayc_contract_address = contract_addresses.first().unwrap();
+                assert_eq!(
+                    bayc_contract_address.next_block_number_to_handle_from,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64
+                );
+
+                ChaindexingRepo::update_next_block_number_to_ingest_from(
+                    &mut conn,
+                    bayc_contract_address,
+                    transfer_event.block_number + 1,
+                )
+                .await;
+            }
+
+            HandleEvents::run(conn.clone(), &config, &mut raw_query_client).await;
+            assert_eq!(HANDLED_TRANSFER_EVENTS_COUNT.load(Ordering::SeqCst), 1);
+        })
+        .await;
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+    struct ReplayedNftState {
+        token_id: i32,
+    }
+    impl ContractState for ReplayedNftState {
+        fn table_name() -> &'static str {
+            "replayed_nft_states"
+        }
+    }
+
+    struct ReplayedNftStateMigrations;
+    impl ContractStateMigrations for ReplayedNftStateMigrations {
+        fn migrations(&self) -> Vec<&'static str> {
+            vec!["CREATE TABLE IF NOT EXISTS replayed_nft_states (token_id INTEGER NOT NULL)"]
+        }
+    }
+
+    struct ReplayedNftStateEventHandler;
+
+    #[async_trait::async_trait]
+    impl EventHandler for ReplayedNftStateEventHandler {
+        async fn handle_event<'a>(&self, event_context: EventContext<'a>) {
+            let token_id = event_context.event.get_params().get("tokenId").cloned();
+            let token_id = token_id.unwrap().into_uint().unwrap().as_u32() as i32;
+
+            ReplayedNftState { token_id }.create(&event_context).await;
+        }
+    }
+
+    #[tokio::test]
+    pub async fn replays_handlers_into_identical_states() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contract = Contract::new("ReplayedBoredApeYachtClub")
+                .add_event(TRANSFER_EVENT_ABI, ReplayedNftStateEventHandler)
+                .add_state_migrations(ReplayedNftStateMigrations)
+                .add_address(
+                    BAYC_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![contract.clone()];
+            let config = Config::new(
+                test_runner::new_repo(),
+                [(Chain::Mainnet, "http://localhost:8545".to_string())].into(),
+            )
+            .add_contract(contract.clone());
+
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+            Chaindexing::reset_migrations_for_contract_states(&raw_query_client, &contracts).await;
+            Chaindexing::run_migrations_for_contract_states(&raw_query_client, &contracts).await;
+
+            let transfer_event = transfer_event_with_contract(contract);
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            ChaindexingRepo::create_events(&mut conn, &vec![transfer_event.clone()]).await;
+            let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+            let contract_address = contract_addresses.first().unwrap();
+            ChaindexingRepo::update_next_block_number_to_ingest_from(
+                &mut conn,
+                contract_address,
+                transfer_event.block_number + 1,
+            )
+            .await;
+
+            let conn = Arc::new(Mutex::new(conn));
+            HandleEvents::run(conn.clone(), &config, &mut raw_query_client).await;
+            let handled_states = read_replayed_nft_states(&transfer_event).await;
+
+            // No JSON RPC is involved: replaying only reads already ingested events
+            HandleEvents::replay_with_conn(
+                conn.clone(),
+                &mut raw_query_client,
+                &config,
+                BlockNumber::new(BAYC_CONTRACT_START_BLOCK_NUMBER as u64),
+                true,
+            )
+            .await;
+            let replayed_states = read_replayed_nft_states(&transfer_event).await;
+
+            assert_eq!(handled_states, vec![ReplayedNftState { token_id: 1661 }]);
+            assert_eq!(handled_states, replayed_states);
+        })
+        .await;
+    }
+
+    async fn read_replayed_nft_states(event: &Event) -> Vec<ReplayedNftState> {
+        let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+        let raw_query_txn_client =
+            ChaindexingRepo::get_raw_query_txn_client(&mut raw_query_client).await;
+        let event_context = EventContext::new(event.clone(), &raw_query_txn_client);
+
+        let mut states = ReplayedNftState::read_many(
+            [("token_id".to_owned(), "1661".to_owned())].into(),
+            &event_context,
+        )
+        .await;
+        states.sort();
+
+        states
+    }
+
+    static SHOULD_FAIL_AFTER_AUDITING: AtomicBool = AtomicBool::new(true);
+
+    struct AuditingTransferEventHandler;
+
+    #[async_trait::async_trait]
+    impl EventHandler for AuditingTransferEventHandler {
+        async fn handle_event<'a>(&self, event_context: EventContext<'a>) {
+            let raw_query_txn_client = event_context.get_raw_query_txn_client();
+            let query = format!(
+                "INSERT INTO transfer_audits (transaction_hash) VALUES ('{}')",
+                event_context.event.transaction_hash
+            );
+            ChaindexingRepo::execute_raw_query_in_txn(raw_query_txn_client, &query).await;
+
+            if SHOULD_FAIL_AFTER_AUDITING.load(Ordering::SeqCst) {
+                panic!("Failed after auditing transfer");
+            }
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct TransferAudit {
+        #[allow(dead_code)]
+        transaction_hash: String,
+    }
+
+    #[tokio::test]
+    pub async fn rolls_back_consumer_queries_with_failed_handlers() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contract = Contract::new("AuditedBoredApeYachtClub")
+                .add_event(TRANSFER_EVENT_ABI, AuditingTransferEventHandler)
+                .add_address(
+                    BAYC_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![contract.clone()];
+            let config = config_with_contracts(contracts.clone());
+
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+            ChaindexingRepo::execute_raw_query(
+                &raw_query_client,
+                "CREATE TABLE IF NOT EXISTS transfer_audits (transaction_hash TEXT NOT NULL)",
+            )
+            .await;
+            ChaindexingRepo::execute_raw_query(&raw_query_client, "DELETE FROM transfer_audits")
+                .await;
+
+            let transfer_event = transfer_event_with_contract(contract);
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            ChaindexingRepo::create_events(&mut conn, &vec![transfer_event.clone()]).await;
+            let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+            ChaindexingRepo::update_next_block_number_to_ingest_from(
+                &mut conn,
+                contract_addresses.first().unwrap(),
+                transfer_event.block_number + 1,
+            )
+            .await;
+
+            let conn = Arc::new(Mutex::new(conn));
+
+            let failed_handling = AssertUnwindSafe(HandleEvents::run(
+                conn.clone(),
+                &config,
+                &mut raw_query_client,
+            ))
+            .catch_unwind()
+            .await;
+            assert!(failed_handling.is_err());
+            assert!(read_transfer_audits(&raw_query_client).await.is_empty());
+
+            SHOULD_FAIL_AFTER_AUDITING.store(false, Ordering::SeqCst);
+            HandleEvents::run(conn.clone(), &config, &mut raw_query_client).await;
+            assert_eq!(read_transfer_audits(&raw_query_client).await.len(), 1);
+        })
+        .await;
+    }
+
+    static SHOULD_HANG: AtomicBool = AtomicBool::new(true);
+    static HANDLED_HANGING_EVENTS_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    struct HangingTransferEventHandler;
+
+    #[async_trait::async_trait]
+    impl EventHandler for HangingTransferEventHandler {
+        async fn handle_event<'a>(&self, _event_context: EventContext<'a>) {
+            if SHOULD_HANG.load(Ordering::SeqCst) {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+            }
+
+            HANDLED_HANGING_EVENTS_COUNT.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn retries_handlers_running_past_the_handler_timeout() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contract = Contract::new("HangingBoredApeYachtClub")
+                .add_event(TRANSFER_EVENT_ABI, HangingTransferEventHandler)
+                .add_address(
+                    BAYC_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![contract.clone()];
+            let config = config_with_contracts(contracts.clone())
+                .with_handler_timeout(Duration::from_millis(50));
+
+            let transfer_event = transfer_event_with_contract(contract);
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            ChaindexingRepo::create_events(&mut conn, &vec![transfer_event.clone()]).await;
+            let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+            ChaindexingRepo::update_next_block_number_to_ingest_from(
+                &mut conn,
+                contract_addresses.first().unwrap(),
+                transfer_event.block_number + 1,
+            )
+            .await;
+
+            let conn = Arc::new(Mutex::new(conn));
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+
+            tokio::time::timeout(
+                Duration::from_secs(10),
+                HandleEvents::run(conn.clone(), &config, &mut raw_query_client),
+            )
+            .await
+            .expect("Handling should time out instead of hanging");
+            assert_eq!(HANDLED_HANGING_EVENTS_COUNT.load(Ordering::SeqCst), 0);
+
+            {
+                let mut conn = conn.lock().await;
+                let contract_address =
+                    PostgresRepo::get_all_contract_addresses(&mut conn).await.pop().unwrap();
+                assert_eq!(
+                    contract_address.next_block_number_to_handle_from,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64
+                );
+            }
+
+            // The next run handles the timed out event again
+            SHOULD_HANG.store(false, Ordering::SeqCst);
+            HandleEvents::run(conn.clone(), &config, &mut raw_query_client).await;
+            assert_eq!(HANDLED_HANGING_EVENTS_COUNT.load(Ordering::SeqCst), 1);
+        })
+        .await;
+    }
+
+    struct AlwaysHangingTransferEventHandler;
+
+    #[async_trait::async_trait]
+    impl EventHandler for AlwaysHangingTransferEventHandler {
+        async fn handle_event<'a>(&self, _event_context: EventContext<'a>) {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        }
+    }
+
+    #[tokio::test]
+    pub async fn fails_verifying_states_whose_replay_runs_past_the_handler_timeout() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contract = Contract::new("AlwaysHangingBoredApeYachtClub")
+                .add_event(TRANSFER_EVENT_ABI, AlwaysHangingTransferEventHandler)
+                .add_address(
+                    BAYC_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![contract.clone()];
+            let config = config_with_contracts(contracts.clone())
+                .with_handler_timeout(Duration::from_millis(50));
+
+            let transfer_event = transfer_event_with_contract(contract.clone());
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            ChaindexingRepo::create_events(&mut conn, &vec![transfer_event.clone()]).await;
+            let contract_address =
+                PostgresRepo::get_all_contract_addresses(&mut conn).await.pop().unwrap();
+            ChaindexingRepo::update_next_block_number_to_handle_from(
+                &mut conn,
+                contract_address.id(),
+                transfer_event.block_number + 1,
+            )
+            .await;
+
+            let conn = Arc::new(Mutex::new(conn));
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+
+            let verification = tokio::time::timeout(
+                Duration::from_secs(10),
+                HandleEvents::verify_state_with_conn(
+                    conn.clone(),
+                    &mut raw_query_client,
+                    &config,
+                    &contract.name,
+                ),
+            )
+            .await
+            .expect("Verifying should time out instead of hanging");
+            assert!(matches!(
+                verification,
+                Err(ContractStateError::HandlerTimeout(_))
+            ));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn reports_handling_lags_and_handler_errors_as_metrics() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static METRICS: StdMutex<Vec<Metric>> = StdMutex::new(Vec::new());
+
+            let contract = Contract::new("AlwaysHangingBoredApeYachtClub")
+                .add_event(TRANSFER_EVENT_ABI, AlwaysHangingTransferEventHandler)
+                .add_address(
+                    BAYC_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![contract.clone()];
+            let config = config_with_contracts(contracts.clone())
+                .with_handler_timeout(Duration::from_millis(50))
+                .with_on_metric(|metric| METRICS.lock().unwrap().push(metric.clone()));
+
+            let transfer_event = transfer_event_with_contract(contract.clone());
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            ChaindexingRepo::create_events(&mut conn, &vec![transfer_event.clone()]).await;
+            let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+            let next_block_number_to_ingest_from = transfer_event.block_number + 1;
+            ChaindexingRepo::update_next_block_number_to_ingest_from(
+                &mut conn,
+                contract_addresses.first().unwrap(),
+                next_block_number_to_ingest_from,
+            )
+            .await;
+
+            let conn = Arc::new(Mutex::new(conn));
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+
+            tokio::time::timeout(
+                Duration::from_secs(10),
+                HandleEvents::run(conn.clone(), &config, &mut raw_query_client),
+            )
+            .await
+            .expect("Handling should time out instead of hanging");
+
+            let expected_labels = MetricLabels {
+                chain_id: Chain::Mainnet as i32,
+                contract_name: contract.name.clone(),
+                address: BAYC_CONTRACT_ADDRESS.to_lowercase(),
+            };
+            let metrics = METRICS.lock().unwrap();
+            let get_metric_values = |kind: MetricKind| -> Vec<u64> {
+                metrics
+                    .iter()
+                    .filter(|metric| metric.kind == kind && metric.labels == expected_labels)
+                    .map(|metric| metric.value)
+                    .collect()
+            };
+            assert_eq!(
+                get_metric_values(MetricKind::HandlingLag),
+                vec![
+                    (next_block_number_to_ingest_from - BAYC_CONTRACT_START_BLOCK_NUMBER as i64)
+                        as u64
+                ]
+            );
+            assert_eq!(get_metric_values(MetricKind::HandlerErrors), vec![1]);
+            assert!(get_metric_values(MetricKind::EventsHandled).is_empty());
+        })
+        .await;
+    }
+
+    struct IndexerSchemaNftStateMigrations;
+
+    impl ContractStateMigrations for IndexerSchemaNftStateMigrations {
+        fn migrations(&self) -> Vec<&'static str> {
+            vec!["CREATE TABLE IF NOT EXISTS indexer.nft_states (token_id INTEGER NOT NULL)"]
+        }
+
+        fn schema(&self) -> &'static str {
+            "indexer"
+        }
+    }
+
+    #[tokio::test]
+    pub async fn fails_verifying_states_of_unknown_contracts_or_unsupported_schemas() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |conn| async move {
+            let contract = Contract::new("IndexerSchemaBoredApeYachtClub")
+                .add_event(TRANSFER_EVENT_ABI, TransferTestEventHandler)
+                .add_state_migrations(IndexerSchemaNftStateMigrations);
+            let config = config_with_contracts(vec![contract.clone()]);
+
+            let conn = Arc::new(Mutex::new(conn));
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+
+            let verification = HandleEvents::verify_state_with_conn(
+                conn.clone(),
+                &mut raw_query_client,
+                &config,
+                "UnknownBoredApeYachtClub",
+            )
+            .await;
+            assert_eq!(
+                verification,
+                Err(ContractStateError::UnknownContract(
+                    "UnknownBoredApeYachtClub".to_string()
+                ))
+            );
+
+            let verification = HandleEvents::verify_state_with_conn(
+                conn.clone(),
+                &mut raw_query_client,
+                &config,
+                &contract.name,
+            )
+            .await;
+            assert_eq!(
+                verification,
+                Err(ContractStateError::UnsupportedSchema("indexer".to_string()))
+            );
+        })
+        .await;
+    }
+
+    async fn read_transfer_audits(
+        raw_query_client: &chaindexing::ChaindexingRepoRawQueryClient,
+    ) -> Vec<TransferAudit> {
+        ChaindexingRepo::load_data_list_from_raw_query(
+            raw_query_client,
+            "SELECT * FROM transfer_audits",
+        )
+        .await
+    }
+
+    static HANDLED_CONTRACT_NAMES: std::sync::Mutex<Vec<String>> =
+        std::sync::Mutex::new(Vec::new());
+
+    struct OrderRecordingTransferEventHandler;
+
+    #[async_trait::async_trait]
+    impl EventHandler for OrderRecordingTransferEventHandler {
+        async fn handle_event<'a>(&self, event_context: EventContext<'a>) {
+            HANDLED_CONTRACT_NAMES.lock().unwrap().push(event_context.event.contract_name);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn handles_higher_priority_contracts_first() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            const DOODLES_CONTRACT_ADDRESS: &str = "0x8a90CAb2b38dba80c64b7734e58Ee1dB38B8992e";
+
+            let low_priority_contract = Contract::new("LowPriorityBoredApeYachtClub")
+                .add_event(TRANSFER_EVENT_ABI, OrderRecordingTransferEventHandler)
+                .add_address(
+                    BAYC_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let high_priority_contract = Contract::new("HighPriorityDoodles")
+                .with_priority(1)
+                .add_event(TRANSFER_EVENT_ABI, OrderRecordingTransferEventHandler)
+                .add_address(
+                    DOODLES_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![
+                low_priority_contract.clone(),
+                high_priority_contract.clone(),
+            ];
+            let config = config_with_contracts(contracts.clone());
+
+            // Both transfers happen in the same block
+            let transfer_events = vec![
+                transfer_event_with_contract_address(low_priority_contract, BAYC_CONTRACT_ADDRESS),
+                transfer_event_with_contract_address(
+                    high_priority_contract,
+                    DOODLES_CONTRACT_ADDRESS,
+                ),
+            ];
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            ChaindexingRepo::create_events(&mut conn, &transfer_events).await;
+            for contract_address in PostgresRepo::get_all_contract_addresses(&mut conn).await {
+                ChaindexingRepo::update_next_block_number_to_ingest_from(
+                    &mut conn,
+                    &contract_address,
+                    transfer_events[0].block_number + 1,
+                )
+                .await;
+            }
+
+            let conn = Arc::new(Mutex::new(conn));
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+            HandleEvents::run(conn.clone(), &config, &mut raw_query_client).await;
+
+            assert_eq!(
+                *HANDLED_CONTRACT_NAMES.lock().unwrap(),
+                vec!["HighPriorityDoodles", "LowPriorityBoredApeYachtClub"]
+            );
+        })
+        .await;
+    }
+
+    static HANDLED_BLOCK_NUMBERS_BY_ADDRESS: std::sync::Mutex<Vec<(String, i64)>> =
+        std::sync::Mutex::new(Vec::new());
+    static IN_FLIGHT_HANDLERS_COUNT: AtomicUsize = AtomicUsize::new(0);
+    static MAX_IN_FLIGHT_HANDLERS_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    struct ConcurrencyRecordingTransferEventHandler;
+
+    #[async_trait::async_trait]
+    impl EventHandler for ConcurrencyRecordingTransferEventHandler {
+        async fn handle_event<'a>(&self, event_context: EventContext<'a>) {
+            let in_flight_handlers_count = IN_FLIGHT_HANDLERS_COUNT.fetch_add(1, Ordering::SeqCst);
+            MAX_IN_FLIGHT_HANDLERS_COUNT.fetch_max(in_flight_handlers_count + 1, Ordering::SeqCst);
+
+            // Leaves room for other shards to interleave
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+
+            let Event {
+                contract_address,
+                block_number,
+                ..
+            } = event_context.event;
+            HANDLED_BLOCK_NUMBERS_BY_ADDRESS
+                .lock()
+                .unwrap()
+                .push((contract_address, block_number));
+
+            IN_FLIGHT_HANDLERS_COUNT.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn handles_contract_addresses_concurrently_but_their_events_sequentially() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            const ADDRESSES_COUNT: i64 = 6;
+            const EVENTS_PER_ADDRESS_COUNT: i64 = 3;
+
+            let addresses: Vec<_> = (1..=ADDRESSES_COUNT).map(|i| format!("0x{i:040x}")).collect();
+            let addresses: Vec<_> = addresses.iter().map(|a| a.as_str()).collect();
+            let contract = Contract::new("ConcurrentlyHandledBoredApeYachtClub")
+                .add_event(TRANSFER_EVENT_ABI, ConcurrencyRecordingTransferEventHandler)
+                .add_addresses(
+                    &addresses,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![contract.clone()];
+            let config = config_with_contracts(contracts.clone()).with_handler_parallelism(3);
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let mut transfer_events = vec![];
+            for (i, address) in addresses.iter().enumerate() {
+                for j in 0..EVENTS_PER_ADDRESS_COUNT {
+                    let mut transfer_event =
+                        transfer_event_with_contract_address(contract.clone(), address);
+                    transfer_event.block_number = BAYC_CONTRACT_START_BLOCK_NUMBER as i64 + j;
+                    transfer_event.log_index = i as i64 * EVENTS_PER_ADDRESS_COUNT + j;
+                    transfer_events.push(transfer_event);
+                }
+            }
+            ChaindexingRepo::create_events(&mut conn, &transfer_events).await;
+            for contract_address in PostgresRepo::get_all_contract_addresses(&mut conn).await {
+                ChaindexingRepo::update_next_block_number_to_ingest_from(
+                    &mut conn,
+                    &contract_address,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64 + EVENTS_PER_ADDRESS_COUNT,
+                )
+                .await;
+            }
+
+            let conn = Arc::new(Mutex::new(conn));
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+            let mut shard_raw_query_clients =
+                HandleEvents::get_shard_raw_query_clients(&config).await;
+            assert_eq!(shard_raw_query_clients.len(), 2);
+            HandleEvents::run_with_shard_clients(
+                conn.clone(),
+                &config,
+                &mut raw_query_client,
+                &mut shard_raw_query_clients,
+            )
+            .await;
+
+            let handled_block_numbers_by_address = HANDLED_BLOCK_NUMBERS_BY_ADDRESS.lock().unwrap();
+            assert_eq!(
+                handled_block_numbers_by_address.len() as i64,
+                ADDRESSES_COUNT * EVENTS_PER_ADDRESS_COUNT
+            );
+            for address in addresses {
+                let handled_block_numbers: Vec<_> = handled_block_numbers_by_address
+                    .iter()
+                    .filter(|(contract_address, _)| contract_address == address)
+                    .map(|(_, block_number)| *block_number)
+                    .collect();
+                let expected_block_numbers: Vec<_> = (0..EVENTS_PER_ADDRESS_COUNT)
+                    .map(|j| BAYC_CONTRACT_START_BLOCK_NUMBER as i64 + j)
+                    .collect();
+
+                assert_eq!(handled_block_numbers, expected_block_numbers);
+            }
+
+            assert!(MAX_IN_FLIGHT_HANDLERS_COUNT.load(Ordering::SeqCst) > 1);
+            assert!(MAX_IN_FLIGHT_HANDLERS_COUNT.load(Ordering::SeqCst) <= 3);
+        })
+        .await;
+    }
+
+    const REBUILT_CONTRACT_NAME: &str = "RebuiltBoredApeYachtClub";
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct RebuiltNftState {
+        token_id: i32,
+    }
+    impl ContractState for RebuiltNftState {
+        fn table_name() -> &'static str {
+            "rebuilt_nft_states"
+        }
+    }
+
+    struct RebuiltNftStateMigrations;
+    impl ContractStateMigrations for RebuiltNftStateMigrations {
+        fn migrations(&self) -> Vec<&'static str> {
+            vec!["CREATE TABLE IF NOT EXISTS rebuilt_nft_states (token_id INTEGER NOT NULL)"]
+        }
+    }
+
+    struct RebuiltNftStateEventHandler;
+
+    #[async_trait::async_trait]
+    impl EventHandler for RebuiltNftStateEventHandler {
+        async fn handle_event<'a>(&self, event_context: EventContext<'a>) {
+            let token_id = event_context.event.get_params().get("tokenId").cloned();
+            let token_id = token_id.unwrap().into_uint().unwrap().as_u32() as i32;
+
+            RebuiltNftState { token_id }.create(&event_context).await;
+        }
+    }
+
+    // Same state table, with a column added to its migration
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct RebuiltNftStateWithOwner {
+        token_id: i32,
+        owner_address: String,
+    }
+    impl ContractState for RebuiltNftStateWithOwner {
+        fn table_name() -> &'static str {
+            "rebuilt_nft_states"
+        }
+    }
+
+    struct RebuiltNftStateWithOwnerMigrations;
+    impl ContractStateMigrations for RebuiltNftStateWithOwnerMigrations {
+        fn migrations(&self) -> Vec<&'static str> {
+            vec![
+                "CREATE TABLE IF NOT EXISTS rebuilt_nft_states (
+                    token_id INTEGER NOT NULL,
+                    owner_address TEXT NOT NULL
+                )",
+            ]
+        }
+    }
+
+    struct RebuiltNftStateWithOwnerEventHandler;
+
+    #[async_trait::async_trait]
+    impl EventHandler for RebuiltNftStateWithOwnerEventHandler {
+        async fn handle_event<'a>(&self, event_context: EventContext<'a>) {
+            let params = event_context.event.get_params();
+            let token_id = params.get("tokenId").cloned().unwrap().into_uint().unwrap();
+            let owner_address = params.get("to").cloned().unwrap().into_address().unwrap();
+
+            RebuiltNftStateWithOwner {
+                token_id: token_id.as_u32() as i32,
+                owner_address: format!("{owner_address:?}"),
+            }
+            .create(&event_context)
+            .await;
+        }
+    }
+
+    #[tokio::test]
+    pub async fn rebuilds_states_with_changed_migrations() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contract = Contract::new(REBUILT_CONTRACT_NAME)
+                .add_event(TRANSFER_EVENT_ABI, RebuiltNftStateEventHandler)
+                .add_state_migrations(RebuiltNftStateMigrations)
+                .add_address(
+                    BAYC_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![contract.clone()];
+            let config = config_with_contracts(contracts.clone());
+
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+            Chaindexing::reset_migrations_for_contract_states(&raw_query_client, &contracts).await;
+            Chaindexing::run_migrations_for_contract_states(&raw_query_client, &contracts).await;
+
+            let transfer_event = transfer_event_with_contract(contract);
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            ChaindexingRepo::create_events(&mut conn, &vec![transfer_event.clone()]).await;
+            let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+            ChaindexingRepo::update_next_block_number_to_ingest_from(
+                &mut conn,
+                contract_addresses.first().unwrap(),
+                transfer_event.block_number + 1,
+            )
+            .await;
+
+            let conn = Arc::new(Mutex::new(conn));
+            HandleEvents::run(conn.clone(), &config, &mut raw_query_client).await;
+
+            let changed_contract = Contract::new(REBUILT_CONTRACT_NAME)
+                .add_event(TRANSFER_EVENT_ABI, RebuiltNftStateWithOwnerEventHandler)
+                .add_state_migrations(RebuiltNftStateWithOwnerMigrations)
+                .add_address(
+                    BAYC_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let config = Config::new(
+                test_runner::new_repo(),
+                [(Chain::Mainnet, "http://localhost:8545".to_string())].into(),
+            )
+            .add_contract(changed_contract);
+
+            HandleEvents::rebuild_state_with_conn(
+                conn.clone(),
+                &mut raw_query_client,
+                &config,
+                REBUILT_CONTRACT_NAME,
+            )
+            .await
+            .unwrap();
+
+            let raw_query_txn_client =
+                ChaindexingRepo::get_raw_query_txn_client(&mut raw_query_client).await;
+            let event_context = EventContext::new(transfer_event, &raw_query_txn_client);
+            let rebuilt_states = RebuiltNftStateWithOwner::read_many(
+                [("token_id".to_owned(), "1661".to_owned())].into(),
+                &event_context,
+            )
+            .await;
+
+            assert_eq!(
+                rebuilt_states,
+                vec![RebuiltNftStateWithOwner {
+                    token_id: 1661,
+                    owner_address: "0x7dfd6013cf8d92b751e63d481b51fe0e4c5abf5e".to_string()
+                }]
+            );
+        })
+        .await;
+    }
+
+    const VERIFIED_CONTRACT_NAME: &str = "VerifiedBoredApeYachtClub";
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct VerifiedNftState {
+        token_id: i32,
+    }
+    impl ContractState for VerifiedNftState {
+        fn table_name() -> &'static str {
+            "verified_nft_states"
+        }
+    }
+
+    struct VerifiedNftStateMigrations;
+    impl ContractStateMigrations for VerifiedNftStateMigrations {
+        fn migrations(&self) -> Vec<&'static str> {
+            vec!["CREATE TABLE IF NOT EXISTS verified_nft_states (token_id INTEGER NOT NULL)"]
+        }
+    }
+
+    struct VerifiedNftStateEventHandler;
+
+    #[async_trait::async_trait]
+    impl EventHandler for VerifiedNftStateEventHandler {
+        async fn handle_event<'a>(&self, event_context: EventContext<'a>) {
+            let token_id = event_context.event.get_params().get("tokenId").cloned();
+            let token_id = token_id.unwrap().into_uint().unwrap().as_u32() as i32;
+
+            VerifiedNftState { token_id }.create(&event_context).await;
+        }
+    }
+
+    #[tokio::test]
+    pub async fn detects_states_drifting_from_their_events() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contract = Contract::new(VERIFIED_CONTRACT_NAME)
+                .add_event(TRANSFER_EVENT_ABI, VerifiedNftStateEventHandler)
+                .add_state_migrations(VerifiedNftStateMigrations)
+                .add_address(
+                    BAYC_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![contract.clone()];
+            let config = config_with_contracts(contracts.clone());
+
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+            Chaindexing::reset_migrations_for_contract_states(&raw_query_client, &contracts).await;
+            Chaindexing::run_migrations_for_contract_states(&raw_query_client, &contracts).await;
+
+            let transfer_event = transfer_event_with_contract(contract);
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            ChaindexingRepo::create_events(&mut conn, &vec![transfer_event.clone()]).await;
+            let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+            ChaindexingRepo::update_next_block_number_to_ingest_from(
+                &mut conn,
+                contract_addresses.first().unwrap(),
+                transfer_event.block_number + 1,
+            )
+            .await;
+
+            let conn = Arc::new(Mutex::new(conn));
+            HandleEvents::run(conn.clone(), &config, &mut raw_query_client).await;
+
+            let drifts = HandleEvents::verify_state_with_conn(
+                conn.clone(),
+                &mut raw_query_client,
+                &config,
+                VERIFIED_CONTRACT_NAME,
+            )
+            .await
+            .unwrap();
+            assert!(drifts.is_empty());
+
+            ChaindexingRepo::execute_raw_query(
+                &raw_query_client,
+                "UPDATE verified_nft_states SET token_id = 1",
+            )
+            .await;
+
+            let drifts = HandleEvents::verify_state_with_conn(
+                conn.clone(),
+                &mut raw_query_client,
+                &config,
+                VERIFIED_CONTRACT_NAME,
+            )
+            .await
+            .unwrap();
+            let drifted_token_ids: Vec<_> = drifts
+                .iter()
+                .map(|drift| {
+                    (
+                        drift.kind.clone(),
+                        drift.state.get("token_id").unwrap().as_str(),
+                    )
+                })
+                .collect();
+            assert_eq!(
+                drifted_token_ids,
+                vec![
+                    (StateDriftKind::Unexpected, "1"),
+                    (StateDriftKind::Missing, "1661")
+                ]
+            );
+
+            // Replayed states are thrown away
+            let live_states: Vec<VerifiedNftState> =
+                ChaindexingRepo::load_data_list_from_raw_query(
+                    &raw_query_client,
+                    "SELECT token_id FROM verified_nft_states",
+                )
+                .await;
+            assert_eq!(live_states, vec![VerifiedNftState { token_id: 1 }]);
+        })
+        .await;
+    }
+
+    const SHADOWED_CONTRACT_NAME: &str = "ShadowedBoredApeYachtClub";
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct ShadowedNftState {
+        token_id: i32,
+    }
+    impl ContractState for ShadowedNftState {
+        fn table_name() -> &'static str {
+            "shadowed_nft_states"
+        }
+    }
+
+    struct ShadowedNftStateMigrations;
+    impl ContractStateMigrations for ShadowedNftStateMigrations {
+        fn migrations(&self) -> Vec<&'static str> {
+            vec!["CREATE TABLE IF NOT EXISTS shadowed_nft_states (token_id INTEGER NOT NULL)"]
+        }
+    }
+
+    /// Offsets token ids, as a changed handler logic
+    struct ShadowedNftStateEventHandler(i32);
+
+    #[async_trait::async_trait]
+    impl EventHandler for ShadowedNftStateEventHandler {
+        async fn handle_event<'a>(&self, event_context: EventContext<'a>) {
+            let token_id = event_context.event.get_params().get("tokenId").cloned();
+            let token_id = token_id.unwrap().into_uint().unwrap().as_u32() as i32;
+
+            ShadowedNftState {
+                token_id: token_id + self.0,
+            }
+            .create(&event_context)
+            .await;
+        }
+    }
+
+    #[tokio::test]
+    pub async fn runs_shadow_handlers_into_states_isolated_from_live_ones() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contract = Contract::new(SHADOWED_CONTRACT_NAME)
+                .add_event(TRANSFER_EVENT_ABI, ShadowedNftStateEventHandler(0))
+                .add_state_migrations(ShadowedNftStateMigrations)
+                .add_address(
+                    BAYC_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let shadow_contract = Contract::new(SHADOWED_CONTRACT_NAME)
+                .add_event(TRANSFER_EVENT_ABI, ShadowedNftStateEventHandler(1))
+                .add_state_migrations(ShadowedNftStateMigrations);
+            let contracts = vec![contract.clone()];
+            let config =
+                config_with_contracts(contracts.clone()).add_shadow_contract(shadow_contract);
+
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+            Chaindexing::reset_migrations_for_contract_states(&raw_query_client, &contracts).await;
+            Chaindexing::run_migrations_for_contract_states(&raw_query_client, &contracts).await;
+
+            let transfer_event = transfer_event_with_contract(contract);
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            ChaindexingRepo::create_events(&mut conn, &vec![transfer_event.clone()]).await;
+            let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+            ChaindexingRepo::update_next_block_number_to_ingest_from(
+                &mut conn,
+                contract_addresses.first().unwrap(),
+                transfer_event.block_number + 1,
+            )
+            .await;
+
+            let conn = Arc::new(Mutex::new(conn));
+            HandleEvents::run(conn.clone(), &config, &mut raw_query_client).await;
+
+            // Rebuilt from scratch, e.g. when re-run before diffing again
+            for _run in 0..2 {
+                HandleEvents::rebuild_shadow_state_with_conn(
+                    conn.clone(),
+                    &mut raw_query_client,
+                    &config,
+                    SHADOWED_CONTRACT_NAME,
+                )
+                .await
+                .unwrap();
+            }
+
+            let read_states = |table_name: &str| format!("SELECT token_id FROM {table_name}");
+            let live_states: Vec<ShadowedNftState> =
+                ChaindexingRepo::load_data_list_from_raw_query(
+                    &raw_query_client,
+                    &read_states("shadowed_nft_states"),
+                )
+                .await;
+            assert_eq!(live_states, vec![ShadowedNftState { token_id: 1661 }]);
+            let shadow_states: Vec<ShadowedNftState> =
+                ChaindexingRepo::load_data_list_from_raw_query(
+                    &raw_query_client,
+                    &read_states(&format!("{SHADOW_STATE_SCHEMA}.shadowed_nft_states")),
+                )
+                .await;
+            assert_eq!(shadow_states, vec![ShadowedNftState { token_id: 1662 }]);
+
+            let drifts = HandleEvents::diff_shadow_state_with_client(
+                &mut raw_query_client,
+                &config,
+                SHADOWED_CONTRACT_NAME,
+            )
+            .await
+            .unwrap();
+            let drifted_token_ids: Vec<_> = drifts
+                .iter()
+                .map(|drift| {
+                    (
+                        drift.kind.clone(),
+                        drift.state.get("token_id").unwrap().as_str(),
+                    )
+                })
+                .collect();
+            assert_eq!(
+                drifted_token_ids,
+                vec![
+                    (StateDriftKind::Unexpected, "1661"),
+                    (StateDriftKind::Missing, "1662")
+                ]
+            );
+        })
+        .await;
+    }
+
+    const LEAKY_SHADOWED_CONTRACT_NAME: &str = "LeakyShadowedBoredApeYachtClub";
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct LeakyShadowedNftState {
+        token_id: i32,
+    }
+    impl ContractState for LeakyShadowedNftState {
+        fn table_name() -> &'static str {
+            "leaky_shadowed_nft_states"
+        }
+    }
+
+    struct LeakyShadowedNftStateMigrations;
+    impl ContractStateMigrations for LeakyShadowedNftStateMigrations {
+        fn migrations(&self) -> Vec<&'static str> {
+            vec!["CREATE TABLE IF NOT EXISTS leaky_shadowed_nft_states (token_id INTEGER NOT NULL)"]
+        }
+    }
+
+    struct LeakyShadowedNftStateEventHandler(i32);
+
+    #[async_trait::async_trait]
+    impl EventHandler for LeakyShadowedNftStateEventHandler {
+        async fn handle_event<'a>(&self, event_context: EventContext<'a>) {
+            let token_id = event_context.event.get_params().get("tokenId").cloned();
+            let token_id = token_id.unwrap().into_uint().unwrap().as_u32() as i32;
+
+            LeakyShadowedNftState {
+                token_id: token_id + self.0,
+            }
+            .create(&event_context)
+            .await;
+        }
+    }
+
+    #[tokio::test]
+    pub async fn rolls_back_shadow_handlers_writing_to_live_states() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contract = Contract::new(LEAKY_SHADOWED_CONTRACT_NAME)
+                .add_event(TRANSFER_EVENT_ABI, LeakyShadowedNftStateEventHandler(0))
+                .add_state_migrations(LeakyShadowedNftStateMigrations)
+                .add_address(
+                    BAYC_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            // Without the state migrations, its states resolve to the live tables
+            let shadow_contract = Contract::new(LEAKY_SHADOWED_CONTRACT_NAME)
+                .add_event(TRANSFER_EVENT_ABI, LeakyShadowedNftStateEventHandler(1));
+            let contracts = vec![contract.clone()];
+            let config =
+                config_with_contracts(contracts.clone()).add_shadow_contract(shadow_contract);
+
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+            Chaindexing::reset_migrations_for_contract_states(&raw_query_client, &contracts).await;
+            Chaindexing::run_migrations_for_contract_states(&raw_query_client, &contracts).await;
+
+            let transfer_event = transfer_event_with_contract(contract);
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            ChaindexingRepo::create_events(&mut conn, &vec![transfer_event.clone()]).await;
+            let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+            ChaindexingRepo::update_next_block_number_to_ingest_from(
+                &mut conn,
+                contract_addresses.first().unwrap(),
+                transfer_event.block_number + 1,
+            )
+            .await;
+
+            let conn = Arc::new(Mutex::new(conn));
+            HandleEvents::run(conn.clone(), &config, &mut raw_query_client).await;
+
+            let rebuild = HandleEvents::rebuild_shadow_state_with_conn(
+                conn.clone(),
+                &mut raw_query_client,
+                &config,
+                LEAKY_SHADOWED_CONTRACT_NAME,
+            )
+            .await;
+            assert!(matches!(
+                rebuild,
+                Err(ContractStateError::ShadowWritesOutsideShadowSchema(table_names))
+                    if table_names.contains(&"public.leaky_shadowed_nft_states".to_string())
+            ));
+
+            let live_states: Vec<LeakyShadowedNftState> =
+                ChaindexingRepo::load_data_list_from_raw_query(
+                    &raw_query_client,
+                    "SELECT token_id FROM leaky_shadowed_nft_states",
+                )
+                .await;
+            assert_eq!(live_states, vec![LeakyShadowedNftState { token_id: 1661 }]);
+        })
+        .await;
+    }
+
+    const SNAPSHOTTED_CONTRACT_NAME: &str = "SnapshottedBoredApeYachtClub";
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct SnapshottedNftState {
+        token_id: i32,
+    }
+    impl ContractState for SnapshottedNftState {
+        fn table_name() -> &'static str {
+            "snapshotted_nft_states"
+        }
+    }
+
+    struct SnapshottedNftStateMigrations;
+    impl ContractStateMigrations for SnapshottedNftStateMigrations {
+        fn migrations(&self) -> Vec<&'static str> {
+            vec!["CREATE TABLE IF NOT EXISTS snapshotted_nft_states (token_id INTEGER NOT NULL)"]
+        }
+    }
+
+    struct SnapshottedNftStateEventHandler;
+
+    #[async_trait::async_trait]
+    impl EventHandler for SnapshottedNftStateEventHandler {
+        async fn handle_event<'a>(&self, event_context: EventContext<'a>) {
+            let token_id = event_context.event.get_params().get("tokenId").cloned();
+            let token_id = token_id.unwrap().into_uint().unwrap().as_u32() as i32;
+
+            SnapshottedNftState { token_id }.create(&event_context).await;
+        }
+    }
+
+    #[tokio::test]
+    pub async fn restores_snapshotted_states_and_handling_cursors() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contract = Contract::new(SNAPSHOTTED_CONTRACT_NAME)
+                .add_event(TRANSFER_EVENT_ABI, SnapshottedNftStateEventHandler)
+                .add_state_migrations(SnapshottedNftStateMigrations)
+                .add_address(
+                    BAYC_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![contract.clone()];
+            let config = config_with_contracts(contracts.clone());
+
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+            Chaindexing::reset_migrations_for_contract_states(&raw_query_client, &contracts).await;
+            Chaindexing::run_migrations_for_contract_states(&raw_query_client, &contracts).await;
+
+            let transfer_event = transfer_event_with_contract(contract);
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            ChaindexingRepo::create_events(&mut conn, &vec![transfer_event.clone()]).await;
+            let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+            ChaindexingRepo::update_next_block_number_to_ingest_from(
+                &mut conn,
+                contract_addresses.first().unwrap(),
+                transfer_event.block_number + 1,
+            )
+            .await;
+
+            let conn = Arc::new(Mutex::new(conn));
+            HandleEvents::run(conn.clone(), &config, &mut raw_query_client).await;
+
+            let snapshot = HandleEvents::snapshot_state_with_client(
+                &mut raw_query_client,
+                &config,
+                SNAPSHOTTED_CONTRACT_NAME,
+            )
+            .await
+            .unwrap();
+            let snapshot = StateSnapshot::from_json(&snapshot.to_json()).unwrap();
+
+            // As on a new replica
+            Chaindexing::reset_migrations_for_contract_states(&raw_query_client, &contracts).await;
+            Chaindexing::run_migrations_for_contract_states(&raw_query_client, &contracts).await;
+            ChaindexingRepo::execute_raw_query(
+                &raw_query_client,
+                "UPDATE chaindexing_contract_addresses
+                SET next_block_number_to_ingest_from = start_block_number,
+                next_block_number_to_handle_from = start_block_number",
+            )
+            .await;
+            ChaindexingRepo::execute_raw_query(
+                &raw_query_client,
+                "DELETE FROM chaindexing_handler_checkpoints",
+            )
+            .await;
+
+            HandleEvents::restore_state_with_client(&mut raw_query_client, &config, &snapshot)
+                .await
+                .unwrap();
+
+            let restored_states: Vec<SnapshottedNftState> =
+                ChaindexingRepo::load_data_list_from_raw_query(
+                    &raw_query_client,
+                    "SELECT token_id FROM snapshotted_nft_states",
+                )
+                .await;
+            assert_eq!(
+                restored_states,
+                vec![SnapshottedNftState { token_id: 1661 }]
+            );
+
+            let mut conn = conn.lock().await;
+            let contract_address = PostgresRepo::get_all_contract_addresses(&mut conn)
+                .await
+                .into_iter()
+                .find(|c| c.contract_name == SNAPSHOTTED_CONTRACT_NAME)
+                .unwrap();
+            assert_eq!(
+                contract_address.next_block_number_to_handle_from,
+                transfer_event.block_number + 1
+            );
+            assert_eq!(
+                contract_address.next_block_number_to_ingest_from,
+                contract_address.next_block_number_to_handle_from
+            );
+
+            let handler_checkpoint =
+                ChaindexingRepo::get_handler_checkpoint(&mut conn, contract_address.id)
+                    .await
+                    .unwrap();
+            assert_eq!(handler_checkpoint.block_number, transfer_event.block_number);
+            assert_eq!(handler_checkpoint.log_index, transfer_event.log_index);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn only_streams_events_with_the_given_abis() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let transfer_event = transfer_event_with_contract(bayc_contract());
+            let mut approval_event = transfer_event_with_contract(bayc_contract());
+            approval_event.abi = APPROCAL_EVENT_ABI.to_string();
+            approval_event.log_index = transfer_event.log_index + 1;
+            ChaindexingRepo::create_events(
+                &mut conn,
+                &vec![transfer_event.clone(), approval_event],
+            )
+            .await;
+
+            let conn = Arc::new(Mutex::new(conn));
+            let streamed_events: Vec<Event> = ChaindexingRepo::get_events_stream_for_abis(
+                conn,
+                BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                vec![TRANSFER_EVENT_ABI.to_string()],
+                500,
+            )
+            .concat()
+            .await;
+
+            assert_eq!(streamed_events.len(), 1);
+            assert_eq!(streamed_events.first().unwrap().id, transfer_event.id);
+        })
+        .await;
+    }
+
+    static HANDLED_BURST_EVENTS_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    struct CountingBurstEventHandler;
+
+    #[async_trait::async_trait]
+    impl EventHandler for CountingBurstEventHandler {
+        async fn handle_event<'a>(&self, _event_context: EventContext<'a>) {
+            HANDLED_BURST_EVENTS_COUNT.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn handles_events_in_batches_of_at_most_the_max_events() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            const BURSTING_CONTRACT_ADDRESS: &str = "0x60e4d786628fea6478f785a6d7e704777c86a7c6";
+
+            let contract = Contract::new("BurstingBoredApeYachtClub")
+                .add_event(TRANSFER_EVENT_ABI, CountingBurstEventHandler)
+                .add_address(
+                    BURSTING_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![contract.clone()];
+            let config =
+                config_with_contracts(contracts.clone()).with_max_events_per_handling_batch(2);
+
+            // The first block's three events cannot fit in a single batch
+            let bursting_transfer_event = || {
+                transfer_event_with_contract_address(contract.clone(), BURSTING_CONTRACT_ADDRESS)
+            };
+            let first_block_number = bursting_transfer_event().block_number;
+            let transfer_events: Vec<Event> = [0, 0, 0, 1, 2]
+                .iter()
+                .enumerate()
+                .map(|(index, block_offset)| {
+                    let mut transfer_event = bursting_transfer_event();
+                    transfer_event.block_number = first_block_number + block_offset;
+                    transfer_event.log_index = index as i64;
+                    transfer_event
+                })
+                .collect();
+            let last_block_number = transfer_events.last().unwrap().block_number;
+
+            // Committed, unlike the test transaction's writes, so the handling
+            // transaction's cursor updates can be read back
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+            for query in [
+                format!(
+                    "DELETE FROM chaindexing_contract_addresses
+                    WHERE address = '{BURSTING_CONTRACT_ADDRESS}'"
+                ),
+                format!(
+                    "INSERT INTO chaindexing_contract_addresses (address, contract_name, chain_id,
+                    start_block_number, next_block_number_to_ingest_from, next_block_number_to_handle_from)
+                    VALUES ('{BURSTING_CONTRACT_ADDRESS}', 'BurstingBoredApeYachtClub', 1,
+                    {first_block_number}, {next_block_number}, {first_block_number})",
+                    next_block_number = last_block_number + 1
+                ),
+            ] {
+                ChaindexingRepo::execute_raw_query(&raw_query_client, &query).await;
+            }
+            ChaindexingRepo::create_events(&mut conn, &transfer_events).await;
+
+            let conn = Arc::new(Mutex::new(conn));
+            let events_batches: Vec<Vec<Event>> = ChaindexingRepo::get_events_stream_for_abis(
+                conn.clone(),
+                BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                vec![TRANSFER_EVENT_ABI.to_string()],
+                2,
+            )
+            .collect()
+            .await;
+            let events_batches_sizes: Vec<usize> = events_batches.iter().map(|b| b.len()).collect();
+            assert_eq!(events_batches_sizes, vec![2, 2, 1]);
+
+            HandleEvents::run(conn.clone(), &config, &mut raw_query_client).await;
+            assert_eq!(HANDLED_BURST_EVENTS_COUNT.load(Ordering::SeqCst), 5);
+
+            let handling_cursor: HashMap<String, i64> = ChaindexingRepo::load_data_from_raw_query(
+                &raw_query_client,
+                &format!(
+                    "SELECT next_block_number_to_handle_from FROM chaindexing_contract_addresses
+                    WHERE address = '{BURSTING_CONTRACT_ADDRESS}'"
+                ),
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                handling_cursor["next_block_number_to_handle_from"],
+                last_block_number + 1
+            );
+        })
+        .await;
+    }
+
+    static HANDLED_EVENT_POSITIONS: std::sync::Mutex<Vec<(i64, i64)>> =
+        std::sync::Mutex::new(Vec::new());
+
+    struct PositionRecordingTransferEventHandler;
+
+    #[async_trait::async_trait]
+    impl EventHandler for PositionRecordingTransferEventHandler {
+        async fn handle_event<'a>(&self, event_context: EventContext<'a>) {
+            let event = event_context.event;
+
+            HANDLED_EVENT_POSITIONS
+                .lock()
+                .unwrap()
+                .push((event.transaction_index, event.log_index));
+        }
+    }
+
+    #[tokio::test]
+    pub async fn handles_events_of_a_block_in_transaction_order() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contract = Contract::new("TransactionOrderedBoredApeYachtClub")
+                .add_event(TRANSFER_EVENT_ABI, PositionRecordingTransferEventHandler)
+                .add_address(
+                    BAYC_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![contract.clone()];
+            let config =
+                config_with_contracts(contracts.clone()).with_max_events_per_handling_batch(2);
+
+            // Some providers number logs per transaction, so log indexes alone
+            // do not tell the order of a block's events
+            let transfer_events: Vec<Event> = [(2, 0), (0, 7), (1, 6), (1, 5)]
+                .iter()
+                .map(|(transaction_index, log_index)| {
+                    let mut transfer_event = transfer_event_with_contract(contract.clone());
+                    transfer_event.transaction_index = *transaction_index;
+                    transfer_event.log_index = *log_index;
+                    transfer_event
+                })
+                .collect();
+            let block_number = transfer_events.first().unwrap().block_number;
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            ChaindexingRepo::create_events(&mut conn, &transfer_events).await;
+            let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+            ChaindexingRepo::update_next_block_number_to_ingest_from(
+                &mut conn,
+                contract_addresses.first().unwrap(),
+                block_number + 1,
+            )
+            .await;
+
+            let conn = Arc::new(Mutex::new(conn));
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+            HandleEvents::run(conn.clone(), &config, &mut raw_query_client).await;
+
+            assert_eq!(
+                *HANDLED_EVENT_POSITIONS.lock().unwrap(),
+                vec![(0, 7), (1, 5), (1, 6), (2, 0)]
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn streams_handled_events_to_subscribers() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contract = bayc_contract();
+            let contracts = vec![contract.clone()];
+            let config = config_with_contracts(contracts.clone());
+
+            let mut transfer_event = transfer_event_with_contract(contract.clone());
+            transfer_event.log_index = 1;
+            let mut approval_event = transfer_event_with_contract(contract);
+            approval_event.abi = APPROCAL_EVENT_ABI.to_string();
+            approval_event.log_index = 2;
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            ChaindexingRepo::create_events(
+                &mut conn,
+                &vec![transfer_event.clone(), approval_event.clone()],
+            )
+            .await;
+            let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+            ChaindexingRepo::update_next_block_number_to_ingest_from(
+                &mut conn,
+                contract_addresses.first().unwrap(),
+                transfer_event.block_number + 1,
+            )
+            .await;
+
+            let mut all_events_subscription = config.subscribe_to_events("BoredApeYachtClub", &[]);
+            let mut transfer_events_subscription =
+                config.subscribe_to_events("BoredApeYachtClub", &[TRANSFER_EVENT_ABI]);
+
+            let conn = Arc::new(Mutex::new(conn));
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+            HandleEvents::run(conn.clone(), &config, &mut raw_query_client).await;
+
+            assert_eq!(
+                all_events_subscription.next().await.unwrap().id,
+                transfer_event.id
+            );
+            assert_eq!(
+                all_events_subscription.next().await.unwrap().id,
+                approval_event.id
+            );
+
+            assert_eq!(
+                transfer_events_subscription.next().await.unwrap().id,
+                transfer_event.id
+            );
+            assert!(transfer_events_subscription.next().now_or_never().is_none());
+        })
+        .await;
+    }
+
+    static HANDLED_TOKEN_ID: AtomicUsize = AtomicUsize::new(0);
+
+    struct TokenIdRecordingEventHandler;
+
+    #[async_trait::async_trait]
+    impl EventHandler for TokenIdRecordingEventHandler {
+        async fn handle_event<'a>(&self, event_context: EventContext<'a>) {
+            let token_id = event_context.event.get_params().get("tokenId").cloned();
+            let token_id = token_id.unwrap().into_uint().unwrap().as_usize();
+
+            HANDLED_TOKEN_ID.store(token_id, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn handles_built_events_without_ingestion() {
+        let event = Event::builder()
+            .with_abi(TRANSFER_EVENT_ABI)
+            .with_contract_address(BAYC_CONTRACT_ADDRESS)
+            .with_block_number(BAYC_CONTRACT_START_BLOCK_NUMBER as i64)
+            .with_log_index(3)
+            .add_param("tokenId", Token::Uint(U256::from(1661)))
+            .build();
+        assert_eq!(event.abi, TRANSFER_EVENT_ABI);
+        assert_eq!(event.log_index, 3);
+
+        let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+        let raw_query_txn_client =
+            ChaindexingRepo::get_raw_query_txn_client(&mut raw_query_client).await;
+        TokenIdRecordingEventHandler
+            .handle_event(EventContext::new(event, &raw_query_txn_client))
+            .await;
+
+        assert_eq!(HANDLED_TOKEN_ID.load(Ordering::SeqCst), 1661);
+    }
+
+    /// Only accepts events once done failing the given number of publishes
+    #[derive(Clone, Default)]
+    struct InMemoryEventSink {
+        published_events: Arc<std::sync::Mutex<Vec<Event>>>,
+        failures_left: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl EventSink for InMemoryEventSink {
+        async fn publish(&self, event: &Event) -> Result<(), EventSinkError> {
+            if self.failures_left.load(Ordering::SeqCst) > 0 {
+                self.failures_left.fetch_sub(1, Ordering::SeqCst);
+
+                return Err(EventSinkError("Sink unavailable".to_string()));
+            }
+
+            self.published_events.lock().unwrap().push(event.clone());
+
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    pub async fn publishes_every_handled_event_to_sinks() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let sink = InMemoryEventSink::default();
+            sink.failures_left.store(1, Ordering::SeqCst);
+            let sink_handler = EventSinkHandler::new(sink.clone());
+
+            let contract = Contract::new("BoredApeYachtClub")
+                .add_event(
+                    TRANSFER_EVENT_ABI,
+                    sink_handler.clone().with_event_handler(TransferTestEventHandler),
+                )
+                .add_event(APPROCAL_EVENT_ABI, sink_handler)
+                .add_address(
+                    BAYC_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![contract.clone()];
+            let config = config_with_contracts(contracts.clone());
+
+            let mut transfer_event = transfer_event_with_contract(contract.clone());
+            transfer_event.log_index = 1;
+            let mut approval_event = transfer_event_with_contract(contract);
+            approval_event.abi = APPROCAL_EVENT_ABI.to_string();
+            approval_event.log_index = 2;
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            ChaindexingRepo::create_events(
+                &mut conn,
+                &vec![transfer_event.clone(), approval_event.clone()],
+            )
+            .await;
+            let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+            ChaindexingRepo::update_next_block_number_to_ingest_from(
+                &mut conn,
+                contract_addresses.first().unwrap(),
+                transfer_event.block_number + 1,
+            )
+            .await;
+
+            let conn = Arc::new(Mutex::new(conn));
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+            HandleEvents::run(conn.clone(), &config, &mut raw_query_client).await;
+
+            // The failed publish got retried instead of dropping the event
+            let published_event_ids: Vec<_> =
+                sink.published_events.lock().unwrap().iter().map(|e| e.id).collect();
+            assert!(published_event_ids.contains(&transfer_event.id));
+            assert!(published_event_ids.contains(&approval_event.id));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn is_fully_synced_once_every_contract_address_is_ingested_and_handled() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+            static CURRENT_BLOCK_NUMBER: u64 = START_BLOCK_NUMBER + 100;
+
+            let contracts = vec![bayc_contract()];
+            let config = config_with_contracts(contracts.clone())
+                .with_blocks_per_batch(200)
+                .with_min_confirmation_count(1)
+                .with_caught_up_window(10);
+            let current_block_numbers =
+                HashMap::from([(Chain::Mainnet, BlockNumber::new(CURRENT_BLOCK_NUMBER))]);
+
+            // Not even saved yet
+            assert!(!config.is_fully_synced_with_conn(&mut conn, &current_block_numbers).await);
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            assert!(!config.is_fully_synced_with_conn(&mut conn, &current_block_numbers).await);
+
+            let mut transfer_log = transfer_log(BAYC_CONTRACT_ADDRESS);
+            transfer_log.block_number = Some((START_BLOCK_NUMBER + 30).into());
+            let json_rpc = Arc::new(json_rpc_with_served_logs(
+                CURRENT_BLOCK_NUMBER,
+                vec![transfer_log],
+            ));
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            // Ingested up to the current block, but its event is not handled yet
+            {
+                let mut conn = conn.lock().await;
+                assert!(!config.is_fully_synced_with_conn(&mut conn, &current_block_numbers).await);
+            }
+
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+            HandleEvents::run(conn.clone(), &config, &mut raw_query_client).await;
+
+            // Synced, even though the handling cursor stays at the last event's block
+            let mut conn = conn.lock().await;
+            assert!(config.is_fully_synced_with_conn(&mut conn, &current_block_numbers).await);
+        })
+        .await;
+    }
+
+    static HANDLED_CONFLICTING_TRANSFERS_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    struct ConflictingTransferEventHandler;
+
+    #[async_trait::async_trait]
+    impl EventHandler for ConflictingTransferEventHandler {
+        async fn handle_event<'a>(&self, event_context: EventContext<'a>) {
+            HANDLED_CONFLICTING_TRANSFERS_COUNT.fetch_add(1, Ordering::SeqCst);
+
+            let raw_query_txn_client = event_context.get_raw_query_txn_client();
+            let query = format!(
+                "INSERT INTO conflicting_transfers (transaction_hash) VALUES ('{}')",
+                event_context.event.transaction_hash
+            );
+            ChaindexingRepo::execute_raw_query_in_txn(raw_query_txn_client, &query).await;
+        }
+    }
+
+    #[tokio::test]
+    pub async fn retries_handler_transactions_failing_to_commit_on_conflicts() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contract = Contract::new("ConflictingBoredApeYachtClub")
+                .add_event(TRANSFER_EVENT_ABI, ConflictingTransferEventHandler)
+                .add_address(
+                    BAYC_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![contract.clone()];
+            let config = config_with_contracts(contracts.clone()).with_max_handler_commit_retries(2);
+
+            // Simulates a conflict with a concurrent transaction by failing the
+            // first commit, the way Postgres does at commit time
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+            for query in [
+                "CREATE TABLE IF NOT EXISTS conflicting_transfers (transaction_hash TEXT NOT NULL)",
+                "DELETE FROM conflicting_transfers",
+                "DROP SEQUENCE IF EXISTS conflicting_transfers_commits",
+                "CREATE SEQUENCE conflicting_transfers_commits",
+                "CREATE OR REPLACE FUNCTION fail_first_conflicting_transfers_commit() RETURNS TRIGGER AS $$
+                BEGIN
+                    IF nextval('conflicting_transfers_commits') = 1 THEN
+                        RAISE EXCEPTION 'could not serialize access' USING ERRCODE = 'serialization_failure';
+                    END IF;
+
+                    RETURN NEW;
+                END
+                $$ LANGUAGE plpgsql",
+                "DROP TRIGGER IF EXISTS fail_first_conflicting_transfers_commit ON conflicting_transfers",
+                "CREATE CONSTRAINT TRIGGER fail_first_conflicting_transfers_commit
+                AFTER INSERT ON conflicting_transfers DEFERRABLE INITIALLY DEFERRED
+                FOR EACH ROW EXECUTE FUNCTION fail_first_conflicting_transfers_commit()",
+            ] {
+                ChaindexingRepo::execute_raw_query(&raw_query_client, query).await;
+            }
+
+            let transfer_event = transfer_event_with_contract(contract);
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            ChaindexingRepo::create_events(&mut conn, &vec![transfer_event.clone()]).await;
+            let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+            ChaindexingRepo::update_next_block_number_to_ingest_from(
+                &mut conn,
+                contract_addresses.first().unwrap(),
+                transfer_event.block_number + 1,
+            )
+            .await;
+
+            let conn = Arc::new(Mutex::new(conn));
+            HandleEvents::run(conn.clone(), &config, &mut raw_query_client).await;
+
+            // Re-run on a fresh transaction, which committed
+            assert_eq!(HANDLED_CONFLICTING_TRANSFERS_COUNT.load(Ordering::SeqCst), 2);
+            let conflicting_transfers: Vec<HashMap<String, String>> =
+                ChaindexingRepo::load_data_list_from_raw_query(
+                    &raw_query_client,
+                    "SELECT transaction_hash FROM conflicting_transfers",
+                )
+                .await;
+            assert_eq!(
+                conflicting_transfers,
+                vec![HashMap::from([(
+                    "transaction_hash".to_string(),
+                    transfer_event.transaction_hash
+                )])]
+            );
+        })
+        .await;
+    }
+
+    static HANDLED_DEADLOCKING_TRANSFERS_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    struct DeadlockingTransferEventHandler;
+
+    #[async_trait::async_trait]
+    impl EventHandler for DeadlockingTransferEventHandler {
+        async fn handle_event<'a>(&self, event_context: EventContext<'a>) {
+            let handlings_count =
+                HANDLED_DEADLOCKING_TRANSFERS_COUNT.fetch_add(1, Ordering::SeqCst);
+
+            let raw_query_txn_client = event_context.get_raw_query_txn_client();
+            ChaindexingRepo::execute_raw_query_in_txn(
+                raw_query_txn_client,
+                "UPDATE deadlocking_transfers SET count = count + 1 WHERE id = 1",
+            )
+            .await;
+
+            if handlings_count == 0 {
+                // A concurrent transaction locks the second row, then waits on
+                // the first one right after this handling waits on the second
+                let (locked_sender, locked_receiver) = tokio::sync::oneshot::channel();
+                tokio::spawn(async move {
+                    let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+                    let raw_query_txn_client =
+                        ChaindexingRepo::get_raw_query_txn_client(&mut raw_query_client).await;
+                    ChaindexingRepo::execute_raw_query_in_txn(
+                        &raw_query_txn_client,
+                        "UPDATE deadlocking_transfers SET count = count + 1 WHERE id = 2",
+                    )
+                    .await;
+                    locked_sender.send(()).unwrap();
+
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    ChaindexingRepo::execute_raw_query_in_txn(
+                        &raw_query_txn_client,
+                        "UPDATE deadlocking_transfers SET count = count + 1 WHERE id = 1",
+                    )
+                    .await;
+                    ChaindexingRepo::commit_raw_query_txns(raw_query_txn_client).await;
+                });
+                locked_receiver.await.unwrap();
+            }
+
+            // Postgres detects the deadlock on this statement, having waited
+            // first, and aborts it
+            ChaindexingRepo::execute_raw_query_in_txn(
+                raw_query_txn_client,
+                "UPDATE deadlocking_transfers SET count = count + 1 WHERE id = 2",
+            )
+            .await;
+        }
+    }
+
+    #[tokio::test]
+    pub async fn retries_handler_transactions_whose_statements_deadlock() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contract = Contract::new("DeadlockingBoredApeYachtClub")
+                .add_event(TRANSFER_EVENT_ABI, DeadlockingTransferEventHandler)
+                .add_address(
+                    BAYC_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![contract.clone()];
+            let config = config_with_contracts(contracts.clone()).with_max_handler_commit_retries(2);
+
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+            for query in [
+                "CREATE TABLE IF NOT EXISTS deadlocking_transfers (id INTEGER PRIMARY KEY, count INTEGER NOT NULL)",
+                "DELETE FROM deadlocking_transfers",
+                "INSERT INTO deadlocking_transfers (id, count) VALUES (1, 0), (2, 0)",
+            ] {
+                ChaindexingRepo::execute_raw_query(&raw_query_client, query).await;
+            }
+
+            let transfer_event = transfer_event_with_contract(contract);
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            ChaindexingRepo::create_events(&mut conn, &vec![transfer_event.clone()]).await;
+            let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+            ChaindexingRepo::update_next_block_number_to_ingest_from(
+                &mut conn,
+                contract_addresses.first().unwrap(),
+                transfer_event.block_number + 1,
+            )
+            .await;
+
+            let conn = Arc::new(Mutex::new(conn));
+            HandleEvents::run(conn.clone(), &config, &mut raw_query_client).await;
+
+            // Re-run on a fresh transaction once the concurrent one committed
+            assert_eq!(HANDLED_DEADLOCKING_TRANSFERS_COUNT.load(Ordering::SeqCst), 2);
+            let deadlocking_transfers: Vec<HashMap<String, i64>> =
+                ChaindexingRepo::load_data_list_from_raw_query(
+                    &raw_query_client,
+                    "SELECT id, count FROM deadlocking_transfers ORDER BY id",
+                )
+                .await;
+            assert_eq!(
+                deadlocking_transfers,
+                vec![
+                    HashMap::from([("id".to_string(), 1), ("count".to_string(), 2)]),
+                    HashMap::from([("id".to_string(), 2), ("count".to_string(), 2)]),
+                ]
+            );
+        })
+        .await;
+    }
+
+    static THROTTLED_TRANSFERS_HANDLED_AT: StdMutex<Vec<Instant>> = StdMutex::new(vec![]);
+
+    struct ThrottledTransferEventHandler;
+
+    #[async_trait::async_trait]
+    impl EventHandler for ThrottledTransferEventHandler {
+        async fn handle_event<'a>(&self, _event_context: EventContext<'a>) {
+            THROTTLED_TRANSFERS_HANDLED_AT.lock().unwrap().push(Instant::now());
+        }
+    }
+
+    #[tokio::test]
+    pub async fn paces_handling_batches_to_the_max_events_per_second() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+
+            let contract = Contract::new("ThrottledBoredApeYachtClub")
+                .add_event(TRANSFER_EVENT_ABI, ThrottledTransferEventHandler)
+                .add_address(
+                    BAYC_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![contract];
+            // Handled in batches of two events, a second's worth
+            let config = config_with_contracts(contracts.clone())
+                .with_blocks_per_batch(200)
+                .with_min_confirmation_count(1)
+                .with_max_events_per_second(2);
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let logs = (1..=5)
+                .map(|block_offset| {
+                    let block_number = START_BLOCK_NUMBER + block_offset;
+                    let mut transfer_log = transfer_log(BAYC_CONTRACT_ADDRESS);
+                    transfer_log.block_number = Some(block_number.into());
+                    transfer_log.transaction_hash = Some(H256::from_low_u64_be(block_number));
+
+                    transfer_log
+                })
+                .collect();
+            let json_rpc = Arc::new(json_rpc_with_served_logs(START_BLOCK_NUMBER + 20, logs));
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+            HandleEvents::run(conn.clone(), &config, &mut raw_query_client).await;
+
+            let handled_at = THROTTLED_TRANSFERS_HANDLED_AT.lock().unwrap().clone();
+            assert_eq!(handled_at.len(), 5);
+            // No two batches handled closer than a second apart
+            for batch_handled_at in handled_at.iter().step_by(2).collect::<Vec<_>>().windows(2) {
+                assert!(*batch_handled_at[1] - *batch_handled_at[0] >= Duration::from_millis(950));
+            }
+        })
+        .await;
+    }
+}
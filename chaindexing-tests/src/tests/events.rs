@@ -0,0 +1,448 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chaindexing::{
+        Chain, Chaindexing, ChaindexingRepo, ContractEvent, Event, Events, EventsCursor,
+        ExecutesWithRawQuery, HasRawQueryClient, MalformedEventError, Repo, RepoMigrations,
+        UnsavedContractAddress,
+    };
+    use ethers::abi::{encode, Token};
+    use ethers::types::{Address, Block, Bytes, Log, H256, U256};
+
+    use crate::factory::{
+        bayc_contract, config_with_contracts, transfer_event_with_contract, transfer_log,
+        TransferTestEventHandler, BAYC_CONTRACT_ADDRESS, BAYC_CONTRACT_START_BLOCK_NUMBER,
+        TRANSFER_EVENT_ABI,
+    };
+    use crate::{db, test_runner};
+
+    #[tokio::test]
+    pub async fn gets_events_of_a_transaction_ordered_by_log_index() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            // Emitted by two contracts within the same transaction
+            let tx_events: Vec<Event> = [3, 1, 4, 2]
+                .iter()
+                .map(|log_index| {
+                    let mut event = transfer_event_with_contract(bayc_contract());
+                    event.log_index = *log_index;
+                    if log_index % 2 == 0 {
+                        event.contract_address =
+                            "0x8a90cab2b38dba80c64b7734e58ee1db38b8992e".to_string();
+                    }
+                    event
+                })
+                .collect();
+            let tx_hash = tx_events.first().unwrap().transaction_hash.clone();
+
+            let mut other_tx_event = transfer_event_with_contract(bayc_contract());
+            other_tx_event.transaction_hash = "0xother".to_string();
+
+            ChaindexingRepo::create_events(&mut conn, &tx_events).await;
+            ChaindexingRepo::create_events(&mut conn, &vec![other_tx_event]).await;
+
+            let events = ChaindexingRepo::get_events_by_tx_hash(&mut conn, &tx_hash).await;
+            assert_eq!(
+                events.iter().map(|e| e.log_index).collect::<Vec<_>>(),
+                vec![1, 2, 3, 4]
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn paginates_events_of_a_contract_address_with_cursors() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let events: Vec<Event> = [(10, 2), (10, 1), (11, 5), (12, 3), (12, 4)]
+                .iter()
+                .map(|(block_number, log_index)| {
+                    let mut event = transfer_event_with_contract(bayc_contract());
+                    event.block_number = *block_number;
+                    event.log_index = *log_index;
+                    event
+                })
+                .collect();
+            ChaindexingRepo::create_events(&mut conn, &events).await;
+
+            let (first_page, next_cursor) =
+                ChaindexingRepo::paginate_events(&mut conn, BAYC_CONTRACT_ADDRESS, None, 2).await;
+            assert_eq!(positions(&first_page), vec![(10, 1), (10, 2)]);
+
+            // Cursors round-trip through their opaque encoding, e.g. via URLs
+            let next_cursor = EventsCursor::decode(&next_cursor.unwrap().encode());
+            let (middle_page, next_cursor) =
+                ChaindexingRepo::paginate_events(&mut conn, BAYC_CONTRACT_ADDRESS, next_cursor, 2)
+                    .await;
+            assert_eq!(positions(&middle_page), vec![(11, 5), (12, 3)]);
+
+            let (last_page, next_cursor) =
+                ChaindexingRepo::paginate_events(&mut conn, BAYC_CONTRACT_ADDRESS, next_cursor, 2)
+                    .await;
+            assert_eq!(positions(&last_page), vec![(12, 4)]);
+            assert_eq!(next_cursor, None);
+
+            // A page ending right at the last event is the last page too
+            let (all_events, next_cursor) =
+                ChaindexingRepo::paginate_events(&mut conn, BAYC_CONTRACT_ADDRESS, None, 5).await;
+            assert_eq!(all_events.len(), 5);
+            assert_eq!(next_cursor, None);
+
+            assert_eq!(EventsCursor::decode("not-a-cursor"), None);
+            assert_eq!(EventsCursor::decode(&"00".repeat(20)), None);
+
+            // Cursors encoded without a transaction index still decode
+            let legacy_cursor = format!("{:016x}{:016x}", 12, 3);
+            assert_eq!(
+                EventsCursor::decode(&legacy_cursor),
+                Some(EventsCursor {
+                    block_number: 12,
+                    transaction_index: 0,
+                    log_index: 3
+                })
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn gets_events_of_blocks_since_a_timestamp() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static NOW: i64 = 1_700_000_000;
+
+            // Ten minutes, an hour and two hours ago
+            let events: Vec<Event> = [(3, NOW - 600), (2, NOW - 3600), (1, NOW - 7200)]
+                .iter()
+                .map(|(log_index, block_timestamp)| {
+                    let mut event = transfer_event_with_contract(bayc_contract());
+                    event.log_index = *log_index;
+                    event.block_timestamp = *block_timestamp;
+                    event
+                })
+                .collect();
+            ChaindexingRepo::create_events(&mut conn, &events).await;
+
+            let last_hour_events =
+                ChaindexingRepo::get_events_since(&mut conn, NOW - 3600, 10).await;
+            assert_eq!(
+                last_hour_events.iter().map(|e| e.block_timestamp).collect::<Vec<_>>(),
+                vec![NOW - 3600, NOW - 600]
+            );
+
+            let limited_events = ChaindexingRepo::get_events_since(&mut conn, NOW - 7200, 1).await;
+            assert_eq!(
+                limited_events.iter().map(|e| e.block_timestamp).collect::<Vec<_>>(),
+                vec![NOW - 7200]
+            );
+
+            assert!(ChaindexingRepo::get_events_since(&mut conn, NOW, 10).await.is_empty());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn gets_events_by_a_decoded_parameter_value() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let holder = Address::from_low_u64_be(1);
+            let other_holder = Address::from_low_u64_be(2);
+            let events: Vec<Event> = [(1, holder), (2, other_holder), (3, holder)]
+                .iter()
+                .map(|(log_index, to)| {
+                    Event::builder()
+                        .with_abi(TRANSFER_EVENT_ABI)
+                        .with_contract_address(BAYC_CONTRACT_ADDRESS)
+                        .with_contract_name("BoredApeYachtClub")
+                        .with_log_index(*log_index)
+                        .add_param("to", Token::Address(*to))
+                        .add_param("tokenId", Token::Uint(U256::from(*log_index)))
+                        .build()
+                })
+                .collect();
+            ChaindexingRepo::create_events(&mut conn, &events).await;
+
+            let holder_events = ChaindexingRepo::get_events_where(
+                &mut conn,
+                BAYC_CONTRACT_ADDRESS,
+                &["to"],
+                Token::Address(holder),
+            )
+            .await;
+            assert_eq!(
+                holder_events.iter().map(|e| e.log_index).collect::<Vec<_>>(),
+                vec![1, 3]
+            );
+
+            let token_events = ChaindexingRepo::get_events_where(
+                &mut conn,
+                BAYC_CONTRACT_ADDRESS,
+                &["tokenId", "Uint"],
+                U256::from(2),
+            )
+            .await;
+            assert_eq!(
+                token_events.iter().map(|e| e.log_index).collect::<Vec<_>>(),
+                vec![2]
+            );
+
+            let missing_param_events = ChaindexingRepo::get_events_where(
+                &mut conn,
+                BAYC_CONTRACT_ADDRESS,
+                &["from"],
+                Token::Address(holder),
+            )
+            .await;
+            assert!(missing_param_events.is_empty());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn matches_events_to_contract_addresses_regardless_of_address_case() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            // Configured EIP-55 checksummed, then registered lowercased
+            let bayc_contract = bayc_contract();
+            Chaindexing::create_initial_contract_addresses(&mut conn, &vec![bayc_contract.clone()])
+                .await;
+            let config = config_with_contracts(vec![bayc_contract.clone()]);
+            Chaindexing::register_contract_addresses(
+                &mut conn,
+                &config,
+                &bayc_contract,
+                &[&BAYC_CONTRACT_ADDRESS.to_lowercase()],
+                &Chain::Mainnet,
+                0,
+            )
+            .await
+            .unwrap();
+
+            let contract_addresses = ChaindexingRepo::get_all_contract_addresses(&mut conn).await;
+            assert_eq!(contract_addresses.len(), 1);
+            let bayc_contract_address = contract_addresses.first().unwrap();
+            assert_eq!(
+                bayc_contract_address.address,
+                BAYC_CONTRACT_ADDRESS.to_lowercase()
+            );
+
+            let log = transfer_log(BAYC_CONTRACT_ADDRESS);
+            let blocks_by_tx_hash = HashMap::from([(
+                log.transaction_hash.unwrap(),
+                Block {
+                    timestamp: U256::from(1),
+                    ..Default::default()
+                },
+            )]);
+            let events = Events::new(&vec![log], &vec![bayc_contract], &blocks_by_tx_hash);
+            let event = events.first().unwrap();
+
+            assert!(event.match_contract_address(&bayc_contract_address.address));
+            assert!(event.match_contract_address(&BAYC_CONTRACT_ADDRESS.to_string()));
+
+            ChaindexingRepo::create_events(&mut conn, &events).await;
+            let (ingested_events, _next_cursor) =
+                ChaindexingRepo::paginate_events(&mut conn, BAYC_CONTRACT_ADDRESS, None, 10).await;
+            assert_eq!(ingested_events.len(), 1);
+        })
+        .await;
+    }
+
+    #[test]
+    pub fn separates_indexed_params_from_non_indexed_ones() {
+        const ERC20_TRANSFER_EVENT_ABI: &str =
+            "event Transfer(address indexed from, address indexed to, uint256 value)";
+        const WETH_CONTRACT_ADDRESS: &str = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2";
+
+        let transfer_event = ContractEvent::new(ERC20_TRANSFER_EVENT_ABI);
+        let from = Address::from_low_u64_be(1);
+        let to = Address::from_low_u64_be(2);
+        let log = Log {
+            address: WETH_CONTRACT_ADDRESS.parse().unwrap(),
+            topics: vec![
+                transfer_event.value.signature(),
+                H256::from(from),
+                H256::from(to),
+            ],
+            data: Bytes::from(encode(&[Token::Uint(U256::from(42))])),
+            block_hash: Some(H256::from_low_u64_be(3)),
+            block_number: Some(1.into()),
+            transaction_hash: Some(H256::from_low_u64_be(4)),
+            transaction_index: Some(0.into()),
+            log_index: Some(0.into()),
+            removed: Some(false),
+            ..Default::default()
+        };
+        let contract_address =
+            UnsavedContractAddress::new("WETH", WETH_CONTRACT_ADDRESS, &Chain::Mainnet, 0);
+
+        let event = Event::new(&log, &transfer_event, &contract_address, 0);
+
+        let indexed_params = event.get_indexed_params();
+        assert_eq!(indexed_params.len(), 2);
+        assert_eq!(indexed_params.get("from"), Some(&Token::Address(from)));
+        assert_eq!(indexed_params.get("to"), Some(&Token::Address(to)));
+
+        let non_indexed_params = event.get_non_indexed_params();
+        assert_eq!(non_indexed_params.len(), 1);
+        assert_eq!(
+            non_indexed_params.get("value"),
+            Some(&Token::Uint(U256::from(42)))
+        );
+    }
+
+    #[test]
+    pub fn decodes_proxy_events_with_the_implementation_abi_of_their_block() {
+        // Same topic as the upgraded implementation's, with a differently named param
+        const PRE_UPGRADE_TRANSFER_EVENT_ABI: &str =
+            "event Transfer(address indexed from, address indexed to, uint256 indexed amount)";
+        const UPGRADE_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64 + 100;
+
+        let proxy_contract = bayc_contract().add_implementation_event(
+            BAYC_CONTRACT_ADDRESS,
+            BAYC_CONTRACT_START_BLOCK_NUMBER as u64,
+            UPGRADE_BLOCK_NUMBER - 1,
+            PRE_UPGRADE_TRANSFER_EVENT_ABI,
+            TransferTestEventHandler,
+        );
+        let transfer_log_at = |block_number: u64| {
+            let mut log = transfer_log(BAYC_CONTRACT_ADDRESS);
+            log.block_number = Some(block_number.into());
+            log.transaction_hash = Some(H256::from_low_u64_be(block_number));
+            log
+        };
+        let logs = vec![
+            transfer_log_at(UPGRADE_BLOCK_NUMBER - 1),
+            transfer_log_at(UPGRADE_BLOCK_NUMBER),
+        ];
+        let blocks_by_tx_hash = logs
+            .iter()
+            .map(|log| (log.transaction_hash.unwrap(), Block::default()))
+            .collect();
+
+        let events = Events::new(&logs, &vec![proxy_contract], &blocks_by_tx_hash);
+
+        let pre_upgrade_event = events.first().unwrap();
+        assert_eq!(pre_upgrade_event.abi, PRE_UPGRADE_TRANSFER_EVENT_ABI);
+        assert!(pre_upgrade_event.get_params().contains_key("amount"));
+
+        let post_upgrade_event = events.last().unwrap();
+        assert_eq!(post_upgrade_event.abi, TRANSFER_EVENT_ABI);
+        assert!(post_upgrade_event.get_params().contains_key("tokenId"));
+    }
+
+    #[test]
+    pub fn drops_logs_of_implementation_events_outside_of_their_block_range() {
+        // Only the implementation emits it, within its block range
+        const UPGRADED_EVENT_ABI: &str = "event Upgraded(address indexed implementation)";
+        const UPGRADE_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64 + 100;
+
+        let proxy_contract = bayc_contract().add_implementation_event(
+            BAYC_CONTRACT_ADDRESS,
+            UPGRADE_BLOCK_NUMBER,
+            UPGRADE_BLOCK_NUMBER + 100,
+            UPGRADED_EVENT_ABI,
+            TransferTestEventHandler,
+        );
+        assert!(proxy_contract
+            .get_event_topics()
+            .contains(&ContractEvent::new(UPGRADED_EVENT_ABI).topic));
+
+        let transfer_log = transfer_log(BAYC_CONTRACT_ADDRESS);
+        let mut upgraded_log = transfer_log.clone();
+        upgraded_log.topics = vec![
+            ContractEvent::new(UPGRADED_EVENT_ABI).value.signature(),
+            H256::zero(),
+        ];
+        upgraded_log.log_index = Some(transfer_log.log_index.unwrap() + U256::one());
+        upgraded_log.block_number = Some((UPGRADE_BLOCK_NUMBER - 1).into());
+        let blocks_by_tx_hash =
+            HashMap::from([(transfer_log.transaction_hash.unwrap(), Block::default())]);
+
+        let events = Events::new(
+            &vec![transfer_log, upgraded_log],
+            &vec![proxy_contract],
+            &blocks_by_tx_hash,
+        );
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events.first().unwrap().abi, TRANSFER_EVENT_ABI);
+    }
+
+    #[test]
+    pub fn changes_content_hashes_iff_event_contents_change() {
+        let get_content_hash = |log: Log| {
+            let blocks_by_tx_hash =
+                HashMap::from([(log.transaction_hash.unwrap(), Block::default())]);
+            let events = Events::new(&vec![log], &vec![bayc_contract()], &blocks_by_tx_hash);
+
+            events.first().unwrap().get_content_hash()
+        };
+        let log = transfer_log(BAYC_CONTRACT_ADDRESS);
+
+        assert_eq!(get_content_hash(log.clone()), get_content_hash(log.clone()));
+
+        let mut reingested_log = log.clone();
+        reingested_log.log_index = Some(log.log_index.unwrap() + U256::one());
+        assert_eq!(
+            get_content_hash(log.clone()),
+            get_content_hash(reingested_log)
+        );
+
+        let mut other_token_log = log.clone();
+        other_token_log.topics[3] = H256::from_low_u64_be(1);
+        assert_ne!(
+            get_content_hash(log.clone()),
+            get_content_hash(other_token_log)
+        );
+
+        let mut reorged_log = log.clone();
+        reorged_log.block_hash = Some(H256::from_low_u64_be(1));
+        assert_ne!(get_content_hash(log), get_content_hash(reorged_log));
+    }
+
+    fn positions(events: &Vec<Event>) -> Vec<(i64, i64)> {
+        events.iter().map(|e| (e.block_number, e.log_index)).collect()
+    }
+
+    #[tokio::test]
+    pub async fn reports_malformed_stored_events_without_panicking() {
+        let repo = ChaindexingRepo::new(&db::fresh_database_url("malformed_events"));
+        let raw_query_client = repo.get_raw_query_client().await;
+        ChaindexingRepo::setup(&raw_query_client).await;
+
+        let pool = repo.get_pool(1).await;
+        let mut conn = ChaindexingRepo::get_conn(&pool).await;
+        let malformed_event = transfer_event_with_contract(bayc_contract());
+        ChaindexingRepo::create_events(&mut conn, &vec![malformed_event.clone()]).await;
+
+        // E.g. as written by a buggy version
+        ChaindexingRepo::execute_raw_query(
+            &raw_query_client,
+            &format!(
+                "UPDATE chaindexing_events SET parameters = '{{\"tokenId\": 1}}' WHERE id = '{}'",
+                malformed_event.id
+            ),
+        )
+        .await;
+
+        let events = ChaindexingRepo::get_all_events(&mut conn).await;
+        let error = events.first().unwrap().validate().unwrap_err();
+        assert_eq!(
+            (error.event_id, error.field),
+            (malformed_event.id, "parameters")
+        );
+        assert!(matches!(
+            events.first().unwrap().try_get_params(),
+            Err(MalformedEventError { .. })
+        ));
+
+        assert!(Events::skip_malformed(events).is_empty());
+    }
+}
@@ -1,16 +1,32 @@
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
+    use chrono::DateTime;
+    use ethers::types::{Address, Block, ValueOrArray, H256};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex as StdMutex};
+    use std::time::Duration;
     use tokio::sync::Mutex;
 
     use crate::factory::{
-        bayc_contract, empty_json_rpc, BAYC_CONTRACT_ADDRESS, BAYC_CONTRACT_START_BLOCK_NUMBER,
+        bayc_contract, config_with_contracts, empty_json_rpc, failing_json_rpc,
+        json_rpc_with_batched_logs, json_rpc_with_hanging_logs, json_rpc_with_max_block_range,
+        json_rpc_with_served_logs, json_rpc_with_slow_served_logs, json_rpc_with_stale_logs,
+        json_rpc_with_stray_logs, transfer_event_with_contract,
+        transfer_event_with_contract_address, transfer_log, TransferTestEventHandler,
+        BAYC_CONTRACT_ADDRESS, BAYC_CONTRACT_START_BLOCK_NUMBER, TRANSFER_EVENT_ABI,
     };
     use crate::{
-        json_rpc_with_empty_logs, json_rpc_with_filter_stubber, json_rpc_with_logs, test_runner,
+        json_rpc_with_empty_logs, json_rpc_with_filter_stubber, json_rpc_with_logs,
+        json_rpc_with_reverted_transaction_logs, test_runner,
     };
     use chaindexing::{
-        Chain, Chaindexing, EventsIngester, MinConfirmationCount, PostgresRepo, Repo,
+        BatchTimings, BlockNumber, BlocksPerBatchError, BlocksPerBatchProbe, Chain,
+        ChainCircuitState, Chaindexing, ChaindexingRepo, ChaindexingRepoConn, Clock, Config,
+        Contract, ContractEvent, Event, Events, EventsIngester, EventsIngesterError,
+        FinalityViolation, LaggingNode, Metric, MetricKind, MetricLabels, MockClock, PostgresRepo,
+        RedecodeEventsError, ReorgReport, ReorgedBlock, Repo, UnsavedReorgedBlock,
+        REDECODED_EVENTS_PAGE_SIZE,
     };
 
     #[tokio::test]
@@ -28,17 +44,13 @@ mod tests {
             assert!(PostgresRepo::get_all_events(&mut conn).await.is_empty());
             Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
 
+            let config = config_with_contracts(contracts.clone())
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1);
             let conn = Arc::new(Mutex::new(conn));
-            EventsIngester::ingest(
-                conn.clone(),
-                &contracts,
-                10,
-                json_rpc,
-                &Chain::Mainnet,
-                &MinConfirmationCount::new(1),
-            )
-            .await
-            .unwrap();
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
 
             let mut conn = conn.lock().await;
             let ingested_events = PostgresRepo::get_all_events(&mut conn).await;
@@ -47,6 +59,46 @@ mod tests {
                 first_event.contract_address,
                 BAYC_CONTRACT_ADDRESS.to_lowercase()
             );
+            assert_eq!(first_event.transaction_reverted(), None);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn stamps_ingested_events_with_the_configured_clock() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contracts = vec![bayc_contract()];
+            static CURRENT_BLOCK_NUMBER: u32 = BAYC_CONTRACT_START_BLOCK_NUMBER + 20;
+            let json_rpc = Arc::new(json_rpc_with_logs!(
+                BAYC_CONTRACT_ADDRESS,
+                CURRENT_BLOCK_NUMBER
+            ));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let started_at = DateTime::from_timestamp(1_600_000_000, 0).unwrap().naive_utc();
+            let clock = MockClock::new(started_at);
+            clock.advance(Duration::from_secs(60));
+            let config = config_with_contracts(contracts.clone())
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1)
+                .with_clock(clock);
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            let mut conn = conn.lock().await;
+            let ingested_events = PostgresRepo::get_all_events(&mut conn).await;
+            assert!(!ingested_events.is_empty());
+            for ingested_event in ingested_events {
+                assert_eq!(
+                    ingested_event.get_inserted_at(),
+                    started_at + chrono::Duration::seconds(60)
+                );
+            }
         })
         .await;
     }
@@ -75,17 +127,11 @@ mod tests {
                 }
             ));
 
+            let config = config_with_contracts(contracts.clone())
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1);
             let conn = Arc::new(Mutex::new(conn));
-            EventsIngester::ingest(
-                conn,
-                &contracts,
-                10,
-                json_rpc,
-                &Chain::Mainnet,
-                &MinConfirmationCount::new(1),
-            )
-            .await
-            .unwrap();
+            EventsIngester::ingest(conn, json_rpc, &Chain::Mainnet, &config).await.unwrap();
         })
         .await;
     }
@@ -106,17 +152,13 @@ mod tests {
 
             let conn = Arc::new(Mutex::new(conn));
             let blocks_per_batch = 10;
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(blocks_per_batch)
+                .with_min_confirmation_count(1);
 
-            EventsIngester::ingest(
-                conn.clone(),
-                &contracts,
-                blocks_per_batch,
-                json_rpc,
-                &Chain::Mainnet,
-                &MinConfirmationCount::new(1),
-            )
-            .await
-            .unwrap();
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
 
             let mut conn = conn.lock().await;
             let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
@@ -131,60 +173,2245 @@ mod tests {
         .await;
     }
 
-    // TODO:
     #[tokio::test]
-    pub async fn continues_from_next_block_number_to_ingest_from() {}
+    pub async fn pauses_ingestion_while_database_writes_are_slow() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contracts = vec![bayc_contract()];
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let json_rpc = Arc::new(json_rpc_with_served_logs(
+                BAYC_CONTRACT_START_BLOCK_NUMBER as u64 + 40,
+                vec![],
+            ));
+            let conn = Arc::new(Mutex::new(conn));
+            let blocks_per_batch = 10;
+            // Any actual write is slower than allowed
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(blocks_per_batch)
+                .with_min_confirmation_count(1)
+                .with_initial_sync_parallelism(2)
+                .with_ingestion_checkpoint_interval(blocks_per_batch + 1)
+                .with_max_write_latency(Duration::ZERO, Duration::from_secs(3600));
+
+            // Remaining checkpointed batches and later passes get skipped
+            for _tick in 0..2 {
+                EventsIngester::ingest(conn.clone(), json_rpc.clone(), &Chain::Mainnet, &config)
+                    .await
+                    .unwrap();
+            }
+
+            let mut conn = conn.lock().await;
+            let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+            assert_eq!(
+                contract_addresses.first().unwrap().next_block_number_to_ingest_from as u64,
+                BAYC_CONTRACT_START_BLOCK_NUMBER as u64 + blocks_per_batch + 1
+            );
+        })
+        .await;
+    }
 
     #[tokio::test]
-    pub async fn does_nothing_when_there_are_no_contracts() {
+    pub async fn ingests_contracts_starting_at_the_genesis_block() {
         let pool = test_runner::get_pool().await;
 
-        test_runner::run_test(&pool, |conn| async move {
-            let contracts = vec![];
-            let json_rpc = Arc::new(empty_json_rpc());
+        test_runner::run_test(&pool, |mut conn| async move {
+            static FETCHED_BLOCK_RANGES: StdMutex<Vec<(u64, u64)>> = StdMutex::new(Vec::new());
+
+            let contracts = vec![Contract::new("BoredApeYachtClub")
+                .add_event(TRANSFER_EVENT_ABI, TransferTestEventHandler)
+                .add_address(BAYC_CONTRACT_ADDRESS, &Chain::Mainnet, 0)];
+            let json_rpc = Arc::new(json_rpc_with_filter_stubber!(
+                BAYC_CONTRACT_ADDRESS,
+                20,
+                |filter: &Filter| {
+                    FETCHED_BLOCK_RANGES.lock().unwrap().push((
+                        filter.get_from_block().unwrap().as_u64(),
+                        filter.get_to_block().unwrap().as_u64(),
+                    ));
+                }
+            ));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1);
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            let mut fetched_block_ranges = FETCHED_BLOCK_RANGES.lock().unwrap().clone();
+            fetched_block_ranges.sort();
+            fetched_block_ranges.dedup();
+            assert_eq!(fetched_block_ranges, vec![(0, 10)]);
+
+            let mut conn = conn.lock().await;
+            let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+            assert_eq!(
+                contract_addresses.first().unwrap().next_block_number_to_ingest_from,
+                11
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn clamps_confirmation_windows_wider_than_the_current_height() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static FETCHED_BLOCK_RANGES: StdMutex<Vec<(u64, u64)>> = StdMutex::new(Vec::new());
+
+            let contracts = vec![Contract::new("BoredApeYachtClub")
+                .add_event(TRANSFER_EVENT_ABI, TransferTestEventHandler)
+                .add_address(BAYC_CONTRACT_ADDRESS, &Chain::Mainnet, 0)];
+            let json_rpc = Arc::new(json_rpc_with_filter_stubber!(
+                BAYC_CONTRACT_ADDRESS,
+                5,
+                |filter: &Filter| {
+                    FETCHED_BLOCK_RANGES.lock().unwrap().push((
+                        filter.get_from_block().unwrap().as_u64(),
+                        filter.get_to_block().unwrap().as_u64(),
+                    ));
+                }
+            ));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(10);
+            let conn = Arc::new(Mutex::new(conn));
+            // Confirms below the ingested blocks on the second tick
+            for _tick in 0..2 {
+                EventsIngester::ingest(conn.clone(), json_rpc.clone(), &Chain::Mainnet, &config)
+                    .await
+                    .unwrap();
+            }
+
+            let mut fetched_block_ranges = FETCHED_BLOCK_RANGES.lock().unwrap().clone();
+            fetched_block_ranges.sort();
+            fetched_block_ranges.dedup();
+            assert_eq!(fetched_block_ranges, vec![(0, 5)]);
+
+            let mut conn = conn.lock().await;
+            let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+            assert_eq!(
+                contract_addresses.first().unwrap().next_block_number_to_ingest_from,
+                6
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn skips_blocked_block_ranges() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+            static FETCHED_BLOCK_RANGES: StdMutex<Vec<(u64, u64)>> = StdMutex::new(Vec::new());
+
+            let contracts = vec![bayc_contract()
+                .add_blocked_block_range(START_BLOCK_NUMBER + 3, START_BLOCK_NUMBER + 5)];
+            let json_rpc = Arc::new(json_rpc_with_filter_stubber!(
+                BAYC_CONTRACT_ADDRESS,
+                START_BLOCK_NUMBER + 20,
+                |filter: &Filter| {
+                    FETCHED_BLOCK_RANGES.lock().unwrap().push((
+                        filter.get_from_block().unwrap().as_u64(),
+                        filter.get_to_block().unwrap().as_u64(),
+                    ));
+                }
+            ));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
             let blocks_per_batch = 10;
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(blocks_per_batch)
+                .with_min_confirmation_count(1);
             let conn = Arc::new(Mutex::new(conn));
-            EventsIngester::ingest(
-                conn.clone(),
-                &contracts,
-                blocks_per_batch,
-                json_rpc,
-                &Chain::Mainnet,
-                &MinConfirmationCount::new(1),
-            )
-            .await
-            .unwrap();
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            let mut fetched_block_ranges = FETCHED_BLOCK_RANGES.lock().unwrap().clone();
+            fetched_block_ranges.sort();
+            fetched_block_ranges.dedup();
+            assert_eq!(
+                fetched_block_ranges,
+                vec![
+                    (START_BLOCK_NUMBER, START_BLOCK_NUMBER + 2),
+                    (
+                        START_BLOCK_NUMBER + 6,
+                        START_BLOCK_NUMBER + blocks_per_batch
+                    )
+                ]
+            );
+
             let mut conn = conn.lock().await;
-            assert!(PostgresRepo::get_all_events(&mut conn).await.is_empty());
+            let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+            let bayc_contract_address = contract_addresses.first().unwrap();
+            assert_eq!(
+                bayc_contract_address.next_block_number_to_ingest_from as u64,
+                START_BLOCK_NUMBER + blocks_per_batch + 1
+            );
         })
         .await;
     }
 
     #[tokio::test]
-    pub async fn does_nothing_when_there_are_no_events_from_contracts() {
+    pub async fn fast_forwards_empty_batches_near_the_current_block() {
         let pool = test_runner::get_pool().await;
 
         test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+            static FETCHED_BLOCK_RANGES: StdMutex<Vec<(u64, u64)>> = StdMutex::new(Vec::new());
+
             let contracts = vec![bayc_contract()];
-            let json_rpc = Arc::new(json_rpc_with_empty_logs!(BAYC_CONTRACT_ADDRESS));
+            let json_rpc = Arc::new(json_rpc_with_filter_stubber!(
+                BAYC_CONTRACT_ADDRESS,
+                START_BLOCK_NUMBER + 100,
+                |filter: &Filter| {
+                    FETCHED_BLOCK_RANGES.lock().unwrap().push((
+                        filter.get_from_block().unwrap().as_u64(),
+                        filter.get_to_block().unwrap().as_u64(),
+                    ));
+                }
+            ));
 
-            assert!(PostgresRepo::get_all_events(&mut conn).await.is_empty());
             Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
 
+            // Without confirmations, only ingestion fetches logs
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(10)
+                .with_chain_min_confirmation_count(&Chain::Mainnet, 0)
+                .with_fast_forward_window(90);
             let conn = Arc::new(Mutex::new(conn));
-            EventsIngester::ingest(
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            assert_eq!(
+                *FETCHED_BLOCK_RANGES.lock().unwrap(),
+                vec![
+                    (START_BLOCK_NUMBER, START_BLOCK_NUMBER + 10),
+                    (START_BLOCK_NUMBER + 11, START_BLOCK_NUMBER + 100)
+                ]
+            );
+
+            let mut conn = conn.lock().await;
+            let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+            let bayc_contract_address = contract_addresses.first().unwrap();
+            assert_eq!(
+                bayc_contract_address.next_block_number_to_ingest_from as u64,
+                START_BLOCK_NUMBER + 101
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn resumes_parallel_batches_from_their_last_checkpoint_after_crashing() {
+        use futures_util::FutureExt;
+        use std::panic::AssertUnwindSafe;
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+            static SHOULD_CRASH: AtomicBool = AtomicBool::new(true);
+            static FETCHED_BLOCK_RANGES: StdMutex<Vec<(u64, u64)>> = StdMutex::new(Vec::new());
+
+            let contracts = vec![bayc_contract()];
+            let json_rpc = Arc::new(json_rpc_with_filter_stubber!(
+                BAYC_CONTRACT_ADDRESS,
+                START_BLOCK_NUMBER + 100,
+                |filter: &Filter| {
+                    let from_block_number = filter.get_from_block().unwrap().as_u64();
+                    let to_block_number = filter.get_to_block().unwrap().as_u64();
+
+                    // Crashes after the first checkpoint
+                    if SHOULD_CRASH.load(Ordering::SeqCst)
+                        && from_block_number >= START_BLOCK_NUMBER + 22
+                    {
+                        panic!("Crashed fetching logs");
+                    }
+
+                    FETCHED_BLOCK_RANGES.lock().unwrap().push((from_block_number, to_block_number));
+                }
+            ));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            // Four batches of 11 blocks per pass, checkpointed every two
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(10)
+                .with_initial_sync_parallelism(4)
+                .with_ingestion_checkpoint_interval(22)
+                .with_chain_min_confirmation_count(&Chain::Mainnet, 0);
+            let conn = Arc::new(Mutex::new(conn));
+
+            let crashed_ingestion = AssertUnwindSafe(EventsIngester::ingest(
                 conn.clone(),
-                &contracts,
-                10,
-                json_rpc,
+                json_rpc.clone(),
                 &Chain::Mainnet,
-                &MinConfirmationCount::new(1),
-            )
-            .await
-            .unwrap();
+                &config,
+            ))
+            .catch_unwind()
+            .await;
+            assert!(crashed_ingestion.is_err());
 
-            let mut conn = conn.lock().await;
-            assert!(PostgresRepo::get_all_events(&mut conn).await.is_empty());
+            let contract_addresses =
+                PostgresRepo::get_all_contract_addresses(&mut *conn.lock().await).await;
+            assert_eq!(
+                contract_addresses.first().unwrap().next_block_number_to_ingest_from as u64,
+                START_BLOCK_NUMBER + 22
+            );
+
+            SHOULD_CRASH.store(false, Ordering::SeqCst);
+            FETCHED_BLOCK_RANGES.lock().unwrap().clear();
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            let mut fetched_block_ranges = FETCHED_BLOCK_RANGES.lock().unwrap().clone();
+            fetched_block_ranges.sort();
+            assert_eq!(
+                fetched_block_ranges.first(),
+                Some(&(START_BLOCK_NUMBER + 22, START_BLOCK_NUMBER + 32))
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn never_ingests_beyond_the_max_blocks_per_tick() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+            static FETCHED_BLOCK_RANGES: StdMutex<Vec<(u64, u64)>> = StdMutex::new(Vec::new());
+
+            let contracts = vec![bayc_contract()];
+            let json_rpc = Arc::new(json_rpc_with_filter_stubber!(
+                BAYC_CONTRACT_ADDRESS,
+                START_BLOCK_NUMBER + 100,
+                |filter: &Filter| {
+                    FETCHED_BLOCK_RANGES.lock().unwrap().push((
+                        filter.get_from_block().unwrap().as_u64(),
+                        filter.get_to_block().unwrap().as_u64(),
+                    ));
+                }
+            ));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            // Four batches of 11 blocks would otherwise get ingested per tick
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(10)
+                .with_initial_sync_parallelism(4)
+                .with_max_blocks_per_tick(25)
+                .with_chain_min_confirmation_count(&Chain::Mainnet, 0);
+            let conn = Arc::new(Mutex::new(conn));
+
+            for tick in 1..=2 {
+                FETCHED_BLOCK_RANGES.lock().unwrap().clear();
+                EventsIngester::ingest(conn.clone(), json_rpc.clone(), &Chain::Mainnet, &config)
+                    .await
+                    .unwrap();
+
+                let fetched_blocks_count: u64 = FETCHED_BLOCK_RANGES
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|(from, to)| to - from + 1)
+                    .sum();
+                assert_eq!(fetched_blocks_count, 25);
+
+                // The remaining blocks carry over to the next tick
+                let contract_addresses =
+                    PostgresRepo::get_all_contract_addresses(&mut *conn.lock().await).await;
+                assert_eq!(
+                    contract_addresses.first().unwrap().next_block_number_to_ingest_from as u64,
+                    START_BLOCK_NUMBER + 25 * tick
+                );
+            }
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn splits_the_max_blocks_per_tick_fairly_between_contract_addresses() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+            static FETCHED_BLOCKS_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+            let contracts = vec![bayc_contract().add_address(
+                "0x0000000000000000000000000000000000000001",
+                &Chain::Mainnet,
+                START_BLOCK_NUMBER as i64,
+            )];
+            let json_rpc = Arc::new(json_rpc_with_filter_stubber!(
+                BAYC_CONTRACT_ADDRESS,
+                START_BLOCK_NUMBER + 100,
+                |filter: &Filter| {
+                    let blocks_count = filter.get_to_block().unwrap().as_u64()
+                        - filter.get_from_block().unwrap().as_u64()
+                        + 1;
+                    FETCHED_BLOCKS_COUNT.fetch_add(blocks_count as usize, Ordering::SeqCst);
+                }
+            ));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            // The first contract address alone would otherwise spend it all
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(10)
+                .with_initial_sync_parallelism(4)
+                .with_max_blocks_per_tick(25)
+                .with_chain_min_confirmation_count(&Chain::Mainnet, 0);
+            let conn = Arc::new(Mutex::new(conn));
+
+            EventsIngester::ingest(conn.clone(), json_rpc.clone(), &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            assert_eq!(FETCHED_BLOCKS_COUNT.load(Ordering::SeqCst), 25);
+
+            let contract_addresses =
+                PostgresRepo::get_all_contract_addresses(&mut *conn.lock().await).await;
+            let mut ingested_blocks_counts: Vec<_> = contract_addresses
+                .iter()
+                .map(|contract_address| {
+                    contract_address.next_block_number_to_ingest_from as u64 - START_BLOCK_NUMBER
+                })
+                .collect();
+            ingested_blocks_counts.sort();
+            assert_eq!(ingested_blocks_counts, vec![12, 13]);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn never_persists_events_dropped_before_persisting_them() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contracts = vec![bayc_contract()];
+            static CURRENT_BLOCK_NUMBER: u32 = BAYC_CONTRACT_START_BLOCK_NUMBER + 40;
+            // One log at the start of each batch
+            let json_rpc = Arc::new(json_rpc_with_logs!(
+                BAYC_CONTRACT_ADDRESS,
+                CURRENT_BLOCK_NUMBER
+            ));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let is_spam = |event: &Event| event.block_number % 2 == 1;
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(10)
+                .with_initial_sync_parallelism(4)
+                .with_chain_min_confirmation_count(&Chain::Mainnet, 0)
+                .with_on_events_ingested(move |events| events.retain(|event| !is_spam(event)));
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            let ingested_events = PostgresRepo::get_all_events(&mut *conn.lock().await).await;
+            assert_eq!(ingested_events.len(), 2);
+            assert!(ingested_events.iter().all(|event| !is_spam(event)));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn ingests_consecutive_batches_in_parallel_without_gaps() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+            static FETCHED_BLOCK_RANGES: StdMutex<Vec<(u64, u64)>> = StdMutex::new(Vec::new());
+
+            let contracts = vec![bayc_contract()];
+            let json_rpc = Arc::new(json_rpc_with_filter_stubber!(
+                BAYC_CONTRACT_ADDRESS,
+                START_BLOCK_NUMBER + 100,
+                |filter: &Filter| {
+                    FETCHED_BLOCK_RANGES.lock().unwrap().push((
+                        filter.get_from_block().unwrap().as_u64(),
+                        filter.get_to_block().unwrap().as_u64(),
+                    ));
+                }
+            ));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(9)
+                .with_initial_sync_parallelism(4)
+                .with_min_confirmation_count(1);
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            let mut fetched_block_ranges = FETCHED_BLOCK_RANGES.lock().unwrap().clone();
+            fetched_block_ranges.sort();
+            fetched_block_ranges.dedup();
+            assert_eq!(
+                fetched_block_ranges,
+                vec![
+                    (START_BLOCK_NUMBER, START_BLOCK_NUMBER + 9),
+                    (START_BLOCK_NUMBER + 10, START_BLOCK_NUMBER + 19),
+                    (START_BLOCK_NUMBER + 20, START_BLOCK_NUMBER + 29),
+                    (START_BLOCK_NUMBER + 30, START_BLOCK_NUMBER + 39),
+                ]
+            );
+
+            let mut conn = conn.lock().await;
+            let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+            let bayc_contract_address = contract_addresses.first().unwrap();
+            assert_eq!(
+                bayc_contract_address.next_block_number_to_ingest_from as u64,
+                START_BLOCK_NUMBER + 40
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn drops_logs_outside_of_requested_block_ranges() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let start_block_number = BAYC_CONTRACT_START_BLOCK_NUMBER as i64;
+            let contracts = vec![bayc_contract()];
+            let json_rpc = Arc::new(json_rpc_with_stray_logs(
+                BAYC_CONTRACT_ADDRESS,
+                start_block_number as u64 + 20,
+            ));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1);
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            let mut conn = conn.lock().await;
+            let ingested_events = PostgresRepo::get_all_events(&mut conn).await;
+            assert_eq!(ingested_events.len(), 1);
+            assert_eq!(
+                ingested_events.first().unwrap().block_number,
+                start_block_number
+            );
+
+            // The confirmation diff sees the same, in-range events
+            assert!(PostgresRepo::get_unhandled_reorged_blocks(&mut conn).await.is_empty());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn does_not_backtrack_events_of_the_same_address_on_other_chains() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+
+            let contracts = vec![bayc_contract()];
+            let json_rpc = Arc::new(json_rpc_with_filter_stubber!(
+                BAYC_CONTRACT_ADDRESS,
+                START_BLOCK_NUMBER + 20,
+                |_filter: &Filter| {}
+            ));
+
+            // Same address, deployed on another chain, with an event within
+            // the range Mainnet's reorgs get checked over
+            let mut polygon_event = transfer_event_with_contract(bayc_contract());
+            polygon_event.chain_id = Chain::Polygon as i32;
+            polygon_event.block_number = START_BLOCK_NUMBER as i64 + 5;
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            ChaindexingRepo::create_events(&mut conn, &vec![polygon_event.clone()]).await;
+
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1);
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            let mut conn = conn.lock().await;
+            let events = PostgresRepo::get_all_events(&mut conn).await;
+            assert_eq!(events.len(), 1);
+            assert_eq!(events.first().unwrap().id, polygon_event.id);
+            assert!(PostgresRepo::get_unhandled_reorged_blocks(&mut conn).await.is_empty());
+        })
+        .await;
+    }
+
+    static FILTERED_ADDRESSES: StdMutex<Vec<Option<ValueOrArray<Address>>>> =
+        StdMutex::new(Vec::new());
+
+    #[tokio::test]
+    pub async fn never_filters_single_chain_contracts_on_other_chains() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            const DOODLES_CONTRACT_ADDRESS: &str = "0x8a90CAb2b38dba80c64b7734e58Ee1dB38B8992e";
+
+            let mainnet_contract = bayc_contract().with_chains(&[Chain::Mainnet]);
+            let polygon_contract = Contract::new("Doodles")
+                .add_event(TRANSFER_EVENT_ABI, TransferTestEventHandler)
+                .add_address(
+                    DOODLES_CONTRACT_ADDRESS,
+                    &Chain::Polygon,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                )
+                .with_chains(&[Chain::Polygon]);
+            let contracts = vec![mainnet_contract, polygon_contract];
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let config = config_with_contracts(contracts.clone()).with_blocks_per_batch(10);
+            let json_rpc = Arc::new(json_rpc_with_filter_stubber!(
+                BAYC_CONTRACT_ADDRESS,
+                BAYC_CONTRACT_START_BLOCK_NUMBER + 20,
+                |filter: &Filter| {
+                    FILTERED_ADDRESSES.lock().unwrap().push(filter.address.clone());
+                }
+            ));
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Polygon, &config)
+                .await
+                .unwrap();
+
+            let filtered_addresses = FILTERED_ADDRESSES.lock().unwrap();
+            assert!(!filtered_addresses.is_empty());
+            assert!(filtered_addresses.iter().all(|address| {
+                *address
+                    == Some(ValueOrArray::Value(
+                        DOODLES_CONTRACT_ADDRESS.parse::<Address>().unwrap(),
+                    ))
+            }));
+        })
+        .await;
+    }
+
+    #[test]
+    #[should_panic(expected = "does not run on chain")]
+    pub fn rejects_contract_addresses_on_chains_their_contracts_do_not_run_on() {
+        let contract = bayc_contract().with_chains(&[Chain::Polygon]);
+
+        config_with_contracts(vec![contract]);
+    }
+
+    #[tokio::test]
+    pub async fn hard_deletes_events_removed_by_reorgs_by_default() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |conn| async move {
+            let config = config_with_contracts(vec![bayc_contract()])
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1);
+            let (conn, reorged_event) = ingest_with_reorged_event(conn, &config).await;
+
+            let mut conn = conn.lock().await;
+
+            let events = PostgresRepo::get_all_events(&mut conn).await;
+            assert!(events.iter().all(|e| e.id != reorged_event.id));
+            assert_eq!(
+                PostgresRepo::get_unhandled_reorged_blocks(&mut conn).await.len(),
+                1
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn soft_deletes_events_removed_by_reorgs() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |conn| async move {
+            let config = config_with_contracts(vec![bayc_contract()])
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1)
+                .with_soft_delete_removed_events(true);
+            let (conn, reorged_event) = ingest_with_reorged_event(conn, &config).await;
+
+            let mut conn = conn.lock().await;
+
+            let events = PostgresRepo::get_all_events(&mut conn).await;
+            let soft_deleted_event = events.iter().find(|e| e.id == reorged_event.id).unwrap();
+            assert!(!soft_deleted_event.not_removed());
+
+            let reorged_blocks = PostgresRepo::get_unhandled_reorged_blocks(&mut conn).await;
+            assert_eq!(reorged_blocks.len(), 1);
+            assert_eq!(
+                soft_deleted_event.reorged_block_id,
+                Some(reorged_blocks.first().unwrap().id)
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn detects_reorgs_served_as_stale_block_ranges_by_block_hash() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |conn| async move {
+            let (events, reorged_blocks) =
+                confirm_stale_event_by_block_hash(conn, true, true).await;
+
+            // Its block hash is no longer the canonical one
+            assert!(events.is_empty());
+            assert_eq!(reorged_blocks.len(), 1);
+            assert_eq!(
+                reorged_blocks.first().unwrap().block_number,
+                BAYC_CONTRACT_START_BLOCK_NUMBER as i64 + 10
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn confirms_stale_block_ranges_unless_confirming_by_block_hash() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |conn| async move {
+            let (events, reorged_blocks) =
+                confirm_stale_event_by_block_hash(conn, true, false).await;
+
+            // The stale block number range looks confirmed
+            assert_eq!(events.len(), 1);
+            assert!(reorged_blocks.is_empty());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn removes_events_of_blocks_unknown_to_the_json_rpc_by_block_hash() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |conn| async move {
+            let (events, reorged_blocks) =
+                confirm_stale_event_by_block_hash(conn, false, true).await;
+
+            assert!(events.is_empty());
+            assert_eq!(reorged_blocks.len(), 1);
+        })
+        .await;
+    }
+
+    /// Confirms an already ingested event the JSON RPC still serves, though
+    /// from a reorged-out block, returning the events and reorged blocks left
+    async fn confirm_stale_event_by_block_hash<'a>(
+        mut conn: ChaindexingRepoConn<'a>,
+        knows_stale_block_number: bool,
+        confirm_by_block_hash: bool,
+    ) -> (Vec<Event>, Vec<ReorgedBlock>) {
+        static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+
+        let contracts = vec![bayc_contract()];
+        let json_rpc = Arc::new(json_rpc_with_stale_logs(
+            BAYC_CONTRACT_ADDRESS,
+            START_BLOCK_NUMBER + 20,
+            START_BLOCK_NUMBER + 10,
+            knows_stale_block_number,
+        ));
+
+        let mut stale_event = transfer_event_with_contract(bayc_contract());
+        stale_event.block_number = START_BLOCK_NUMBER as i64 + 10;
+
+        Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+        ChaindexingRepo::create_events(&mut conn, &vec![stale_event.clone()]).await;
+
+        // Only the confirmation pass covers the stale event's block
+        let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+        ChaindexingRepo::update_next_block_number_to_ingest_from(
+            &mut conn,
+            contract_addresses.first().unwrap(),
+            stale_event.block_number + 1,
+        )
+        .await;
+
+        let config = config_with_contracts(contracts)
+            .with_blocks_per_batch(10)
+            .with_min_confirmation_count(1)
+            .with_confirm_by_block_hash(confirm_by_block_hash);
+        let conn = Arc::new(Mutex::new(conn));
+        EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+            .await
+            .unwrap();
+
+        let mut conn = conn.lock().await;
+        (
+            PostgresRepo::get_all_events(&mut conn).await,
+            PostgresRepo::get_unhandled_reorged_blocks(&mut conn).await,
+        )
+    }
+
+    #[tokio::test]
+    pub async fn skips_confirming_events_of_instant_finality_chains() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |conn| async move {
+            let config = config_with_contracts(vec![bayc_contract()])
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1)
+                .with_chain_min_confirmation_count(&Chain::Mainnet, 0);
+            let (conn, unconfirmed_event) = ingest_with_reorged_event(conn, &config).await;
+
+            let mut conn = conn.lock().await;
+
+            let events = PostgresRepo::get_all_events(&mut conn).await;
+            assert!(events.iter().any(|e| e.id == unconfirmed_event.id));
+            assert!(PostgresRepo::get_unhandled_reorged_blocks(&mut conn).await.is_empty());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn confirms_events_over_each_contract_min_confirmation_count() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            const DOODLES_CONTRACT_ADDRESS: &str = "0x8a90CAb2b38dba80c64b7734e58Ee1dB38B8992e";
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+
+            let json_rpc = Arc::new(json_rpc_with_filter_stubber!(
+                BAYC_CONTRACT_ADDRESS,
+                START_BLOCK_NUMBER + 20,
+                |_filter: &Filter| {}
+            ));
+
+            // Both on Mainnet, with only BAYC confirming deep enough to
+            // notice its event is gone
+            let bayc_contract = bayc_contract().with_min_confirmation_count(5);
+            let doodles_contract = Contract::new("Doodles")
+                .add_event(TRANSFER_EVENT_ABI, TransferTestEventHandler)
+                .add_address(
+                    DOODLES_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![bayc_contract.clone(), doodles_contract.clone()];
+
+            let mut bayc_event = transfer_event_with_contract(bayc_contract);
+            let mut doodles_event =
+                transfer_event_with_contract_address(doodles_contract, DOODLES_CONTRACT_ADDRESS);
+            for (log_index, event) in [&mut bayc_event, &mut doodles_event].into_iter().enumerate()
+            {
+                event.block_number = START_BLOCK_NUMBER as i64 + 8;
+                event.log_index = log_index as i64;
+            }
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            ChaindexingRepo::create_events(
+                &mut conn,
+                &vec![bayc_event.clone(), doodles_event.clone()],
+            )
+            .await;
+            for contract_address in PostgresRepo::get_all_contract_addresses(&mut conn).await {
+                ChaindexingRepo::update_next_block_number_to_ingest_from(
+                    &mut conn,
+                    &contract_address,
+                    START_BLOCK_NUMBER as i64 + 11,
+                )
+                .await;
+            }
+
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1);
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            let mut conn = conn.lock().await;
+            let events = PostgresRepo::get_all_events(&mut conn).await;
+            assert!(events.iter().all(|e| e.id != bayc_event.id));
+            assert!(events.iter().any(|e| e.id == doodles_event.id));
+            assert_eq!(
+                PostgresRepo::get_unhandled_reorged_blocks(&mut conn).await.len(),
+                1
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn reports_reorgs_deeper_than_the_confirmation_window() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+
+            let contracts = vec![bayc_contract()];
+            let mut log = transfer_log(BAYC_CONTRACT_ADDRESS);
+            log.block_number = Some((START_BLOCK_NUMBER + 5).into());
+            let blocks_by_tx_hash =
+                HashMap::from([(log.transaction_hash.unwrap(), Block::default())]);
+            let finalized_event =
+                Events::new(&vec![log.clone()], &contracts, &blocks_by_tx_hash).remove(0);
+            let mut reorged_log = log;
+            reorged_log.block_hash = Some(H256::from_low_u64_be(1));
+            let json_rpc = Arc::new(json_rpc_with_served_logs(
+                START_BLOCK_NUMBER + 20,
+                vec![reorged_log],
+            ));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            ChaindexingRepo::create_events(&mut conn, &vec![finalized_event.clone()]).await;
+            // Confirming from 3 blocks back, past the finalized event's block
+            let contract_address =
+                PostgresRepo::get_all_contract_addresses(&mut conn).await.remove(0);
+            ChaindexingRepo::update_next_block_number_to_ingest_from(
+                &mut conn,
+                &contract_address,
+                finalized_event.block_number + 6,
+            )
+            .await;
+
+            let finality_violations = Arc::new(StdMutex::new(vec![]));
+            let reported_finality_violations = finality_violations.clone();
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(3)
+                .with_on_finality_violation(move |finality_violation| {
+                    reported_finality_violations.lock().unwrap().push(finality_violation.clone())
+                });
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            assert_eq!(
+                *finality_violations.lock().unwrap(),
+                vec![FinalityViolation {
+                    chain: Chain::Mainnet,
+                    block_number: finalized_event.block_number,
+                    ingested_block_hash: finalized_event.block_hash.clone(),
+                    json_rpc_block_hash: Some(format!("0x{:064x}", 1)),
+                }]
+            );
+
+            // Left for operators to reconcile
+            let mut conn = conn.lock().await;
+            let events = PostgresRepo::get_all_events(&mut conn).await;
+            assert!(events.iter().any(|e| e.id == finalized_event.id));
+            assert!(PostgresRepo::get_unhandled_reorged_blocks(&mut conn).await.is_empty());
+        })
+        .await;
+    }
+
+    /// Ingests with an already ingested event the JSON RPC no longer returns
+    async fn ingest_with_reorged_event<'a>(
+        mut conn: ChaindexingRepoConn<'a>,
+        config: &Config,
+    ) -> (Arc<Mutex<ChaindexingRepoConn<'a>>>, Event) {
+        static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+
+        let json_rpc = Arc::new(json_rpc_with_filter_stubber!(
+            BAYC_CONTRACT_ADDRESS,
+            START_BLOCK_NUMBER + 20,
+            |_filter: &Filter| {}
+        ));
+
+        let mut reorged_event = transfer_event_with_contract(bayc_contract());
+        reorged_event.block_number = START_BLOCK_NUMBER as i64 + 5;
+
+        Chaindexing::create_initial_contract_addresses(&mut conn, &config.contracts).await;
+        ChaindexingRepo::create_events(&mut conn, &vec![reorged_event.clone()]).await;
+
+        let conn = Arc::new(Mutex::new(conn));
+        EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, config)
+            .await
+            .unwrap();
+
+        (conn, reorged_event)
+    }
+
+    // TODO:
+    #[tokio::test]
+    pub async fn continues_from_next_block_number_to_ingest_from() {}
+
+    #[tokio::test]
+    pub async fn does_nothing_when_there_are_no_contracts() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |conn| async move {
+            let contracts = vec![];
+            let json_rpc = Arc::new(empty_json_rpc());
+            let config = config_with_contracts(contracts.clone())
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1);
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+            let mut conn = conn.lock().await;
+            assert!(PostgresRepo::get_all_events(&mut conn).await.is_empty());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn does_nothing_when_there_are_no_events_from_contracts() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contracts = vec![bayc_contract()];
+            let json_rpc = Arc::new(json_rpc_with_empty_logs!(BAYC_CONTRACT_ADDRESS));
+
+            assert!(PostgresRepo::get_all_events(&mut conn).await.is_empty());
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let config = config_with_contracts(contracts.clone())
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1);
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            let mut conn = conn.lock().await;
+            assert!(PostgresRepo::get_all_events(&mut conn).await.is_empty());
+            // Confirming no events against no events is no reorg
+            assert!(PostgresRepo::get_unhandled_reorged_blocks(&mut conn).await.is_empty());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn stores_raw_logs_to_decode_events_again() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contracts = vec![bayc_contract()];
+            static CURRENT_BLOCK_NUMBER: u32 = BAYC_CONTRACT_START_BLOCK_NUMBER + 20;
+            let json_rpc = Arc::new(json_rpc_with_logs!(
+                BAYC_CONTRACT_ADDRESS,
+                CURRENT_BLOCK_NUMBER
+            ));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1)
+                .with_store_raw_logs(true);
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            let mut conn = conn.lock().await;
+            let ingested_events = PostgresRepo::get_all_events(&mut conn).await;
+            let ingested_event = ingested_events.first().unwrap();
+            let raw_log = ingested_event.get_raw_log().unwrap();
+            assert_eq!(raw_log.topics, transfer_log(BAYC_CONTRACT_ADDRESS).topics);
+
+            let decoded_log =
+                ContractEvent::new(TRANSFER_EVENT_ABI).value.parse_log(raw_log).unwrap();
+            let ingested_params = ingested_event.get_params();
+            for param in decoded_log.params {
+                assert_eq!(ingested_params.get(&param.name), Some(&param.value));
+            }
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn redecodes_stored_events_with_a_fixed_abi() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            // Same topic as the fixed ABI, with a misnamed param
+            const WRONG_TRANSFER_EVENT_ABI: &str =
+                "event Transfer(address indexed from, address indexed to, uint256 indexed amount)";
+            let wrong_bayc_contract = Contract::new("BoredApeYachtClub")
+                .add_event(WRONG_TRANSFER_EVENT_ABI, TransferTestEventHandler)
+                .add_address(
+                    BAYC_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![wrong_bayc_contract];
+            static CURRENT_BLOCK_NUMBER: u32 = BAYC_CONTRACT_START_BLOCK_NUMBER + 20;
+            let json_rpc = Arc::new(json_rpc_with_logs!(
+                BAYC_CONTRACT_ADDRESS,
+                CURRENT_BLOCK_NUMBER
+            ));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1)
+                .with_store_raw_logs(true);
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            let mut conn = conn.lock().await;
+            let ingested_events = PostgresRepo::get_all_events(&mut conn).await;
+            assert!(!ingested_events.is_empty());
+            assert!(ingested_events.iter().all(|e| e.get_params().contains_key("amount")));
+
+            let fixed_bayc_contract = Contract::new("BoredApeYachtClub")
+                .add_event(TRANSFER_EVENT_ABI, TransferTestEventHandler);
+            // A page per event
+            Chaindexing::redecode_events_with_conn(&mut conn, &fixed_bayc_contract, 1)
+                .await
+                .unwrap();
+
+            let redecoded_events = PostgresRepo::get_all_events(&mut conn).await;
+            assert_eq!(redecoded_events.len(), ingested_events.len());
+            for redecoded_event in redecoded_events {
+                assert_eq!(redecoded_event.abi, TRANSFER_EVENT_ABI);
+
+                let redecoded_params = redecoded_event.get_params();
+                assert!(redecoded_params.contains_key("tokenId"));
+                assert!(!redecoded_params.contains_key("amount"));
+            }
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn fails_redecoding_events_of_unknown_contracts() {
+        let config = config_with_contracts(vec![bayc_contract()]);
+
+        let redecoding = Chaindexing::redecode_events(&config, "UnknownBoredApeYachtClub").await;
+
+        assert!(matches!(
+            redecoding,
+            Err(RedecodeEventsError::UnknownContract(contract_name))
+                if contract_name == "UnknownBoredApeYachtClub"
+        ));
+    }
+
+    #[tokio::test]
+    pub async fn confirms_redecoded_events_without_reporting_reorgs() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+
+            const WRONG_TRANSFER_EVENT_ABI: &str =
+                "event Transfer(address indexed from, address indexed to, uint256 indexed amount)";
+            let bayc_contract_with_abi = |transfer_event_abi| {
+                Contract::new("BoredApeYachtClub")
+                    .add_event(transfer_event_abi, TransferTestEventHandler)
+                    .add_address(
+                        BAYC_CONTRACT_ADDRESS,
+                        &Chain::Mainnet,
+                        START_BLOCK_NUMBER as i64,
+                    )
+            };
+            let wrong_bayc_contract = bayc_contract_with_abi(WRONG_TRANSFER_EVENT_ABI);
+            let fixed_bayc_contract = bayc_contract_with_abi(TRANSFER_EVENT_ABI);
+
+            // Within the confirmation window of the next ingestion
+            let mut transfer_log = transfer_log(BAYC_CONTRACT_ADDRESS);
+            transfer_log.block_number = Some((START_BLOCK_NUMBER + 15).into());
+            let json_rpc = Arc::new(json_rpc_with_served_logs(
+                START_BLOCK_NUMBER + 20,
+                vec![transfer_log],
+            ));
+
+            Chaindexing::create_initial_contract_addresses(
+                &mut conn,
+                &vec![wrong_bayc_contract.clone()],
+            )
+            .await;
+
+            let config_with_contract = |contract| {
+                config_with_contracts(vec![contract])
+                    .with_blocks_per_batch(50)
+                    .with_min_confirmation_count(10)
+                    .with_store_raw_logs(true)
+            };
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(
+                conn.clone(),
+                json_rpc.clone(),
+                &Chain::Mainnet,
+                &config_with_contract(wrong_bayc_contract),
+            )
+            .await
+            .unwrap();
+
+            {
+                let mut conn = conn.lock().await;
+                Chaindexing::redecode_events_with_conn(
+                    &mut conn,
+                    &fixed_bayc_contract,
+                    REDECODED_EVENTS_PAGE_SIZE,
+                )
+                .await
+                .unwrap();
+            }
+
+            // The JSON RPC's events, decoded with the fixed ABI, match the
+            // redecoded ones
+            EventsIngester::ingest(
+                conn.clone(),
+                json_rpc,
+                &Chain::Mainnet,
+                &config_with_contract(fixed_bayc_contract),
+            )
+            .await
+            .unwrap();
+
+            let mut conn = conn.lock().await;
+            let events = PostgresRepo::get_all_events(&mut conn).await;
+            assert_eq!(events.len(), 1);
+            assert_eq!(events.first().unwrap().abi, TRANSFER_EVENT_ABI);
+            assert!(PostgresRepo::get_unhandled_reorged_blocks(&mut conn).await.is_empty());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn skips_undecodable_logs_when_configured() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+
+            let contracts = vec![bayc_contract()];
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let mut decodable_log = transfer_log(BAYC_CONTRACT_ADDRESS);
+            decodable_log.block_number = Some((START_BLOCK_NUMBER + 1).into());
+            decodable_log.log_index = Some(1.into());
+            // Missing the indexed tokenId topic
+            let mut undecodable_log = decodable_log.clone();
+            undecodable_log.log_index = Some(2.into());
+            undecodable_log.topics.truncate(3);
+
+            let json_rpc = Arc::new(json_rpc_with_served_logs(
+                START_BLOCK_NUMBER + 20,
+                vec![decodable_log, undecodable_log],
+            ));
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1)
+                .with_skip_undecodable_logs(true);
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            let mut conn = conn.lock().await;
+            let ingested_events = PostgresRepo::get_all_events(&mut conn).await;
+            assert_eq!(ingested_events.len(), 1);
+            assert_eq!(ingested_events.first().unwrap().log_index, 1);
+
+            let contract_address = PostgresRepo::get_all_contract_addresses(&mut conn)
+                .await
+                .into_iter()
+                .next()
+                .unwrap();
+            assert!(
+                contract_address.next_block_number_to_ingest_from > START_BLOCK_NUMBER as i64 + 1
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn stores_transaction_statuses_to_identify_reverted_transactions() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contracts = vec![bayc_contract()];
+            static CURRENT_BLOCK_NUMBER: u32 = BAYC_CONTRACT_START_BLOCK_NUMBER + 20;
+            let json_rpc = Arc::new(json_rpc_with_reverted_transaction_logs!(
+                BAYC_CONTRACT_ADDRESS,
+                CURRENT_BLOCK_NUMBER
+            ));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1)
+                .with_fetch_transaction_statuses(true);
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            let mut conn = conn.lock().await;
+            let ingested_events = PostgresRepo::get_all_events(&mut conn).await;
+            assert!(!ingested_events.is_empty());
+            assert!(ingested_events.iter().all(|e| e.transaction_reverted() == Some(true)));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn notifies_once_when_caught_up_to_the_current_block() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+            static CAUGHT_UP_ADDRESSES: StdMutex<Vec<String>> = StdMutex::new(Vec::new());
+
+            let contracts = vec![bayc_contract()];
+            let json_rpc = Arc::new(json_rpc_with_filter_stubber!(
+                BAYC_CONTRACT_ADDRESS,
+                START_BLOCK_NUMBER + 20,
+                |_filter: &Filter| {}
+            ));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1)
+                .with_caught_up_window(5)
+                .with_on_caught_up(|contract_address| {
+                    CAUGHT_UP_ADDRESSES.lock().unwrap().push(contract_address.address.clone());
+                });
+            let conn = Arc::new(Mutex::new(conn));
+
+            // Still backfilling after the first batch
+            EventsIngester::ingest(conn.clone(), json_rpc.clone(), &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+            assert!(CAUGHT_UP_ADDRESSES.lock().unwrap().is_empty());
+
+            // Reaches the current block, then stays at it
+            for _tick in 0..3 {
+                EventsIngester::ingest(conn.clone(), json_rpc.clone(), &Chain::Mainnet, &config)
+                    .await
+                    .unwrap();
+            }
+
+            assert_eq!(
+                *CAUGHT_UP_ADDRESSES.lock().unwrap(),
+                vec![BAYC_CONTRACT_ADDRESS.to_string()]
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn warns_when_the_current_block_regresses_behind_ingested_blocks() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+            static LAGGING_NODES: StdMutex<Vec<LaggingNode>> = StdMutex::new(Vec::new());
+
+            let contracts = vec![bayc_contract()];
+            let json_rpc = Arc::new(json_rpc_with_filter_stubber!(
+                BAYC_CONTRACT_ADDRESS,
+                START_BLOCK_NUMBER + 20,
+                |_filter: &Filter| {}
+            ));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1)
+                .with_on_lagging_node(|lagging_node| {
+                    LAGGING_NODES.lock().unwrap().push(lagging_node.clone());
+                });
+            let conn = Arc::new(Mutex::new(conn));
+
+            // Reaches the current block, then stays at it
+            for _tick in 0..3 {
+                EventsIngester::ingest(conn.clone(), json_rpc.clone(), &Chain::Mainnet, &config)
+                    .await
+                    .unwrap();
+            }
+            assert!(LAGGING_NODES.lock().unwrap().is_empty());
+
+            let lagging_json_rpc = Arc::new(json_rpc_with_filter_stubber!(
+                BAYC_CONTRACT_ADDRESS,
+                START_BLOCK_NUMBER + 10,
+                |_filter: &Filter| {}
+            ));
+            EventsIngester::ingest(conn.clone(), lagging_json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            assert_eq!(
+                *LAGGING_NODES.lock().unwrap(),
+                vec![LaggingNode {
+                    chain: Chain::Mainnet,
+                    current_block_number: BlockNumber::new(START_BLOCK_NUMBER + 10),
+                    last_ingested_block_number: BlockNumber::new(START_BLOCK_NUMBER + 20),
+                }]
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn labels_metrics_with_contract_names_and_addresses() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            const DOODLES_CONTRACT_ADDRESS: &str = "0x8a90CAb2b38dba80c64b7734e58Ee1dB38B8992e";
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+            static METRICS: StdMutex<Vec<Metric>> = StdMutex::new(Vec::new());
+
+            let doodles_contract = Contract::new("Doodles")
+                .add_event(TRANSFER_EVENT_ABI, TransferTestEventHandler)
+                .add_address(
+                    DOODLES_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![bayc_contract(), doodles_contract];
+            let json_rpc = Arc::new(json_rpc_with_filter_stubber!(
+                BAYC_CONTRACT_ADDRESS,
+                START_BLOCK_NUMBER + 20,
+                |_filter: &Filter| {}
+            ));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1)
+                .with_on_metric(|metric| METRICS.lock().unwrap().push(metric.clone()));
+            let conn = Arc::new(Mutex::new(conn));
+
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            let metrics = METRICS.lock().unwrap();
+            for (contract_name, address) in [
+                ("BoredApeYachtClub", BAYC_CONTRACT_ADDRESS),
+                ("Doodles", DOODLES_CONTRACT_ADDRESS),
+            ] {
+                let expected_labels = MetricLabels {
+                    chain_id: Chain::Mainnet as i32,
+                    contract_name: contract_name.to_string(),
+                    address: address.to_lowercase(),
+                };
+
+                for kind in [MetricKind::IngestionLag, MetricKind::EventsIngested] {
+                    assert!(metrics
+                        .iter()
+                        .any(|metric| metric.kind == kind && metric.labels == expected_labels));
+                }
+            }
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn reports_the_timing_of_each_phase_of_ingested_batches() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+            static BATCH_TIMINGS: StdMutex<Vec<BatchTimings>> = StdMutex::new(Vec::new());
+            const JSON_RPC_DELAY: Duration = Duration::from_millis(50);
+
+            let contracts = vec![bayc_contract()];
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let mut log = transfer_log(BAYC_CONTRACT_ADDRESS);
+            log.block_number = Some((START_BLOCK_NUMBER + 1).into());
+            let json_rpc = Arc::new(json_rpc_with_slow_served_logs(
+                START_BLOCK_NUMBER + 20,
+                vec![log],
+                JSON_RPC_DELAY,
+            ));
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1)
+                .with_on_batch_timings(|timings| {
+                    BATCH_TIMINGS.lock().unwrap().push(timings.clone())
+                });
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            let batch_timings = BATCH_TIMINGS.lock().unwrap().clone();
+            let timings = batch_timings.first().unwrap();
+            assert_eq!(timings.chain_id, Chain::Mainnet as i32);
+            assert!(timings.get_logs >= JSON_RPC_DELAY);
+            assert!(timings.get_blocks >= JSON_RPC_DELAY);
+            assert!(timings.db_writes > Duration::ZERO);
+            assert!(timings.get_phases_total() <= timings.total);
+            assert!(timings.get_untracked() < JSON_RPC_DELAY);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn skips_paused_chains_until_resumed() {
+        let chains = [
+            (Chain::Mainnet, "http://mainnet.json-rpc".to_string()),
+            (Chain::Polygon, "http://polygon.json-rpc".to_string()),
+        ]
+        .into();
+        let config = Config::new(test_runner::new_repo(), chains);
+        let running_ingester_config = config.clone();
+
+        config.pause_chain(&Chain::Polygon);
+
+        let unpaused_chains = running_ingester_config.get_unpaused_chains();
+        assert!(unpaused_chains.contains_key(&Chain::Mainnet));
+        assert!(!unpaused_chains.contains_key(&Chain::Polygon));
+
+        config.resume_chain(&Chain::Polygon);
+
+        let unpaused_chains = running_ingester_config.get_unpaused_chains();
+        assert!(unpaused_chains.contains_key(&Chain::Polygon));
+    }
+
+    #[tokio::test]
+    pub async fn skips_chains_with_open_circuits_until_they_recover() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contracts = vec![bayc_contract()];
+            let requests_count = Arc::new(AtomicUsize::new(0));
+            let failing_json_rpc = Arc::new(failing_json_rpc(requests_count.clone()));
+            static CURRENT_BLOCK_NUMBER: u32 = BAYC_CONTRACT_START_BLOCK_NUMBER + 20;
+            let json_rpc = Arc::new(json_rpc_with_logs!(
+                BAYC_CONTRACT_ADDRESS,
+                CURRENT_BLOCK_NUMBER
+            ));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let clock = MockClock::new(DateTime::from_timestamp(0, 0).unwrap().naive_utc());
+            // Without retries, failing ticks do not back off
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(10)
+                .with_min_confirmation_count(1)
+                .with_chain_circuit_breaker(3, Duration::from_secs(60))
+                .with_max_json_rpc_retries(0)
+                .with_clock(clock.clone());
+            let conn = Arc::new(Mutex::new(conn));
+
+            for _ in 0..3 {
+                assert!(EventsIngester::ingest_unless_circuit_open(
+                    conn.clone(),
+                    failing_json_rpc.clone(),
+                    &Chain::Mainnet,
+                    &config,
+                )
+                .await
+                .is_err());
+            }
+            assert_eq!(requests_count.load(Ordering::SeqCst), 3);
+            assert_eq!(
+                config.get_chain_circuit_state(&Chain::Mainnet),
+                ChainCircuitState::Open
+            );
+
+            EventsIngester::ingest_unless_circuit_open(
+                conn.clone(),
+                failing_json_rpc,
+                &Chain::Mainnet,
+                &config,
+            )
+            .await
+            .unwrap();
+            assert_eq!(requests_count.load(Ordering::SeqCst), 3);
+
+            clock.advance(Duration::from_secs(60));
+            assert_eq!(
+                config.get_chain_circuit_state(&Chain::Mainnet),
+                ChainCircuitState::HalfOpen
+            );
+
+            EventsIngester::ingest_unless_circuit_open(
+                conn.clone(),
+                json_rpc,
+                &Chain::Mainnet,
+                &config,
+            )
+            .await
+            .unwrap();
+            assert!(!PostgresRepo::get_all_events(&mut *conn.lock().await).await.is_empty());
+            assert_eq!(
+                config.get_chain_circuit_state(&Chain::Mainnet),
+                ChainCircuitState::Closed
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn fails_ticks_once_json_rpc_requests_ran_out_of_retries() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contracts = vec![bayc_contract()];
+            let requests_count = Arc::new(AtomicUsize::new(0));
+            let json_rpc = Arc::new(failing_json_rpc(requests_count.clone()));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let config = config_with_contracts(contracts).with_max_json_rpc_retries(1);
+            let conn = Arc::new(Mutex::new(conn));
+
+            let ingestion = EventsIngester::ingest(conn, json_rpc, &Chain::Mainnet, &config).await;
+
+            assert!(matches!(
+                ingestion,
+                Err(EventsIngesterError::JsonRpcError(_))
+            ));
+            assert_eq!(requests_count.load(Ordering::SeqCst), 2);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn accepts_blocks_per_batch_within_provider_range_limits() {
+        let json_rpc = json_rpc_with_max_block_range(2_000);
+
+        assert_eq!(BlocksPerBatchProbe::run(&json_rpc, 2_000).await, Ok(()));
+    }
+
+    #[tokio::test]
+    pub async fn shrinks_blocks_per_batch_rejected_by_the_provider() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contracts = vec![bayc_contract()];
+            let json_rpc = Arc::new(json_rpc_with_max_block_range(10));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(100)
+                .with_adaptive_blocks_per_batch(1, 100);
+            let conn = Arc::new(Mutex::new(conn));
+
+            EventsIngester::ingest(conn.clone(), json_rpc.clone(), &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            // Halved from 100 until accepted
+            assert_eq!(config.get_blocks_per_batch(&Chain::Mainnet), 6);
+            // The whole batch still got ingested, only in narrower fetches
+            let contract_address =
+                ChaindexingRepo::get_all_contract_addresses(&mut *conn.lock().await)
+                    .await
+                    .pop()
+                    .unwrap();
+            assert_eq!(
+                contract_address.next_block_number_to_ingest_from,
+                BAYC_CONTRACT_START_BLOCK_NUMBER as i64 + 101
+            );
+
+            for _tick in 0..10 {
+                EventsIngester::ingest(conn.clone(), json_rpc.clone(), &Chain::Mainnet, &config)
+                    .await
+                    .unwrap();
+            }
+
+            // Keeps probing wider batches, but falls back to the accepted widths
+            assert!((6..20).contains(&config.get_blocks_per_batch(&Chain::Mainnet)));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn suggests_a_safe_blocks_per_batch_beyond_provider_range_limits() {
+        let json_rpc = json_rpc_with_max_block_range(2_000);
+
+        assert_eq!(
+            BlocksPerBatchProbe::run(&json_rpc, 8_000).await,
+            Err(BlocksPerBatchError::TooWide {
+                blocks_per_batch: 8_000,
+                safe_blocks_per_batch: 2_000
+            })
+        );
+    }
+
+    #[tokio::test]
+    pub async fn batches_logs_fetches_of_chains_sharing_a_json_rpc() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            const DOODLES_CONTRACT_ADDRESS: &str = "0x8a90CAb2b38dba80c64b7734e58Ee1dB38B8992e";
+
+            let doodles_contract = Contract::new("Doodles")
+                .add_event(TRANSFER_EVENT_ABI, TransferTestEventHandler)
+                .add_address(
+                    DOODLES_CONTRACT_ADDRESS,
+                    &Chain::Polygon,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![bayc_contract(), doodles_contract];
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let json_rpc_url = "http://localhost:8545".to_string();
+            let chains = [
+                (Chain::Mainnet, json_rpc_url.clone()),
+                (Chain::Polygon, json_rpc_url),
+            ];
+            // Without confirmations, logs only get fetched to be ingested
+            let config = contracts
+                .into_iter()
+                .fold(
+                    Config::new(test_runner::new_repo(), chains.into()),
+                    |config, contract| config.add_contract(contract),
+                )
+                .with_blocks_per_batch(10)
+                .with_chain_min_confirmation_count(&Chain::Mainnet, 0)
+                .with_chain_min_confirmation_count(&Chain::Polygon, 0);
+
+            let batched_filters_counts = Arc::new(StdMutex::new(vec![]));
+            let json_rpc = Arc::new(json_rpc_with_batched_logs(
+                BAYC_CONTRACT_START_BLOCK_NUMBER as u64 + 20,
+                batched_filters_counts.clone(),
+            ));
+            let conn = Arc::new(Mutex::new(conn));
+            for chain in [Chain::Mainnet, Chain::Polygon] {
+                EventsIngester::ingest(conn.clone(), json_rpc.clone(), &chain, &config)
+                    .await
+                    .unwrap();
+            }
+
+            // A single call per ingestion pass
+            assert_eq!(batched_filters_counts.lock().unwrap().len(), 2);
+
+            let mut conn = conn.lock().await;
+            let ingested_addresses: Vec<_> = PostgresRepo::get_all_events(&mut conn)
+                .await
+                .into_iter()
+                .map(|event| event.contract_address)
+                .collect();
+            assert!(ingested_addresses.contains(&BAYC_CONTRACT_ADDRESS.to_lowercase()));
+            assert!(ingested_addresses.contains(&DOODLES_CONTRACT_ADDRESS.to_lowercase()));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn coalesces_log_filters_of_contract_addresses_over_the_same_blocks() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            const OTHER_BAYC_CONTRACT_ADDRESS: &str = "0x8a90CAb2b38dba80c64b7734e58Ee1dB38B8992e";
+
+            let contracts = vec![bayc_contract().add_address(
+                OTHER_BAYC_CONTRACT_ADDRESS,
+                &Chain::Mainnet,
+                BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+            )];
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            let conn = Arc::new(Mutex::new(conn));
+
+            let mut batched_filters_counts = vec![];
+            for coalesce_log_filters in [false, true] {
+                let config = config_with_contracts(contracts.clone())
+                    .with_blocks_per_batch(10)
+                    .with_min_confirmation_count(0)
+                    .with_coalesce_log_filters(coalesce_log_filters);
+                let filters_counts = Arc::new(StdMutex::new(vec![]));
+                let json_rpc = Arc::new(json_rpc_with_batched_logs(
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as u64 + 40,
+                    filters_counts.clone(),
+                ));
+                EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                    .await
+                    .unwrap();
+
+                batched_filters_counts.push(filters_counts.lock().unwrap().clone());
+            }
+
+            assert_eq!(batched_filters_counts, vec![vec![2], vec![1]]);
+
+            let mut conn = conn.lock().await;
+            let ingested_addresses: Vec<_> = PostgresRepo::get_all_events(&mut conn)
+                .await
+                .into_iter()
+                .map(|event| (event.block_number, event.contract_address))
+                .collect();
+            let next_batch_block_number = BAYC_CONTRACT_START_BLOCK_NUMBER as i64 + 11;
+            for contract_address in [BAYC_CONTRACT_ADDRESS, OTHER_BAYC_CONTRACT_ADDRESS] {
+                assert!(ingested_addresses
+                    .contains(&(next_batch_block_number, contract_address.to_lowercase())));
+            }
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn backfills_the_exact_block_range_regardless_of_cursors() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+            static FETCHED_BLOCK_RANGES: StdMutex<Vec<(u64, u64)>> = StdMutex::new(Vec::new());
+
+            let contracts = vec![bayc_contract()];
+            let json_rpc = Arc::new(json_rpc_with_filter_stubber!(
+                BAYC_CONTRACT_ADDRESS,
+                START_BLOCK_NUMBER + 200,
+                |filter: &Filter| {
+                    FETCHED_BLOCK_RANGES.lock().unwrap().push((
+                        filter.get_from_block().unwrap().as_u64(),
+                        filter.get_to_block().unwrap().as_u64(),
+                    ));
+                }
+            ));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let config = config_with_contracts(contracts).with_blocks_per_batch(10);
+
+            // Way ahead of the ingestion cursor, still at the start block
+            EventsIngester::backfill_with_conn(
+                &mut conn,
+                json_rpc,
+                &Chain::Mainnet,
+                BlockNumber::new(START_BLOCK_NUMBER + 100),
+                BlockNumber::new(START_BLOCK_NUMBER + 130),
+                &config,
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(
+                *FETCHED_BLOCK_RANGES.lock().unwrap(),
+                vec![
+                    (START_BLOCK_NUMBER + 100, START_BLOCK_NUMBER + 110),
+                    (START_BLOCK_NUMBER + 111, START_BLOCK_NUMBER + 121),
+                    (START_BLOCK_NUMBER + 122, START_BLOCK_NUMBER + 130),
+                ]
+            );
+
+            let contract_address =
+                ChaindexingRepo::get_all_contract_addresses(&mut conn).await.pop().unwrap();
+            assert_eq!(
+                contract_address.next_block_number_to_ingest_from,
+                START_BLOCK_NUMBER as i64
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn backfills_a_single_block() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contracts = vec![bayc_contract()];
+            let log = transfer_log(BAYC_CONTRACT_ADDRESS);
+            let block_number = log.block_number.unwrap().as_u64();
+            let json_rpc = Arc::new(json_rpc_with_served_logs(block_number + 10, vec![log]));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let config = config_with_contracts(contracts).with_blocks_per_batch(10);
+            EventsIngester::backfill_with_conn(
+                &mut conn,
+                json_rpc,
+                &Chain::Mainnet,
+                BlockNumber::new(block_number),
+                BlockNumber::new(block_number),
+                &config,
+            )
+            .await
+            .unwrap();
+
+            let events = PostgresRepo::get_all_events(&mut conn).await;
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0].block_number, block_number as i64);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn reconciles_changed_logs_over_a_block_range_on_demand() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+
+            let contracts = vec![bayc_contract()];
+            let config = config_with_contracts(contracts.clone());
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            let contract_addresses = PostgresRepo::get_all_contract_addresses(&mut conn).await;
+            ChaindexingRepo::update_next_block_number_to_ingest_from(
+                &mut conn,
+                contract_addresses.first().unwrap(),
+                START_BLOCK_NUMBER as i64 + 10,
+            )
+            .await;
+
+            let transfer_log_at = |block_number: u64, log_index: u64, block_hash: u64| {
+                let mut log = transfer_log(BAYC_CONTRACT_ADDRESS);
+                log.block_number = Some(block_number.into());
+                log.log_index = Some(log_index.into());
+                log.block_hash = Some(H256::from_low_u64_be(block_hash));
+                log
+            };
+            let kept_log = transfer_log_at(START_BLOCK_NUMBER + 1, 1, 1);
+            let reorged_log = transfer_log_at(START_BLOCK_NUMBER + 2, 2, 2);
+            let added_log = transfer_log_at(START_BLOCK_NUMBER + 3, 3, 3);
+
+            let blocks_by_tx_hash = HashMap::from([(
+                kept_log.transaction_hash.unwrap(),
+                Block {
+                    ..Default::default()
+                },
+            )]);
+            let ingested_events = Events::new(
+                &vec![kept_log.clone(), reorged_log],
+                &contracts,
+                &blocks_by_tx_hash,
+            );
+            ChaindexingRepo::create_events(&mut conn, &ingested_events).await;
+
+            let json_rpc = Arc::new(json_rpc_with_served_logs(
+                START_BLOCK_NUMBER + 20,
+                vec![kept_log, added_log],
+            ));
+            EventsIngester::reconcile_with_conn(
+                &mut conn,
+                json_rpc,
+                &Chain::Mainnet,
+                BlockNumber::new(START_BLOCK_NUMBER),
+                BlockNumber::new(START_BLOCK_NUMBER + 5),
+                &config,
+            )
+            .await
+            .unwrap();
+
+            let mut reconciled_positions: Vec<_> = PostgresRepo::get_all_events(&mut conn)
+                .await
+                .into_iter()
+                .map(|event| (event.block_number, event.log_index))
+                .collect();
+            reconciled_positions.sort();
+            assert_eq!(
+                reconciled_positions,
+                vec![
+                    (START_BLOCK_NUMBER as i64 + 1, 1),
+                    (START_BLOCK_NUMBER as i64 + 3, 3)
+                ]
+            );
+
+            let reorged_blocks = PostgresRepo::get_unhandled_reorged_blocks(&mut conn).await;
+            assert_eq!(reorged_blocks.len(), 1);
+            assert_eq!(
+                reorged_blocks.first().unwrap().block_number,
+                START_BLOCK_NUMBER as i64 + 2
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn retries_json_rpc_requests_timing_out() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+
+            let contracts = vec![bayc_contract()];
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let mut transfer_log = transfer_log(BAYC_CONTRACT_ADDRESS);
+            transfer_log.block_number = Some((START_BLOCK_NUMBER + 1).into());
+            let get_logs_calls_count = Arc::new(AtomicUsize::new(0));
+            let json_rpc = Arc::new(json_rpc_with_hanging_logs(
+                START_BLOCK_NUMBER + 20,
+                vec![transfer_log],
+                get_logs_calls_count.clone(),
+            ));
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(200)
+                .with_min_confirmation_count(1)
+                .with_json_rpc_timeout(Duration::from_millis(50));
+            let conn = Arc::new(Mutex::new(conn));
+
+            // Would never return without the timeout firing on the hung request
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            assert!(get_logs_calls_count.load(Ordering::SeqCst) >= 2);
+            let mut conn = conn.lock().await;
+            assert_eq!(PostgresRepo::get_all_events(&mut conn).await.len(), 1);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn only_ingests_events_of_blocks_matching_the_block_filter() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+
+            let contracts = vec![bayc_contract()];
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let logs = (1..=4)
+                .map(|block_offset| {
+                    let block_number = START_BLOCK_NUMBER + block_offset;
+                    let mut transfer_log = transfer_log(BAYC_CONTRACT_ADDRESS);
+                    transfer_log.block_number = Some(block_number.into());
+                    transfer_log.transaction_hash = Some(H256::from_low_u64_be(block_number));
+
+                    transfer_log
+                })
+                .collect();
+            let json_rpc = Arc::new(json_rpc_with_served_logs(START_BLOCK_NUMBER + 20, logs));
+            // Samples every other block
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(200)
+                .with_min_confirmation_count(1)
+                .with_block_filter(|block| block.number.unwrap().as_u64() % 2 == 0);
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            let mut conn = conn.lock().await;
+            let mut ingested_block_numbers: Vec<_> = PostgresRepo::get_all_events(&mut conn)
+                .await
+                .iter()
+                .map(|event| event.block_number as u64)
+                .collect();
+            ingested_block_numbers.sort();
+            let even_block_numbers: Vec<_> = (1..=4)
+                .map(|block_offset| START_BLOCK_NUMBER + block_offset)
+                .filter(|block_number| block_number % 2 == 0)
+                .collect();
+            assert_eq!(ingested_block_numbers, even_block_numbers);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn halts_ingestion_at_the_end_block() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+            static END_BLOCK_NUMBER: u64 = START_BLOCK_NUMBER + 2;
+
+            let contracts = vec![bayc_contract()];
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let logs = (1..=6)
+                .map(|block_offset| {
+                    let block_number = START_BLOCK_NUMBER + block_offset;
+                    let mut transfer_log = transfer_log(BAYC_CONTRACT_ADDRESS);
+                    transfer_log.block_number = Some(block_number.into());
+                    transfer_log.transaction_hash = Some(H256::from_low_u64_be(block_number));
+
+                    transfer_log
+                })
+                .collect();
+            let json_rpc = Arc::new(json_rpc_with_served_logs(START_BLOCK_NUMBER + 20, logs));
+            // Batches of 2 blocks, so the first one stops right before the end block
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(1)
+                .with_min_confirmation_count(1)
+                .with_end_block(END_BLOCK_NUMBER);
+            let conn = Arc::new(Mutex::new(conn));
+
+            for _tick in 0..4 {
+                EventsIngester::ingest(conn.clone(), json_rpc.clone(), &Chain::Mainnet, &config)
+                    .await
+                    .unwrap();
+            }
+
+            let mut conn = conn.lock().await;
+            let mut ingested_block_numbers: Vec<_> = PostgresRepo::get_all_events(&mut conn)
+                .await
+                .iter()
+                .map(|event| event.block_number as u64)
+                .collect();
+            ingested_block_numbers.sort();
+            assert_eq!(
+                ingested_block_numbers,
+                vec![START_BLOCK_NUMBER + 1, END_BLOCK_NUMBER]
+            );
+
+            let contract_address =
+                PostgresRepo::get_all_contract_addresses(&mut conn).await.pop().unwrap();
+            assert_eq!(
+                contract_address.next_block_number_to_ingest_from,
+                END_BLOCK_NUMBER as i64 + 1
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn reports_reorgs_of_the_last_interval_per_chain() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contracts = vec![bayc_contract()];
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let clock =
+                MockClock::new(DateTime::from_timestamp(1_600_000_000, 0).unwrap().naive_utc());
+            let reorged_block = |block_number: i64, chain: &Chain, minutes_ago: i64| {
+                UnsavedReorgedBlock::new(
+                    block_number,
+                    chain,
+                    clock.now() - chrono::Duration::minutes(minutes_ago),
+                )
+                .with_current_block_number(Some(BlockNumber::new(100)))
+            };
+            for reorged_block in [
+                reorged_block(98, &Chain::Mainnet, 10),
+                reorged_block(99, &Chain::Mainnet, 30),
+                // Before the last interval
+                reorged_block(90, &Chain::Mainnet, 90),
+                reorged_block(80, &Chain::Polygon, 10),
+            ] {
+                ChaindexingRepo::create_reorged_block(&mut conn, &reorged_block).await;
+            }
+
+            let reorg_reports = Arc::new(StdMutex::new(vec![]));
+            let reported_reorgs = reorg_reports.clone();
+            let config = config_with_contracts(contracts)
+                .with_min_confirmation_count(1)
+                .with_reorg_reports(Duration::from_secs(3600), move |reorg_report| {
+                    reported_reorgs.lock().unwrap().push(reorg_report.clone())
+                })
+                .with_clock(clock.clone());
+            let json_rpc = Arc::new(json_rpc_with_served_logs(
+                BAYC_CONTRACT_START_BLOCK_NUMBER as u64 + 20,
+                vec![],
+            ));
+            let conn = Arc::new(Mutex::new(conn));
+
+            // Only reported once per interval
+            for _tick in 0..2 {
+                EventsIngester::ingest(conn.clone(), json_rpc.clone(), &Chain::Mainnet, &config)
+                    .await
+                    .unwrap();
+            }
+            assert_eq!(
+                *reorg_reports.lock().unwrap(),
+                vec![ReorgReport {
+                    chain: Chain::Mainnet,
+                    interval: Duration::from_secs(3600),
+                    count: 2,
+                    max_depth: 3
+                }]
+            );
+
+            clock.advance(Duration::from_secs(3600));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+            assert_eq!(
+                reorg_reports.lock().unwrap().last(),
+                Some(&ReorgReport {
+                    chain: Chain::Mainnet,
+                    interval: Duration::from_secs(3600),
+                    count: 0,
+                    max_depth: 0
+                })
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn prunes_handled_reorged_blocks_older_than_their_retention() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contracts = vec![bayc_contract()];
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let clock =
+                MockClock::new(DateTime::from_timestamp(1_600_000_000, 0).unwrap().naive_utc());
+            let reorged_block = |block_number: i64, chain: &Chain, minutes_ago: i64| {
+                let inserted_at = clock.now() - chrono::Duration::minutes(minutes_ago);
+
+                UnsavedReorgedBlock::new(block_number, chain, inserted_at)
+            };
+            for reorged_block in [
+                reorged_block(98, &Chain::Mainnet, 10).with_handled_at(clock.now()),
+                reorged_block(90, &Chain::Mainnet, 90).with_handled_at(clock.now()),
+                // Still to be handled
+                reorged_block(80, &Chain::Mainnet, 90),
+                reorged_block(70, &Chain::Polygon, 90).with_handled_at(clock.now()),
+            ] {
+                ChaindexingRepo::create_reorged_block(&mut conn, &reorged_block).await;
+            }
+
+            let config = config_with_contracts(contracts)
+                .with_min_confirmation_count(1)
+                .with_reorged_blocks_retention(Duration::from_secs(3600))
+                .with_clock(clock.clone());
+            let json_rpc = Arc::new(json_rpc_with_served_logs(
+                BAYC_CONTRACT_START_BLOCK_NUMBER as u64 + 20,
+                vec![],
+            ));
+            let conn = Arc::new(Mutex::new(conn));
+            let get_reorged_block_numbers = |chain: Chain| {
+                let conn = conn.clone();
+
+                async move {
+                    let mut conn = conn.lock().await;
+                    let since = DateTime::from_timestamp(0, 0).unwrap().naive_utc();
+                    let mut reorged_blocks =
+                        ChaindexingRepo::get_reorged_blocks_since(&mut conn, &chain, since).await;
+                    reorged_blocks.sort_by_key(|reorged_block| reorged_block.block_number);
+
+                    reorged_blocks
+                        .iter()
+                        .map(|reorged_block| reorged_block.block_number)
+                        .collect::<Vec<_>>()
+                }
+            };
+
+            EventsIngester::ingest(conn.clone(), json_rpc.clone(), &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+            assert_eq!(
+                get_reorged_block_numbers(Chain::Mainnet).await,
+                vec![80, 98]
+            );
+            assert_eq!(get_reorged_block_numbers(Chain::Polygon).await, vec![70]);
+
+            clock.advance(Duration::from_secs(3600));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+            assert_eq!(get_reorged_block_numbers(Chain::Mainnet).await, vec![80]);
         })
         .await;
     }
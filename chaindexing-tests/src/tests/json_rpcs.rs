@@ -0,0 +1,348 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use chaindexing::{
+        Chain, Chaindexing, ChaindexingRepo, Config, Contract, ContractState,
+        ContractStateMigrations, EventContext, EventHandler, EventHandlers, EventsIngester,
+        EventsIngesterJsonRpc, FileBackedJsonRpc, HasRawQueryClient, JsonRpcFixture,
+        LoadsDataWithRawQuery,
+    };
+    use ethers::types::{Filter, Log, H256};
+    use serde::{Deserialize, Serialize};
+    use serde_json::{json, Value};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::Mutex;
+
+    use crate::factory::{
+        config_with_contracts, json_rpc_with_served_logs, transfer_log, BAYC_CONTRACT_ADDRESS,
+        BAYC_CONTRACT_START_BLOCK_NUMBER, TRANSFER_EVENT_ABI,
+    };
+    use crate::test_runner;
+
+    #[tokio::test]
+    pub async fn sends_configured_headers_to_json_rpcs() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let json_rpc_url = format!("http://{}", listener.local_addr().unwrap());
+        let config = Config::new(
+            test_runner::new_repo(),
+            [(Chain::Mainnet, json_rpc_url)].into(),
+        )
+        .add_json_rpc_header(&Chain::Mainnet, "Authorization", "Bearer secret-token");
+
+        // Captures the request's head, then hangs up without responding
+        let json_rpc_server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![];
+            let mut buffer = [0; 1024];
+
+            while !String::from_utf8_lossy(&request).contains("\r\n\r\n") {
+                let read_size = stream.read(&mut buffer).await.unwrap();
+                if read_size == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buffer[..read_size]);
+            }
+
+            String::from_utf8_lossy(&request).to_lowercase()
+        });
+
+        let json_rpc = config.get_json_rpc(&Chain::Mainnet);
+        assert!(json_rpc.get_block_number().await.is_err());
+
+        let request = json_rpc_server.await.unwrap();
+        assert!(request.contains("authorization: bearer secret-token"));
+    }
+
+    #[tokio::test]
+    pub async fn batches_logs_requests_into_a_single_http_request() {
+        let (json_rpc_url, _connections_count, http_requests) = serve_json_rpc().await;
+        let config = Config::new(
+            test_runner::new_repo(),
+            [(Chain::Mainnet, json_rpc_url)].into(),
+        );
+
+        let filters: Vec<_> = [10_u64, 20, 30]
+            .into_iter()
+            .map(|from_block_number| Filter::new().from_block(from_block_number))
+            .collect();
+        let filters_refs: Vec<_> = filters.iter().collect();
+
+        let logs = config
+            .get_json_rpc(&Chain::Mainnet)
+            .get_logs_batch(&filters_refs)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *http_requests.lock().await,
+            vec![vec!["eth_getLogs", "eth_getLogs", "eth_getLogs"]]
+        );
+        // In the filters' order, despite the batch response's
+        let logs_block_numbers: Vec<_> =
+            logs.iter().map(|logs| logs[0].block_number.unwrap().as_u64()).collect();
+        assert_eq!(logs_block_numbers, vec![10, 20, 30]);
+    }
+
+    #[tokio::test]
+    pub async fn shares_connections_of_chains_with_the_same_json_rpc_endpoint() {
+        let (json_rpc_url, connections_count, http_requests) = serve_json_rpc().await;
+        let config = Config::new(
+            test_runner::new_repo(),
+            [
+                (Chain::Mainnet, json_rpc_url.clone()),
+                (Chain::Polygon, json_rpc_url),
+            ]
+            .into(),
+        );
+
+        for chain in [Chain::Mainnet, Chain::Polygon] {
+            config.get_json_rpc(&chain).get_block_number().await.unwrap();
+        }
+
+        assert_eq!(http_requests.lock().await.len(), 2);
+        assert_eq!(connections_count.load(Ordering::SeqCst), 1);
+    }
+
+    /// Serves JSON RPC requests over HTTP, keeping connections alive. Answers
+    /// `eth_getLogs` calls with a log at each filter's from block, reversing
+    /// batch responses as nodes may return them in any order. Counts accepted
+    /// connections and records each HTTP request's methods.
+    async fn serve_json_rpc() -> (String, Arc<AtomicUsize>, Arc<Mutex<Vec<Vec<String>>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let json_rpc_url = format!("http://{}", listener.local_addr().unwrap());
+        let connections_count = Arc::new(AtomicUsize::new(0));
+        let http_requests = Arc::new(Mutex::new(vec![]));
+
+        let connections_count_ = connections_count.clone();
+        let http_requests_ = http_requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                connections_count_.fetch_add(1, Ordering::SeqCst);
+                let http_requests = http_requests_.clone();
+
+                tokio::spawn(async move {
+                    while let Some(body) = read_http_request_body(&mut stream).await {
+                        let (methods, response) = match body {
+                            Value::Array(calls) => (
+                                calls.iter().map(get_json_rpc_method).collect(),
+                                Value::Array(calls.iter().rev().map(respond_to_json_rpc).collect()),
+                            ),
+                            call => (vec![get_json_rpc_method(&call)], respond_to_json_rpc(&call)),
+                        };
+                        http_requests.lock().await.push(methods);
+
+                        let response = response.to_string();
+                        let response = format!(
+                            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                            response.len(),
+                            response
+                        );
+                        stream.write_all(response.as_bytes()).await.unwrap();
+                    }
+                });
+            }
+        });
+
+        (json_rpc_url, connections_count, http_requests)
+    }
+
+    async fn read_http_request_body(stream: &mut tokio::net::TcpStream) -> Option<Value> {
+        let mut request = vec![];
+        let mut buffer = [0; 1024];
+
+        let head_size = loop {
+            if let Some(position) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+                break position + 4;
+            }
+
+            let read_size = stream.read(&mut buffer).await.ok()?;
+            if read_size == 0 {
+                return None;
+            }
+            request.extend_from_slice(&buffer[..read_size]);
+        };
+
+        let head = String::from_utf8_lossy(&request[..head_size]).to_lowercase();
+        let content_length: usize = head
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+
+        while request.len() < head_size + content_length {
+            let read_size = stream.read(&mut buffer).await.ok()?;
+            if read_size == 0 {
+                return None;
+            }
+            request.extend_from_slice(&buffer[..read_size]);
+        }
+
+        serde_json::from_slice(&request[head_size..head_size + content_length]).ok()
+    }
+
+    fn get_json_rpc_method(call: &Value) -> String {
+        call["method"].as_str().unwrap().to_string()
+    }
+
+    fn respond_to_json_rpc(call: &Value) -> Value {
+        let result = match call["method"].as_str().unwrap() {
+            "eth_blockNumber" => json!("0x64"),
+            "eth_getLogs" => json!([{
+                "address": BAYC_CONTRACT_ADDRESS,
+                "topics": [],
+                "data": "0x",
+                "blockNumber": call["params"][0]["fromBlock"],
+            }]),
+            method => panic!("Unexpected JSON RPC method: {method}"),
+        };
+
+        json!({ "jsonrpc": "2.0", "id": call["id"], "result": result })
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+    struct FixtureNftState {
+        token_id: i32,
+    }
+    impl ContractState for FixtureNftState {
+        fn table_name() -> &'static str {
+            "fixture_nft_states"
+        }
+    }
+
+    struct FixtureNftStateMigrations;
+    impl ContractStateMigrations for FixtureNftStateMigrations {
+        fn migrations(&self) -> Vec<&'static str> {
+            vec!["CREATE TABLE IF NOT EXISTS fixture_nft_states (token_id INTEGER NOT NULL)"]
+        }
+    }
+
+    struct FixtureNftStateEventHandler;
+
+    #[async_trait::async_trait]
+    impl EventHandler for FixtureNftStateEventHandler {
+        async fn handle_event<'a>(&self, event_context: EventContext<'a>) {
+            let token_id = event_context.event.get_params().get("tokenId").cloned();
+            let token_id = token_id.unwrap().into_uint().unwrap().as_u32() as i32;
+
+            FixtureNftState { token_id }.create(&event_context).await;
+        }
+    }
+
+    #[tokio::test]
+    pub async fn ingests_and_handles_captured_fixtures_through_a_reorg() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+
+            let contract = Contract::new("FixtureBoredApeYachtClub")
+                .add_event(TRANSFER_EVENT_ABI, FixtureNftStateEventHandler)
+                .add_state_migrations(FixtureNftStateMigrations)
+                .add_address(
+                    BAYC_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![contract];
+            let config = config_with_contracts(contracts.clone())
+                .with_blocks_per_batch(20)
+                .with_min_confirmation_count(5);
+
+            // Captured from both branches of the chain, diverging at the last transfer
+            let logs = vec![
+                fixture_transfer_log(START_BLOCK_NUMBER + 2, 1, 1),
+                fixture_transfer_log(START_BLOCK_NUMBER + 5, 2, 1),
+                fixture_transfer_log(START_BLOCK_NUMBER + 8, 3, 1),
+            ];
+            let mut reorged_logs = logs.clone();
+            reorged_logs[2] = fixture_transfer_log(START_BLOCK_NUMBER + 8, 4, 2);
+
+            let fixture_path = std::env::temp_dir().join("chaindexing_bayc_fixture.json");
+            JsonRpcFixture::capture(
+                &json_rpc_with_served_logs(START_BLOCK_NUMBER + 10, logs),
+                &contracts,
+                &Chain::Mainnet,
+                START_BLOCK_NUMBER,
+                START_BLOCK_NUMBER + 10,
+            )
+            .await
+            .unwrap()
+            .write(&fixture_path)
+            .unwrap();
+            let reorged_fixture = JsonRpcFixture::capture(
+                &json_rpc_with_served_logs(START_BLOCK_NUMBER + 20, reorged_logs),
+                &contracts,
+                &Chain::Mainnet,
+                START_BLOCK_NUMBER,
+                START_BLOCK_NUMBER + 20,
+            )
+            .await
+            .unwrap();
+
+            let json_rpc = FileBackedJsonRpc::read(&fixture_path)
+                .unwrap()
+                .with_reorg(START_BLOCK_NUMBER + 20, reorged_fixture);
+
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+            Chaindexing::reset_migrations_for_contract_states(&raw_query_client, &contracts).await;
+            Chaindexing::run_migrations_for_contract_states(&raw_query_client, &contracts).await;
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            let conn = Arc::new(Mutex::new(conn));
+
+            EventsIngester::ingest(
+                conn.clone(),
+                Arc::new(json_rpc.clone()),
+                &Chain::Mainnet,
+                &config,
+            )
+            .await
+            .unwrap();
+            EventHandlers::run(conn.clone(), &config, &mut raw_query_client).await;
+            assert_eq!(
+                read_fixture_nft_states(&raw_query_client).await,
+                vec![1, 2, 3]
+            );
+
+            json_rpc.set_block_number(START_BLOCK_NUMBER + 20);
+            EventsIngester::ingest(conn.clone(), Arc::new(json_rpc), &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+            // Backtracks the reorged block's states, then handles its new events
+            EventHandlers::run(conn.clone(), &config, &mut raw_query_client).await;
+            EventHandlers::run(conn.clone(), &config, &mut raw_query_client).await;
+            assert_eq!(
+                read_fixture_nft_states(&raw_query_client).await,
+                vec![1, 2, 4]
+            );
+        })
+        .await;
+    }
+
+    fn fixture_transfer_log(block_number: u64, token_id: u64, block_hash: u64) -> Log {
+        let mut log = transfer_log(BAYC_CONTRACT_ADDRESS);
+        log.topics[3] = H256::from_low_u64_be(token_id);
+        log.block_number = Some(block_number.into());
+        log.block_hash = Some(H256::from_low_u64_be(block_number * 10 + block_hash));
+        log.transaction_hash = Some(H256::from_low_u64_be(token_id));
+
+        log
+    }
+
+    async fn read_fixture_nft_states(
+        raw_query_client: &chaindexing::ChaindexingRepoRawQueryClient,
+    ) -> Vec<i32> {
+        let states: Vec<FixtureNftState> = ChaindexingRepo::load_data_list_from_raw_query(
+            raw_query_client,
+            "SELECT token_id FROM fixture_nft_states ORDER BY token_id",
+        )
+        .await;
+
+        states.into_iter().map(|state| state.token_id).collect()
+    }
+}
@@ -0,0 +1,313 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex as StdMutex};
+    use std::time::Duration;
+    use tokio::sync::mpsc::unbounded_channel;
+    use tokio::sync::Mutex;
+    use tokio::time::timeout;
+
+    use chaindexing::{
+        Chain, Chaindexing, Contract, CoupledPipeline, EventContext, EventHandler,
+        EventsIngesterError, FatalErrorPolicy, HasRawQueryClient, PipelineMode, PipelineSpanKind,
+        PostgresRepo, Repo,
+    };
+
+    use crate::factory::{
+        bayc_contract, config_with_contracts, failing_json_rpc, json_rpc_with_served_logs,
+        transfer_log, BAYC_CONTRACT_ADDRESS, BAYC_CONTRACT_START_BLOCK_NUMBER, TRANSFER_EVENT_ABI,
+    };
+    use crate::{json_rpc_with_logs, test_runner};
+
+    static HANDLED_TRANSFER_EVENTS_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    struct CountingTransferEventHandler;
+
+    #[async_trait::async_trait]
+    impl EventHandler for CountingTransferEventHandler {
+        async fn handle_event<'a>(&self, _event_context: EventContext<'a>) {
+            HANDLED_TRANSFER_EVENTS_COUNT.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn handles_every_ingested_event_within_each_coupled_tick() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contract = Contract::new("BoredApeYachtClub")
+                .add_event(TRANSFER_EVENT_ABI, CountingTransferEventHandler)
+                .add_address(
+                    BAYC_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let contracts = vec![contract];
+            // Without confirmations, re-fetched logs cannot show up as reorgs
+            let config = config_with_contracts(contracts.clone())
+                .with_blocks_per_batch(10)
+                .with_chain_min_confirmation_count(&Chain::Mainnet, 0)
+                .with_pipeline_mode(PipelineMode::Coupled);
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            let conn = Arc::new(Mutex::new(conn));
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+
+            static FIRST_TICK_BLOCK_NUMBER: u32 = BAYC_CONTRACT_START_BLOCK_NUMBER + 20;
+            let json_rpc = Arc::new(json_rpc_with_logs!(
+                BAYC_CONTRACT_ADDRESS,
+                FIRST_TICK_BLOCK_NUMBER
+            ));
+            CoupledPipeline::tick(
+                conn.clone(),
+                &mut raw_query_client,
+                &mut [],
+                json_rpc,
+                &Chain::Mainnet,
+                &config,
+            )
+            .await
+            .unwrap();
+
+            let ingested_events = PostgresRepo::get_all_events(&mut *conn.lock().await).await;
+            assert!(!ingested_events.is_empty());
+            assert_eq!(
+                HANDLED_TRANSFER_EVENTS_COUNT.load(Ordering::SeqCst),
+                ingested_events.len()
+            );
+
+            static SECOND_TICK_BLOCK_NUMBER: u32 = BAYC_CONTRACT_START_BLOCK_NUMBER + 40;
+            let json_rpc = Arc::new(json_rpc_with_logs!(
+                BAYC_CONTRACT_ADDRESS,
+                SECOND_TICK_BLOCK_NUMBER
+            ));
+            CoupledPipeline::tick(
+                conn.clone(),
+                &mut raw_query_client,
+                &mut [],
+                json_rpc,
+                &Chain::Mainnet,
+                &config,
+            )
+            .await
+            .unwrap();
+
+            let ingested_events_after_second_tick =
+                PostgresRepo::get_all_events(&mut *conn.lock().await).await;
+            assert!(ingested_events_after_second_tick.len() > ingested_events.len());
+            assert_eq!(
+                HANDLED_TRANSFER_EVENTS_COUNT.load(Ordering::SeqCst),
+                ingested_events_after_second_tick.len()
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn traces_events_from_ingestion_through_handling() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+
+            let contracts = vec![bayc_contract()];
+            let pipeline_spans = Arc::new(StdMutex::new(vec![]));
+            let recorded_pipeline_spans = pipeline_spans.clone();
+            let config = config_with_contracts(contracts.clone())
+                .with_blocks_per_batch(10)
+                .with_chain_min_confirmation_count(&Chain::Mainnet, 0)
+                .with_pipeline_mode(PipelineMode::Coupled)
+                .with_on_pipeline_span(move |pipeline_span| {
+                    recorded_pipeline_spans.lock().unwrap().push(pipeline_span.clone())
+                });
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            let conn = Arc::new(Mutex::new(conn));
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+
+            let mut transfer_log = transfer_log(BAYC_CONTRACT_ADDRESS);
+            transfer_log.block_number = Some((START_BLOCK_NUMBER + 5).into());
+            let json_rpc = Arc::new(json_rpc_with_served_logs(
+                START_BLOCK_NUMBER + 20,
+                vec![transfer_log],
+            ));
+            CoupledPipeline::tick(
+                conn.clone(),
+                &mut raw_query_client,
+                &mut [],
+                json_rpc,
+                &Chain::Mainnet,
+                &config,
+            )
+            .await
+            .unwrap();
+
+            let ingested_events = PostgresRepo::get_all_events(&mut *conn.lock().await).await;
+            assert_eq!(ingested_events.len(), 1);
+            let event_id = ingested_events.first().unwrap().id;
+
+            let pipeline_spans = pipeline_spans.lock().unwrap().clone();
+            let event_spans: Vec<_> = pipeline_spans
+                .iter()
+                .filter(|pipeline_span| pipeline_span.event_ids.contains(&event_id))
+                .collect();
+            let event_span_kinds: Vec<_> =
+                event_spans.iter().map(|span| span.kind.clone()).collect();
+            assert_eq!(
+                event_span_kinds,
+                vec![PipelineSpanKind::IngestBatch, PipelineSpanKind::HandleEvent]
+            );
+
+            let (ingest_batch_span, handle_event_span) = (event_spans[0], event_spans[1]);
+            assert_eq!(handle_event_span.event_ids, vec![event_id]);
+            assert_ne!(ingest_batch_span.id, handle_event_span.id);
+            assert!(ingest_batch_span.started_at <= handle_event_span.started_at);
+        })
+        .await;
+    }
+
+    struct HangingTransferEventHandler;
+
+    #[async_trait::async_trait]
+    impl EventHandler for HangingTransferEventHandler {
+        async fn handle_event<'a>(&self, _event_context: EventContext<'a>) {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        }
+    }
+
+    #[tokio::test]
+    pub async fn never_traces_handling_that_got_rolled_back() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            static START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+
+            let contracts = vec![Contract::new("HangingBoredApeYachtClub")
+                .add_event(TRANSFER_EVENT_ABI, HangingTransferEventHandler)
+                .add_address(
+                    BAYC_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    START_BLOCK_NUMBER as i64,
+                )];
+            let pipeline_spans = Arc::new(StdMutex::new(vec![]));
+            let recorded_pipeline_spans = pipeline_spans.clone();
+            let config = config_with_contracts(contracts.clone())
+                .with_blocks_per_batch(10)
+                .with_chain_min_confirmation_count(&Chain::Mainnet, 0)
+                .with_pipeline_mode(PipelineMode::Coupled)
+                .with_handler_timeout(Duration::from_millis(50))
+                .with_on_pipeline_span(move |pipeline_span| {
+                    recorded_pipeline_spans.lock().unwrap().push(pipeline_span.clone())
+                });
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+            let conn = Arc::new(Mutex::new(conn));
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+
+            let mut transfer_log = transfer_log(BAYC_CONTRACT_ADDRESS);
+            transfer_log.block_number = Some((START_BLOCK_NUMBER + 5).into());
+            let json_rpc = Arc::new(json_rpc_with_served_logs(
+                START_BLOCK_NUMBER + 20,
+                vec![transfer_log],
+            ));
+            CoupledPipeline::tick(
+                conn.clone(),
+                &mut raw_query_client,
+                &mut [],
+                json_rpc,
+                &Chain::Mainnet,
+                &config,
+            )
+            .await
+            .unwrap();
+
+            // Its handling timed out, so its transaction got rolled back
+            let pipeline_span_kinds: Vec<_> = pipeline_spans
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|pipeline_span| pipeline_span.kind.clone())
+                .collect();
+            assert_eq!(pipeline_span_kinds, vec![PipelineSpanKind::IngestBatch]);
+        })
+        .await;
+    }
+
+    async fn run_failing_coupled_pipeline(
+        fatal_error_policy: FatalErrorPolicy,
+        requests_count: Arc<AtomicUsize>,
+    ) {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| {
+            let fatal_error_policy = fatal_error_policy.clone();
+            let requests_count = requests_count.clone();
+
+            async move {
+                let contracts = vec![bayc_contract()];
+                // Without retries, the dead chain fails each tick right away
+                let config = config_with_contracts(contracts.clone())
+                    .with_max_json_rpc_retries(0)
+                    .with_fatal_error_policy(fatal_error_policy)
+                    .with_pipeline_mode(PipelineMode::Coupled);
+
+                Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+                let conn = Arc::new(Mutex::new(conn));
+                let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+                let json_rpc = Arc::new(failing_json_rpc(requests_count));
+
+                let _ = timeout(
+                    Duration::from_millis(200),
+                    CoupledPipeline::run(
+                        conn,
+                        &mut raw_query_client,
+                        &mut [],
+                        |_chain| json_rpc.clone(),
+                        &config,
+                    ),
+                )
+                .await;
+            }
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "Events Ingester Error")]
+    pub async fn panics_on_failed_coupled_ticks_by_default() {
+        run_failing_coupled_pipeline(FatalErrorPolicy::Panic, Arc::new(AtomicUsize::new(0))).await;
+    }
+
+    #[tokio::test]
+    pub async fn retries_failed_coupled_ticks_after_the_delay() {
+        let requests_count = Arc::new(AtomicUsize::new(0));
+
+        run_failing_coupled_pipeline(
+            FatalErrorPolicy::Retry(Duration::from_millis(10)),
+            requests_count.clone(),
+        )
+        .await;
+
+        assert!(requests_count.load(Ordering::SeqCst) > 1);
+    }
+
+    #[tokio::test]
+    pub async fn shuts_down_on_failed_coupled_ticks() {
+        let requests_count = Arc::new(AtomicUsize::new(0));
+        let (fatal_errors_sender, mut fatal_errors) = unbounded_channel();
+
+        run_failing_coupled_pipeline(
+            FatalErrorPolicy::Shutdown(fatal_errors_sender),
+            requests_count.clone(),
+        )
+        .await;
+
+        assert_eq!(requests_count.load(Ordering::SeqCst), 1);
+        let fatal_error = fatal_errors.try_recv().unwrap();
+        assert_eq!(fatal_error.chain, Chain::Mainnet);
+        assert!(matches!(
+            fatal_error.error,
+            EventsIngesterError::JsonRpcError(_)
+        ));
+    }
+}
@@ -0,0 +1,262 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    use chaindexing::{
+        BlockNumber, Chain, Chaindexing, ChaindexingRepo, Config, Contract, EventContext,
+        EventHandler, EventsPartitioning, ExecutesWithRawQuery, HandleEvents, HasRawQueryClient,
+        LoadsDataWithRawQuery, ReorgDepthStats, Repo, RepoMigrations, UnsavedReorgedBlock,
+    };
+
+    use crate::factory::{
+        bayc_contract, config_with_contracts, transfer_event_with_contract,
+        transfer_event_with_contract_address, BAYC_CONTRACT_START_BLOCK_NUMBER, TRANSFER_EVENT_ABI,
+    };
+    use crate::{db, test_runner};
+
+    #[tokio::test]
+    pub async fn sets_up_core_tables_on_a_fresh_database() {
+        let repo = ChaindexingRepo::new(&db::fresh_database_url("setup"));
+        let raw_query_client = repo.get_raw_query_client().await;
+
+        // Idempotent, e.g. on every startup
+        ChaindexingRepo::setup(&raw_query_client).await;
+        ChaindexingRepo::setup(&raw_query_client).await;
+
+        let tables: Vec<HashMap<String, String>> = ChaindexingRepo::load_data_list_from_raw_query(
+            &raw_query_client,
+            "SELECT table_name::TEXT FROM information_schema.tables
+            WHERE table_schema = 'public' ORDER BY table_name",
+        )
+        .await;
+        let table_names: Vec<_> = tables.iter().map(|t| t["table_name"].as_str()).collect();
+
+        assert_eq!(
+            table_names,
+            vec![
+                "chaindexing_contract_addresses",
+                "chaindexing_events",
+                "chaindexing_handler_checkpoints",
+                "chaindexing_migration_checksums",
+                "chaindexing_reorged_blocks",
+                "chaindexing_reset_counts",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    pub async fn routes_events_to_their_chain_partition() {
+        let repo = ChaindexingRepo::new(&db::fresh_database_url("partitioned_events"));
+        let chains = [
+            (Chain::Mainnet, "http://localhost:8545".to_string()),
+            (Chain::Polygon, "http://localhost:8546".to_string()),
+        ]
+        .into();
+        let config = Config::new(repo.clone(), chains)
+            .add_contract(bayc_contract())
+            .with_events_partitioning(EventsPartitioning::ByChain);
+
+        // Idempotent, e.g. on every startup
+        Chaindexing::setup(&config).await.unwrap();
+        Chaindexing::setup(&config).await.unwrap();
+
+        let mainnet_event = transfer_event_with_contract(bayc_contract());
+        let mut polygon_event = transfer_event_with_contract(bayc_contract());
+        polygon_event.chain_id = Chain::Polygon as i32;
+
+        let pool = repo.get_pool(1).await;
+        let mut conn = ChaindexingRepo::get_conn(&pool).await;
+        ChaindexingRepo::create_events(&mut conn, &vec![mainnet_event, polygon_event.clone()])
+            .await;
+
+        let raw_query_client = repo.get_raw_query_client().await;
+        let partitions: Vec<HashMap<String, String>> =
+            ChaindexingRepo::load_data_list_from_raw_query(
+                &raw_query_client,
+                "SELECT tableoid::regclass::TEXT AS partition, chain_id::TEXT
+                FROM chaindexing_events ORDER BY chain_id",
+            )
+            .await;
+        let partitions: Vec<_> = partitions.iter().map(|p| p["partition"].as_str()).collect();
+
+        assert_eq!(
+            partitions,
+            vec!["chaindexing_events_chain_1", "chaindexing_events_chain_137"]
+        );
+
+        let polygon_events = ChaindexingRepo::get_events(
+            &mut conn,
+            polygon_event.contract_address.clone(),
+            Chain::Polygon as i32,
+            BlockNumber::new(0),
+            BlockNumber::new(polygon_event.block_number as u64 + 1),
+        )
+        .await;
+        assert_eq!(polygon_events, vec![polygon_event]);
+    }
+
+    static HANDLED_REPLAYED_EVENTS_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    struct CountingReplayedEventHandler;
+
+    #[async_trait::async_trait]
+    impl EventHandler for CountingReplayedEventHandler {
+        async fn handle_event<'a>(&self, _event_context: EventContext<'a>) {
+            HANDLED_REPLAYED_EVENTS_COUNT.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn bulk_updates_handling_cursors() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let contracts = vec![bayc_contract()
+                .add_address(
+                    "0xb47e3cd837dDF8e4c57F05d70Ab865de6e193BBB",
+                    &Chain::Mainnet,
+                    3914495,
+                )
+                .add_address(
+                    "0x60E4d786628Fea6478F785A6d7e704777c86a7c6",
+                    &Chain::Mainnet,
+                    14397126,
+                )];
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let mut contract_addresses =
+                ChaindexingRepo::get_all_contract_addresses(&mut conn).await;
+            contract_addresses.sort_by_key(|contract_address| contract_address.id);
+            let handling_cursors = contract_addresses[..2]
+                .iter()
+                .map(|contract_address| (contract_address.id(), 20_000_000))
+                .collect();
+
+            ChaindexingRepo::update_handling_cursors(&mut conn, handling_cursors).await;
+
+            let mut contract_addresses =
+                ChaindexingRepo::get_all_contract_addresses(&mut conn).await;
+            contract_addresses.sort_by_key(|contract_address| contract_address.id);
+            let next_block_numbers_to_handle_from: Vec<_> = contract_addresses
+                .iter()
+                .map(|contract_address| contract_address.next_block_number_to_handle_from)
+                .collect();
+            assert_eq!(
+                next_block_numbers_to_handle_from,
+                vec![
+                    20_000_000,
+                    20_000_000,
+                    contract_addresses[2].start_block_number
+                ]
+            );
+
+            // Replays from the rewound cursors handle their events again
+            const REPLAYED_CONTRACT_ADDRESS: &str = "0x3bf2922f4520a8ba0c2efc3d2a1539678dad5e9d";
+
+            let contract = Contract::new("ReplayedBoredApeYachtClub")
+                .add_event(TRANSFER_EVENT_ABI, CountingReplayedEventHandler)
+                .add_address(
+                    REPLAYED_CONTRACT_ADDRESS,
+                    &Chain::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as i64,
+                );
+            let config = config_with_contracts(vec![contract.clone()]);
+            let transfer_event =
+                transfer_event_with_contract_address(contract, REPLAYED_CONTRACT_ADDRESS);
+
+            // Committed, unlike the test transaction's writes, so the rewind
+            // does not hold onto the checkpoint the replay records again
+            let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+            for query in [
+                format!(
+                    "DELETE FROM chaindexing_contract_addresses
+                    WHERE address = '{REPLAYED_CONTRACT_ADDRESS}'"
+                ),
+                format!(
+                    "INSERT INTO chaindexing_contract_addresses (address, contract_name, chain_id,
+                    start_block_number, next_block_number_to_ingest_from, next_block_number_to_handle_from)
+                    VALUES ('{REPLAYED_CONTRACT_ADDRESS}', 'ReplayedBoredApeYachtClub', 1,
+                    {block_number}, {next_block_number}, {block_number})",
+                    block_number = transfer_event.block_number,
+                    next_block_number = transfer_event.block_number + 1
+                ),
+            ] {
+                ChaindexingRepo::execute_raw_query(&raw_query_client, &query).await;
+            }
+            ChaindexingRepo::create_events(&mut conn, &vec![transfer_event.clone()]).await;
+            let replayed_contract_address = ChaindexingRepo::get_all_contract_addresses(&mut conn)
+                .await
+                .into_iter()
+                .find(|contract_address| contract_address.address == REPLAYED_CONTRACT_ADDRESS)
+                .unwrap();
+
+            let conn = Arc::new(Mutex::new(conn));
+            HandleEvents::run(conn.clone(), &config, &mut raw_query_client).await;
+            assert_eq!(HANDLED_REPLAYED_EVENTS_COUNT.load(Ordering::SeqCst), 1);
+
+            let pool = test_runner::get_pool().await;
+            let mut committed_conn = ChaindexingRepo::get_conn(&pool).await;
+            ChaindexingRepo::update_handling_cursors(
+                &mut committed_conn,
+                vec![(replayed_contract_address.id(), transfer_event.block_number)],
+            )
+            .await;
+
+            HandleEvents::run(conn.clone(), &config, &mut raw_query_client).await;
+            assert_eq!(HANDLED_REPLAYED_EVENTS_COUNT.load(Ordering::SeqCst), 2);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn computes_reorg_depth_stats_of_the_latest_reorged_blocks() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            let now = chrono::Utc::now().naive_utc();
+            let reorged_block = |block_number: i64, chain: &Chain, current_block_number| {
+                UnsavedReorgedBlock::new(block_number, chain, now)
+                    .with_current_block_number(current_block_number)
+            };
+
+            for reorged_block in [
+                reorged_block(99, &Chain::Arbitrum, Some(BlockNumber::new(100))),
+                reorged_block(98, &Chain::Arbitrum, Some(BlockNumber::new(100))),
+                // Recorded before depths were
+                reorged_block(50, &Chain::Arbitrum, None),
+                reorged_block(20, &Chain::Optimism, Some(BlockNumber::new(100))),
+                reorged_block(95, &Chain::Arbitrum, Some(BlockNumber::new(100))),
+                reorged_block(90, &Chain::Arbitrum, Some(BlockNumber::new(100))),
+            ] {
+                ChaindexingRepo::create_reorged_block(&mut conn, &reorged_block).await;
+            }
+
+            assert_eq!(
+                ChaindexingRepo::reorg_depth_stats(&mut conn, &Chain::Arbitrum, 10).await,
+                ReorgDepthStats {
+                    max: 11,
+                    p95: 11,
+                    mean: 5.5,
+                    count: 4
+                }
+            );
+            assert_eq!(
+                ChaindexingRepo::reorg_depth_stats(&mut conn, &Chain::Arbitrum, 2).await,
+                ReorgDepthStats {
+                    max: 11,
+                    p95: 11,
+                    mean: 8.5,
+                    count: 2
+                }
+            );
+            assert_eq!(
+                ChaindexingRepo::reorg_depth_stats(&mut conn, &Chain::Polygon, 10).await,
+                ReorgDepthStats::default()
+            );
+        })
+        .await;
+    }
+}
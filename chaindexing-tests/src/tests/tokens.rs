@@ -0,0 +1,172 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicI64, Ordering};
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    use chaindexing::{
+        Address, Chain, Chaindexing, ChaindexingRepo, Contract, ContractAddress, ContractState,
+        Erc20Balance, Erc20TransferHandler, Erc721Token, Erc721TransferHandler, Event,
+        EventBuilder, EventContext, EventHandler, EventsIngester, HasRawQueryClient, PostgresRepo,
+        Repo, ERC20_TRANSFER_EVENT_ABI, ERC721_TRANSFER_EVENT_ABI,
+    };
+    use ethers::abi::Token;
+    use ethers::types::{Bytes, H256};
+
+    use crate::factory::{config_with_contracts, json_rpc_with_served_logs, transfer_log};
+    use crate::test_runner;
+
+    const TOKEN_CONTRACT_ADDRESS: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+
+    fn transfer_event(abi: &str, amount_param: &str, transfer: (u64, u64, u64)) -> Event {
+        let (from, to, amount) = transfer;
+        static LOG_INDEX: AtomicI64 = AtomicI64::new(0);
+
+        EventBuilder::default()
+            .with_contract_address(TOKEN_CONTRACT_ADDRESS)
+            .with_contract_name("Token")
+            .with_abi(abi)
+            .add_param("from", Token::Address(Address::from_low_u64_be(from)))
+            .add_param("to", Token::Address(Address::from_low_u64_be(to)))
+            .add_param(amount_param, Token::Uint(amount.into()))
+            .with_log_index(LOG_INDEX.fetch_add(1, Ordering::SeqCst))
+            .build()
+    }
+
+    fn holder_address(holder: u64) -> String {
+        ContractAddress::address_to_string(&Address::from_low_u64_be(holder))
+    }
+
+    #[tokio::test]
+    pub async fn tracks_erc20_balances_of_mints_transfers_and_burns() {
+        let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+        let raw_query_txn_client =
+            ChaindexingRepo::get_raw_query_txn_client(&mut raw_query_client).await;
+
+        // Minted 100 to 1, who sends 30 to 2, then burns the remaining 70
+        let transfers = [(0, 1, 100), (1, 2, 30), (1, 0, 70)];
+        let mut event_context = None;
+        for transfer in transfers {
+            let event = transfer_event(ERC20_TRANSFER_EVENT_ABI, "value", transfer);
+            Erc20TransferHandler
+                .handle_event(EventContext::new(event.clone(), &raw_query_txn_client))
+                .await;
+            event_context = Some(EventContext::new(event, &raw_query_txn_client));
+        }
+        let event_context = event_context.unwrap();
+
+        let read_balance = |holder: u64| {
+            Erc20Balance::read_one(
+                [("holder_address".to_owned(), holder_address(holder))].into(),
+                &event_context,
+            )
+        };
+
+        assert_eq!(read_balance(1).await, None);
+        assert_eq!(read_balance(2).await.unwrap().get_balance(), 30.into());
+        assert_eq!(read_balance(0).await, None);
+    }
+
+    #[tokio::test]
+    pub async fn tracks_erc721_ownership_of_mints_transfers_and_burns() {
+        let mut raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+        let raw_query_txn_client =
+            ChaindexingRepo::get_raw_query_txn_client(&mut raw_query_client).await;
+
+        // Token 7 minted to 1, then sent to 2, while token 8 is minted then burnt
+        let transfers = [(0, 1, 7), (1, 2, 7), (0, 1, 8), (1, 0, 8)];
+        let mut event_context = None;
+        for transfer in transfers {
+            let event = transfer_event(ERC721_TRANSFER_EVENT_ABI, "tokenId", transfer);
+            Erc721TransferHandler
+                .handle_event(EventContext::new(event.clone(), &raw_query_txn_client))
+                .await;
+            event_context = Some(EventContext::new(event, &raw_query_txn_client));
+        }
+        let event_context = event_context.unwrap();
+
+        let read_token = |token_id: u64| {
+            Erc721Token::read_one(
+                [("token_id".to_owned(), token_id.to_string())].into(),
+                &event_context,
+            )
+        };
+
+        let token = read_token(7).await.unwrap();
+        assert_eq!(token.owner_address, holder_address(2));
+        assert_eq!(token.get_token_id(), 7.into());
+        assert_eq!(read_token(8).await, None);
+    }
+
+    #[tokio::test]
+    pub async fn ingests_erc20_and_erc721_transfers_of_the_same_config() {
+        let pool = test_runner::get_pool().await;
+
+        test_runner::run_test(&pool, |mut conn| async move {
+            const ERC20_CONTRACT_ADDRESS: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+            const ERC721_CONTRACT_ADDRESS: &str = "0xbc4ca0eda7647a8ab7c2061c2e118a18a936f13d";
+
+            // Same topic, told apart by their contracts only
+            let erc721_transfer_log = transfer_log(ERC721_CONTRACT_ADDRESS);
+            let mut erc20_transfer_log = transfer_log(ERC20_CONTRACT_ADDRESS);
+            erc20_transfer_log.topics.truncate(3);
+            erc20_transfer_log.data = Bytes::from(H256::from_low_u64_be(100).as_bytes().to_vec());
+            erc20_transfer_log.log_index =
+                Some((erc721_transfer_log.log_index.unwrap() + 1).into());
+
+            let block_number = erc721_transfer_log.block_number.unwrap().as_u64();
+            let start_block_number = block_number as i64 - 10;
+            let contracts = vec![
+                Contract::new("USDC")
+                    .add_event(ERC20_TRANSFER_EVENT_ABI, Erc20TransferHandler)
+                    .add_address(ERC20_CONTRACT_ADDRESS, &Chain::Mainnet, start_block_number),
+                Contract::new("BoredApeYachtClub")
+                    .add_event(ERC721_TRANSFER_EVENT_ABI, Erc721TransferHandler)
+                    .add_address(ERC721_CONTRACT_ADDRESS, &Chain::Mainnet, start_block_number),
+            ];
+            let json_rpc = Arc::new(json_rpc_with_served_logs(
+                block_number + 10,
+                vec![erc20_transfer_log, erc721_transfer_log],
+            ));
+
+            Chaindexing::create_initial_contract_addresses(&mut conn, &contracts).await;
+
+            let config = config_with_contracts(contracts)
+                .with_blocks_per_batch(50)
+                .with_min_confirmation_count(1);
+            let conn = Arc::new(Mutex::new(conn));
+            EventsIngester::ingest(conn.clone(), json_rpc, &Chain::Mainnet, &config)
+                .await
+                .unwrap();
+
+            let mut conn = conn.lock().await;
+            let mut events = PostgresRepo::get_all_events(&mut conn).await;
+            events.sort_by_key(|event| event.contract_name.clone());
+            assert_eq!(events.len(), 2);
+
+            let (erc721_event, erc20_event) = (&events[0], &events[1]);
+            assert_eq!(erc20_event.abi, ERC20_TRANSFER_EVENT_ABI);
+            assert_eq!(
+                erc20_event.get_params().get("value").unwrap().clone().into_uint(),
+                Some(100.into())
+            );
+            assert_eq!(erc721_event.abi, ERC721_TRANSFER_EVENT_ABI);
+            assert!(erc721_event.get_params().contains_key("tokenId"));
+        })
+        .await;
+    }
+}
+
+use chaindexing::{
+    Chaindexing, Contract, Erc20BalancesMigrations, Erc721TokensMigrations, HasRawQueryClient,
+};
+
+use crate::test_runner;
+
+pub async fn setup() {
+    let token_contract = Contract::new("Token")
+        .add_state_migrations(Erc20BalancesMigrations)
+        .add_state_migrations(Erc721TokensMigrations);
+    let raw_query_client = test_runner::new_repo().get_raw_query_client().await;
+    Chaindexing::run_migrations_for_contract_states(&raw_query_client, &vec![token_contract]).await;
+}
@@ -0,0 +1,45 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Called with the time each phase of every ingested batch took, e.g. to
+/// tell whether slow ingestion is bound by the JSON RPC or the database.
+pub type OnBatchTimings = Arc<dyn Fn(&BatchTimings) + Send + Sync>;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BatchTimings {
+    pub chain_id: i32,
+    /// Fetching logs, including retries
+    pub get_logs: Duration,
+    /// Fetching the logs' blocks, for their timestamps
+    pub get_blocks: Duration,
+    /// Decoding logs into events
+    pub decode: Duration,
+    /// Only spent with `Config::fetch_transaction_statuses`
+    pub get_transaction_receipts: Duration,
+    /// Persisting events and advancing ingestion cursors
+    pub db_writes: Duration,
+    /// From fetching logs through persisting events
+    pub total: Duration,
+}
+
+impl BatchTimings {
+    pub fn new(chain_id: i32) -> Self {
+        Self {
+            chain_id,
+            ..Default::default()
+        }
+    }
+
+    pub fn get_phases_total(&self) -> Duration {
+        self.get_logs
+            + self.get_blocks
+            + self.decode
+            + self.get_transaction_receipts
+            + self.db_writes
+    }
+
+    /// Time spent between phases, e.g. in `Config::on_events_ingested`
+    pub fn get_untracked(&self) -> Duration {
+        self.total.saturating_sub(self.get_phases_total())
+    }
+}
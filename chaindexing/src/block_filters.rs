@@ -0,0 +1,23 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use ethers::types::{Block, Log, TxHash};
+
+/// Whether a block's events get ingested, e.g. to only ingest blocks of a
+/// given miner, or every other block for sampling.
+pub type BlockFilter = Arc<dyn Fn(&Block<TxHash>) -> bool + Send + Sync>;
+
+/// Keeps the logs of blocks matching the filter only
+pub fn filter_logs_by_block(
+    logs: Vec<Log>,
+    blocks_by_tx_hash: &HashMap<TxHash, Block<TxHash>>,
+    block_filter: &BlockFilter,
+) -> Vec<Log> {
+    logs.into_iter()
+        .filter(|log| {
+            log.transaction_hash
+                .and_then(|tx_hash| blocks_by_tx_hash.get(&tx_hash))
+                .map_or(true, |block| block_filter(block))
+        })
+        .collect()
+}
@@ -0,0 +1,251 @@
+use std::cmp::{max, min};
+
+use derive_more::Display;
+use ethers::types::U64;
+
+#[derive(Debug, Display, PartialEq)]
+pub enum BlockNumberError {
+    #[display(fmt = "Block number cannot be negative: {}", _0)]
+    Negative(i64),
+    #[display(fmt = "Block number cannot be stored as i64: {}", _0)]
+    Overflow(u64),
+}
+
+/// Block numbers are stored as signed BIGINTs but served as unsigned integers
+/// by JSON RPCs. `BlockNumber` keeps them non-negative and only converts
+/// between both representations with checks.
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct BlockNumber(u64);
+
+impl BlockNumber {
+    pub fn new(value: u64) -> Self {
+        Self(value)
+    }
+
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+
+    pub fn saturating_add(&self, count: u64) -> Self {
+        Self(self.0.saturating_add(count))
+    }
+
+    pub fn saturating_sub(&self, count: u64) -> Self {
+        Self(self.0.saturating_sub(count))
+    }
+}
+
+impl From<u64> for BlockNumber {
+    fn from(value: u64) -> Self {
+        Self(value)
+    }
+}
+
+impl From<U64> for BlockNumber {
+    fn from(value: U64) -> Self {
+        Self(value.as_u64())
+    }
+}
+
+impl From<BlockNumber> for U64 {
+    fn from(BlockNumber(value): BlockNumber) -> Self {
+        U64::from(value)
+    }
+}
+
+impl TryFrom<i64> for BlockNumber {
+    type Error = BlockNumberError;
+
+    fn try_from(value: i64) -> Result<Self, Self::Error> {
+        u64::try_from(value).map(Self).map_err(|_| BlockNumberError::Negative(value))
+    }
+}
+
+impl TryFrom<BlockNumber> for i64 {
+    type Error = BlockNumberError;
+
+    fn try_from(BlockNumber(value): BlockNumber) -> Result<Self, Self::Error> {
+        i64::try_from(value).map_err(|_| BlockNumberError::Overflow(value))
+    }
+}
+
+/// Inclusive block ranges to only ingest from (allowed) or to skip (blocked).
+/// Blocked ranges take precedence over allowed ones.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BlockRanges {
+    allowed: Vec<(BlockNumber, BlockNumber)>,
+    blocked: Vec<(BlockNumber, BlockNumber)>,
+}
+
+impl BlockRanges {
+    pub fn allow(&mut self, from: BlockNumber, to: BlockNumber) {
+        self.allowed.push((from, to));
+    }
+
+    pub fn block(&mut self, from: BlockNumber, to: BlockNumber) {
+        self.blocked.push((from, to));
+    }
+
+    /// Splits the inclusive range `from..=to` into the disjoint, ordered
+    /// sub-ranges that remain after applying the allowed and blocked ranges.
+    pub fn split(&self, from: BlockNumber, to: BlockNumber) -> Vec<(BlockNumber, BlockNumber)> {
+        let mut ranges: Vec<_> = if self.allowed.is_empty() {
+            vec![(from, to)]
+        } else {
+            self.allowed
+                .iter()
+                .map(|&(allowed_from, allowed_to)| (max(allowed_from, from), min(allowed_to, to)))
+                .filter(|(from, to)| from <= to)
+                .collect()
+        };
+
+        ranges.sort();
+
+        self.blocked.iter().fold(ranges, |ranges, &(blocked_from, blocked_to)| {
+            ranges
+                .into_iter()
+                .flat_map(|(from, to)| {
+                    let mut remaining_ranges = vec![];
+
+                    if from < blocked_from {
+                        remaining_ranges.push((from, min(to, blocked_from.saturating_sub(1))));
+                    }
+
+                    if to > blocked_to {
+                        remaining_ranges.push((max(from, blocked_to.saturating_add(1)), to));
+                    }
+
+                    remaining_ranges
+                })
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod block_number_conversions_test {
+    use super::*;
+
+    #[test]
+    fn converts_non_negative_i64s() {
+        assert_eq!(BlockNumber::try_from(0_i64), Ok(BlockNumber::new(0)));
+        assert_eq!(
+            BlockNumber::try_from(i64::MAX),
+            Ok(BlockNumber::new(i64::MAX as u64))
+        );
+    }
+
+    #[test]
+    fn rejects_negative_i64s() {
+        assert_eq!(
+            BlockNumber::try_from(-1_i64),
+            Err(BlockNumberError::Negative(-1))
+        );
+        assert_eq!(
+            BlockNumber::try_from(i64::MIN),
+            Err(BlockNumberError::Negative(i64::MIN))
+        );
+    }
+
+    #[test]
+    fn converts_back_to_i64_up_to_its_max() {
+        assert_eq!(i64::try_from(BlockNumber::new(0)), Ok(0));
+        assert_eq!(
+            i64::try_from(BlockNumber::new(i64::MAX as u64)),
+            Ok(i64::MAX)
+        );
+    }
+
+    #[test]
+    fn rejects_u64s_beyond_i64_max() {
+        let beyond_i64_max = i64::MAX as u64 + 1;
+
+        assert_eq!(
+            i64::try_from(BlockNumber::new(beyond_i64_max)),
+            Err(BlockNumberError::Overflow(beyond_i64_max))
+        );
+        assert_eq!(
+            i64::try_from(BlockNumber::new(u64::MAX)),
+            Err(BlockNumberError::Overflow(u64::MAX))
+        );
+    }
+
+    #[test]
+    fn saturates_arithmetic_at_boundaries() {
+        assert_eq!(BlockNumber::new(3).saturating_sub(10), BlockNumber::new(0));
+        assert_eq!(
+            BlockNumber::new(u64::MAX).saturating_add(1),
+            BlockNumber::new(u64::MAX)
+        );
+    }
+
+    #[test]
+    fn converts_to_and_from_u64s() {
+        let block_number = BlockNumber::from(U64::from(17773490));
+
+        assert_eq!(block_number.value(), 17773490);
+        assert_eq!(U64::from(block_number), U64::from(17773490));
+    }
+}
+
+#[cfg(test)]
+mod block_ranges_split_test {
+    use super::*;
+
+    #[test]
+    fn returns_the_whole_range_by_default() {
+        assert_eq!(
+            BlockRanges::default().split(block(10), block(20)),
+            vec![(block(10), block(20))]
+        );
+    }
+
+    #[test]
+    fn carves_out_blocked_ranges() {
+        let mut block_ranges = BlockRanges::default();
+        block_ranges.block(block(13), block(15));
+        block_ranges.block(block(18), block(30));
+
+        assert_eq!(
+            block_ranges.split(block(10), block(20)),
+            vec![(block(10), block(12)), (block(16), block(17))]
+        );
+    }
+
+    #[test]
+    fn returns_nothing_when_the_whole_range_is_blocked() {
+        let mut block_ranges = BlockRanges::default();
+        block_ranges.block(block(5), block(25));
+
+        assert!(block_ranges.split(block(10), block(20)).is_empty());
+    }
+
+    #[test]
+    fn only_keeps_allowed_ranges() {
+        let mut block_ranges = BlockRanges::default();
+        block_ranges.allow(block(18), block(40));
+        block_ranges.allow(block(0), block(11));
+        block_ranges.allow(block(50), block(60));
+
+        assert_eq!(
+            block_ranges.split(block(10), block(20)),
+            vec![(block(10), block(11)), (block(18), block(20))]
+        );
+    }
+
+    #[test]
+    fn carves_out_blocked_ranges_from_allowed_ones() {
+        let mut block_ranges = BlockRanges::default();
+        block_ranges.allow(block(10), block(20));
+        block_ranges.block(block(12), block(12));
+
+        assert_eq!(
+            block_ranges.split(block(0), block(100)),
+            vec![(block(10), block(11)), (block(13), block(20))]
+        );
+    }
+
+    fn block(value: u64) -> BlockNumber {
+        BlockNumber::new(value)
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+
+use crate::{BlockNumber, ContractAddress};
+
+/// Called with a contract address once its ingestion first gets within
+/// `Config::caught_up_window` blocks of its chain's current block.
+pub type OnCaughtUp = Arc<dyn Fn(&ContractAddress) + Send + Sync>;
+
+/// Contract addresses whose ingestion has caught up to their chains' heads.
+/// Clones share the same addresses, so `on_caught_up` fires once per contract
+/// address for the indexer's lifetime.
+#[derive(Clone, Debug, Default)]
+pub struct CaughtUpContractAddresses(Arc<RwLock<HashSet<i32>>>);
+
+impl CaughtUpContractAddresses {
+    /// Returns true only the first time the contract address is within the window
+    pub fn catch_up(
+        &self,
+        contract_address_id: i32,
+        next_block_number_to_ingest_from: BlockNumber,
+        current_block_number: BlockNumber,
+        window: u64,
+    ) -> bool {
+        next_block_number_to_ingest_from.saturating_add(window) >= current_block_number
+            && self.0.write().unwrap().insert(contract_address_id)
+    }
+
+    pub fn is_caught_up(&self, contract_address_id: i32) -> bool {
+        self.0.read().unwrap().contains(&contract_address_id)
+    }
+}
+
+#[cfg(test)]
+mod caught_up_contract_addresses_test {
+    use super::*;
+
+    #[test]
+    fn does_not_catch_up_outside_the_window() {
+        let caught_up_contract_addresses = CaughtUpContractAddresses::default();
+
+        assert!(!caught_up_contract_addresses.catch_up(
+            1,
+            BlockNumber::new(80),
+            BlockNumber::new(100),
+            10
+        ));
+        assert!(!caught_up_contract_addresses.is_caught_up(1));
+    }
+
+    #[test]
+    fn catches_up_only_once() {
+        let caught_up_contract_addresses = CaughtUpContractAddresses::default();
+        let cloned_caught_up_contract_addresses = caught_up_contract_addresses.clone();
+
+        assert!(caught_up_contract_addresses.catch_up(
+            1,
+            BlockNumber::new(95),
+            BlockNumber::new(100),
+            10
+        ));
+        assert!(!cloned_caught_up_contract_addresses.catch_up(
+            1,
+            BlockNumber::new(101),
+            BlockNumber::new(101),
+            10
+        ));
+        assert!(cloned_caught_up_contract_addresses.is_caught_up(1));
+    }
+}
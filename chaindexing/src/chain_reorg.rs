@@ -1,6 +1,7 @@
 use std::{cmp::max, collections::HashMap};
 
 use crate::diesels::schema::chaindexing_reorged_blocks;
+use crate::BlockNumber;
 use diesel::prelude::{Insertable, Queryable};
 
 use ethers::types::Chain;
@@ -16,8 +17,21 @@ impl MinConfirmationCount {
         Self { value }
     }
 
-    pub fn deduct_from(&self, block_number: u64, start_block_number: u64) -> u64 {
-        max(start_block_number, block_number - (self.value as u64))
+    /// Instantly final chains never reorg, so have nothing to confirm
+    pub fn is_instant_finality(&self) -> bool {
+        self.value == 0
+    }
+
+    /// Clamped to the start block, which saturates at the genesis block
+    pub fn deduct_from(
+        &self,
+        block_number: BlockNumber,
+        start_block_number: BlockNumber,
+    ) -> BlockNumber {
+        max(
+            start_block_number,
+            block_number.saturating_sub(u64::from(self.value)),
+        )
     }
 }
 
@@ -25,6 +39,17 @@ impl MinConfirmationCount {
 pub enum Execution<'a> {
     Main,
     Confirmation(&'a MinConfirmationCount),
+    /// Over the blocks right past the confirmation window, as many as it
+    /// spans, to detect reorgs deeper than it. See `FinalityViolation`.
+    FinalityCheck(&'a MinConfirmationCount),
+    /// Over an explicit block range, e.g. to reconcile a window on demand
+    Reconciliation(BlockNumber, BlockNumber),
+    /// Over an exact block range regardless of ingestion cursors, e.g. to
+    /// ingest history again
+    Backfill {
+        from: BlockNumber,
+        to: BlockNumber,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Queryable)]
@@ -35,6 +60,18 @@ pub struct ReorgedBlock {
     pub chain_id: i32,
     handled_at: Option<chrono::NaiveDateTime>,
     inserted_at: chrono::NaiveDateTime,
+    /// The chain's current block when the reorg got detected
+    pub current_block_number: Option<i64>,
+}
+
+impl ReorgedBlock {
+    /// Blocks from the reorged block through the chain's current block when
+    /// the reorg got detected, unless recorded before depths were
+    pub fn get_depth(&self) -> Option<u64> {
+        let current_block_number = self.current_block_number?;
+
+        Some(u64::try_from(current_block_number - self.block_number + 1).unwrap_or(0))
+    }
 }
 
 #[derive(Debug, Clone, Insertable)]
@@ -44,15 +81,61 @@ pub struct UnsavedReorgedBlock {
     pub chain_id: i32,
     handled_at: Option<chrono::NaiveDateTime>,
     inserted_at: chrono::NaiveDateTime,
+    current_block_number: Option<i64>,
 }
 
 impl UnsavedReorgedBlock {
-    pub fn new(block_number: i64, chain: &Chain) -> Self {
+    pub fn new(block_number: i64, chain: &Chain, inserted_at: chrono::NaiveDateTime) -> Self {
         Self {
             block_number,
             chain_id: *chain as i32,
             handled_at: None,
-            inserted_at: chrono::Utc::now().naive_utc(),
+            inserted_at,
+            current_block_number: None,
+        }
+    }
+
+    pub fn with_current_block_number(mut self, current_block_number: Option<BlockNumber>) -> Self {
+        self.current_block_number =
+            current_block_number.map(|block_number| i64::try_from(block_number).unwrap());
+
+        self
+    }
+
+    pub fn with_handled_at(mut self, handled_at: chrono::NaiveDateTime) -> Self {
+        self.handled_at = Some(handled_at);
+
+        self
+    }
+}
+
+/// How deep a chain's reorgs got, in blocks, e.g. to pick its
+/// `MinConfirmationCount` from data rather than guessing
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReorgDepthStats {
+    pub max: u64,
+    /// Nearest-rank 95th percentile
+    pub p95: u64,
+    pub mean: f64,
+    pub count: u64,
+}
+
+impl ReorgDepthStats {
+    pub fn new(reorged_blocks: &[ReorgedBlock]) -> Self {
+        let mut depths: Vec<_> = reorged_blocks.iter().filter_map(|r| r.get_depth()).collect();
+        depths.sort();
+
+        let Some(max) = depths.last().copied() else {
+            return Self::default();
+        };
+        let count = depths.len() as u64;
+        let p95_rank = (count * 95).div_ceil(100);
+
+        Self {
+            max,
+            p95: depths[p95_rank as usize - 1],
+            mean: depths.iter().sum::<u64>() as f64 / count as f64,
+            count,
         }
     }
 }
@@ -1,5 +1,53 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
 
 pub use ethers::prelude::Chain;
 
 pub type Chains = HashMap<Chain, String>;
+
+/// Runtime switch for temporarily pausing the ingestion of specific chains,
+/// e.g. during a provider's maintenance. Paused chains keep their cursors
+/// and resume from where they stopped.
+#[derive(Clone, Debug, Default)]
+pub struct PausedChains(Arc<RwLock<HashSet<Chain>>>);
+
+impl PausedChains {
+    pub fn pause(&self, chain: &Chain) {
+        self.0.write().unwrap().insert(*chain);
+    }
+
+    pub fn resume(&self, chain: &Chain) {
+        self.0.write().unwrap().remove(chain);
+    }
+
+    pub fn is_paused(&self, chain: &Chain) -> bool {
+        self.0.read().unwrap().contains(chain)
+    }
+}
+
+#[cfg(test)]
+mod paused_chains_test {
+    use super::*;
+
+    #[test]
+    fn pauses_and_resumes_chains() {
+        let paused_chains = PausedChains::default();
+
+        paused_chains.pause(&Chain::Mainnet);
+        assert!(paused_chains.is_paused(&Chain::Mainnet));
+        assert!(!paused_chains.is_paused(&Chain::Polygon));
+
+        paused_chains.resume(&Chain::Mainnet);
+        assert!(!paused_chains.is_paused(&Chain::Mainnet));
+    }
+
+    #[test]
+    fn shares_paused_chains_across_clones() {
+        let paused_chains = PausedChains::default();
+        let cloned_paused_chains = paused_chains.clone();
+
+        paused_chains.pause(&Chain::Mainnet);
+
+        assert!(cloned_paused_chains.is_paused(&Chain::Mainnet));
+    }
+}
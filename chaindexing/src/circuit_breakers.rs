@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::Chain;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChainCircuitState {
+    /// Ingesting as usual
+    Closed,
+    /// Skipped after sustained failures, until the cooldown elapses
+    Open,
+    /// Cooled down, with the next tick testing whether the chain recovered
+    HalfOpen,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct ChainCircuit {
+    consecutive_failures_count: u64,
+    opened_at: Option<Instant>,
+}
+
+/// Stops ingesting a chain once `failure_threshold` ticks in a row failed,
+/// for `cooldown`, then lets a single tick through: succeeding closes the
+/// circuit again, while failing re-opens it for another cooldown. Clones
+/// share the same circuits, so they persist across ticks.
+#[derive(Clone, Debug)]
+pub struct ChainCircuitBreakers {
+    failure_threshold: u64,
+    cooldown: Duration,
+    by_chain: Arc<RwLock<HashMap<Chain, ChainCircuit>>>,
+}
+
+impl ChainCircuitBreakers {
+    pub fn new(failure_threshold: u64, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            by_chain: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    pub fn get_state(&self, chain: &Chain, now: Instant) -> ChainCircuitState {
+        let by_chain = self.by_chain.read().unwrap();
+
+        match by_chain.get(chain).and_then(|circuit| circuit.opened_at) {
+            None => ChainCircuitState::Closed,
+            Some(opened_at) if now.saturating_duration_since(opened_at) < self.cooldown => {
+                ChainCircuitState::Open
+            }
+            Some(_) => ChainCircuitState::HalfOpen,
+        }
+    }
+
+    pub fn succeed(&self, chain: &Chain) {
+        self.by_chain.write().unwrap().remove(chain);
+    }
+
+    /// Returns true whenever the failure (re-)opens the chain's circuit
+    pub fn fail(&self, chain: &Chain, now: Instant) -> bool {
+        let mut by_chain = self.by_chain.write().unwrap();
+        let circuit = by_chain.entry(*chain).or_default();
+
+        circuit.consecutive_failures_count += 1;
+
+        if circuit.consecutive_failures_count >= self.failure_threshold {
+            circuit.opened_at = Some(now);
+
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod chain_circuit_breakers_test {
+    use super::*;
+
+    #[test]
+    fn opens_after_consecutive_failures_only() {
+        let circuit_breakers = ChainCircuitBreakers::new(3, Duration::from_secs(60));
+        let now = Instant::now();
+
+        assert!(!circuit_breakers.fail(&Chain::Mainnet, now));
+        circuit_breakers.succeed(&Chain::Mainnet);
+        assert!(!circuit_breakers.fail(&Chain::Mainnet, now));
+        assert!(!circuit_breakers.fail(&Chain::Mainnet, now));
+        assert_eq!(
+            circuit_breakers.get_state(&Chain::Mainnet, now),
+            ChainCircuitState::Closed
+        );
+
+        assert!(circuit_breakers.fail(&Chain::Mainnet, now));
+        assert_eq!(
+            circuit_breakers.get_state(&Chain::Mainnet, now),
+            ChainCircuitState::Open
+        );
+        assert_eq!(
+            circuit_breakers.get_state(&Chain::Polygon, now),
+            ChainCircuitState::Closed
+        );
+    }
+
+    #[test]
+    fn half_opens_after_the_cooldown_to_test_recovery() {
+        let circuit_breakers = ChainCircuitBreakers::new(1, Duration::from_secs(60));
+        let opened_at = Instant::now();
+        circuit_breakers.fail(&Chain::Mainnet, opened_at);
+
+        let cooled_down_at = opened_at + Duration::from_secs(60);
+        assert_eq!(
+            circuit_breakers.get_state(&Chain::Mainnet, cooled_down_at),
+            ChainCircuitState::HalfOpen
+        );
+
+        // Failing again re-opens it for another cooldown
+        assert!(circuit_breakers.clone().fail(&Chain::Mainnet, cooled_down_at));
+        assert_eq!(
+            circuit_breakers.get_state(&Chain::Mainnet, cooled_down_at),
+            ChainCircuitState::Open
+        );
+
+        circuit_breakers.succeed(&Chain::Mainnet);
+        assert_eq!(
+            circuit_breakers.get_state(&Chain::Mainnet, cooled_down_at),
+            ChainCircuitState::Closed
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use chrono::NaiveDateTime;
+
+/// Source of time for chaindexing's time-dependent logic, e.g. timestamps of
+/// ingested events and reorged blocks, or the adaptive ingestion interval.
+pub trait Clock: Send + Sync {
+    /// Current UTC date and time
+    fn now(&self) -> NaiveDateTime;
+    /// Monotonic time, for measuring elapsed durations
+    fn instant(&self) -> Instant;
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        chrono::Utc::now().naive_utc()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Only moves when advanced, for deterministic tests of time-dependent logic.
+/// Clones share the same time, so tests can keep advancing the config's clock.
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    started_at: NaiveDateTime,
+    started_at_instant: Instant,
+    elapsed: Arc<RwLock<Duration>>,
+}
+
+impl MockClock {
+    pub fn new(started_at: NaiveDateTime) -> Self {
+        Self {
+            started_at,
+            started_at_instant: Instant::now(),
+            elapsed: Arc::new(RwLock::new(Duration::ZERO)),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.write().unwrap() += duration;
+    }
+
+    fn get_elapsed(&self) -> Duration {
+        *self.elapsed.read().unwrap()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> NaiveDateTime {
+        self.started_at + chrono::Duration::from_std(self.get_elapsed()).unwrap()
+    }
+
+    fn instant(&self) -> Instant {
+        self.started_at_instant + self.get_elapsed()
+    }
+}
+
+#[cfg(test)]
+mod mock_clock_test {
+    use super::*;
+
+    #[test]
+    fn only_moves_when_advanced() {
+        let started_at = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap().naive_utc();
+        let clock = MockClock::new(started_at);
+        let started_at_instant = clock.instant();
+
+        assert_eq!(clock.now(), started_at);
+        assert_eq!(clock.instant(), started_at_instant);
+
+        clock.clone().advance(Duration::from_secs(90));
+
+        assert_eq!(clock.now(), started_at + chrono::Duration::seconds(90));
+        assert_eq!(
+            clock.instant().duration_since(started_at_instant),
+            Duration::from_secs(90)
+        );
+    }
+}
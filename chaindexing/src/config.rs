@@ -1,64 +1,949 @@
-use crate::{ChaindexingRepo, Chains, Contract, MinConfirmationCount};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ethers::types::{Block, TxHash};
+use futures_core::Stream;
+use reqwest::Url;
+
+use crate::chains::PausedChains;
+use crate::env_configs::{
+    EnvVars, BLOCKS_PER_BATCH_ENV_VAR, CHAINS_ENV_VAR, CHAIN_MIN_CONFIRMATION_COUNTS_ENV_VAR,
+    DATABASE_URL_ENV_VAR, HANDLER_INTERVAL_MS_ENV_VAR, INGESTION_INTERVAL_MS_ENV_VAR,
+    MIN_CONFIRMATION_COUNT_ENV_VAR,
+};
+use crate::{
+    AdaptiveBlocksPerBatch, BatchTimings, BlockFilter, BlockNumber, CaughtUpContractAddresses,
+    Chain, ChainCircuitBreakers, ChainCircuitState, ChaindexingRepo, ChaindexingRepoConn, Chains,
+    Clock, Contract, ContractAddress, ContractStatus, Contracts, Deployment, DeploymentManifest,
+    DeploymentManifestError, EnvConfigError, Event, EventSubscriptions, EventsIngesterJsonRpc,
+    EventsPartitioning, FatalErrorPolicy, FinalityViolation, HandlerMismatch, HandlingThrottle,
+    HttpJsonRpc, HttpJsonRpcClients, InconsistentEvent, LaggingNode, Metric, MinConfirmationCount,
+    OnBatchTimings, OnCaughtUp, OnEventsIngested, OnFinalityViolation, OnInconsistentEvent,
+    OnLaggingNode, OnMetric, PipelineMode, ReorgReport, ReorgReporter, Repo, SystemClock,
+    WritePressure,
+};
+#[cfg(feature = "pipeline-spans")]
+use crate::{OnPipelineSpan, PipelineSpan};
 
 #[derive(Clone)]
 pub struct Config {
     pub chains: Chains,
+    pub json_rpc_headers: HashMap<Chain, HashMap<String, String>>,
+    pub json_rpc_timeout: Option<Duration>,
+    pub max_json_rpc_retries: Option<u32>,
+    pub json_rpc_clients: HttpJsonRpcClients,
     pub repo: ChaindexingRepo,
+    pub events_partitioning: Option<EventsPartitioning>,
     pub contracts: Vec<Contract>,
+    pub shadow_contracts: Vec<Contract>,
     pub min_confirmation_count: MinConfirmationCount,
+    pub chain_min_confirmation_counts: HashMap<Chain, MinConfirmationCount>,
     pub blocks_per_batch: u64,
+    pub adaptive_blocks_per_batch: Option<AdaptiveBlocksPerBatch>,
+    pub initial_sync_parallelism: u64,
+    pub ingestion_checkpoint_interval: u64,
+    pub write_pressure: Option<WritePressure>,
+    pub fast_forward_window: u64,
+    pub max_blocks_per_tick: u64,
+    pub end_block_number: Option<BlockNumber>,
     pub handler_interval_ms: u64,
+    pub handler_parallelism: u64,
     pub ingestion_interval_ms: u64,
+    pub adaptive_ingestion_interval: bool,
+    pub pipeline_mode: PipelineMode,
     pub reset_count: u8,
+    pub paused_chains: PausedChains,
+    pub chain_circuit_breakers: Option<ChainCircuitBreakers>,
+    pub fatal_error_policy: FatalErrorPolicy,
+    pub fetch_transaction_statuses: bool,
+    pub store_raw_logs: bool,
+    pub skip_undecodable_logs: bool,
+    pub block_filter: Option<BlockFilter>,
+    pub coalesce_log_filters: bool,
+    pub soft_delete_removed_events: bool,
+    pub confirm_by_block_hash: bool,
+    pub deduplicate_state_versions: bool,
+    pub max_events_per_handling_batch: u64,
+    pub handler_timeout: Option<Duration>,
+    pub handling_throttle: Option<HandlingThrottle>,
+    pub max_handler_commit_retries: u64,
+    pub on_caught_up: Option<OnCaughtUp>,
+    pub caught_up_window: u64,
+    pub caught_up_contract_addresses: CaughtUpContractAddresses,
+    pub on_metric: Option<OnMetric>,
+    pub on_events_ingested: Option<OnEventsIngested>,
+    pub on_lagging_node: Option<OnLaggingNode>,
+    pub on_finality_violation: Option<OnFinalityViolation>,
+    pub on_inconsistent_event: Option<OnInconsistentEvent>,
+    pub on_batch_timings: Option<OnBatchTimings>,
+    #[cfg(feature = "pipeline-spans")]
+    pub on_pipeline_span: Option<OnPipelineSpan>,
+    pub reorg_reporter: Option<ReorgReporter>,
+    pub reorged_blocks_retention: Option<Duration>,
+    pub event_subscriptions: EventSubscriptions,
+    pub clock: Arc<dyn Clock>,
 }
 
 impl Config {
     pub fn new(repo: ChaindexingRepo, chains: Chains) -> Self {
         Self {
             repo,
+            events_partitioning: None,
             chains,
+            json_rpc_headers: HashMap::new(),
+            json_rpc_timeout: None,
+            max_json_rpc_retries: None,
+            json_rpc_clients: HttpJsonRpcClients::default(),
             contracts: vec![],
+            shadow_contracts: vec![],
             min_confirmation_count: MinConfirmationCount::new(40),
+            chain_min_confirmation_counts: HashMap::new(),
             blocks_per_batch: 10000,
+            adaptive_blocks_per_batch: None,
+            initial_sync_parallelism: 1,
+            ingestion_checkpoint_interval: 0,
+            write_pressure: None,
+            fast_forward_window: 0,
+            max_blocks_per_tick: 0,
+            end_block_number: None,
             handler_interval_ms: 4000,
+            handler_parallelism: 1,
             ingestion_interval_ms: 4000,
+            adaptive_ingestion_interval: false,
+            pipeline_mode: PipelineMode::default(),
             reset_count: 0,
+            paused_chains: PausedChains::default(),
+            chain_circuit_breakers: None,
+            fatal_error_policy: FatalErrorPolicy::default(),
+            fetch_transaction_statuses: false,
+            store_raw_logs: false,
+            skip_undecodable_logs: false,
+            block_filter: None,
+            coalesce_log_filters: false,
+            soft_delete_removed_events: false,
+            confirm_by_block_hash: false,
+            deduplicate_state_versions: false,
+            max_events_per_handling_batch: 1000,
+            handler_timeout: None,
+            handling_throttle: None,
+            max_handler_commit_retries: 3,
+            on_caught_up: None,
+            caught_up_window: 10,
+            caught_up_contract_addresses: CaughtUpContractAddresses::default(),
+            on_metric: None,
+            on_events_ingested: None,
+            on_lagging_node: None,
+            on_finality_violation: None,
+            on_inconsistent_event: None,
+            on_batch_timings: None,
+            #[cfg(feature = "pipeline-spans")]
+            on_pipeline_span: None,
+            reorg_reporter: None,
+            reorged_blocks_retention: None,
+            event_subscriptions: EventSubscriptions::default(),
+            clock: Arc::new(SystemClock),
         }
     }
 
+    /// Reads the database URL, chains and tuning from the process's
+    /// environment variables, see `env_configs`. Contracts still get added in
+    /// code. Errs with every missing or invalid environment variable at once.
+    /// There is no pool size to read: ingestion and handling each hold a
+    /// single connection, and consumers size their own pools with
+    /// `Chaindexing::get_pool`.
+    pub fn from_env() -> Result<Self, Vec<EnvConfigError>> {
+        Self::from_env_vars(&std::env::vars().collect())
+    }
+
+    pub fn from_env_vars(vars: &HashMap<String, String>) -> Result<Self, Vec<EnvConfigError>> {
+        let mut env_vars = EnvVars::new(vars);
+
+        let database_url = env_vars.get_required(DATABASE_URL_ENV_VAR);
+        let chains = env_vars
+            .get_required(CHAINS_ENV_VAR)
+            .map(|chains| env_vars.parse_by_chain::<String>(CHAINS_ENV_VAR, &chains));
+        if chains.as_ref().is_some_and(|chains| chains.is_empty()) {
+            env_vars.errors.push(EnvConfigError::Invalid(
+                CHAINS_ENV_VAR,
+                "Expected at least one chain".to_string(),
+            ));
+        }
+        let blocks_per_batch = env_vars.parse(BLOCKS_PER_BATCH_ENV_VAR);
+        let ingestion_interval_ms = env_vars.parse(INGESTION_INTERVAL_MS_ENV_VAR);
+        let handler_interval_ms = env_vars.parse(HANDLER_INTERVAL_MS_ENV_VAR);
+        let min_confirmation_count = env_vars.parse(MIN_CONFIRMATION_COUNT_ENV_VAR);
+        let chain_min_confirmation_counts = env_vars
+            .get(CHAIN_MIN_CONFIRMATION_COUNTS_ENV_VAR)
+            .map(|counts| {
+                env_vars.parse_by_chain::<u8>(CHAIN_MIN_CONFIRMATION_COUNTS_ENV_VAR, &counts)
+            })
+            .unwrap_or_default();
+
+        let (Some(database_url), Some(chains), true) =
+            (database_url, chains, env_vars.errors.is_empty())
+        else {
+            return Err(env_vars.errors);
+        };
+
+        let mut config = Self::new(ChaindexingRepo::new(&database_url), chains);
+
+        if let Some(blocks_per_batch) = blocks_per_batch {
+            config = config.with_blocks_per_batch(blocks_per_batch);
+        }
+        if let Some(ingestion_interval_ms) = ingestion_interval_ms {
+            config = config.with_ingestion_interval_ms(ingestion_interval_ms);
+        }
+        if let Some(handler_interval_ms) = handler_interval_ms {
+            config = config.with_handler_interval_ms(handler_interval_ms);
+        }
+        if let Some(min_confirmation_count) = min_confirmation_count {
+            config = config.with_min_confirmation_count(min_confirmation_count);
+        }
+        for (chain, min_confirmation_count) in chain_min_confirmation_counts {
+            config = config.with_chain_min_confirmation_count(&chain, min_confirmation_count);
+        }
+
+        Ok(config)
+    }
+
+    /// Panics when any of the contract's addresses is on a chain the contract
+    /// is not restricted to, see `Contract::with_chains`
     pub fn add_contract(mut self, contract: Contract) -> Self {
+        if let Some(misplaced_address) = contract.get_misplaced_addresses().first() {
+            panic!(
+                "Contract {} does not run on chain {} of its address {}",
+                contract.name,
+                misplaced_address.chain_id,
+                misplaced_address.get_address()
+            );
+        }
+
         self.contracts.push(contract);
 
         self
     }
 
+    /// Registers other handlers, and state migrations, for the added contract
+    /// of the same name, e.g. with changed logic, to run in shadow mode with
+    /// `HandleEvents::rebuild_shadow_state`. Their states get written to the
+    /// `SHADOW_STATE_SCHEMA` schema, to be compared with the live ones using
+    /// `HandleEvents::diff_shadow_state` before cutting over. Its addresses
+    /// are ignored: the ones of the added contract get replayed.
+    pub fn add_shadow_contract(mut self, shadow_contract: Contract) -> Self {
+        self.shadow_contracts.push(shadow_contract);
+
+        self
+    }
+
+    /// Adds the manifest's contract addresses to the already added contracts
+    /// of the same names, instead of hand-coding each `add_address`
+    pub fn add_deployment_manifest(
+        mut self,
+        manifest: &DeploymentManifest,
+    ) -> Result<Self, DeploymentManifestError> {
+        for Deployment {
+            chain,
+            contract_name,
+            address,
+            start_block_number,
+        } in manifest.deployments.iter()
+        {
+            let contract = self
+                .contracts
+                .iter_mut()
+                .find(|contract| &contract.name == contract_name)
+                .ok_or_else(|| DeploymentManifestError::UnknownContract(contract_name.clone()))?;
+
+            if !contract.runs_on(chain) {
+                return Err(DeploymentManifestError::UnsupportedChain(format!(
+                    "{contract_name} on {chain}"
+                )));
+            }
+
+            *contract = contract.add_address(address, chain, *start_block_number);
+        }
+
+        Ok(self)
+    }
+
+    /// Sends the HTTP header along with every request to the chain's JSON RPC,
+    /// e.g. `Authorization`, to avoid embedding secrets in its URL.
+    pub fn add_json_rpc_header(mut self, chain: &Chain, name: &str, value: &str) -> Self {
+        self.json_rpc_headers
+            .entry(*chain)
+            .or_default()
+            .insert(name.to_string(), value.to_string());
+
+        self
+    }
+
     pub fn reset(mut self, count: u8) -> Self {
         self.reset_count = count;
 
         self
     }
 
+    /// Zero skips confirming ingested events against chain reorgs altogether,
+    /// e.g. when every configured chain has instant finality.
     pub fn with_min_confirmation_count(mut self, min_confirmation_count: u8) -> Self {
         self.min_confirmation_count = MinConfirmationCount::new(min_confirmation_count);
 
         self
     }
 
+    /// Overrides `min_confirmation_count` for the chain, e.g. zero for chains
+    /// with instant finality, which never reorg.
+    pub fn with_chain_min_confirmation_count(
+        mut self,
+        chain: &Chain,
+        min_confirmation_count: u8,
+    ) -> Self {
+        self.chain_min_confirmation_counts
+            .insert(*chain, MinConfirmationCount::new(min_confirmation_count));
+
+        self
+    }
+
     pub fn with_blocks_per_batch(mut self, blocks_per_batch: u64) -> Self {
         self.blocks_per_batch = blocks_per_batch;
 
         self
     }
 
+    /// Halves each chain's `blocks_per_batch` whenever fetching logs fails,
+    /// e.g. on rate limit or range limit errors, retrying right away with
+    /// narrower ranges, then grows it back after sustained success, all within
+    /// the given bounds. See `AdaptiveBlocksPerBatch`.
+    pub fn with_adaptive_blocks_per_batch(
+        mut self,
+        min_blocks_per_batch: u64,
+        max_blocks_per_batch: u64,
+    ) -> Self {
+        self.adaptive_blocks_per_batch = Some(AdaptiveBlocksPerBatch::new(
+            min_blocks_per_batch,
+            max_blocks_per_batch,
+        ));
+
+        self
+    }
+
+    /// Fetches up to this many consecutive batches of a contract address's
+    /// logs concurrently while it is far behind, e.g. on a cold start. The
+    /// batches' events are inserted together with the advanced cursor, so a
+    /// crash resumes from the last fully ingested batches.
+    pub fn with_initial_sync_parallelism(mut self, initial_sync_parallelism: u64) -> Self {
+        self.initial_sync_parallelism = initial_sync_parallelism;
+
+        self
+    }
+
+    /// Commits concurrently fetched batches, with their advanced cursors,
+    /// about every this many blocks instead of once per ingestion pass, so that
+    /// crashing midway through `initial_sync_parallelism` batches resumes from
+    /// the last checkpoint. Rounded down to whole batches. Disabled by default.
+    pub fn with_ingestion_checkpoint_interval(
+        mut self,
+        ingestion_checkpoint_interval: u64,
+    ) -> Self {
+        self.ingestion_checkpoint_interval = ingestion_checkpoint_interval;
+
+        self
+    }
+
+    /// Pauses ingestion for `backoff` whenever writing a batch's events to the
+    /// database takes longer than `max_write_latency`, resuming once writes
+    /// get fast again. With `with_ingestion_checkpoint_interval`, remaining
+    /// batches of the pass get skipped too. See `WritePressure`.
+    pub fn with_max_write_latency(
+        mut self,
+        max_write_latency: Duration,
+        backoff: Duration,
+    ) -> Self {
+        self.write_pressure = Some(WritePressure::new(max_write_latency, backoff));
+
+        self
+    }
+
+    /// Contract addresses whose batches had no events, and are then within
+    /// this many blocks of the current block, get fetched through the current
+    /// block in one go rather than another batch per ingestion pass, e.g. for
+    /// chains with sparse relevant activity. Keep it within the JSON RPC's
+    /// `eth_getLogs` range limit. Disabled by default.
+    pub fn with_fast_forward_window(mut self, fast_forward_window: u64) -> Self {
+        self.fast_forward_window = fast_forward_window;
+
+        self
+    }
+
+    /// Caps the blocks ingested across all contract addresses of a chain
+    /// within a single ingestion tick, so that ticks stay short with large
+    /// batches and many contracts. Contract addresses get the budget in order,
+    /// and whatever is left carries over to the next ticks. Unlimited by default.
+    pub fn with_max_blocks_per_tick(mut self, max_blocks_per_tick: u64) -> Self {
+        self.max_blocks_per_tick = max_blocks_per_tick;
+
+        self
+    }
+
+    /// Stops ingesting every contract address once it has ingested this
+    /// (inclusive) block, instead of following the chain's head, e.g. for
+    /// bounded analyses over a fixed block range. The events ingester then
+    /// idles, while handling carries on up to the end block.
+    pub fn with_end_block(mut self, end_block_number: u64) -> Self {
+        self.end_block_number = Some(BlockNumber::new(end_block_number));
+
+        self
+    }
+
     pub fn with_handler_interval_ms(mut self, handler_interval_ms: u64) -> Self {
         self.handler_interval_ms = handler_interval_ms;
 
         self
     }
 
+    /// Handles up to this many contract addresses of equal priorities
+    /// concurrently, each over its own database connection. A contract
+    /// address's events are still handled one after the other.
+    pub fn with_handler_parallelism(mut self, handler_parallelism: u64) -> Self {
+        self.handler_parallelism = handler_parallelism.max(1);
+
+        self
+    }
+
     pub fn with_ingestion_interval_ms(mut self, ingestion_interval_ms: u64) -> Self {
         self.ingestion_interval_ms = ingestion_interval_ms;
 
         self
     }
+
+    /// Polls each chain about as often as it produces blocks, as observed from
+    /// its current block numbers, with `ingestion_interval_ms` as the slowest
+    /// interval. Costs an extra JSON RPC call per chain and poll.
+    pub fn with_adaptive_ingestion_interval(mut self, adaptive_ingestion_interval: bool) -> Self {
+        self.adaptive_ingestion_interval = adaptive_ingestion_interval;
+
+        self
+    }
+
+    /// See `PipelineMode`, `Decoupled` by default
+    pub fn with_pipeline_mode(mut self, pipeline_mode: PipelineMode) -> Self {
+        self.pipeline_mode = pipeline_mode;
+
+        self
+    }
+
+    /// Fetches each ingested event's transaction receipt to store its status.
+    /// Costs an extra JSON RPC call per transaction, hence off by default.
+    pub fn with_fetch_transaction_statuses(mut self, fetch_transaction_statuses: bool) -> Self {
+        self.fetch_transaction_statuses = fetch_transaction_statuses;
+
+        self
+    }
+
+    /// Stores each ingested event's raw log, i.e. its topics and data, to be
+    /// able to decode it again, e.g. after fixing a wrong ABI, without fetching
+    /// it again. See `Event::get_raw_log`.
+    pub fn with_store_raw_logs(mut self, store_raw_logs: bool) -> Self {
+        self.store_raw_logs = store_raw_logs;
+
+        self
+    }
+
+    /// Skips logs failing to decode, e.g. with corrupt data or an unexpected
+    /// ABI, with a warning, instead of halting their contract's ingestion.
+    /// Skipped logs are not stored: reconcile their blocks once their ABI or
+    /// log decoder is fixed, see `EventsIngester::reconcile`.
+    pub fn with_skip_undecodable_logs(mut self, skip_undecodable_logs: bool) -> Self {
+        self.skip_undecodable_logs = skip_undecodable_logs;
+
+        self
+    }
+
+    /// Only ingests events of blocks matching the filter, e.g. blocks of a
+    /// given miner, or every other block for sampling. Only consulted for
+    /// blocks with events of the contracts, once fetched. Events of skipped
+    /// blocks are never stored, so they are never checked for reorgs either,
+    /// while ingestion cursors still move past them.
+    pub fn with_block_filter(
+        mut self,
+        block_filter: impl Fn(&Block<TxHash>) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.block_filter = Some(Arc::new(block_filter));
+
+        self
+    }
+
+    /// Merges the log filters of contract addresses over the same blocks and
+    /// event topics into multi-address filters, to fetch their logs with
+    /// fewer `eth_getLogs` calls, e.g. for many addresses of the same contract.
+    /// Beware of providers capping the number of addresses per filter.
+    pub fn with_coalesce_log_filters(mut self, coalesce_log_filters: bool) -> Self {
+        self.coalesce_log_filters = coalesce_log_filters;
+
+        self
+    }
+
+    /// Keeps events removed by chain reorgs, marked as `removed` and referencing
+    /// their reorged block, instead of deleting them. Handlers still skip them.
+    pub fn with_soft_delete_removed_events(mut self, soft_delete_removed_events: bool) -> Self {
+        self.soft_delete_removed_events = soft_delete_removed_events;
+
+        self
+    }
+
+    /// Checks events' block hashes against the canonical blocks at their
+    /// numbers when confirming them, instead of only comparing block number
+    /// ranges, so events of a reorged-out block get detected even when a
+    /// provider still serves the stale branch. Blocks the provider does not
+    /// know of count as reorged out. Costs an extra JSON RPC call per block.
+    pub fn with_confirm_by_block_hash(mut self, confirm_by_block_hash: bool) -> Self {
+        self.confirm_by_block_hash = confirm_by_block_hash;
+
+        self
+    }
+
+    /// Skips writing a state version when an update leaves every field of the
+    /// state's latest version unchanged, instead of appending an identical row.
+    pub fn with_deduplicate_state_versions(mut self, deduplicate_state_versions: bool) -> Self {
+        self.deduplicate_state_versions = deduplicate_state_versions;
+
+        self
+    }
+
+    /// Caps how many events get loaded at once while handling. A block's
+    /// events can span several batches, in which case they are still handled
+    /// in a single transaction, with at most two batches in memory.
+    pub fn with_max_events_per_handling_batch(
+        mut self,
+        max_events_per_handling_batch: u64,
+    ) -> Self {
+        self.max_events_per_handling_batch = max_events_per_handling_batch;
+
+        self
+    }
+
+    /// Creates `chaindexing_events` as a partitioned table, e.g. for very large
+    /// indexers. Only takes effect on a fresh database or along with a reset:
+    /// setup panics on an already created, unpartitioned events table.
+    pub fn with_events_partitioning(mut self, events_partitioning: EventsPartitioning) -> Self {
+        self.events_partitioning = Some(events_partitioning);
+
+        self
+    }
+
+    /// Fails any JSON RPC request of the events ingester taking longer than
+    /// this, e.g. to an endpoint accepting connections but never responding,
+    /// instead of stalling the whole tick. Timeouts are retried with backoff
+    /// like any other provider error.
+    pub fn with_json_rpc_timeout(mut self, json_rpc_timeout: Duration) -> Self {
+        self.json_rpc_timeout = Some(json_rpc_timeout);
+
+        self
+    }
+
+    /// Fails the events ingester's tick once a JSON RPC request failed again
+    /// after this many retries, instead of retrying with backoff forever, e.g.
+    /// for a dead chain to open its circuit. See `with_chain_circuit_breaker`.
+    pub fn with_max_json_rpc_retries(mut self, max_json_rpc_retries: u32) -> Self {
+        self.max_json_rpc_retries = Some(max_json_rpc_retries);
+
+        self
+    }
+
+    /// Fails handling whenever a single `handle_event` call takes longer than
+    /// this, e.g. awaiting a hung external call, instead of blocking its
+    /// contract address's handling forever. Only handlers yielding to the
+    /// runtime can be timed out, not ones blocking their thread. Its
+    /// transaction is rolled back and the contract address's handling cursor
+    /// left as is, so the next run handles its events again from the last
+    /// committed block. Other contract addresses keep getting handled
+    /// meanwhile. Replays, e.g. `HandleEvents::verify_state`, get rolled back
+    /// and fail with `ContractStateError::HandlerTimeout` instead.
+    pub fn with_handler_timeout(mut self, handler_timeout: Duration) -> Self {
+        self.handler_timeout = Some(handler_timeout);
+
+        self
+    }
+
+    /// Paces handling to at most `max_events_per_second` events in total,
+    /// e.g. when handlers call an external API with a rate limit. Replays,
+    /// e.g. `HandleEvents::verify_state`, are not paced. See `HandlingThrottle`.
+    pub fn with_max_events_per_second(mut self, max_events_per_second: u64) -> Self {
+        self.handling_throttle = Some(HandlingThrottle::new(max_events_per_second));
+
+        self
+    }
+
+    /// Re-runs a handling transaction whose statements or commit failed on a
+    /// serialization failure or deadlock, e.g. with `with_handler_parallelism`,
+    /// on a fresh transaction up to this many times before panicking. Handlers
+    /// of its events get invoked again, so their side effects outside of the
+    /// transaction get repeated. Defaults to 3.
+    pub fn with_max_handler_commit_retries(mut self, max_handler_commit_retries: u64) -> Self {
+        self.max_handler_commit_retries = max_handler_commit_retries;
+
+        self
+    }
+
+    /// Notifies when a contract address is done backfilling history, i.e. its
+    /// ingestion first gets within `caught_up_window` blocks of the current
+    /// block, e.g. to flip downstream systems from batch to real-time mode.
+    pub fn with_on_caught_up(
+        mut self,
+        on_caught_up: impl Fn(&ContractAddress) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_caught_up = Some(Arc::new(on_caught_up));
+
+        self
+    }
+
+    pub fn with_caught_up_window(mut self, caught_up_window: u64) -> Self {
+        self.caught_up_window = caught_up_window;
+
+        self
+    }
+
+    /// Records ingestion and handling metrics, each labeled with its contract
+    /// address's chain, contract name and address, e.g. to spot a single
+    /// contract address lagging behind. See `MetricKind` for what is recorded.
+    pub fn with_on_metric(mut self, on_metric: impl Fn(&Metric) + Send + Sync + 'static) -> Self {
+        self.on_metric = Some(Arc::new(on_metric));
+
+        self
+    }
+
+    /// Inspects, augments or filters each batch of ingested events in one
+    /// place, right before persisting it. Runs before any handler, which only
+    /// gets the events left. Events added back by reorgs skip it.
+    pub fn with_on_events_ingested(
+        mut self,
+        on_events_ingested: impl Fn(&mut Vec<Event>) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_events_ingested = Some(Arc::new(on_events_ingested));
+
+        self
+    }
+
+    /// Notifies, besides warning, whenever a chain's current block is behind
+    /// blocks already ingested, instead of ingestion silently idling until the
+    /// node catches back up, e.g. to fail over to another JSON RPC.
+    pub fn with_on_lagging_node(
+        mut self,
+        on_lagging_node: impl Fn(&LaggingNode) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_lagging_node = Some(Arc::new(on_lagging_node));
+
+        self
+    }
+
+    /// Checks, on every confirmation pass, the blocks right past the
+    /// confirmation window for reorgs deeper than it, notifying besides
+    /// warning about each, e.g. to reconcile the chain manually. Costs a
+    /// `get_logs` call per confirmation pass. See `FinalityViolation`.
+    pub fn with_on_finality_violation(
+        mut self,
+        on_finality_violation: impl Fn(&FinalityViolation) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_finality_violation = Some(Arc::new(on_finality_violation));
+
+        self
+    }
+
+    /// Notifies, besides warning, whenever a JSON RPC serves an already
+    /// ingested log, by its transaction hash and log index, with different
+    /// data, e.g. to tell provider bugs apart from reorgs. See `InconsistentEvent`.
+    pub fn with_on_inconsistent_event(
+        mut self,
+        on_inconsistent_event: impl Fn(&InconsistentEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_inconsistent_event = Some(Arc::new(on_inconsistent_event));
+
+        self
+    }
+
+    /// Profiles ingestion with the time each batch spent fetching logs and
+    /// blocks, decoding and writing to the database. See `BatchTimings`.
+    pub fn with_on_batch_timings(
+        mut self,
+        on_batch_timings: impl Fn(&BatchTimings) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_batch_timings = Some(Arc::new(on_batch_timings));
+
+        self
+    }
+
+    /// Hooks into every event's journey from ingestion through handling, with
+    /// a span for each ingested batch and each handled event, reported once
+    /// committed, to bridge into any tracer. See `PipelineSpan`.
+    #[cfg(feature = "pipeline-spans")]
+    pub fn with_on_pipeline_span(
+        mut self,
+        on_pipeline_span: impl Fn(&PipelineSpan) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_pipeline_span = Some(Arc::new(on_pipeline_span));
+
+        self
+    }
+
+    /// Reports how many reorgs each chain had, and how deep, over every
+    /// `interval`, e.g. to alert on a chain reorging more than usual. Chains
+    /// with reorgs also get logged. See `ReorgReport`.
+    pub fn with_reorg_reports(
+        mut self,
+        interval: Duration,
+        on_reorg_report: impl Fn(&ReorgReport) + Send + Sync + 'static,
+    ) -> Self {
+        self.reorg_reporter = Some(ReorgReporter::new(interval, Arc::new(on_reorg_report)));
+
+        self
+    }
+
+    /// Prunes handled reorged blocks once older than `retention`, from the
+    /// confirmation pass, so they don't pile up on reorg-prone chains. Keep it
+    /// longer than the reorg reports' interval for them to stay complete.
+    pub fn with_reorged_blocks_retention(mut self, retention: Duration) -> Self {
+        self.reorged_blocks_retention = Some(retention);
+
+        self
+    }
+
+    /// Caps how many handled events each subscriber buffers before missing the
+    /// oldest ones. See `EventSubscriptions`.
+    pub fn with_event_subscriptions_capacity(mut self, capacity: usize) -> Self {
+        self.event_subscriptions = EventSubscriptions::new(capacity);
+
+        self
+    }
+
+    /// Streams the contract's events as they get handled, e.g. for reactive
+    /// consumers without an `EventHandler`. Filters by the given event ABIs,
+    /// unless empty. Subscribe before starting handlers to receive every event.
+    pub fn subscribe_to_events(
+        &self,
+        contract_name: &str,
+        event_abis: &[&str],
+    ) -> impl Stream<Item = Event> + Send + Unpin {
+        self.event_subscriptions.subscribe(contract_name, event_abis)
+    }
+
+    /// Replaces the system clock, e.g. with a `MockClock` to deterministically
+    /// test time-dependent logic.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+
+        self
+    }
+
+    /// Pauses ingesting the given chain without restarting the indexer.
+    /// Clones of this config (including the running ingester's) share the
+    /// paused state.
+    pub fn pause_chain(&self, chain: &Chain) {
+        self.paused_chains.pause(chain);
+    }
+
+    pub fn resume_chain(&self, chain: &Chain) {
+        self.paused_chains.resume(chain);
+    }
+
+    /// Skips ingesting a chain for `cooldown` once `failure_threshold` ticks
+    /// in a row failed, instead of stopping the ingester, then tests whether it
+    /// recovered with a single tick. See `ChainCircuitBreakers`. JSON RPC
+    /// requests get retried 3 times at most per tick, unless set otherwise
+    /// with `with_max_json_rpc_retries`, for failing chains to fail their ticks.
+    pub fn with_chain_circuit_breaker(
+        mut self,
+        failure_threshold: u64,
+        cooldown: Duration,
+    ) -> Self {
+        self.chain_circuit_breakers = Some(ChainCircuitBreakers::new(failure_threshold, cooldown));
+        self.max_json_rpc_retries.get_or_insert(3);
+
+        self
+    }
+
+    /// What the events ingester, or the coupled pipeline, does when a tick
+    /// fails without any chain circuit breaker, which panics by default. See
+    /// `FatalErrorPolicy`.
+    pub fn with_fatal_error_policy(mut self, fatal_error_policy: FatalErrorPolicy) -> Self {
+        self.fatal_error_policy = fatal_error_policy;
+
+        self
+    }
+
+    /// Always closed without `with_chain_circuit_breaker`
+    pub fn get_chain_circuit_state(&self, chain: &Chain) -> ChainCircuitState {
+        match &self.chain_circuit_breakers {
+            Some(chain_circuit_breakers) => {
+                chain_circuit_breakers.get_state(chain, self.clock.instant())
+            }
+            None => ChainCircuitState::Closed,
+        }
+    }
+
+    pub fn get_blocks_per_batch(&self, chain: &Chain) -> u64 {
+        match &self.adaptive_blocks_per_batch {
+            Some(adaptive_blocks_per_batch) => {
+                adaptive_blocks_per_batch.get(*chain as i32, self.blocks_per_batch)
+            }
+            None => self.blocks_per_batch,
+        }
+    }
+
+    pub fn get_min_confirmation_count(&self, chain: &Chain) -> &MinConfirmationCount {
+        self.chain_min_confirmation_counts
+            .get(chain)
+            .unwrap_or(&self.min_confirmation_count)
+    }
+
+    /// Reports handlers that never run and contract events that never get
+    /// handled, e.g. to fail at startup instead of silently handling nothing
+    pub fn validate_handlers(&self) -> Result<(), Vec<HandlerMismatch>> {
+        let handler_mismatches = Contracts::get_handler_mismatches(&self.contracts);
+
+        if handler_mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(handler_mismatches)
+        }
+    }
+
+    pub fn get_unpaused_chains(&self) -> Chains {
+        self.chains
+            .clone()
+            .into_iter()
+            .filter(|(chain, _json_rpc_url)| !self.paused_chains.is_paused(chain))
+            .collect()
+    }
+
+    /// Every registered contract with its addresses, as saved, along with
+    /// their current cursors, e.g. for an admin or debug endpoint. See
+    /// `ContractStatus::new`.
+    pub async fn contracts_status(&self) -> Vec<ContractStatus> {
+        let pool = self.repo.get_pool(1).await;
+        let mut conn = ChaindexingRepo::get_conn(&pool).await;
+
+        self.contracts_status_with_conn(&mut conn).await
+    }
+
+    pub async fn contracts_status_with_conn<'a>(
+        &self,
+        conn: &mut ChaindexingRepoConn<'a>,
+    ) -> Vec<ContractStatus> {
+        let saved_contract_addresses = ChaindexingRepo::get_all_contract_addresses(conn).await;
+
+        self.contracts
+            .iter()
+            .map(|contract| {
+                ContractStatus::new(contract, &saved_contract_addresses, &self.paused_chains)
+            })
+            .collect()
+    }
+
+    /// Whether every contract address has both ingested and handled its
+    /// events up to within `caught_up_window` blocks of its chain's current
+    /// block, e.g. to gate a readiness probe or downstream reads until the
+    /// index is complete. Not synced while any chain's current block cannot
+    /// be fetched.
+    pub async fn is_fully_synced(&self) -> bool {
+        let mut current_block_numbers = HashMap::new();
+
+        for chain in self.chains.keys() {
+            match self.get_json_rpc(chain).get_block_number().await {
+                Ok(current_block_number) => {
+                    current_block_numbers.insert(*chain, BlockNumber::from(current_block_number));
+                }
+                Err(provider_error) => {
+                    eprintln!("Provider Error: {}", provider_error);
+
+                    return false;
+                }
+            }
+        }
+
+        let pool = self.repo.get_pool(1).await;
+        let mut conn = ChaindexingRepo::get_conn(&pool).await;
+
+        self.is_fully_synced_with_conn(&mut conn, &current_block_numbers).await
+    }
+
+    /// Contract addresses on chains without a current block are left out
+    pub async fn is_fully_synced_with_conn<'a>(
+        &self,
+        conn: &mut ChaindexingRepoConn<'a>,
+        current_block_numbers: &HashMap<Chain, BlockNumber>,
+    ) -> bool {
+        let saved_contract_addresses = ChaindexingRepo::get_all_contract_addresses(conn).await;
+
+        for contract in self.contracts.iter() {
+            let event_abis: Vec<_> =
+                contract.get_event_abis().iter().map(|abi| abi.to_string()).collect();
+            let ContractStatus { addresses, .. } =
+                ContractStatus::new(contract, &saved_contract_addresses, &self.paused_chains);
+
+            for contract_address in addresses {
+                let Some(current_block_number) = current_block_numbers
+                    .iter()
+                    .find(|(chain, _)| **chain as i32 == contract_address.chain_id)
+                    .map(|(_, current_block_number)| *current_block_number)
+                else {
+                    continue;
+                };
+
+                let (
+                    Some(next_block_number_to_ingest_from),
+                    Some(next_block_number_to_handle_from),
+                ) = (
+                    contract_address.next_block_number_to_ingest_from,
+                    contract_address.next_block_number_to_handle_from,
+                )
+                else {
+                    return false;
+                };
+
+                let next_block_number_to_ingest_from =
+                    BlockNumber::try_from(next_block_number_to_ingest_from).unwrap();
+                if next_block_number_to_ingest_from.saturating_add(self.caught_up_window)
+                    < current_block_number
+                {
+                    return false;
+                }
+
+                // The handling cursor only moves past handled events, so it
+                // stays behind for contract addresses without recent events.
+                // Unhandled events outside the window are what is behind.
+                let next_block_number_to_handle_from =
+                    BlockNumber::try_from(next_block_number_to_handle_from).unwrap();
+                let window_start_block_number =
+                    current_block_number.saturating_sub(self.caught_up_window);
+
+                if next_block_number_to_handle_from < window_start_block_number
+                    && ChaindexingRepo::has_events_for_abis(
+                        conn,
+                        &contract_address.address,
+                        contract_address.chain_id,
+                        &event_abis,
+                        next_block_number_to_handle_from,
+                        window_start_block_number.saturating_sub(1),
+                    )
+                    .await
+                {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Chains sharing an endpoint, along with its headers, share a single
+    /// HTTP client: see `HttpJsonRpcClients`.
+    pub fn get_json_rpc(&self, chain: &Chain) -> HttpJsonRpc {
+        let json_rpc_url = Url::parse(self.chains.get(chain).unwrap()).unwrap();
+        let json_rpc_headers = self.json_rpc_headers.get(chain).cloned().unwrap_or_default();
+
+        let client = self.json_rpc_clients.get_or_create(&json_rpc_url, &json_rpc_headers);
+
+        HttpJsonRpc::new(json_rpc_url, client)
+    }
 }
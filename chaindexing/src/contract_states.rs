@@ -2,18 +2,49 @@ use std::sync::Arc;
 use std::{collections::HashMap, fmt::Debug};
 
 mod migrations;
+mod state_snapshots;
 mod state_versions;
 mod state_views;
 
 pub use crate::event_handlers::{EventHandlerContext, UseEventHandlerContext};
-use crate::{ChaindexingRepo, ChaindexingRepoRawQueryTxnClient, LoadsDataWithRawQuery};
-pub use migrations::ContractStateMigrations;
+use crate::{
+    ChaindexingRepo, ChaindexingRepoRawQueryTxnClient, ContractStateError, ExecutesWithRawQuery,
+    LoadsDataWithRawQuery,
+};
+use migrations::DEFAULT_STATE_SCHEMA;
+pub use migrations::{ContractStateMigrations, StateVersionsPrimaryKey};
+pub use state_snapshots::StateSnapshot;
 
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use state_versions::{StateVersion, StateVersions, STATE_VERSIONS_TABLE_PREFIX};
+use state_versions::{StateVersion, StateVersions};
 use state_views::{StateView, StateViews};
 
+/// Schema states get replayed into to be verified, only ever created within
+/// a transaction that gets rolled back
+pub const STATE_VERIFICATION_SCHEMA: &str = "chaindexing_state_verification";
+
+/// Schema shadow handlers write their states to, see `Config::add_shadow_contract`
+pub const SHADOW_STATE_SCHEMA: &str = "chaindexing_shadow_states";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StateDriftKind {
+    /// Live state that replaying events does not produce, e.g. left behind
+    /// by a handler bug or a partially handled reorg
+    Unexpected,
+    /// State produced by replaying events, but missing from the live states
+    Missing,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateDrift {
+    pub kind: StateDriftKind,
+    pub table_name: String,
+    /// Fields of the drifted state, without its `state_version_group_id`,
+    /// which is generated anew by every replay
+    pub state: HashMap<String, String>,
+}
+
 pub struct ContractStates;
 
 impl ContractStates {
@@ -29,6 +60,10 @@ impl ContractStates {
             let state_versions =
                 StateVersions::get(block_number, chain_id, &table_name, client).await;
 
+            if state_versions.is_empty() {
+                continue;
+            }
+
             let state_version_ids = StateVersions::get_ids(&state_versions);
             StateVersions::delete_by_ids(&state_version_ids, &table_name, client).await;
 
@@ -37,6 +72,145 @@ impl ContractStates {
         }
     }
 
+    /// Creates the state tables in the verification schema, which takes
+    /// precedence over the default one for the rest of the transaction, so
+    /// that handlers write their states to these instead of the live ones.
+    pub async fn create_verification_states<'a>(
+        state_migrations: &Vec<Arc<dyn ContractStateMigrations>>,
+        client: &ChaindexingRepoRawQueryTxnClient<'a>,
+    ) -> Result<(), ContractStateError> {
+        Self::create_states_in_schema(state_migrations, STATE_VERIFICATION_SCHEMA, client).await
+    }
+
+    /// Same as `create_verification_states`, but in the shadow schema, dropping
+    /// any shadow states from previous runs
+    pub async fn create_shadow_states<'a>(
+        state_migrations: &Vec<Arc<dyn ContractStateMigrations>>,
+        client: &ChaindexingRepoRawQueryTxnClient<'a>,
+    ) -> Result<(), ContractStateError> {
+        let query = format!("DROP SCHEMA IF EXISTS {SHADOW_STATE_SCHEMA} CASCADE");
+        ChaindexingRepo::execute_raw_query_in_txn(client, &query).await;
+
+        Self::create_states_in_schema(state_migrations, SHADOW_STATE_SCHEMA, client).await
+    }
+
+    /// Tables outside of the shadow schema written to so far in the
+    /// transaction, e.g. live ones shadow handlers wrote to since they are
+    /// missing from their state migrations
+    pub async fn get_table_names_written_outside_shadow_schema<'a>(
+        client: &ChaindexingRepoRawQueryTxnClient<'a>,
+    ) -> Vec<String> {
+        let query = format!(
+            "SELECT schemaname || '.' || relname AS table_name FROM pg_stat_xact_user_tables
+            WHERE schemaname <> '{SHADOW_STATE_SCHEMA}' AND n_tup_ins + n_tup_upd + n_tup_del > 0
+            ORDER BY table_name"
+        );
+
+        let rows: Vec<HashMap<String, String>> =
+            ChaindexingRepo::load_data_list_from_raw_query_with_txn_client(client, &query).await;
+
+        rows.into_iter().filter_map(|mut row| row.remove("table_name")).collect()
+    }
+
+    async fn create_states_in_schema<'a>(
+        state_migrations: &Vec<Arc<dyn ContractStateMigrations>>,
+        schema: &str,
+        client: &ChaindexingRepoRawQueryTxnClient<'a>,
+    ) -> Result<(), ContractStateError> {
+        Self::ensure_default_schema(state_migrations)?;
+
+        let setup_queries = [
+            format!("CREATE SCHEMA {schema}"),
+            format!("SET LOCAL search_path TO {schema}, {DEFAULT_STATE_SCHEMA}"),
+        ];
+
+        for query in setup_queries {
+            ChaindexingRepo::execute_raw_query_in_txn(client, &query).await;
+        }
+
+        for state_migration in state_migrations {
+            for migration in state_migration.get_migrations() {
+                ChaindexingRepo::execute_raw_query_in_txn(client, &migration).await;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Only states in the default schema can be replayed into another one,
+    /// which takes precedence over the default one alone
+    fn ensure_default_schema(
+        state_migrations: &Vec<Arc<dyn ContractStateMigrations>>,
+    ) -> Result<(), ContractStateError> {
+        match state_migrations.iter().find(|m| m.schema() != DEFAULT_STATE_SCHEMA) {
+            Some(state_migration) => Err(ContractStateError::UnsupportedSchema(
+                state_migration.schema().to_string(),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Diffs the live states against the ones in the verification schema
+    pub async fn get_drifts<'a>(
+        state_migrations: &Vec<Arc<dyn ContractStateMigrations>>,
+        client: &ChaindexingRepoRawQueryTxnClient<'a>,
+    ) -> Vec<StateDrift> {
+        Self::get_drifts_from_schema(state_migrations, STATE_VERIFICATION_SCHEMA, client).await
+    }
+
+    /// Diffs the live states against the ones in the shadow schema, the live
+    /// tables being the ones named like the shadow ones
+    pub async fn get_shadow_drifts<'a>(
+        state_migrations: &Vec<Arc<dyn ContractStateMigrations>>,
+        client: &ChaindexingRepoRawQueryTxnClient<'a>,
+    ) -> Vec<StateDrift> {
+        Self::get_drifts_from_schema(state_migrations, SHADOW_STATE_SCHEMA, client).await
+    }
+
+    async fn get_drifts_from_schema<'a>(
+        state_migrations: &Vec<Arc<dyn ContractStateMigrations>>,
+        schema: &str,
+        client: &ChaindexingRepoRawQueryTxnClient<'a>,
+    ) -> Vec<StateDrift> {
+        let mut drifts = vec![];
+
+        for table_name in Self::get_all_table_names(state_migrations) {
+            let live_table_name = format!("{DEFAULT_STATE_SCHEMA}.{table_name}");
+            let replayed_table_name = format!("{schema}.{table_name}");
+
+            for (kind, from_table_name, except_table_name) in [
+                (
+                    StateDriftKind::Unexpected,
+                    &live_table_name,
+                    &replayed_table_name,
+                ),
+                (
+                    StateDriftKind::Missing,
+                    &replayed_table_name,
+                    &live_table_name,
+                ),
+            ] {
+                let query = format!(
+                    "SELECT to_jsonb(states) - 'state_version_group_id' AS state FROM {from_table_name} states
+                    EXCEPT
+                    SELECT to_jsonb(states) - 'state_version_group_id' AS state FROM {except_table_name} states"
+                );
+
+                let rows: Vec<HashMap<String, HashMap<String, serde_json::Value>>> =
+                    ChaindexingRepo::load_data_list_from_raw_query_with_txn_client(client, &query)
+                        .await;
+
+                drifts.extend(rows.into_iter().map(|mut row| StateDrift {
+                    kind: kind.clone(),
+                    table_name: table_name.clone(),
+                    state: serde_map_to_string_map(row.remove("state").unwrap()),
+                }));
+            }
+        }
+
+        drifts
+    }
+
     pub fn get_all_table_names(
         state_migrations: &Vec<Arc<dyn ContractStateMigrations>>,
     ) -> Vec<String> {
@@ -51,6 +225,12 @@ impl ContractStates {
 // Investigate HashMap Interface Vs Json (Serde)
 // Move Queries to Repo level and prevent SQLInjection
 // Create Into<StateVersionEvent> to extract events fields we care about once and avoid passing around the whole Event struct
+/// Cross-chain state groups: to aggregate a logical entity across chains,
+/// e.g. a token's total supply over L1 and L2s, keep one state per chain.
+/// Declare `chain_id` in both the state's table and struct, create and update
+/// each chain's share with `read_one_on_chain`, then aggregate all the shares
+/// read with `read_many`. Reorgs then only backtrack their own chain's shares,
+/// which a single state updated from several chains would not allow.
 #[async_trait::async_trait]
 pub trait ContractState:
     DeserializeOwned + Serialize + Clone + Debug + Sync + Send + 'static
@@ -94,6 +274,12 @@ pub trait ContractState:
         let table_name = Self::table_name();
         let state_view = self.to_complete_view(&table_name, &client).await;
 
+        if context.deduplicates_state_versions()
+            && StateVersion::is_unchanged(&state_view, &updates)
+        {
+            return;
+        }
+
         let latest_state_version =
             StateVersion::update(&state_view, &updates, table_name, event, client).await;
         StateView::refresh(&latest_state_version, table_name, client).await;
@@ -135,6 +321,26 @@ pub trait ContractState:
         ChaindexingRepo::load_data_list_from_raw_query_with_txn_client(client, &raw_query).await
     }
 
+    /// Same as `read_one`, over states of the handled event's chain only
+    async fn read_one_on_chain<'a>(
+        filters: HashMap<String, String>,
+        context: &EventHandlerContext,
+    ) -> Option<Self> {
+        let states = Self::read_many_on_chain(filters, context).await;
+
+        states.first().cloned()
+    }
+
+    /// Same as `read_many`, over states of the handled event's chain only
+    async fn read_many_on_chain<'a>(
+        mut filters: HashMap<String, String>,
+        context: &EventHandlerContext,
+    ) -> Vec<Self> {
+        filters.insert("chain_id".to_owned(), context.get_chain_id().to_string());
+
+        Self::read_many(filters, context).await
+    }
+
     async fn get_state_fields<'a>(
         client: &ChaindexingRepoRawQueryTxnClient<'a>,
     ) -> Option<Vec<String>> {
@@ -1,6 +1,43 @@
 use std::collections::HashMap;
 
-use super::STATE_VERSIONS_TABLE_PREFIX;
+use super::state_versions::StateVersion;
+
+/// Schema state tables are created in unless overridden, left unqualified in migrations
+pub const DEFAULT_STATE_SCHEMA: &str = "public";
+
+/// Type of the `state_version_id` primary key of state versions' tables.
+/// Composite natural keys are not supported: a state's versions all share its
+/// natural key, and backtracking and snapshots identify versions by their
+/// `state_version_id` alone. The unique index over the state versions' fields,
+/// see `state_versions_unique_index_default_fields`, already keeps each
+/// version of a natural key unique.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum StateVersionsPrimaryKey {
+    #[default]
+    BigSerial,
+    /// Generated with `gen_random_uuid()`, which requires Postgres 13+
+    Uuid,
+}
+
+impl StateVersionsPrimaryKey {
+    fn get_column_migration(&self) -> &'static str {
+        match self {
+            Self::BigSerial => "state_version_id BIGSERIAL PRIMARY KEY",
+            Self::Uuid => "state_version_id UUID PRIMARY KEY",
+        }
+    }
+
+    // Kept out of the create migration since the table fields'
+    // extraction does not account for parentheses in defaults
+    fn get_default_value_migration(&self, table_name: &str) -> Option<String> {
+        match self {
+            Self::BigSerial => None,
+            Self::Uuid => Some(format!(
+                "ALTER TABLE {table_name} ALTER COLUMN state_version_id SET DEFAULT gen_random_uuid()"
+            )),
+        }
+    }
+}
 
 // Since contract states are rebuildable from ground up, we can
 // easen the type strictness for consumer applications.
@@ -8,10 +45,37 @@ use super::STATE_VERSIONS_TABLE_PREFIX;
 pub trait ContractStateMigrations: Send + Sync {
     fn migrations(&self) -> Vec<&'static str>;
 
+    /// Default fields (contract_address, chain_id, block_hash, etc.) to include
+    /// in the state versions' unique index. Override to exclude fields that
+    /// only bloat the index for high-cardinality states.
+    fn state_versions_unique_index_default_fields(&self) -> Vec<&'static str> {
+        DefaultMigration::get_fields().to_vec()
+    }
+
+    fn state_versions_primary_key(&self) -> StateVersionsPrimaryKey {
+        StateVersionsPrimaryKey::default()
+    }
+
+    /// Postgres schema to create the state tables (and their state versions)
+    /// in, e.g. for access control in a shared database. Their `CREATE TABLE`
+    /// migrations get qualified automatically, but not other migrations, nor
+    /// `ContractState::table_name`, which must return the qualified name.
+    fn schema(&self) -> &'static str {
+        DEFAULT_STATE_SCHEMA
+    }
+
+    /// `CREATE OR REPLACE VIEW` migrations of read models derived from the
+    /// state tables, e.g. with computed columns. Run after the state
+    /// migrations, so always over their latest tables, and dropped before them
+    /// on reset. Unlike state tables, views do not get qualified automatically.
+    fn view_migrations(&self) -> Vec<&'static str> {
+        vec![]
+    }
+
     fn get_table_names(&self) -> Vec<String> {
         self.migrations().iter().fold(vec![], |mut table_names, migration| {
             if migration.starts_with("CREATE TABLE IF NOT EXISTS") {
-                let table_name = extract_table_name(&migration);
+                let table_name = extract_table_name(&set_schema(&migration, self.schema()));
                 table_names.push(table_name)
             }
 
@@ -20,12 +84,22 @@ pub trait ContractStateMigrations: Send + Sync {
     }
 
     fn get_migrations(&self) -> Vec<String> {
-        self.migrations()
+        let schema = self.schema();
+        let create_schema_migrations = if schema == DEFAULT_STATE_SCHEMA {
+            vec![]
+        } else {
+            vec![format!("CREATE SCHEMA IF NOT EXISTS {schema}")]
+        };
+
+        let state_migrations = self
+            .migrations()
             .iter()
             .flat_map(|user_migration| {
                 validate_migration(user_migration);
 
                 if user_migration.starts_with("CREATE TABLE IF NOT EXISTS") {
+                    let user_migration = set_schema(user_migration, schema);
+
                     let create_state_views_table_migration =
                         append_migration(&user_migration, &get_remaining_state_views_migration());
                     let create_state_views_table_migration =
@@ -33,9 +107,10 @@ pub trait ContractStateMigrations: Send + Sync {
                             &create_state_views_table_migration,
                         );
 
+                    let state_versions_primary_key = self.state_versions_primary_key();
                     let create_state_versions_table_migration = append_migration(
                         &user_migration,
-                        &get_remaining_state_versions_migration(),
+                        &get_remaining_state_versions_migration(&state_versions_primary_key),
                     );
                     let create_state_versions_table_migration =
                         set_state_versions_table_name(&create_state_versions_table_migration);
@@ -53,30 +128,58 @@ pub trait ContractStateMigrations: Send + Sync {
                         get_unique_index_migration_for_state_versions(
                             &state_versions_table_name,
                             state_versions_fields,
+                            &self.state_versions_unique_index_default_fields(),
                         );
 
-                    vec![
+                    let mut migrations = vec![
                         create_state_views_table_migration,
                         create_state_versions_table_migration,
                         state_versions_unique_index_migration,
-                    ]
+                    ];
+
+                    if let Some(primary_key_default_value_migration) = state_versions_primary_key
+                        .get_default_value_migration(&state_versions_table_name)
+                    {
+                        migrations.push(primary_key_default_value_migration);
+                    }
+
+                    migrations
                 } else {
                     vec![user_migration.to_string()]
                 }
             })
+            .collect::<Vec<_>>();
+
+        let view_migrations = self.view_migrations().into_iter().map(|view_migration| {
+            validate_view_migration(view_migration);
+
+            view_migration.to_string()
+        });
+
+        create_schema_migrations
+            .into_iter()
+            .chain(state_migrations)
+            .chain(view_migrations)
             .collect()
     }
 
     fn get_reset_migrations(&self) -> Vec<String> {
-        self.get_migrations()
-            .iter()
+        // Views depend on the state tables, which cannot get dropped before them
+        let drop_views_migrations = self.view_migrations().into_iter().map(|view_migration| {
+            format!("DROP VIEW IF EXISTS {}", extract_view_name(view_migration))
+        });
+
+        let drop_tables_migrations = self
+            .get_migrations()
+            .into_iter()
             .filter(|m| m.starts_with("CREATE TABLE IF NOT EXISTS"))
             .map(|create_migration| {
                 let table_name = extract_table_name(&create_migration);
 
                 format!("DROP TABLE IF EXISTS {table_name}")
-            })
-            .collect()
+            });
+
+        drop_views_migrations.chain(drop_tables_migrations).collect()
     }
 }
 
@@ -113,14 +216,53 @@ fn extract_table_fields(migration: &str) -> Vec<String> {
 fn get_unique_index_migration_for_state_versions(
     table_name: &str,
     table_fields: Vec<String>,
+    unique_index_default_fields: &[&str],
 ) -> String {
-    let table_fields: Vec<String> =
-        table_fields.into_iter().filter(|f| f.as_str() != "state_version_id").collect();
+    let excluded_default_fields =
+        DefaultMigration::get_fields_excluded_from(unique_index_default_fields);
+
+    let table_fields: Vec<String> = table_fields
+        .into_iter()
+        .filter(|f| f.as_str() != "state_version_id")
+        .filter(|f| !excluded_default_fields.contains(&f.as_str()))
+        .collect();
     let fields_by_comma = table_fields.join(",");
 
-    format!(
-        "CREATE UNIQUE INDEX IF NOT EXISTS unique_{table_name} ON {table_name}({fields_by_comma})"
-    )
+    // Indexes live in their tables' schema, so cannot be named with it
+    let index_name = format!("unique_{}", unqualify_table_name(table_name));
+
+    format!("CREATE UNIQUE INDEX IF NOT EXISTS {index_name} ON {table_name}({fields_by_comma})")
+}
+
+fn set_schema(create_migration: &str, schema: &str) -> String {
+    if schema == DEFAULT_STATE_SCHEMA {
+        create_migration.to_string()
+    } else {
+        create_migration.replacen(
+            "CREATE TABLE IF NOT EXISTS ",
+            &format!("CREATE TABLE IF NOT EXISTS {schema}."),
+            1,
+        )
+    }
+}
+
+fn unqualify_table_name(table_name: &str) -> &str {
+    table_name.rsplit('.').next().unwrap()
+}
+
+fn extract_view_name(view_migration: &str) -> String {
+    view_migration
+        .replace("CREATE OR REPLACE VIEW", "")
+        .split_whitespace()
+        .next()
+        .unwrap()
+        .to_string()
+}
+
+fn validate_view_migration(view_migration: &str) {
+    if !view_migration.starts_with("CREATE OR REPLACE VIEW") {
+        panic!("View migrations must start with CREATE OR REPLACE VIEW: {view_migration}")
+    }
 }
 
 fn validate_migration(migration: &str) {
@@ -146,14 +288,15 @@ fn append_migration(migration: &str, migration_to_append: &str) -> String {
         .replace(", ,", ",")
 }
 
-fn get_remaining_state_versions_migration() -> String {
+fn get_remaining_state_versions_migration(primary_key: &StateVersionsPrimaryKey) -> String {
     // TOOO:: Maybe add `chaindexing_` here to prevent the user from
     // overriding these fields (including state_version_group_id)
     format!(
-        "state_version_id BIGSERIAL PRIMARY KEY,
+        "{},
         state_version_is_deleted BOOL NOT NULL default false,
         {}
         ",
+        primary_key.get_column_migration(),
         DefaultMigration::get()
     )
 }
@@ -163,9 +306,15 @@ fn get_remaining_state_views_migration() -> String {
 }
 
 fn set_state_versions_table_name(migration: &str) -> String {
-    migration.replace(
-        "CREATE TABLE IF NOT EXISTS ",
-        format!("CREATE TABLE IF NOT EXISTS {STATE_VERSIONS_TABLE_PREFIX}",).as_str(),
+    let table_name = extract_table_name(migration);
+
+    migration.replacen(
+        &format!("CREATE TABLE IF NOT EXISTS {table_name}"),
+        &format!(
+            "CREATE TABLE IF NOT EXISTS {}",
+            StateVersion::table_name(&table_name)
+        ),
+        1,
     )
 }
 
@@ -196,6 +345,14 @@ impl DefaultMigration {
         ]
     }
 
+    pub fn get_fields_excluded_from(included_fields: &[&str]) -> Vec<&'static str> {
+        Self::get_fields()
+            .iter()
+            .filter(|f| !included_fields.contains(*f))
+            .cloned()
+            .collect()
+    }
+
     fn remove_repeating_occurrences(migration: &str) -> String {
         let repeating_state_fields: Vec<_> = Self::get_fields()
             .iter()
@@ -238,6 +395,7 @@ impl DefaultMigration {
 #[cfg(test)]
 mod contract_state_migrations_get_migration_test {
     use super::*;
+    use crate::contract_states::state_versions::STATE_VERSIONS_TABLE_PREFIX;
 
     #[test]
     fn returns_two_more_migrations_for_create_state_migrations() {
@@ -291,6 +449,64 @@ mod contract_state_migrations_get_migration_test {
             .for_each(|field| assert!(migration.contains(field)));
     }
 
+    #[test]
+    fn excludes_default_fields_from_state_versions_unique_index() {
+        let contract_state = test_contract_state_with_reduced_unique_index();
+        let migrations = contract_state.get_migrations();
+        let unique_index_migration = migrations.get(2).unwrap();
+
+        assert!(unique_index_migration.starts_with("CREATE UNIQUE INDEX IF NOT EXISTS"));
+        assert!(unique_index_migration.contains("token_id"));
+        assert!(unique_index_migration.contains("block_number"));
+        assert!(unique_index_migration.contains("log_index"));
+        assert!(!unique_index_migration.contains("block_hash"));
+        assert!(!unique_index_migration.contains("transaction_hash"));
+    }
+
+    #[test]
+    fn includes_all_default_fields_in_state_versions_unique_index_by_default() {
+        let contract_state = test_contract_state();
+        let migrations = contract_state.get_migrations();
+        let unique_index_migration = migrations.get(2).unwrap();
+
+        assert_default_migration(unique_index_migration);
+    }
+
+    #[test]
+    fn creates_state_versions_with_a_bigserial_primary_key_by_default() {
+        let contract_state = test_contract_state();
+        let migrations = contract_state.get_migrations();
+        let create_state_versions_migration = migrations.get(1).unwrap();
+
+        assert!(create_state_versions_migration.contains("state_version_id BIGSERIAL PRIMARY KEY"));
+    }
+
+    #[test]
+    fn creates_state_versions_with_a_uuid_primary_key() {
+        let contract_state = test_contract_state_with_uuid_primary_key();
+        let migrations = contract_state.get_migrations();
+
+        assert_eq!(migrations.len(), contract_state.migrations().len() + 3);
+
+        let create_state_versions_migration = migrations.get(1).unwrap();
+        assert!(create_state_versions_migration.contains("state_version_id UUID PRIMARY KEY"));
+        assert!(!create_state_versions_migration.contains("BIGSERIAL"));
+        assert_default_migration(create_state_versions_migration);
+
+        let unique_index_migration = migrations.get(2).unwrap();
+        assert!(unique_index_migration.starts_with("CREATE UNIQUE INDEX IF NOT EXISTS"));
+        assert!(!unique_index_migration.contains("state_version_id"));
+        assert_default_migration(unique_index_migration);
+
+        let primary_key_default_value_migration = migrations.get(3).unwrap();
+        assert_eq!(
+            primary_key_default_value_migration,
+            &format!(
+                "ALTER TABLE {STATE_VERSIONS_TABLE_PREFIX}nft_states ALTER COLUMN state_version_id SET DEFAULT gen_random_uuid()"
+            )
+        );
+    }
+
     #[test]
     fn returns_other_migrations_untouched() {
         let contract_state = test_contract_state();
@@ -301,6 +517,85 @@ mod contract_state_migrations_get_migration_test {
         );
     }
 
+    #[test]
+    fn qualifies_state_tables_with_a_custom_schema() {
+        let contract_state = test_contract_state_in_schema();
+        let migrations = contract_state.get_migrations();
+
+        assert_eq!(migrations.len(), contract_state.migrations().len() + 3);
+        assert_eq!(
+            migrations.first().unwrap(),
+            "CREATE SCHEMA IF NOT EXISTS indexer"
+        );
+        assert!(migrations[1].starts_with("CREATE TABLE IF NOT EXISTS indexer.nft_states ("));
+        assert!(migrations[2].starts_with(&format!(
+            "CREATE TABLE IF NOT EXISTS indexer.{STATE_VERSIONS_TABLE_PREFIX}nft_states ("
+        )));
+        assert!(migrations[3].starts_with(&format!(
+            "CREATE UNIQUE INDEX IF NOT EXISTS unique_{STATE_VERSIONS_TABLE_PREFIX}nft_states ON indexer.{STATE_VERSIONS_TABLE_PREFIX}nft_states("
+        )));
+
+        assert_eq!(
+            contract_state.get_table_names(),
+            vec!["indexer.nft_states".to_string()]
+        );
+        assert_eq!(
+            contract_state.get_reset_migrations(),
+            vec![
+                "DROP TABLE IF EXISTS indexer.nft_states".to_string(),
+                format!("DROP TABLE IF EXISTS indexer.{STATE_VERSIONS_TABLE_PREFIX}nft_states")
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_state_tables_unqualified_in_the_default_schema() {
+        let contract_state = test_contract_state();
+        let migrations = contract_state.get_migrations();
+
+        assert!(migrations[0].starts_with("CREATE TABLE IF NOT EXISTS nft_states ("));
+        assert!(migrations[1].starts_with(&format!(
+            "CREATE TABLE IF NOT EXISTS {STATE_VERSIONS_TABLE_PREFIX}nft_states ("
+        )));
+    }
+
+    #[test]
+    fn runs_view_migrations_after_state_migrations() {
+        let contract_state = test_contract_state_with_view();
+        let migrations = contract_state.get_migrations();
+
+        assert_eq!(
+            migrations.last().unwrap(),
+            contract_state.view_migrations().first().unwrap()
+        );
+        assert_eq!(
+            contract_state.get_reset_migrations(),
+            vec![
+                "DROP VIEW IF EXISTS nft_token_ranks".to_string(),
+                "DROP TABLE IF EXISTS nft_states".to_string(),
+                format!("DROP TABLE IF EXISTS {STATE_VERSIONS_TABLE_PREFIX}nft_states")
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_view_migrations_that_cannot_get_replaced() {
+        struct TestContractState;
+
+        impl ContractStateMigrations for TestContractState {
+            fn migrations(&self) -> Vec<&'static str> {
+                vec![]
+            }
+
+            fn view_migrations(&self) -> Vec<&'static str> {
+                vec!["CREATE VIEW nft_token_ranks AS SELECT 1"]
+            }
+        }
+
+        TestContractState.get_migrations();
+    }
+
     fn test_contract_state() -> impl ContractStateMigrations {
         struct TestContractState;
 
@@ -321,4 +616,92 @@ mod contract_state_migrations_get_migration_test {
 
         TestContractState
     }
+
+    fn test_contract_state_with_reduced_unique_index() -> impl ContractStateMigrations {
+        struct TestContractState;
+
+        impl ContractStateMigrations for TestContractState {
+            fn migrations(&self) -> Vec<&'static str> {
+                vec![
+                    "CREATE TABLE IF NOT EXISTS nft_states (
+                      token_id INTEGER NOT NULL,
+                      owner_address TEXT NOT NULL
+                  )",
+                ]
+            }
+
+            fn state_versions_unique_index_default_fields(&self) -> Vec<&'static str> {
+                vec!["contract_address", "chain_id", "block_number", "log_index"]
+            }
+        }
+
+        TestContractState
+    }
+
+    fn test_contract_state_in_schema() -> impl ContractStateMigrations {
+        struct TestContractState;
+
+        impl ContractStateMigrations for TestContractState {
+            fn migrations(&self) -> Vec<&'static str> {
+                vec![
+                    "CREATE TABLE IF NOT EXISTS nft_states (
+                      token_id INTEGER NOT NULL,
+                      owner_address TEXT NOT NULL
+                  )",
+                ]
+            }
+
+            fn schema(&self) -> &'static str {
+                "indexer"
+            }
+        }
+
+        TestContractState
+    }
+
+    fn test_contract_state_with_uuid_primary_key() -> impl ContractStateMigrations {
+        struct TestContractState;
+
+        impl ContractStateMigrations for TestContractState {
+            fn migrations(&self) -> Vec<&'static str> {
+                vec![
+                    "CREATE TABLE IF NOT EXISTS nft_states (
+                      token_id INTEGER NOT NULL,
+                      owner_address TEXT NOT NULL
+                  )",
+                ]
+            }
+
+            fn state_versions_primary_key(&self) -> StateVersionsPrimaryKey {
+                StateVersionsPrimaryKey::Uuid
+            }
+        }
+
+        TestContractState
+    }
+
+    fn test_contract_state_with_view() -> impl ContractStateMigrations {
+        struct TestContractState;
+
+        impl ContractStateMigrations for TestContractState {
+            fn migrations(&self) -> Vec<&'static str> {
+                vec![
+                    "CREATE TABLE IF NOT EXISTS nft_states (
+                      token_id INTEGER NOT NULL,
+                      owner_address TEXT NOT NULL
+                  )",
+                ]
+            }
+
+            fn view_migrations(&self) -> Vec<&'static str> {
+                vec![
+                    "CREATE OR REPLACE VIEW nft_token_ranks AS
+                  SELECT token_id, owner_address, token_id * 2 AS doubled_token_id
+                  FROM nft_states",
+                ]
+            }
+        }
+
+        TestContractState
+    }
 }
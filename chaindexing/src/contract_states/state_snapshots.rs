@@ -0,0 +1,207 @@
+use std::sync::Arc;
+
+use serde_json::json;
+
+use super::state_versions::StateVersion;
+use super::ContractStates;
+use crate::{
+    ChaindexingRepo, ChaindexingRepoRawQueryTxnClient, ContractStateError, ContractStateMigrations,
+    ExecutesWithRawQuery, LoadsDataWithRawQuery, StateVersionsPrimaryKey,
+};
+
+/// A contract's state tables, state versions included, along with its
+/// addresses' handling cursors, at a single point in time, e.g. to bootstrap
+/// a new replica instead of replaying every event. Stored as JSON:
+///
+/// ```json
+/// { "contract_name": "BoredApeYachtClub", "tables": [["nfts", [...]], ...], "cursors": [...] }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StateSnapshot {
+    pub contract_name: String,
+    /// Rows of each state table, by table name
+    pub tables: Vec<(String, Vec<serde_json::Value>)>,
+    /// Handling cursors and checkpoints of each of the contract's addresses
+    pub cursors: Vec<serde_json::Value>,
+}
+
+impl StateSnapshot {
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let snapshot: serde_json::Value =
+            serde_json::from_str(json).map_err(|error| error.to_string())?;
+
+        let get_field =
+            |name: &str| snapshot.get(name).cloned().ok_or_else(|| format!("Expected {name}"));
+
+        Ok(Self {
+            contract_name: serde_json::from_value(get_field("contract_name")?)
+                .map_err(|error| error.to_string())?,
+            tables: serde_json::from_value(get_field("tables")?)
+                .map_err(|error| error.to_string())?,
+            cursors: serde_json::from_value(get_field("cursors")?)
+                .map_err(|error| error.to_string())?,
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        json!({
+            "contract_name": self.contract_name,
+            "tables": self.tables,
+            "cursors": self.cursors,
+        })
+        .to_string()
+    }
+}
+
+impl ContractStates {
+    /// Reads every table within a single repeatable read transaction, so the
+    /// states and cursors are consistent with each other
+    pub async fn snapshot<'a>(
+        contract_name: &str,
+        state_migrations: &Vec<Arc<dyn ContractStateMigrations>>,
+        client: &ChaindexingRepoRawQueryTxnClient<'a>,
+    ) -> StateSnapshot {
+        ChaindexingRepo::execute_raw_query_in_txn(
+            client,
+            "SET TRANSACTION ISOLATION LEVEL REPEATABLE READ",
+        )
+        .await;
+
+        let mut tables = vec![];
+        for table_name in get_snapshot_table_names(state_migrations) {
+            let query = format!("SELECT * FROM {table_name}");
+            let rows =
+                ChaindexingRepo::load_data_list_from_raw_query_with_txn_client(client, &query)
+                    .await;
+
+            tables.push((table_name, rows));
+        }
+
+        let query = format!(
+            "SELECT contract_addresses.chain_id, contract_addresses.address,
+            contract_addresses.start_block_number, contract_addresses.next_block_number_to_handle_from,
+            handler_checkpoints.block_number AS checkpoint_block_number,
+            handler_checkpoints.transaction_index AS checkpoint_transaction_index,
+            handler_checkpoints.log_index AS checkpoint_log_index
+            FROM chaindexing_contract_addresses contract_addresses
+            LEFT JOIN chaindexing_handler_checkpoints handler_checkpoints
+            ON handler_checkpoints.contract_address_id = contract_addresses.id
+            WHERE contract_addresses.contract_name = '{contract_name}'"
+        );
+        let cursors =
+            ChaindexingRepo::load_data_list_from_raw_query_with_txn_client(client, &query).await;
+
+        StateSnapshot {
+            contract_name: contract_name.to_string(),
+            tables,
+            cursors,
+        }
+    }
+
+    /// Replaces the state tables' rows with the snapshot's, which must have
+    /// been migrated already, then moves the contract's handling cursors to
+    /// the snapshot's. Ingestion resumes from the handling cursors too, since
+    /// snapshots carry no events. Contract addresses missing from the config
+    /// get registered. Nothing gets restored if any of the snapshot's tables
+    /// is missing from the migrations.
+    pub async fn restore<'a>(
+        snapshot: &StateSnapshot,
+        state_migrations: &Vec<Arc<dyn ContractStateMigrations>>,
+        client: &ChaindexingRepoRawQueryTxnClient<'a>,
+    ) -> Result<(), ContractStateError> {
+        let table_names = get_snapshot_table_names(state_migrations);
+
+        if let Some((unknown_table_name, _rows)) = snapshot
+            .tables
+            .iter()
+            .find(|(table_name, _rows)| !table_names.contains(table_name))
+        {
+            return Err(ContractStateError::UnknownStateTable(
+                unknown_table_name.clone(),
+            ));
+        }
+
+        for (table_name, rows) in snapshot.tables.iter() {
+            let query = format!("DELETE FROM {table_name}");
+            ChaindexingRepo::execute_raw_query_in_txn(client, &query).await;
+
+            if !rows.is_empty() {
+                let rows = escape_json(&json!(rows));
+                let query = format!(
+                    "INSERT INTO {table_name}
+                    SELECT * FROM jsonb_populate_recordset(NULL::{table_name}, '{rows}')"
+                );
+                ChaindexingRepo::execute_raw_query_in_txn(client, &query).await;
+            }
+        }
+
+        // Restored serial state version ids must not get generated again
+        for state_migration in state_migrations {
+            if state_migration.state_versions_primary_key() != StateVersionsPrimaryKey::BigSerial {
+                continue;
+            }
+
+            for table_name in state_migration.get_table_names() {
+                let table_name = StateVersion::table_name(&table_name);
+                let query = format!(
+                    "SELECT setval(pg_get_serial_sequence('{table_name}', 'state_version_id'),
+                    COALESCE(MAX(state_version_id), 0) + 1, false) FROM {table_name}"
+                );
+
+                ChaindexingRepo::execute_raw_query_in_txn(client, &query).await;
+            }
+        }
+
+        let contract_name = &snapshot.contract_name;
+        let cursors = escape_json(&json!(snapshot.cursors));
+        let queries = [
+            format!(
+                "INSERT INTO chaindexing_contract_addresses (chain_id, address, contract_name,
+                start_block_number, next_block_number_to_ingest_from, next_block_number_to_handle_from)
+                SELECT chain_id, address, '{contract_name}', start_block_number,
+                next_block_number_to_handle_from, next_block_number_to_handle_from
+                FROM jsonb_to_recordset('{cursors}') AS cursors(chain_id INTEGER, address TEXT,
+                start_block_number BIGINT, next_block_number_to_handle_from BIGINT)
+                ON CONFLICT (address) DO UPDATE
+                SET next_block_number_to_ingest_from = excluded.next_block_number_to_ingest_from,
+                next_block_number_to_handle_from = excluded.next_block_number_to_handle_from"
+            ),
+            format!(
+                "DELETE FROM chaindexing_handler_checkpoints WHERE contract_address_id IN
+                (SELECT id FROM chaindexing_contract_addresses WHERE contract_name = '{contract_name}')"
+            ),
+            format!(
+                "INSERT INTO chaindexing_handler_checkpoints (contract_address_id, block_number, transaction_index, log_index)
+                SELECT contract_addresses.id, cursors.checkpoint_block_number,
+                cursors.checkpoint_transaction_index, cursors.checkpoint_log_index
+                FROM jsonb_to_recordset('{cursors}') AS cursors(address TEXT, checkpoint_block_number BIGINT,
+                checkpoint_transaction_index BIGINT, checkpoint_log_index BIGINT)
+                JOIN chaindexing_contract_addresses contract_addresses ON contract_addresses.address = cursors.address
+                WHERE cursors.checkpoint_block_number IS NOT NULL"
+            ),
+        ];
+
+        for query in queries {
+            ChaindexingRepo::execute_raw_query_in_txn(client, &query).await;
+        }
+
+        Ok(())
+    }
+}
+
+fn get_snapshot_table_names(
+    state_migrations: &Vec<Arc<dyn ContractStateMigrations>>,
+) -> Vec<String> {
+    ContractStates::get_all_table_names(state_migrations)
+        .into_iter()
+        .flat_map(|table_name| {
+            let state_versions_table_name = StateVersion::table_name(&table_name);
+
+            [table_name, state_versions_table_name]
+        })
+        .collect()
+}
+
+fn escape_json(json: &serde_json::Value) -> String {
+    json.to_string().replace('\'', "''")
+}
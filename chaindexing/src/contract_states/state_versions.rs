@@ -62,7 +62,7 @@ impl StateVersions {
             "DELETE FROM {table_name}
             WHERE state_version_id IN ({ids})",
             table_name = StateVersion::table_name(state_table_name),
-            ids = ids.join(",")
+            ids = ids.iter().map(|id| format!("'{id}'")).collect::<Vec<_>>().join(",")
         );
 
         ChaindexingRepo::execute_raw_query_in_txn(client, &query).await;
@@ -94,10 +94,14 @@ impl StateVersions {
 pub struct StateVersion;
 
 impl StateVersion {
+    /// Keeps the state table's schema, if qualified with one
     pub fn table_name(state_table_name: &str) -> String {
-        let mut table_name = STATE_VERSIONS_TABLE_PREFIX.to_string();
-        table_name.push_str(state_table_name);
-        table_name
+        match state_table_name.rsplit_once('.') {
+            Some((schema, state_table_name)) => {
+                format!("{schema}.{STATE_VERSIONS_TABLE_PREFIX}{state_table_name}")
+            }
+            None => format!("{STATE_VERSIONS_TABLE_PREFIX}{state_table_name}"),
+        }
     }
 
     pub fn was_deleted(state_version: &HashMap<String, String>) -> bool {
@@ -108,6 +112,14 @@ impl StateVersion {
         state_version.get("state_version_group_id").unwrap().to_owned()
     }
 
+    /// Whether applying the updates to the state would leave all its fields as is
+    pub fn is_unchanged(
+        state: &HashMap<String, String>,
+        updates: &HashMap<String, String>,
+    ) -> bool {
+        updates.iter().all(|(field, value)| state.get(field) == Some(value))
+    }
+
     pub async fn create<'a>(
         state: &HashMap<String, String>,
         state_table_name: &str,
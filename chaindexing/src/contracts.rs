@@ -1,41 +1,163 @@
-use std::{collections::HashMap, str::FromStr, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::Arc,
+};
 
+use crate::chains::PausedChains;
 use crate::diesels::schema::chaindexing_contract_addresses;
 use crate::hashes::Hashes;
-use crate::{ContractStateMigrations, EventHandler};
+use crate::{
+    AbiLogDecoder, BlockNumber, BlockRanges, ContractStateMigrations, EventHandler,
+    FixedStartBlock, KeccakTopicHasher, LogDecoder, MinConfirmationCount, StartBlockStrategy,
+    TopicHasher,
+};
+use derive_more::Display;
 use diesel::{Identifiable, Insertable, Queryable};
 
 use ethers::{
-    abi::{Address, Event, HumanReadableParser},
+    abi::{Abi, Address, Event, EventParam, HumanReadableParser},
     prelude::Chain,
     types::H256,
 };
 
 pub type ContractEventTopic = H256;
 
+/// Computes topics from event signatures, e.g. to compare them against
+/// on-chain topics when events do not match
+pub trait FromEventSignature {
+    /// Canonical topic0 of either a signature, e.g. `Transfer(address,address,uint256)`,
+    /// or a human-readable ABI, e.g. `event Transfer(address indexed from, ...)`
+    fn from_signature(signature: &str) -> Self;
+}
+
+impl FromEventSignature for ContractEventTopic {
+    fn from_signature(signature: &str) -> Self {
+        match HumanReadableParser::parse_event(signature) {
+            Ok(event) => event.signature(),
+            Err(_) => {
+                let signature: String = signature.split_whitespace().collect();
+
+                KeccakTopicHasher.hash(&signature)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ContractEvent {
     pub abi: String,
     pub value: Event,
+    /// Hashed by the contract's `TopicHasher`
+    pub topic: ContractEventTopic,
 }
 
 impl ContractEvent {
     pub fn new(abi: &str) -> Self {
+        Self::new_with_topic_hasher(abi, &KeccakTopicHasher)
+    }
+
+    pub fn new_with_topic_hasher(abi: &str, topic_hasher: &dyn TopicHasher) -> Self {
+        let value = HumanReadableParser::parse_event(abi).unwrap();
+
         Self {
             abi: abi.to_string(),
-            value: HumanReadableParser::parse_event(abi).unwrap(),
+            topic: topic_hasher.hash_event(&value),
+            value,
         }
     }
+
+    pub fn from_abi_event(value: Event) -> Self {
+        Self {
+            abi: Self::to_human_readable_abi(&value),
+            topic: KeccakTopicHasher.hash_event(&value),
+            value,
+        }
+    }
+
+    pub fn with_topic_hasher(self, topic_hasher: &dyn TopicHasher) -> Self {
+        Self {
+            topic: topic_hasher.hash_event(&self.value),
+            ..self
+        }
+    }
+
+    fn to_human_readable_abi(Event { name, inputs, .. }: &Event) -> String {
+        let params: Vec<_> = inputs
+            .iter()
+            .map(
+                |EventParam {
+                     name,
+                     kind,
+                     indexed,
+                 }| {
+                    let indexed = if *indexed { " indexed" } else { "" };
+
+                    format!("{kind}{indexed} {name}")
+                },
+            )
+            .collect();
+
+        format!("event {name}({params})", params = params.join(", "))
+    }
 }
 
 type EventAbi = &'static str;
 
+/// Event ABI of an upgradeable proxy's implementation, decoding the proxy
+/// address's logs of its topic within its (inclusive) block range only
+#[derive(Debug, Clone)]
+pub struct ImplementationEvent {
+    /// Normalized, see `ContractAddress::normalize_address`
+    pub address: String,
+    pub from_block_number: BlockNumber,
+    pub to_block_number: BlockNumber,
+    pub event: ContractEvent,
+}
+
+impl ImplementationEvent {
+    pub fn new(address: &str, from: u64, to: u64, event_abi: &str) -> Self {
+        Self {
+            address: ContractAddress::normalize_address(address),
+            from_block_number: BlockNumber::new(from),
+            to_block_number: BlockNumber::new(to),
+            event: ContractEvent::new(event_abi),
+        }
+    }
+
+    pub fn decodes(
+        &self,
+        address: &str,
+        topic: &ContractEventTopic,
+        block_number: BlockNumber,
+    ) -> bool {
+        self.address == ContractAddress::normalize_address(address)
+            && self.event.topic == *topic
+            && self.from_block_number <= block_number
+            && block_number <= self.to_block_number
+    }
+}
+
 #[derive(Clone)]
 pub struct Contract {
     pub addresses: Vec<UnsavedContractAddress>,
     pub name: String,
     pub event_handlers: HashMap<EventAbi, Arc<dyn EventHandler>>,
     pub state_migrations: Vec<Arc<dyn ContractStateMigrations>>,
+    /// Events ingested even without a handler, e.g. from a JSON ABI
+    pub events: Vec<ContractEvent>,
+    pub block_ranges: BlockRanges,
+    /// Contracts with higher priorities get their events handled before
+    /// those of lower priorities, including events in the same block
+    pub priority: u16,
+    pub log_decoder: Arc<dyn LogDecoder>,
+    pub topic_hasher: Arc<dyn TopicHasher>,
+    /// Overrides the chain's reorg tolerance for this contract's events
+    pub min_confirmation_count: Option<MinConfirmationCount>,
+    /// Chains the contract is deployed on, or every chain when None
+    pub chains: Option<HashSet<Chain>>,
+    pub implementation_events: Vec<ImplementationEvent>,
+    pub start_block_strategy: Arc<dyn StartBlockStrategy>,
 }
 
 impl Contract {
@@ -45,6 +167,32 @@ impl Contract {
             state_migrations: vec![],
             name: name.to_string(),
             event_handlers: HashMap::new(),
+            events: vec![],
+            block_ranges: BlockRanges::default(),
+            priority: 0,
+            log_decoder: Arc::new(AbiLogDecoder),
+            topic_hasher: Arc::new(KeccakTopicHasher),
+            min_confirmation_count: None,
+            chains: None,
+            implementation_events: vec![],
+            start_block_strategy: Arc::new(FixedStartBlock),
+        }
+    }
+
+    /// Registers every (non-anonymous) event in the contract's JSON ABI.
+    /// Handlers can still be added with `add_event`, using the event's
+    /// human-readable ABI.
+    pub fn from_abi_json(name: &str, abi_json: &str) -> Self {
+        let abi: Abi = serde_json::from_str(abi_json).unwrap();
+
+        Self {
+            events: abi
+                .events()
+                .filter(|event| !event.anonymous)
+                .cloned()
+                .map(ContractEvent::from_abi_event)
+                .collect(),
+            ..Self::new(name)
         }
     }
 
@@ -64,6 +212,28 @@ impl Contract {
         }
     }
 
+    /// Adds many addresses at once, e.g. every pool deployed by a factory
+    pub fn add_addresses(
+        &self,
+        addresses: &[&str],
+        chain: &Chain,
+        start_block_number: i64,
+    ) -> Self {
+        let mut addresses: Vec<_> = addresses
+            .iter()
+            .map(|address| {
+                UnsavedContractAddress::new(&self.name, address, chain, start_block_number)
+            })
+            .collect();
+        let mut contract_addresses = self.addresses.clone();
+        contract_addresses.append(&mut addresses);
+
+        Self {
+            addresses: contract_addresses,
+            ..self.clone()
+        }
+    }
+
     pub fn add_event(
         mut self,
         event_abi: EventAbi,
@@ -74,6 +244,137 @@ impl Contract {
         self
     }
 
+    /// Only ingests events within the given (inclusive) block range, along
+    /// with any other allowed range
+    pub fn add_allowed_block_range(mut self, from: u64, to: u64) -> Self {
+        self.block_ranges.allow(BlockNumber::new(from), BlockNumber::new(to));
+
+        self
+    }
+
+    /// Skips ingesting events within the given (inclusive) block range,
+    /// e.g. a known spam window
+    pub fn add_blocked_block_range(mut self, from: u64, to: u64) -> Self {
+        self.block_ranges.block(BlockNumber::new(from), BlockNumber::new(to));
+
+        self
+    }
+
+    pub fn with_priority(mut self, priority: u16) -> Self {
+        self.priority = priority;
+
+        self
+    }
+
+    /// Confirms this contract's events over a different number of blocks than
+    /// its chain's, e.g. deeper for a bridge than for an NFT mint
+    pub fn with_min_confirmation_count(mut self, min_confirmation_count: u8) -> Self {
+        self.min_confirmation_count = Some(MinConfirmationCount::new(min_confirmation_count));
+
+        self
+    }
+
+    /// Restricts the contract to the given chains, so that its addresses are
+    /// validated against them and chains without it get skipped altogether
+    pub fn with_chains(mut self, chains: &[Chain]) -> Self {
+        self.chains = Some(chains.iter().copied().collect());
+
+        self
+    }
+
+    pub fn runs_on(&self, chain: &Chain) -> bool {
+        self.chains.as_ref().map_or(true, |chains| chains.contains(chain))
+    }
+
+    fn runs_on_chain_id(&self, chain_id: i32) -> bool {
+        self.chains.as_ref().map_or(true, |chains| {
+            chains.iter().any(|chain| *chain as i32 == chain_id)
+        })
+    }
+
+    /// Addresses on chains the contract does not run on
+    pub fn get_misplaced_addresses(&self) -> Vec<&UnsavedContractAddress> {
+        self.addresses
+            .iter()
+            .filter(|address| !self.runs_on_chain_id(address.chain_id))
+            .collect()
+    }
+
+    /// Decodes the proxy address's logs within the given (inclusive) block range
+    /// with the implementation's event ABI, instead of the one of the same
+    /// topic added with `add_event`, e.g. for an event whose params changed
+    /// with an upgrade. Events decoded with it get handled by its own handler,
+    /// so its ABI must not be added with `add_event` too.
+    pub fn add_implementation_event(
+        mut self,
+        address: &str,
+        from: u64,
+        to: u64,
+        event_abi: EventAbi,
+        event_handler: impl EventHandler + 'static,
+    ) -> Self {
+        self.event_handlers.insert(event_abi, Arc::new(event_handler));
+        let mut implementation_event = ImplementationEvent::new(address, from, to, event_abi);
+        implementation_event.event =
+            implementation_event.event.with_topic_hasher(self.topic_hasher.as_ref());
+        self.implementation_events.push(implementation_event);
+
+        self
+    }
+
+    /// Implementation event decoding the address's log of the topic at the
+    /// block, if any, see `add_implementation_event`
+    pub fn get_implementation_event(
+        &self,
+        address: &str,
+        topic: &ContractEventTopic,
+        block_number: BlockNumber,
+    ) -> Option<&ContractEvent> {
+        self.implementation_events
+            .iter()
+            .find(|implementation_event| implementation_event.decodes(address, topic, block_number))
+            .map(|implementation_event| &implementation_event.event)
+    }
+
+    /// Replaces decoding logs with their events' ABI, for non-standard encodings
+    pub fn with_log_decoder(mut self, log_decoder: impl LogDecoder + 'static) -> Self {
+        self.log_decoder = Arc::new(log_decoder);
+
+        self
+    }
+
+    /// Replaces hashing event signatures into topics with ethers' Keccak-256,
+    /// e.g. for another crypto backend. Decoding logs is left to the log
+    /// decoder, see `with_log_decoder`.
+    pub fn with_topic_hasher(mut self, topic_hasher: impl TopicHasher + 'static) -> Self {
+        self.topic_hasher = Arc::new(topic_hasher);
+
+        let topic_hasher = self.topic_hasher.as_ref();
+        self.events = self.events.into_iter().map(|e| e.with_topic_hasher(topic_hasher)).collect();
+        for implementation_event in self.implementation_events.iter_mut() {
+            implementation_event.event =
+                implementation_event.event.clone().with_topic_hasher(topic_hasher);
+        }
+
+        self
+    }
+
+    /// Resolves the start blocks of the contract's addresses, when they get
+    /// registered, instead of using the configured ones as they are. See
+    /// `StartBlockStrategy`.
+    pub fn with_start_block_strategy(
+        mut self,
+        start_block_strategy: impl StartBlockStrategy + 'static,
+    ) -> Self {
+        self.start_block_strategy = Arc::new(start_block_strategy);
+
+        self
+    }
+
+    pub fn build_event(&self, event_abi: &str) -> ContractEvent {
+        ContractEvent::new_with_topic_hasher(event_abi, self.topic_hasher.as_ref())
+    }
+
     pub fn add_state_migrations(
         mut self,
         state_migration: impl ContractStateMigrations + 'static,
@@ -87,15 +388,48 @@ impl Contract {
         self.event_handlers.clone().into_keys().collect()
     }
 
+    /// Includes topics of implementation events too. Their logs from other
+    /// addresses or outside of their block ranges get dropped when decoded,
+    /// unless an event added with `add_event` shares their topic.
     pub fn get_event_topics(&self) -> Vec<ContractEventTopic> {
-        self.get_event_abis()
+        let implementation_events = self.implementation_events.iter().map(|e| &e.event);
+        let mut topics: Vec<_> = self
+            .build_events()
             .iter()
-            .map(|abi| HumanReadableParser::parse_event(abi).unwrap().signature())
-            .collect()
+            .chain(implementation_events)
+            .map(|e| e.topic)
+            .collect();
+        topics.sort();
+        topics.dedup();
+
+        topics
     }
 
+    pub fn group_events_by_topics(&self) -> HashMap<ContractEventTopic, ContractEvent> {
+        self.build_events().into_iter().map(|e| (e.topic, e)).collect()
+    }
+
+    /// Events with handlers take precedence over their handler-less
+    /// counterparts so that ingested events match their handlers' ABI.
+    /// Implementation events are left out, see `get_implementation_event`.
     pub fn build_events(&self) -> Vec<ContractEvent> {
-        self.get_event_abis().iter().map(|abi| ContractEvent::new(abi)).collect()
+        let implementation_event_abis: HashSet<_> =
+            self.implementation_events.iter().map(|e| e.event.abi.as_str()).collect();
+        let events_with_handlers: Vec<_> = self
+            .get_event_abis()
+            .iter()
+            .filter(|abi| !implementation_event_abis.contains(*abi))
+            .map(|abi| self.build_event(abi))
+            .collect();
+        let topics_with_handlers: HashSet<_> =
+            events_with_handlers.iter().map(|e| e.topic).collect();
+
+        self.events
+            .iter()
+            .filter(|e| !topics_with_handlers.contains(&e.topic))
+            .cloned()
+            .chain(events_with_handlers)
+            .collect()
     }
 }
 
@@ -133,16 +467,76 @@ impl Contracts {
         })
     }
 
-    pub fn group_events_by_topics(
-        contracts: &Vec<Contract>,
-    ) -> HashMap<ContractEventTopic, ContractEvent> {
+    pub fn group_priorities_by_names(contracts: &Vec<Contract>) -> HashMap<String, u16> {
         contracts
             .iter()
-            .flat_map(|c| c.build_events())
-            .map(|e| (e.value.signature(), e))
+            .map(|contract| (contract.name.clone(), contract.priority))
             .collect()
     }
 
+    /// Per contract, since contracts can share topics across different ABIs,
+    /// e.g. ERC-20 and ERC-721 transfers
+    pub fn group_events_by_names_and_topics(
+        contracts: &Vec<Contract>,
+    ) -> HashMap<String, HashMap<ContractEventTopic, ContractEvent>> {
+        contracts.iter().map(|c| (c.name.clone(), c.group_events_by_topics())).collect()
+    }
+
+    /// Cross-references the handlers with the ABIs the contracts' events get
+    /// decoded with, in both directions, see `HandlerMismatch`
+    pub fn get_handler_mismatches(contracts: &Vec<Contract>) -> Vec<HandlerMismatch> {
+        let event_handlers_by_event_abi = Self::get_all_event_handlers_by_event_abi(contracts);
+        let implementation_event_abis: HashSet<_> = contracts
+            .iter()
+            .flat_map(|c| c.implementation_events.iter())
+            .map(|e| e.event.abi.as_str())
+            .collect();
+
+        let mut handler_mismatches = vec![];
+
+        for contract in contracts {
+            let events_by_topics = contract.group_events_by_topics();
+            let mut event_abis = contract.get_event_abis();
+            event_abis.sort();
+
+            for event_abi in event_abis {
+                let topic = contract.build_event(event_abi).topic;
+                let is_decoded_with = implementation_event_abis.contains(event_abi)
+                    || events_by_topics.get(&topic).is_some_and(|e| e.abi == event_abi);
+                // Handlers of the same ABI replace each other across contracts
+                let is_registered = Arc::ptr_eq(
+                    &event_handlers_by_event_abi[event_abi],
+                    &contract.event_handlers[event_abi],
+                );
+
+                if !is_decoded_with || !is_registered {
+                    handler_mismatches.push(HandlerMismatch::DeadHandler {
+                        contract_name: contract.name.clone(),
+                        event_abi: event_abi.to_string(),
+                    });
+                }
+            }
+
+            let mut unhandled_event_abis: Vec<_> = contract
+                .build_events()
+                .iter()
+                .map(|e| events_by_topics[&e.topic].abi.clone())
+                .filter(|abi| !event_handlers_by_event_abi.contains_key(abi.as_str()))
+                .collect();
+            unhandled_event_abis.sort();
+            unhandled_event_abis.dedup();
+
+            handler_mismatches.extend(unhandled_event_abis.into_iter().map(|event_abi| {
+                HandlerMismatch::UnhandledEvent {
+                    contract_name: contract.name.clone(),
+                    event_abi,
+                }
+            }));
+        }
+
+        handler_mismatches
+    }
+
     pub fn get_all_contract_addresses_grouped_by_address<'a>(
         contracts: &'a Vec<Contract>,
     ) -> HashMap<Address, &'a UnsavedContractAddress> {
@@ -161,6 +555,25 @@ impl Contracts {
     }
 }
 
+/// Handler never running, or contract event never handled, both silently
+#[derive(Clone, Debug, Display, PartialEq, Eq)]
+pub enum HandlerMismatch {
+    /// Handler of an ABI no ingested event gets decoded with, e.g. as another
+    /// ABI of the same topic takes precedence, or replaced by another
+    /// contract's handler of the same ABI
+    #[display(fmt = "Dead handler of {}: {}", contract_name, event_abi)]
+    DeadHandler {
+        contract_name: String,
+        event_abi: String,
+    },
+    /// Event ingested without any handler, e.g. from a contract's JSON ABI
+    #[display(fmt = "Unhandled event of {}: {}", contract_name, event_abi)]
+    UnhandledEvent {
+        contract_name: String,
+        event_abi: String,
+    },
+}
+
 #[derive(Debug, Clone, PartialEq, Insertable)]
 #[diesel(table_name = chaindexing_contract_addresses)]
 pub struct UnsavedContractAddress {
@@ -176,13 +589,33 @@ impl UnsavedContractAddress {
     pub fn new(contract_name: &str, address: &str, chain: &Chain, start_block_number: i64) -> Self {
         UnsavedContractAddress {
             contract_name: contract_name.to_string(),
-            address: address.to_string(),
+            address: ContractAddress::normalize_address(address),
             chain_id: *chain as i32,
             start_block_number: start_block_number,
             next_block_number_to_ingest_from: start_block_number,
             next_block_number_to_handle_from: start_block_number,
         }
     }
+
+    pub fn get_address(&self) -> &str {
+        &self.address
+    }
+    pub fn get_start_block_number(&self) -> i64 {
+        self.start_block_number
+    }
+}
+
+impl From<&ContractAddress> for UnsavedContractAddress {
+    fn from(contract_address: &ContractAddress) -> Self {
+        Self {
+            contract_name: contract_address.contract_name.clone(),
+            address: contract_address.address.clone(),
+            chain_id: contract_address.chain_id,
+            start_block_number: contract_address.start_block_number,
+            next_block_number_to_ingest_from: contract_address.next_block_number_to_ingest_from,
+            next_block_number_to_handle_from: contract_address.next_block_number_to_handle_from,
+        }
+    }
 }
 
 pub struct ContractAddressID(pub i32);
@@ -211,7 +644,227 @@ impl ContractAddress {
     pub fn id(&self) -> ContractAddressID {
         ContractAddressID(self.id)
     }
+    pub fn get_chain_id(&self) -> i32 {
+        self.chain_id
+    }
+    pub fn get_start_block_number(&self) -> BlockNumber {
+        BlockNumber::try_from(self.start_block_number).unwrap()
+    }
+    pub fn get_next_block_number_to_ingest_from(&self) -> BlockNumber {
+        BlockNumber::try_from(self.next_block_number_to_ingest_from).unwrap()
+    }
+    pub fn get_next_block_number_to_handle_from(&self) -> BlockNumber {
+        BlockNumber::try_from(self.next_block_number_to_handle_from).unwrap()
+    }
     pub fn address_to_string(address: &Address) -> String {
-        Hashes::h160_to_string(address)
+        Self::normalize_address(&Hashes::h160_to_string(address))
+    }
+    /// Canonical form addresses get stored and compared in, so that EIP-55
+    /// checksummed and lowercased addresses match
+    pub fn normalize_address(address: &str) -> String {
+        address.to_lowercase()
+    }
+}
+
+/// A registered contract as configured, along with its addresses' progress
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContractStatus {
+    pub name: String,
+    pub priority: u16,
+    pub event_abis: Vec<String>,
+    pub addresses: Vec<ContractAddressStatus>,
+}
+
+impl ContractStatus {
+    /// Addresses are the contract's saved ones, including ones registered at
+    /// runtime or from manifests, along with their resolved start blocks,
+    /// followed by its configured ones yet to be saved.
+    pub fn new(
+        contract: &Contract,
+        saved_contract_addresses: &Vec<ContractAddress>,
+        paused_chains: &PausedChains,
+    ) -> Self {
+        let mut event_abis: Vec<_> =
+            contract.build_events().into_iter().map(|event| event.abi).collect();
+        event_abis.sort();
+
+        let mut contract_saved_addresses: Vec<_> = saved_contract_addresses
+            .iter()
+            .filter(|saved| saved.contract_name == contract.name)
+            .collect();
+        contract_saved_addresses.sort_by_key(|saved| saved.id);
+
+        let unsaved_contract_addresses = contract.addresses.iter().filter(|contract_address| {
+            !contract_saved_addresses.iter().any(|saved| {
+                saved.get_chain_id() == contract_address.chain_id
+                    && ContractAddress::normalize_address(&saved.address)
+                        == ContractAddress::normalize_address(contract_address.get_address())
+            })
+        });
+
+        Self {
+            name: contract.name.clone(),
+            priority: contract.priority,
+            event_abis,
+            addresses: contract_saved_addresses
+                .into_iter()
+                .map(|saved| ContractAddressStatus::from_saved(saved, paused_chains))
+                .chain(unsaved_contract_addresses.map(|contract_address| {
+                    ContractAddressStatus::from_unsaved(contract_address, paused_chains)
+                }))
+                .collect(),
+        }
+    }
+}
+
+/// Cursors are only known once the contract address is saved, i.e. after
+/// `Chaindexing::setup`. Contract addresses are enabled unless their chain is
+/// paused.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContractAddressStatus {
+    pub address: String,
+    pub chain_id: i32,
+    pub start_block_number: i64,
+    pub enabled: bool,
+    pub next_block_number_to_ingest_from: Option<i64>,
+    pub next_block_number_to_handle_from: Option<i64>,
+}
+
+impl ContractAddressStatus {
+    fn from_saved(saved_contract_address: &ContractAddress, paused_chains: &PausedChains) -> Self {
+        let chain_id = saved_contract_address.get_chain_id();
+
+        Self {
+            address: saved_contract_address.address.clone(),
+            chain_id,
+            start_block_number: saved_contract_address.start_block_number,
+            enabled: Self::is_enabled(chain_id, paused_chains),
+            next_block_number_to_ingest_from: Some(
+                saved_contract_address.next_block_number_to_ingest_from,
+            ),
+            next_block_number_to_handle_from: Some(
+                saved_contract_address.next_block_number_to_handle_from,
+            ),
+        }
+    }
+
+    fn from_unsaved(
+        contract_address: &UnsavedContractAddress,
+        paused_chains: &PausedChains,
+    ) -> Self {
+        Self {
+            address: contract_address.get_address().to_string(),
+            chain_id: contract_address.chain_id,
+            start_block_number: contract_address.get_start_block_number(),
+            enabled: Self::is_enabled(contract_address.chain_id, paused_chains),
+            next_block_number_to_ingest_from: None,
+            next_block_number_to_handle_from: None,
+        }
+    }
+
+    fn is_enabled(chain_id: i32, paused_chains: &PausedChains) -> bool {
+        Chain::try_from(chain_id as u64)
+            .map(|chain| !paused_chains.is_paused(&chain))
+            .unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod contract_from_abi_json_test {
+    use super::*;
+
+    const ERC721_ABI_JSON: &str = r#"[
+        {
+            "anonymous": false,
+            "inputs": [
+                { "indexed": true, "internalType": "address", "name": "owner", "type": "address" },
+                { "indexed": true, "internalType": "address", "name": "approved", "type": "address" },
+                { "indexed": true, "internalType": "uint256", "name": "tokenId", "type": "uint256" }
+            ],
+            "name": "Approval",
+            "type": "event"
+        },
+        {
+            "anonymous": false,
+            "inputs": [
+                { "indexed": true, "internalType": "address", "name": "owner", "type": "address" },
+                { "indexed": true, "internalType": "address", "name": "operator", "type": "address" },
+                { "indexed": false, "internalType": "bool", "name": "approved", "type": "bool" }
+            ],
+            "name": "ApprovalForAll",
+            "type": "event"
+        },
+        {
+            "anonymous": false,
+            "inputs": [
+                { "indexed": true, "internalType": "address", "name": "from", "type": "address" },
+                { "indexed": true, "internalType": "address", "name": "to", "type": "address" },
+                { "indexed": true, "internalType": "uint256", "name": "tokenId", "type": "uint256" }
+            ],
+            "name": "Transfer",
+            "type": "event"
+        },
+        {
+            "inputs": [{ "internalType": "uint256", "name": "tokenId", "type": "uint256" }],
+            "name": "ownerOf",
+            "outputs": [{ "internalType": "address", "name": "", "type": "address" }],
+            "stateMutability": "view",
+            "type": "function"
+        }
+    ]"#;
+
+    #[test]
+    fn registers_every_event_in_the_abi() {
+        let contract = Contract::from_abi_json("ERC721", ERC721_ABI_JSON);
+
+        let mut topics = contract.get_event_topics();
+        topics.sort();
+
+        let mut expected_topics = vec![
+            topic("0x8c5be1e5ebec7d5bd14f71427d1e84f3dd0314c0f7b2291e5b200ac8c7c3b925"),
+            topic("0x17307eab39ab6107e8899845ad3d59bd9653f200f220920489ca2b5937696c31"),
+            topic("0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"),
+        ];
+        expected_topics.sort();
+
+        assert_eq!(topics, expected_topics);
+    }
+
+    #[test]
+    fn builds_human_readable_abis_for_registered_events() {
+        let contract = Contract::from_abi_json("ERC721", ERC721_ABI_JSON);
+
+        for event in contract.build_events() {
+            assert_eq!(
+                ContractEvent::new(&event.abi).value.signature(),
+                event.value.signature()
+            );
+        }
+
+        assert!(contract.build_events().iter().any(|e| {
+            e.abi
+            == "event Transfer(address indexed from, address indexed to, uint256 indexed tokenId)"
+        }));
+    }
+
+    #[test]
+    fn computes_topics_from_event_signatures() {
+        let erc20_transfer_topic =
+            topic("0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef");
+
+        assert_eq!(
+            ContractEventTopic::from_signature("Transfer(address,address,uint256)"),
+            erc20_transfer_topic
+        );
+        assert_eq!(
+            ContractEventTopic::from_signature(
+                "event Transfer(address indexed from, address indexed to, uint256 value)"
+            ),
+            erc20_transfer_topic
+        );
+    }
+
+    fn topic(hex: &str) -> ContractEventTopic {
+        ContractEventTopic::from_str(hex).unwrap()
     }
 }
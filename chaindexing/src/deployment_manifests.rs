@@ -0,0 +1,116 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use derive_more::Display;
+use ethers::types::Chain;
+
+#[derive(Debug, Display, PartialEq)]
+pub enum DeploymentManifestError {
+    #[display(fmt = "Deployment manifest cannot be read: {}", _0)]
+    Unreadable(String),
+    #[display(fmt = "Deployment manifest is invalid: {}", _0)]
+    Invalid(String),
+    #[display(fmt = "Deployment manifest has an unknown chain: {}", _0)]
+    UnknownChain(String),
+    #[display(
+        fmt = "Deployment manifest has a contract missing from the config: {}",
+        _0
+    )]
+    UnknownContract(String),
+    #[display(
+        fmt = "Deployment manifest has a contract on a chain it does not run on: {}",
+        _0
+    )]
+    UnsupportedChain(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Deployment {
+    pub chain: Chain,
+    pub contract_name: String,
+    pub address: String,
+    pub start_block_number: i64,
+}
+
+/// Contract addresses along with the blocks they got deployed at, grouped by
+/// chains, keyed by names or ids, then by contract names, e.g.:
+///
+/// ```json
+/// {
+///   "mainnet": {
+///     "BoredApeYachtClub": [
+///       { "address": "0xBC4CA0EdA7647A8aB7C2061c2E118A18a936f13D", "start_block_number": 12287507 }
+///     ]
+///   },
+///   "137": { ... }
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeploymentManifest {
+    pub deployments: Vec<Deployment>,
+}
+
+impl DeploymentManifest {
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, DeploymentManifestError> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|error| DeploymentManifestError::Unreadable(error.to_string()))?;
+
+        Self::from_json(&json)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, DeploymentManifestError> {
+        let manifest: serde_json::Value = serde_json::from_str(json)
+            .map_err(|error| DeploymentManifestError::Invalid(error.to_string()))?;
+        let contracts_by_chain = manifest.as_object().ok_or_else(|| {
+            DeploymentManifestError::Invalid("Expected contracts grouped by chains".to_string())
+        })?;
+
+        let mut deployments = vec![];
+
+        for (chain_key, contracts) in contracts_by_chain {
+            let chain = parse_chain(chain_key)?;
+            let addresses_by_contract_name = contracts.as_object().ok_or_else(|| {
+                DeploymentManifestError::Invalid(format!(
+                    "Expected addresses grouped by contract names for {chain_key}"
+                ))
+            })?;
+
+            for (contract_name, addresses) in addresses_by_contract_name {
+                let addresses = addresses.as_array().ok_or_else(|| {
+                    DeploymentManifestError::Invalid(format!(
+                        "Expected a list of addresses for {contract_name}"
+                    ))
+                })?;
+
+                for address in addresses {
+                    let (Some(address), Some(start_block_number)) = (
+                        address.get("address").and_then(|a| a.as_str()),
+                        address.get("start_block_number").and_then(|n| n.as_i64()),
+                    ) else {
+                        return Err(DeploymentManifestError::Invalid(format!(
+                            "Expected an address and a start_block_number for {contract_name}"
+                        )));
+                    };
+
+                    deployments.push(Deployment {
+                        chain,
+                        contract_name: contract_name.to_string(),
+                        address: address.to_string(),
+                        start_block_number,
+                    });
+                }
+            }
+        }
+
+        Ok(Self { deployments })
+    }
+}
+
+fn parse_chain(chain: &str) -> Result<Chain, DeploymentManifestError> {
+    let parsed_chain = match chain.parse::<u64>() {
+        Ok(chain_id) => Chain::try_from(chain_id).ok(),
+        Err(_) => Chain::from_str(chain).ok(),
+    };
+
+    parsed_chain.ok_or_else(|| DeploymentManifestError::UnknownChain(chain.to_string()))
+}
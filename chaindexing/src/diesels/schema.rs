@@ -30,6 +30,20 @@ diesel::table! {
       log_index -> Int8,
       removed -> Bool,
       inserted_at -> Timestamptz,
+      transaction_status -> Nullable<Int8>,
+      reorged_block_id -> Nullable<Int4>,
+      raw_log -> Nullable<Json>,
+      content_hash -> Nullable<Text>,
+  }
+}
+
+diesel::table! {
+  chaindexing_handler_checkpoints (contract_address_id) {
+      contract_address_id -> Int4,
+      block_number -> Int8,
+      log_index -> Int8,
+      updated_at -> Timestamptz,
+      transaction_index -> Nullable<Int8>,
   }
 }
 
@@ -47,6 +61,7 @@ diesel::table! {
       chain_id -> Int4,
       handled_at -> Nullable<Timestamptz>,
       inserted_at -> Timestamptz,
+      current_block_number -> Nullable<Int8>,
   }
 }
 
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use derive_more::Display;
+use ethers::types::Chain;
+
+pub const DATABASE_URL_ENV_VAR: &str = "CHAINDEXING_DATABASE_URL";
+/// Comma-separated chain ids or names with their JSON RPC URLs, e.g.
+/// `1=https://eth.example.com,polygon=https://polygon.example.com`
+pub const CHAINS_ENV_VAR: &str = "CHAINDEXING_CHAINS";
+pub const BLOCKS_PER_BATCH_ENV_VAR: &str = "CHAINDEXING_BLOCKS_PER_BATCH";
+pub const INGESTION_INTERVAL_MS_ENV_VAR: &str = "CHAINDEXING_INGESTION_INTERVAL_MS";
+pub const HANDLER_INTERVAL_MS_ENV_VAR: &str = "CHAINDEXING_HANDLER_INTERVAL_MS";
+pub const MIN_CONFIRMATION_COUNT_ENV_VAR: &str = "CHAINDEXING_MIN_CONFIRMATION_COUNT";
+/// Comma-separated chain ids or names with their confirmation counts, e.g.
+/// `42161=0,137=100`
+pub const CHAIN_MIN_CONFIRMATION_COUNTS_ENV_VAR: &str = "CHAINDEXING_CHAIN_MIN_CONFIRMATION_COUNTS";
+
+#[derive(Clone, Debug, Display, PartialEq)]
+pub enum EnvConfigError {
+    #[display(fmt = "Environment variable is missing: {}", _0)]
+    Missing(&'static str),
+    #[display(fmt = "Environment variable {} is invalid: {}", _0, _1)]
+    Invalid(&'static str, String),
+}
+
+/// Reads environment variables, collecting every missing or invalid one so
+/// they can all be reported at once
+pub struct EnvVars<'a> {
+    vars: &'a HashMap<String, String>,
+    pub errors: Vec<EnvConfigError>,
+}
+
+impl<'a> EnvVars<'a> {
+    pub fn new(vars: &'a HashMap<String, String>) -> Self {
+        Self {
+            vars,
+            errors: vec![],
+        }
+    }
+
+    pub fn get_required(&mut self, name: &'static str) -> Option<String> {
+        let value = self.get(name);
+
+        if value.is_none() {
+            self.errors.push(EnvConfigError::Missing(name));
+        }
+
+        value
+    }
+
+    /// Empty values count as unset, as commonly left by container templates
+    pub fn get(&self, name: &'static str) -> Option<String> {
+        self.vars
+            .get(name)
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    pub fn parse<T: FromStr>(&mut self, name: &'static str) -> Option<T>
+    where
+        T::Err: std::fmt::Display,
+    {
+        let value = self.get(name)?;
+
+        value
+            .parse()
+            .map_err(|error: T::Err| self.invalid(name, error.to_string()))
+            .ok()
+    }
+
+    /// Parses `chain=value` pairs, keyed by chain ids or names
+    pub fn parse_by_chain<T: FromStr>(
+        &mut self,
+        name: &'static str,
+        value: &str,
+    ) -> HashMap<Chain, T>
+    where
+        T::Err: std::fmt::Display,
+    {
+        let mut values_by_chain = HashMap::new();
+
+        for pair in value.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let Some((chain, chain_value)) = pair.split_once('=') else {
+                self.invalid(name, format!("Expected chain=value in {pair}"));
+                continue;
+            };
+
+            let Some(chain) = parse_chain(chain.trim()) else {
+                self.invalid(name, format!("Unknown chain {chain}"));
+                continue;
+            };
+
+            match chain_value.trim().parse() {
+                Ok(chain_value) => {
+                    values_by_chain.insert(chain, chain_value);
+                }
+                Err(error) => self.invalid(name, format!("{chain}: {error}")),
+            }
+        }
+
+        values_by_chain
+    }
+
+    fn invalid(&mut self, name: &'static str, error: String) {
+        self.errors.push(EnvConfigError::Invalid(name, error));
+    }
+}
+
+fn parse_chain(chain: &str) -> Option<Chain> {
+    match chain.parse::<u64>() {
+        Ok(chain_id) => Chain::try_from(chain_id).ok(),
+        Err(_) => Chain::from_str(chain).ok(),
+    }
+}
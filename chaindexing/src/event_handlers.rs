@@ -2,19 +2,25 @@ use std::{sync::Arc, time::Duration};
 
 mod handle_events;
 mod handled_events;
+mod handling_throttle;
 
 use tokio::{sync::Mutex, time::interval};
 
-use crate::{contracts::Contracts, events::Event, ChaindexingRepo, Config, Repo};
-use crate::{ChaindexingRepoRawQueryTxnClient, HasRawQueryClient};
+use crate::{events::Event, ChaindexingRepo, Config, Repo};
+use crate::{
+    ChaindexingRepoConn, ChaindexingRepoRawQueryClient, ChaindexingRepoRawQueryTxnClient,
+    HasRawQueryClient,
+};
 
-use handle_events::HandleEvents;
+pub use handle_events::{ContractStateError, HandleEvents};
 use handled_events::MaybeBacktrackHandledEvents;
+pub use handling_throttle::HandlingThrottle;
 
 #[derive(Clone)]
 pub struct EventHandlerContext<'a> {
     pub event: Event,
     raw_query_client: &'a ChaindexingRepoRawQueryTxnClient<'a>,
+    deduplicate_state_versions: bool,
 }
 
 impl<'a> EventHandlerContext<'a> {
@@ -22,8 +28,35 @@ impl<'a> EventHandlerContext<'a> {
         Self {
             event,
             raw_query_client: client,
+            deduplicate_state_versions: false,
         }
     }
+
+    /// See `Config::with_deduplicate_state_versions`
+    pub fn with_deduplicate_state_versions(mut self, deduplicate_state_versions: bool) -> Self {
+        self.deduplicate_state_versions = deduplicate_state_versions;
+
+        self
+    }
+
+    pub fn deduplicates_state_versions(&self) -> bool {
+        self.deduplicate_state_versions
+    }
+
+    /// Chain of the event being handled, e.g. to key states per chain
+    pub fn get_chain_id(&self) -> i32 {
+        self.event.chain_id
+    }
+
+    /// The transaction the handler runs in. Consumer queries executed with it
+    /// are committed or rolled back together with chaindexing's own, e.g. when
+    /// a handler panics. It is only borrowed for the handler's call: neither
+    /// commit it nor hold onto it beyond `handle_event`. Only conflicts of
+    /// queries executed with `ChaindexingRepo`'s raw query helpers get the
+    /// handling transaction re-run.
+    pub fn get_raw_query_txn_client(&self) -> &'a ChaindexingRepoRawQueryTxnClient<'a> {
+        self.raw_query_client
+    }
 }
 
 pub trait UseEventHandlerContext<'a> {
@@ -52,30 +85,51 @@ impl EventHandlers {
             let conn = ChaindexingRepo::get_conn(&pool).await;
 
             let mut raw_query_client = config.repo.get_raw_query_client().await;
+            let mut shard_raw_query_clients =
+                HandleEvents::get_shard_raw_query_clients(&config).await;
 
             let conn = Arc::new(Mutex::new(conn));
             let mut interval = interval(Duration::from_millis(config.handler_interval_ms));
-            let event_handlers_by_event_abi =
-                Contracts::get_all_event_handlers_by_event_abi(&config.contracts);
 
             loop {
                 interval.tick().await;
 
-                HandleEvents::run(
+                Self::run_with_shard_clients(
                     conn.clone(),
-                    &event_handlers_by_event_abi,
+                    &config,
                     &mut raw_query_client,
-                )
-                .await;
-
-                let state_migrations = Contracts::get_state_migrations(&config.contracts);
-                MaybeBacktrackHandledEvents::run(
-                    conn.clone(),
-                    &mut raw_query_client,
-                    &state_migrations,
+                    &mut shard_raw_query_clients,
                 )
                 .await;
             }
         });
     }
+
+    /// Handles ingested events, then backtracks states of reorged blocks
+    pub async fn run<'a>(
+        conn: Arc<Mutex<ChaindexingRepoConn<'a>>>,
+        config: &Config,
+        raw_query_client: &mut ChaindexingRepoRawQueryClient,
+    ) {
+        Self::run_with_shard_clients(conn, config, raw_query_client, &mut []).await;
+    }
+
+    /// Same as `run`, handling events concurrently over the shard clients,
+    /// see `HandleEvents::get_shard_raw_query_clients`
+    pub async fn run_with_shard_clients<'a>(
+        conn: Arc<Mutex<ChaindexingRepoConn<'a>>>,
+        config: &Config,
+        raw_query_client: &mut ChaindexingRepoRawQueryClient,
+        shard_raw_query_clients: &mut [ChaindexingRepoRawQueryClient],
+    ) {
+        HandleEvents::run_with_shard_clients(
+            conn.clone(),
+            config,
+            raw_query_client,
+            shard_raw_query_clients,
+        )
+        .await;
+
+        MaybeBacktrackHandledEvents::run(conn, raw_query_client, config).await;
+    }
 }
@@ -1,12 +1,24 @@
-use std::{collections::HashMap, sync::Arc};
+use std::time::Duration;
+use std::{cmp::Reverse, collections::HashMap, pin::Pin, sync::Arc};
 
+use derive_more::Display;
+use futures_util::future::join_all;
 use futures_util::StreamExt;
 use tokio::sync::Mutex;
+use tokio::time::timeout;
+use uuid::Uuid;
 
-use crate::{events::Event, ChaindexingRepo};
+use crate::metrics::{record_metric, MetricKind};
+#[cfg(feature = "pipeline-spans")]
+use crate::pipeline_spans::{
+    new_pipeline_span, record_pipeline_spans, PipelineSpan, PipelineSpanKind,
+};
+use crate::{contracts::Contracts, events::Event, ChaindexingRepo};
 use crate::{
-    ChaindexingRepoConn, ChaindexingRepoRawQueryClient, ContractAddress, ExecutesWithRawQuery,
-    HasRawQueryClient, Streamable,
+    BlockNumber, Chaindexing, ChaindexingRepoConn, ChaindexingRepoRawQueryClient,
+    ChaindexingRepoRawQueryTxnClient, Config, Contract, ContractAddress, ContractStates,
+    ExecutesWithRawQuery, HasRawQueryClient, Repo, RepoError, StateDrift, StateSnapshot,
+    Streamable,
 };
 
 use super::{EventHandler, EventHandlerContext};
@@ -14,70 +26,916 @@ use super::{EventHandler, EventHandlerContext};
 pub struct HandleEvents;
 
 impl HandleEvents {
+    /// Handles every contract address over the single raw query client, see
+    /// `run_with_shard_clients` to handle them concurrently
     pub async fn run<'a>(
         conn: Arc<Mutex<ChaindexingRepoConn<'a>>>,
-        event_handlers_by_event_abi: &HashMap<&str, Arc<dyn EventHandler>>,
+        config: &Config,
+        raw_query_client: &mut ChaindexingRepoRawQueryClient,
+    ) {
+        Self::run_with_shard_clients(conn, config, raw_query_client, &mut []).await;
+    }
+
+    /// Raw query clients for the shards beyond the first one, which uses the
+    /// handling's own client: `handler_parallelism - 1` of them. Meant to be
+    /// opened once and reused across runs.
+    pub async fn get_shard_raw_query_clients(
+        config: &Config,
+    ) -> Vec<ChaindexingRepoRawQueryClient> {
+        let mut shard_raw_query_clients = vec![];
+
+        for _ in 1..config.handler_parallelism {
+            shard_raw_query_clients.push(config.repo.get_raw_query_client().await);
+        }
+
+        shard_raw_query_clients
+    }
+
+    /// Contract addresses are handled by descending priority of their
+    /// contracts, so that a contract's events for a block are handled before
+    /// those of any lower-priority contract. Contract addresses of equal
+    /// priorities are sharded by id over the raw query client and the shard
+    /// ones, see `get_shard_raw_query_clients`: shards run concurrently, but
+    /// each one handles its contract addresses one after the other, keeping
+    /// an address's events sequential.
+    pub async fn run_with_shard_clients<'a>(
+        conn: Arc<Mutex<ChaindexingRepoConn<'a>>>,
+        config: &Config,
         raw_query_client: &mut ChaindexingRepoRawQueryClient,
+        shard_raw_query_clients: &mut [ChaindexingRepoRawQueryClient],
     ) {
+        let contracts = &config.contracts;
+        let event_handlers_by_event_abi = Contracts::get_all_event_handlers_by_event_abi(contracts);
+        let priorities_by_contract_name = Contracts::group_priorities_by_names(contracts);
+
         let mut contract_addresses_stream =
             ChaindexingRepo::get_contract_addresses_stream(conn.clone());
+        let mut contract_addresses = vec![];
 
-        while let Some(contract_addresses) = contract_addresses_stream.next().await {
-            for contract_address in contract_addresses {
-                Self::handle_events_for_contract_address(
-                    conn.clone(),
-                    &contract_address,
-                    event_handlers_by_event_abi,
-                    raw_query_client,
+        while let Some(contract_addresses_batch) = contract_addresses_stream.next().await {
+            contract_addresses.extend(contract_addresses_batch);
+        }
+
+        // Stable, so contract addresses of equal priorities keep their order
+        contract_addresses.sort_by_key(|contract_address| {
+            Reverse(priorities_by_contract_name.get(&contract_address.contract_name).cloned())
+        });
+
+        let mut raw_query_clients: Vec<_> = std::iter::once(raw_query_client)
+            .chain(shard_raw_query_clients.iter_mut())
+            .take(contract_addresses.len().max(1))
+            .collect();
+
+        let contract_addresses_by_priority =
+            Self::group_by_priority(contract_addresses, &priorities_by_contract_name);
+
+        for contract_addresses in contract_addresses_by_priority {
+            let shards = Self::shard_by_id(contract_addresses, raw_query_clients.len());
+
+            let raw_query_clients = raw_query_clients.iter_mut().map(|client| &mut **client);
+
+            join_all(shards.into_iter().zip(raw_query_clients).map(
+                |(contract_addresses, raw_query_client)| {
+                    let conn = conn.clone();
+                    let event_handlers_by_event_abi = &event_handlers_by_event_abi;
+
+                    async move {
+                        for contract_address in contract_addresses {
+                            Self::handle_events_for_contract_address(
+                                conn.clone(),
+                                &contract_address,
+                                event_handlers_by_event_abi,
+                                config,
+                                raw_query_client,
+                            )
+                            .await
+                        }
+                    }
+                },
+            ))
+            .await;
+        }
+    }
+
+    /// Groups contract addresses, already sorted by priority, by equal priorities
+    fn group_by_priority(
+        contract_addresses: Vec<ContractAddress>,
+        priorities_by_contract_name: &HashMap<String, u16>,
+    ) -> Vec<Vec<ContractAddress>> {
+        let mut contract_addresses_by_priority: Vec<Vec<ContractAddress>> = vec![];
+        let mut last_priority = None;
+
+        for contract_address in contract_addresses {
+            let priority = priorities_by_contract_name.get(&contract_address.contract_name);
+
+            match contract_addresses_by_priority.last_mut() {
+                Some(group) if priority == last_priority => group.push(contract_address),
+                _ => contract_addresses_by_priority.push(vec![contract_address]),
+            }
+
+            last_priority = priority;
+        }
+
+        contract_addresses_by_priority
+    }
+
+    fn shard_by_id(
+        contract_addresses: Vec<ContractAddress>,
+        shards_count: usize,
+    ) -> Vec<Vec<ContractAddress>> {
+        let mut shards = vec![vec![]; shards_count];
+
+        for contract_address in contract_addresses {
+            shards[contract_address.id as usize % shards_count].push(contract_address);
+        }
+
+        shards
+    }
+
+    /// Re-runs handlers over already ingested events from the given block
+    /// without touching any JSON RPC; `chaindexing_events` is left untouched.
+    /// States from the block onwards are backtracked before replaying, or
+    /// dropped and re-created entirely with `reset_states`.
+    pub async fn replay(config: &Config, from_block_number: BlockNumber, reset_states: bool) {
+        let pool = config.repo.get_pool(1).await;
+        let conn = ChaindexingRepo::get_conn(&pool).await;
+        let conn = Arc::new(Mutex::new(conn));
+        let mut raw_query_client = config.repo.get_raw_query_client().await;
+
+        Self::replay_with_conn(
+            conn,
+            &mut raw_query_client,
+            config,
+            from_block_number,
+            reset_states,
+        )
+        .await;
+    }
+
+    pub async fn replay_with_conn<'a>(
+        conn: Arc<Mutex<ChaindexingRepoConn<'a>>>,
+        raw_query_client: &mut ChaindexingRepoRawQueryClient,
+        config: &Config,
+        from_block_number: BlockNumber,
+        reset_states: bool,
+    ) {
+        let Config {
+            contracts, chains, ..
+        } = config;
+
+        if contracts.is_empty() {
+            return;
+        }
+
+        if reset_states {
+            Chaindexing::reset_migrations_for_contract_states(raw_query_client, contracts).await;
+            Chaindexing::run_migrations_for_contract_states(raw_query_client, contracts).await;
+        }
+
+        let from_block_number = i64::try_from(from_block_number).unwrap();
+        let state_migrations = Contracts::get_state_migrations(contracts);
+        let contract_names: Vec<String> = contracts.iter().map(|c| c.name.clone()).collect();
+
+        let raw_query_txn_client =
+            ChaindexingRepo::get_raw_query_txn_client(raw_query_client).await;
+
+        for chain in chains.keys() {
+            ContractStates::backtrack_states(
+                &state_migrations,
+                *chain as i32,
+                from_block_number,
+                &raw_query_txn_client,
+            )
+            .await;
+        }
+
+        ChaindexingRepo::update_every_next_block_number_to_handle_from_for_contracts_in_txn(
+            &raw_query_txn_client,
+            &contract_names,
+            from_block_number,
+        )
+        .await;
+
+        ChaindexingRepo::commit_raw_query_txns(raw_query_txn_client).await;
+
+        Self::run(conn, config, raw_query_client).await;
+    }
+
+    /// Re-derives a contract's states from scratch, e.g. after changing its
+    /// state migrations: its state tables are dropped and re-created with the
+    /// current migrations, then its handlers are replayed over already
+    /// ingested events from its start block.
+    pub async fn rebuild_state(
+        config: &Config,
+        contract_name: &str,
+    ) -> Result<(), ContractStateError> {
+        let pool = config.repo.get_pool(1).await;
+        let conn = ChaindexingRepo::get_conn(&pool).await;
+        let conn = Arc::new(Mutex::new(conn));
+        let mut raw_query_client = config.repo.get_raw_query_client().await;
+
+        Self::rebuild_state_with_conn(conn, &mut raw_query_client, config, contract_name).await
+    }
+
+    pub async fn rebuild_state_with_conn<'a>(
+        conn: Arc<Mutex<ChaindexingRepoConn<'a>>>,
+        raw_query_client: &mut ChaindexingRepoRawQueryClient,
+        config: &Config,
+        contract_name: &str,
+    ) -> Result<(), ContractStateError> {
+        let contract = Self::get_contract(&config.contracts, contract_name)?;
+        let contracts = vec![contract.clone()];
+
+        Chaindexing::reset_migrations_for_contract_states(raw_query_client, &contracts).await;
+        Chaindexing::run_migrations_for_contract_states(raw_query_client, &contracts).await;
+
+        let raw_query_txn_client =
+            ChaindexingRepo::get_raw_query_txn_client(raw_query_client).await;
+
+        // Handling cursors never rewind behind their start block numbers
+        ChaindexingRepo::update_every_next_block_number_to_handle_from_for_contracts_in_txn(
+            &raw_query_txn_client,
+            &vec![contract.name.clone()],
+            0,
+        )
+        .await;
+
+        ChaindexingRepo::commit_raw_query_txns(raw_query_txn_client).await;
+
+        // Other contracts simply carry on from their own cursors
+        Self::run(conn, config, raw_query_client).await;
+
+        Ok(())
+    }
+
+    /// Verifies a contract's live states against states replayed from its
+    /// ingested events into throwaway tables, e.g. to detect drift from a
+    /// handler bug or a partially handled reorg. Each of its addresses gets
+    /// replayed up to where it has been handled, in a transaction that gets
+    /// rolled back, leaving live states and handling cursors untouched.
+    /// Handlers do get re-run though, so any other side effects of theirs,
+    /// e.g. publishing to sinks, get repeated.
+    pub async fn verify_state(
+        config: &Config,
+        contract_name: &str,
+    ) -> Result<Vec<StateDrift>, ContractStateError> {
+        let pool = config.repo.get_pool(1).await;
+        let conn = ChaindexingRepo::get_conn(&pool).await;
+        let conn = Arc::new(Mutex::new(conn));
+        let mut raw_query_client = config.repo.get_raw_query_client().await;
+
+        Self::verify_state_with_conn(conn, &mut raw_query_client, config, contract_name).await
+    }
+
+    pub async fn verify_state_with_conn<'a>(
+        conn: Arc<Mutex<ChaindexingRepoConn<'a>>>,
+        raw_query_client: &mut ChaindexingRepoRawQueryClient,
+        config: &Config,
+        contract_name: &str,
+    ) -> Result<Vec<StateDrift>, ContractStateError> {
+        let contract = Self::get_contract(&config.contracts, contract_name)?;
+
+        let raw_query_txn_client =
+            ChaindexingRepo::get_raw_query_txn_client(raw_query_client).await;
+
+        let replay = match ContractStates::create_verification_states(
+            &contract.state_migrations,
+            &raw_query_txn_client,
+        )
+        .await
+        {
+            Ok(()) => {
+                Self::replay_handled_events_in_txn(conn, contract, config, &raw_query_txn_client)
+                    .await
+            }
+            Err(contract_state_error) => Err(contract_state_error),
+        };
+
+        let drifts = match replay {
+            Ok(()) => Ok(ContractStates::get_drifts(
+                &contract.state_migrations,
+                &raw_query_txn_client,
+            )
+            .await),
+            Err(contract_state_error) => Err(contract_state_error),
+        };
+
+        ChaindexingRepo::rollback_raw_query_txns(raw_query_txn_client).await;
+
+        drifts
+    }
+
+    /// Rebuilds a shadow contract's states, see `Config::add_shadow_contract`,
+    /// from scratch: the shadow schema is dropped, then its handlers are
+    /// replayed over all of its live contract's events, up to where they have
+    /// been handled, into fresh states there. Each call replays the whole
+    /// history again, so re-run it sparingly, e.g. before diffing. Live states
+    /// and handling cursors are left untouched: the rebuild gets rolled back
+    /// if the shadow handlers wrote to any table outside of the shadow schema,
+    /// e.g. a live one missing from their state migrations. Any other side
+    /// effects of theirs, e.g. publishing to sinks, are not isolated though.
+    pub async fn rebuild_shadow_state(
+        config: &Config,
+        contract_name: &str,
+    ) -> Result<(), ContractStateError> {
+        let pool = config.repo.get_pool(1).await;
+        let conn = ChaindexingRepo::get_conn(&pool).await;
+        let conn = Arc::new(Mutex::new(conn));
+        let mut raw_query_client = config.repo.get_raw_query_client().await;
+
+        Self::rebuild_shadow_state_with_conn(conn, &mut raw_query_client, config, contract_name)
+            .await
+    }
+
+    pub async fn rebuild_shadow_state_with_conn<'a>(
+        conn: Arc<Mutex<ChaindexingRepoConn<'a>>>,
+        raw_query_client: &mut ChaindexingRepoRawQueryClient,
+        config: &Config,
+        contract_name: &str,
+    ) -> Result<(), ContractStateError> {
+        let shadow_contract = Self::get_contract(&config.shadow_contracts, contract_name)?;
+
+        let raw_query_txn_client =
+            ChaindexingRepo::get_raw_query_txn_client(raw_query_client).await;
+
+        let replay = match ContractStates::create_shadow_states(
+            &shadow_contract.state_migrations,
+            &raw_query_txn_client,
+        )
+        .await
+        {
+            Ok(()) => {
+                Self::replay_handled_events_in_txn(
+                    conn,
+                    shadow_contract,
+                    config,
+                    &raw_query_txn_client,
                 )
                 .await
             }
+            Err(contract_state_error) => Err(contract_state_error),
+        };
+        if let Err(contract_state_error) = replay {
+            ChaindexingRepo::rollback_raw_query_txns(raw_query_txn_client).await;
+
+            return Err(contract_state_error);
+        }
+
+        let table_names =
+            ContractStates::get_table_names_written_outside_shadow_schema(&raw_query_txn_client)
+                .await;
+        if !table_names.is_empty() {
+            ChaindexingRepo::rollback_raw_query_txns(raw_query_txn_client).await;
+
+            return Err(ContractStateError::ShadowWritesOutsideShadowSchema(
+                table_names,
+            ));
+        }
+
+        ChaindexingRepo::commit_raw_query_txns(raw_query_txn_client).await;
+
+        Ok(())
+    }
+
+    /// Diffs a contract's live states against its shadow ones, as of the last
+    /// `rebuild_shadow_state`: unexpected states are the live ones the shadow handlers
+    /// did not produce, while missing ones got produced by them only
+    pub async fn diff_shadow_state(
+        config: &Config,
+        contract_name: &str,
+    ) -> Result<Vec<StateDrift>, ContractStateError> {
+        let mut raw_query_client = config.repo.get_raw_query_client().await;
+
+        Self::diff_shadow_state_with_client(&mut raw_query_client, config, contract_name).await
+    }
+
+    pub async fn diff_shadow_state_with_client(
+        raw_query_client: &mut ChaindexingRepoRawQueryClient,
+        config: &Config,
+        contract_name: &str,
+    ) -> Result<Vec<StateDrift>, ContractStateError> {
+        let shadow_contract = Self::get_contract(&config.shadow_contracts, contract_name)?;
+
+        let raw_query_txn_client =
+            ChaindexingRepo::get_raw_query_txn_client(raw_query_client).await;
+
+        let drifts = ContractStates::get_shadow_drifts(
+            &shadow_contract.state_migrations,
+            &raw_query_txn_client,
+        )
+        .await;
+
+        ChaindexingRepo::rollback_raw_query_txns(raw_query_txn_client).await;
+
+        Ok(drifts)
+    }
+
+    /// Snapshots a contract's states and handling cursors, see
+    /// `StateSnapshot`, without blocking handlers running meanwhile
+    pub async fn snapshot_state(
+        config: &Config,
+        contract_name: &str,
+    ) -> Result<StateSnapshot, ContractStateError> {
+        let mut raw_query_client = config.repo.get_raw_query_client().await;
+
+        Self::snapshot_state_with_client(&mut raw_query_client, config, contract_name).await
+    }
+
+    pub async fn snapshot_state_with_client(
+        raw_query_client: &mut ChaindexingRepoRawQueryClient,
+        config: &Config,
+        contract_name: &str,
+    ) -> Result<StateSnapshot, ContractStateError> {
+        let contract = Self::get_contract(&config.contracts, contract_name)?;
+
+        let raw_query_txn_client =
+            ChaindexingRepo::get_raw_query_txn_client(raw_query_client).await;
+
+        let snapshot = ContractStates::snapshot(
+            &contract.name,
+            &contract.state_migrations,
+            &raw_query_txn_client,
+        )
+        .await;
+
+        ChaindexingRepo::rollback_raw_query_txns(raw_query_txn_client).await;
+
+        Ok(snapshot)
+    }
+
+    /// Restores a `StateSnapshot` into a setup database, e.g. a new replica's,
+    /// before indexing, which then resumes from the snapshot's cursors instead
+    /// of replaying every event. Must not run while handlers are running.
+    pub async fn restore_state(
+        config: &Config,
+        snapshot: &StateSnapshot,
+    ) -> Result<(), ContractStateError> {
+        let mut raw_query_client = config.repo.get_raw_query_client().await;
+
+        Self::restore_state_with_client(&mut raw_query_client, config, snapshot).await
+    }
+
+    pub async fn restore_state_with_client(
+        raw_query_client: &mut ChaindexingRepoRawQueryClient,
+        config: &Config,
+        snapshot: &StateSnapshot,
+    ) -> Result<(), ContractStateError> {
+        let contract = Self::get_contract(&config.contracts, &snapshot.contract_name)?;
+
+        let raw_query_txn_client =
+            ChaindexingRepo::get_raw_query_txn_client(raw_query_client).await;
+
+        let restore =
+            ContractStates::restore(snapshot, &contract.state_migrations, &raw_query_txn_client)
+                .await;
+
+        match restore {
+            Ok(()) => ChaindexingRepo::commit_raw_query_txns(raw_query_txn_client).await,
+            Err(_) => ChaindexingRepo::rollback_raw_query_txns(raw_query_txn_client).await,
+        }
+
+        restore
+    }
+
+    fn get_contract<'c>(
+        contracts: &'c [Contract],
+        contract_name: &str,
+    ) -> Result<&'c Contract, ContractStateError> {
+        contracts
+            .iter()
+            .find(|contract| contract.name == contract_name)
+            .ok_or_else(|| ContractStateError::UnknownContract(contract_name.to_string()))
+    }
+
+    /// Re-runs the contract's handlers over the events of the contract
+    /// addresses of its name, from their start blocks up to where they have
+    /// been handled, within the transaction, which is left for the caller to
+    /// roll back when a handler times out
+    async fn replay_handled_events_in_txn<'a, 'b>(
+        conn: Arc<Mutex<ChaindexingRepoConn<'a>>>,
+        contract: &Contract,
+        config: &Config,
+        raw_query_txn_client: &ChaindexingRepoRawQueryTxnClient<'b>,
+    ) -> Result<(), ContractStateError> {
+        let event_handlers_by_event_abi =
+            Contracts::get_all_event_handlers_by_event_abi(&vec![contract.clone()]);
+
+        let mut contract_addresses: Vec<ContractAddress> = {
+            let mut conn = conn.lock().await;
+            ChaindexingRepo::get_all_contract_addresses(&mut conn).await
+        }
+        .into_iter()
+        .filter(|contract_address| contract_address.contract_name == contract.name)
+        .collect();
+        contract_addresses.sort_by_key(|contract_address| contract_address.id);
+
+        for contract_address in contract_addresses.iter() {
+            let mut events_stream = ChaindexingRepo::get_events_stream_for_abis(
+                conn.clone(),
+                contract_address.start_block_number,
+                event_handlers_by_event_abi.keys().map(|abi| abi.to_string()).collect(),
+                config.max_events_per_handling_batch as i64,
+            );
+
+            while let Some(events_batch) = events_stream.next().await {
+                let is_last_batch = events_batch.last().is_some_and(|e| {
+                    e.block_number >= contract_address.next_block_number_to_handle_from
+                });
+
+                for event in events_batch.into_iter().filter(|event| {
+                    event.match_contract_address(&contract_address.address)
+                        && event.not_removed()
+                        && event.block_number < contract_address.next_block_number_to_handle_from
+                }) {
+                    let event_handler =
+                        event_handlers_by_event_abi.get(event.abi.as_str()).unwrap();
+                    let event_handler_context =
+                        EventHandlerContext::new(event, raw_query_txn_client)
+                            .with_deduplicate_state_versions(config.deduplicate_state_versions);
+
+                    Self::handle_event(event_handler.as_ref(), event_handler_context, config)
+                        .await
+                        .map_err(|handler_timeout| {
+                            ContractStateError::HandlerTimeout(handler_timeout.to_string())
+                        })?;
+                }
+
+                if is_last_batch {
+                    break;
+                }
+            }
         }
+
+        Ok(())
     }
 
     async fn handle_events_for_contract_address<'a>(
         conn: Arc<Mutex<ChaindexingRepoConn<'a>>>,
         contract_address: &ContractAddress,
         event_handlers_by_event_abi: &HashMap<&str, Arc<dyn EventHandler>>,
+        config: &Config,
         raw_query_client: &mut ChaindexingRepoRawQueryClient,
     ) {
-        let mut events_stream = ChaindexingRepo::get_events_stream(
+        if contract_address.next_block_number_to_handle_from
+            > contract_address.next_block_number_to_ingest_from
+        {
+            eprintln!(
+                "Events Gap: Handling cursor for contract address {} is ahead of its ingestion cursor",
+                contract_address.address
+            );
+
+            return;
+        }
+
+        record_metric(
+            config,
+            MetricKind::HandlingLag,
+            (contract_address.next_block_number_to_ingest_from
+                - contract_address.next_block_number_to_handle_from) as u64,
+            contract_address,
+        );
+
+        let max_events_per_handling_batch = match &config.handling_throttle {
+            Some(handling_throttle) => config
+                .max_events_per_handling_batch
+                .min(handling_throttle.get_max_events_per_batch()),
+            None => config.max_events_per_handling_batch,
+        };
+
+        let mut events_stream = ChaindexingRepo::get_events_stream_for_abis(
             conn.clone(),
             contract_address.next_block_number_to_handle_from,
-        );
+            event_handlers_by_event_abi.keys().map(|abi| abi.to_string()).collect(),
+            max_events_per_handling_batch as i64,
+        )
+        .peekable();
+
+        while let Some(events_batch) = events_stream.next().await {
+            let mut events_batches = vec![events_batch];
+
+            // A block's events can span batches, but must be handled in the same transaction
+            loop {
+                let last_block_number_in_batch =
+                    events_batches.last().and_then(|b| b.last()).map(|e| e.block_number);
+
+                let next_batch_continues_block = match Pin::new(&mut events_stream).peek().await {
+                    Some(next_events_batch) => {
+                        next_events_batch.first().map(|e| e.block_number)
+                            == last_block_number_in_batch
+                    }
+                    None => false,
+                };
+
+                if !next_batch_continues_block {
+                    break;
+                }
+
+                events_batches.push(events_stream.next().await.unwrap());
+            }
+
+            let mut commit_retries_count = 0;
+
+            let handled_batches = loop {
+                // Paced before the transaction opens, so it is never held
+                // open while waiting, including for re-runs
+                if let Some(handling_throttle) = &config.handling_throttle {
+                    let events_count = events_batches.iter().map(|b| b.len() as u64).sum();
+
+                    handling_throttle.wait_for_batch(events_count).await;
+                }
+
+                let raw_query_txn_client =
+                    ChaindexingRepo::get_raw_query_txn_client(raw_query_client).await;
+
+                // Conflicts are reported by the statement running into them
+                // under READ COMMITTED, and by COMMIT under stricter isolation
+                // levels. Either way, the events are re-run in a new transaction.
+                let handling = Self::handle_events_batches_in_txn(
+                    &events_batches,
+                    contract_address,
+                    event_handlers_by_event_abi,
+                    config,
+                    &raw_query_txn_client,
+                )
+                .await;
+
+                let conflict_error = match handling {
+                    Ok(handled_batches) => {
+                        match ChaindexingRepo::try_commit_raw_query_txns(raw_query_txn_client).await
+                        {
+                            Ok(()) => break handled_batches,
+                            Err(RepoError::Conflict(error)) => error,
+                            Err(error) => {
+                                record_metric(
+                                    config,
+                                    MetricKind::HandlerErrors,
+                                    1,
+                                    contract_address,
+                                );
+
+                                panic!(
+                                    "Handler Commit Error: Events of contract address {} failed to commit: {:?}",
+                                    contract_address.address, error
+                                )
+                            }
+                        }
+                    }
+                    // Retried from the last committed block on the next run
+                    Err(HandlingError::HandlerTimeout(handler_timeout)) => {
+                        ChaindexingRepo::rollback_raw_query_txns(raw_query_txn_client).await;
+                        record_metric(config, MetricKind::HandlerErrors, 1, contract_address);
+
+                        eprintln!("{handler_timeout}, retrying on the next run");
+
+                        return;
+                    }
+                    Err(HandlingError::RepoError(RepoError::Conflict(error))) => {
+                        ChaindexingRepo::rollback_raw_query_txns(raw_query_txn_client).await;
+
+                        error
+                    }
+                    Err(HandlingError::RepoError(error)) => {
+                        record_metric(config, MetricKind::HandlerErrors, 1, contract_address);
+
+                        panic!(
+                            "Handler Error: Events of contract address {} failed to be handled: {:?}",
+                            contract_address.address, error
+                        )
+                    }
+                };
+
+                record_metric(config, MetricKind::HandlerErrors, 1, contract_address);
+
+                if commit_retries_count >= config.max_handler_commit_retries {
+                    panic!(
+                        "Handler Commit Error: Events of contract address {} failed to commit: {}",
+                        contract_address.address, conflict_error
+                    );
+                }
+
+                commit_retries_count += 1;
+
+                eprintln!(
+                    "Handler Commit Conflict: Re-running events of contract address {} (retry {}): {}",
+                    contract_address.address, commit_retries_count, conflict_error
+                );
+            };
+
+            record_metric(
+                config,
+                MetricKind::EventsHandled,
+                handled_batches.handled_events_count,
+                contract_address,
+            );
+            config.event_subscriptions.publish(handled_batches.handled_events);
+            #[cfg(feature = "pipeline-spans")]
+            record_pipeline_spans(config, &handled_batches.pipeline_spans);
+
+            if handled_batches.reached_events_gap {
+                eprintln!(
+                    "Events Gap: Halting handling for contract address {} at block {} until its range is fully ingested",
+                    contract_address.address, contract_address.next_block_number_to_ingest_from
+                );
 
-        while let Some(events) = events_stream.next().await {
+                break;
+            }
+        }
+    }
+
+    /// Handles the events batches and advances the handling cursor within the
+    /// transaction, leaving committing it to the caller, so it can be re-run
+    async fn handle_events_batches_in_txn<'a>(
+        events_batches: &Vec<Vec<Event>>,
+        contract_address: &ContractAddress,
+        event_handlers_by_event_abi: &HashMap<&str, Arc<dyn EventHandler>>,
+        config: &Config,
+        raw_query_txn_client: &ChaindexingRepoRawQueryTxnClient<'a>,
+    ) -> Result<HandledEventsBatches, HandlingError> {
+        let mut last_handled_event = None;
+        let mut handled_batches = HandledEventsBatches::default();
+
+        for events_batch in events_batches {
             // TODO: Move this filter to the stream query level
-            let mut events: Vec<Event> = events
-                .into_iter()
+            let events: Vec<Event> = events_batch
+                .iter()
                 .filter(|event| {
                     event.match_contract_address(&contract_address.address) && event.not_removed()
                 })
+                .cloned()
                 .collect();
-            events.sort_by_key(|e| (e.block_number, e.log_index));
 
-            let raw_query_txn_client =
-                ChaindexingRepo::get_raw_query_txn_client(raw_query_client).await;
+            let (events, events_beyond_ingested_range) =
+                Self::split_at_ingestion_cursor(events, contract_address);
 
-            for event in events.clone() {
+            for event in events.iter() {
                 let event_handler = event_handlers_by_event_abi.get(event.abi.as_str()).unwrap();
                 let event_handler_context =
-                    EventHandlerContext::new(event.clone(), &raw_query_txn_client);
+                    EventHandlerContext::new(event.clone(), raw_query_txn_client)
+                        .with_deduplicate_state_versions(config.deduplicate_state_versions);
 
-                event_handler.handle_event(event_handler_context).await;
+                #[cfg(feature = "pipeline-spans")]
+                let handling_started_at = config.clock.instant();
+
+                Self::handle_event(event_handler.as_ref(), event_handler_context, config).await?;
+                // Left behind by handler statements, which cannot return it
+                ChaindexingRepo::get_raw_query_txn_conflict(raw_query_txn_client).await?;
+
+                #[cfg(feature = "pipeline-spans")]
+                handled_batches.pipeline_spans.extend(new_pipeline_span(
+                    config,
+                    PipelineSpanKind::HandleEvent,
+                    event.chain_id,
+                    vec![event.id],
+                    config.clock.instant().saturating_duration_since(handling_started_at),
+                ));
             }
 
-            if let Some(Event { block_number, .. }) = events.last() {
-                let next_block_number_to_handle_from = block_number + 1;
-                ChaindexingRepo::update_next_block_number_to_handle_from_in_txn(
-                    &raw_query_txn_client,
-                    contract_address.id(),
-                    next_block_number_to_handle_from,
-                )
-                .await;
+            if let Some(Event {
+                block_number,
+                transaction_index,
+                log_index,
+                ..
+            }) = events.last()
+            {
+                last_handled_event = Some((*block_number, *transaction_index, *log_index));
+            }
+
+            handled_batches.handled_events_count += events.len() as u64;
+            // Only held onto until committed when there are subscribers to publish to
+            if config.event_subscriptions.has_subscribers() {
+                handled_batches.handled_events.extend(events);
             }
 
-            ChaindexingRepo::commit_raw_query_txns(raw_query_txn_client).await;
+            if !events_beyond_ingested_range.is_empty() {
+                handled_batches.reached_events_gap = true;
+                break;
+            }
+        }
+
+        if let Some((block_number, transaction_index, log_index)) = last_handled_event {
+            let next_block_number_to_handle_from = block_number + 1;
+            ChaindexingRepo::update_next_block_number_to_handle_from_in_txn(
+                raw_query_txn_client,
+                contract_address.id(),
+                next_block_number_to_handle_from,
+            )
+            .await?;
+
+            ChaindexingRepo::update_handler_checkpoint_in_txn(
+                raw_query_txn_client,
+                contract_address.id(),
+                block_number,
+                transaction_index,
+                log_index,
+            )
+            .await?;
         }
+
+        Ok(handled_batches)
+    }
+
+    async fn handle_event<'a>(
+        event_handler: &dyn EventHandler,
+        event_handler_context: EventHandlerContext<'a>,
+        config: &Config,
+    ) -> Result<(), HandlerTimeout> {
+        let Some(handler_timeout) = config.handler_timeout else {
+            event_handler.handle_event(event_handler_context).await;
+
+            return Ok(());
+        };
+
+        let event = event_handler_context.event.clone();
+
+        timeout(
+            handler_timeout,
+            event_handler.handle_event(event_handler_context),
+        )
+        .await
+        .map_err(|_elapsed| HandlerTimeout {
+            event_id: event.id,
+            contract_address: event.contract_address,
+            block_number: event.block_number,
+            timeout: handler_timeout,
+        })
     }
+
+    /// Handling must never advance past a block range that isn't fully ingested.
+    /// Events at or beyond the ingestion cursor can only come from a partially
+    /// applied batch, so they are held back until ingestion catches up.
+    fn split_at_ingestion_cursor(
+        events: Vec<Event>,
+        contract_address: &ContractAddress,
+    ) -> (Vec<Event>, Vec<Event>) {
+        events
+            .into_iter()
+            .partition(|e| e.block_number < contract_address.next_block_number_to_ingest_from)
+    }
+}
+
+/// Why a contract's states could not be replayed, verified or restored
+#[derive(Clone, Debug, Display, PartialEq)]
+pub enum ContractStateError {
+    #[display(
+        fmt = "Shadow handlers wrote to tables outside of the shadow schema: {:?}",
+        _0
+    )]
+    ShadowWritesOutsideShadowSchema(Vec<String>),
+    /// A handler ran past `Config::handler_timeout` while replaying events
+    #[display(fmt = "{}", _0)]
+    HandlerTimeout(String),
+    #[display(fmt = "Contract {} is missing from the config", _0)]
+    UnknownContract(String),
+    /// Only states in the default schema can be replayed or shadowed
+    #[display(fmt = "States in the {} schema cannot be replayed", _0)]
+    UnsupportedSchema(String),
+    #[display(
+        fmt = "State table {} of the snapshot is missing from the migrations",
+        _0
+    )]
+    UnknownStateTable(String),
+}
+
+/// Handler call that ran past `Config::handler_timeout`
+#[derive(Clone, Debug, Display)]
+#[display(
+    fmt = "Handler Timeout: Handling event {} of contract address {} at block {} took longer than {:?}",
+    event_id,
+    contract_address,
+    block_number,
+    timeout
+)]
+struct HandlerTimeout {
+    event_id: Uuid,
+    contract_address: String,
+    block_number: i64,
+    timeout: Duration,
+}
+
+/// Why a handling transaction has to be rolled back
+enum HandlingError {
+    HandlerTimeout(HandlerTimeout),
+    RepoError(RepoError),
+}
+
+impl From<HandlerTimeout> for HandlingError {
+    fn from(handler_timeout: HandlerTimeout) -> Self {
+        HandlingError::HandlerTimeout(handler_timeout)
+    }
+}
+
+impl From<RepoError> for HandlingError {
+    fn from(repo_error: RepoError) -> Self {
+        HandlingError::RepoError(repo_error)
+    }
+}
+
+#[derive(Default)]
+struct HandledEventsBatches {
+    handled_events_count: u64,
+    handled_events: Vec<Event>,
+    reached_events_gap: bool,
+    /// Recorded once committed
+    #[cfg(feature = "pipeline-spans")]
+    pipeline_spans: Vec<PipelineSpan>,
 }
@@ -2,12 +2,13 @@ use std::sync::Arc;
 
 use tokio::sync::Mutex;
 
+use crate::contracts::Contracts;
 use crate::ChaindexingRepo;
 use crate::{
     ChaindexingRepoConn, ChaindexingRepoRawQueryClient, ExecutesWithRawQuery, HasRawQueryClient,
     Repo,
 };
-use crate::{ContractStateMigrations, ContractStates};
+use crate::{Config, ContractStates};
 use crate::{ReorgedBlock, ReorgedBlocks};
 
 pub struct MaybeBacktrackHandledEvents;
@@ -16,8 +17,9 @@ impl MaybeBacktrackHandledEvents {
     pub async fn run<'a>(
         conn: Arc<Mutex<ChaindexingRepoConn<'a>>>,
         raw_query_client: &mut ChaindexingRepoRawQueryClient,
-        state_migrations: &Vec<Arc<dyn ContractStateMigrations>>,
+        config: &Config,
     ) {
+        let state_migrations = &Contracts::get_state_migrations(&config.contracts);
         let mut conn = conn.lock().await;
         let reorged_blocks = ChaindexingRepo::get_unhandled_reorged_blocks(&mut conn).await;
 
@@ -52,6 +54,7 @@ impl MaybeBacktrackHandledEvents {
             ChaindexingRepo::update_reorged_blocks_as_handled_in_txn(
                 &raw_query_txn_client,
                 &reorged_block_ids,
+                config.clock.now(),
             )
             .await;
 
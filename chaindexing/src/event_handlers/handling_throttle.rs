@@ -0,0 +1,97 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::time::sleep;
+
+/// Paces handling to at most `max_events_per_second` events, across every
+/// contract and handler, e.g. to protect a fragile external API handlers call.
+/// Handling batches get paced before their transactions open, so none is held
+/// open while waiting: batches are capped to a second's worth of events, and
+/// each one waits for its events' slots to be due. Clones share the same pace,
+/// e.g. across parallel handling tasks.
+#[derive(Clone, Debug)]
+pub struct HandlingThrottle {
+    max_events_per_second: u64,
+    interval: Duration,
+    next_slot_at: Arc<Mutex<Option<Instant>>>,
+}
+
+impl HandlingThrottle {
+    pub fn new(max_events_per_second: u64) -> Self {
+        let max_events_per_second = max_events_per_second.max(1);
+
+        Self {
+            max_events_per_second,
+            interval: Duration::from_nanos(1_000_000_000 / max_events_per_second),
+            next_slot_at: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Most events a handling batch can take, i.e. a second's worth. Batches
+    /// still run over it to cover a block's events whole.
+    pub fn get_max_events_per_batch(&self) -> u64 {
+        self.max_events_per_second
+    }
+
+    /// Waits for the slots of a handling batch's events to be due
+    pub async fn wait_for_batch(&self, events_count: u64) {
+        let delay = self.reserve_slots(Instant::now(), events_count);
+
+        if !delay.is_zero() {
+            sleep(delay).await;
+        }
+    }
+
+    /// Returns how long until the first of the reserved slots. Slots missed
+    /// while idle are not caught up with, so handling never bursts past a
+    /// batch's worth of events.
+    fn reserve_slots(&self, now: Instant, events_count: u64) -> Duration {
+        let mut next_slot_at = self.next_slot_at.lock().unwrap();
+        let slot_at = next_slot_at.map_or(now, |next_slot_at| next_slot_at.max(now));
+
+        *next_slot_at = Some(slot_at + self.interval * events_count as u32);
+
+        slot_at - now
+    }
+}
+
+#[cfg(test)]
+mod handling_throttle_test {
+    use super::*;
+
+    #[test]
+    fn spaces_batches_by_their_events_slots() {
+        let handling_throttle = HandlingThrottle::new(4);
+        let now = Instant::now();
+
+        assert_eq!(handling_throttle.reserve_slots(now, 1), Duration::ZERO);
+        assert_eq!(
+            handling_throttle.clone().reserve_slots(now, 2),
+            Duration::from_millis(250)
+        );
+        assert_eq!(
+            handling_throttle.reserve_slots(now + Duration::from_millis(100), 1),
+            Duration::from_millis(650)
+        );
+    }
+
+    #[test]
+    fn does_not_burst_after_idling() {
+        let handling_throttle = HandlingThrottle::new(4);
+        let now = Instant::now();
+        handling_throttle.reserve_slots(now, 1);
+
+        let later = now + Duration::from_secs(10);
+        assert_eq!(handling_throttle.reserve_slots(later, 1), Duration::ZERO);
+        assert_eq!(
+            handling_throttle.reserve_slots(later, 1),
+            Duration::from_millis(250)
+        );
+    }
+
+    #[test]
+    fn caps_batches_to_a_second_worth_of_events() {
+        assert_eq!(HandlingThrottle::new(4).get_max_events_per_batch(), 4);
+        assert_eq!(HandlingThrottle::new(0).get_max_events_per_batch(), 1);
+    }
+}
@@ -0,0 +1,73 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use derive_more::Display;
+use tokio::time::sleep;
+
+use crate::{Event, EventContext, EventHandler};
+
+const MAX_BACKOFF_SECS: u64 = 60;
+
+#[derive(Debug, Display)]
+pub struct EventSinkError(pub String);
+
+/// Destination for handled events outside of the database, e.g. a Kafka topic
+/// or a NATS subject. Adapters only need to publish a single event.
+#[async_trait::async_trait]
+pub trait EventSink: Send + Sync {
+    async fn publish(&self, event: &Event) -> Result<(), EventSinkError>;
+}
+
+/// Publishes every event it handles to the sink, after running the wrapped
+/// handler if any, e.g. to index states and feed a message bus together.
+///
+/// Events get published within their handling transaction, which only gets
+/// committed once the sink accepts them: failed publishes get retried with a
+/// backoff, and events handled again after a crash or a reorg get published
+/// again. Delivery is hence at-least-once, so consumers should be idempotent,
+/// e.g. keyed by transaction hash and log index.
+#[derive(Clone)]
+pub struct EventSinkHandler {
+    sink: Arc<dyn EventSink>,
+    event_handler: Option<Arc<dyn EventHandler>>,
+}
+
+impl EventSinkHandler {
+    pub fn new(sink: impl EventSink + 'static) -> Self {
+        Self {
+            sink: Arc::new(sink),
+            event_handler: None,
+        }
+    }
+
+    pub fn with_event_handler(mut self, event_handler: impl EventHandler + 'static) -> Self {
+        self.event_handler = Some(Arc::new(event_handler));
+
+        self
+    }
+
+    async fn publish(&self, event: &Event) {
+        let mut retries_so_far = 0;
+
+        while let Err(sink_error) = self.sink.publish(event).await {
+            eprintln!("Event Sink Error: {sink_error}");
+
+            let backoff_secs = 2u64.saturating_pow(retries_so_far).min(MAX_BACKOFF_SECS);
+            sleep(Duration::from_secs(backoff_secs)).await;
+            retries_so_far += 1;
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EventHandler for EventSinkHandler {
+    async fn handle_event<'a>(&self, event_context: EventContext<'a>) {
+        let event = event_context.event.clone();
+
+        if let Some(event_handler) = &self.event_handler {
+            event_handler.handle_event(event_context).await;
+        }
+
+        self.publish(&event).await;
+    }
+}
@@ -0,0 +1,79 @@
+use futures_core::Stream;
+use futures_util::stream;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::Event;
+
+/// Fans handled events out to subscribers, once their handling transaction is
+/// committed. Clones share the same subscribers, so subscribing through any
+/// clone of the config receives the running handlers' events.
+///
+/// Each subscriber buffers at most `capacity` events. Handling never waits for
+/// subscribers: a subscriber lagging further behind misses its oldest events,
+/// which gets logged, and carries on with the most recent ones.
+#[derive(Clone, Debug)]
+pub struct EventSubscriptions {
+    sender: broadcast::Sender<Event>,
+}
+
+impl Default for EventSubscriptions {
+    fn default() -> Self {
+        Self::new(1000)
+    }
+}
+
+impl EventSubscriptions {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _receiver) = broadcast::channel(capacity);
+
+        Self { sender }
+    }
+
+    /// Streams handled events of the contract, only with the given ABIs unless empty
+    pub fn subscribe(
+        &self,
+        contract_name: &str,
+        event_abis: &[&str],
+    ) -> impl Stream<Item = Event> + Send + Unpin {
+        let contract_name = contract_name.to_string();
+        let event_abis: Vec<String> = event_abis.iter().map(|abi| abi.to_string()).collect();
+
+        let events_stream = stream::unfold(self.sender.subscribe(), move |mut receiver| {
+            let contract_name = contract_name.clone();
+            let event_abis = event_abis.clone();
+
+            async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(event) => {
+                            if event.contract_name == contract_name
+                                && (event_abis.is_empty() || event_abis.contains(&event.abi))
+                            {
+                                return Some((event, receiver));
+                            }
+                        }
+                        Err(RecvError::Lagged(skipped_events_count)) => {
+                            eprintln!(
+                                "Event Subscription: Lagged behind, skipping {skipped_events_count} events of {contract_name}"
+                            );
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            }
+        });
+
+        Box::pin(events_stream)
+    }
+
+    pub fn has_subscribers(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+
+    pub fn publish(&self, events: Vec<Event>) {
+        for event in events {
+            // Only fails without subscribers
+            let _ = self.sender.send(event);
+        }
+    }
+}
@@ -1,16 +1,49 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use std::sync::Arc;
 
 use crate::contracts::{ContractAddress, Contracts, UnsavedContractAddress};
 use crate::diesels::schema::chaindexing_events;
 use crate::hashes::Hashes;
+use derive_more::Display;
 use diesel::{Insertable, Queryable};
-use ethers::abi::{LogParam, Token};
-use ethers::types::{Block, Log, TxHash};
+use ethers::abi::{HumanReadableParser, LogParam, RawLog, Token};
+use ethers::types::{Address, Block, Bytes, Chain, Log, TransactionReceipt, TxHash, H160, H256};
+use ethers::utils::{hex, keccak256};
+use serde::de::DeserializeOwned;
 
-use crate::{Contract, ContractEvent};
+use crate::{AbiLogDecoder, BlockNumber, Contract, ContractEvent, LogDecoder, RepoError};
 use uuid::Uuid;
 
+/// Called with each batch of ingested events right before persisting them,
+/// hence before any handler, e.g. to attach off-chain metadata or drop spam.
+pub type OnEventsIngested = Arc<dyn Fn(&mut Vec<Event>) + Send + Sync>;
+
+/// Stored event whose JSON field cannot be deserialized, e.g. as written by a
+/// buggy version
+#[derive(Clone, Debug, Display, PartialEq)]
+#[display(fmt = "Event {} has malformed {}: {}", event_id, field, error)]
+pub struct MalformedEventError {
+    pub event_id: Uuid,
+    pub field: &'static str,
+    pub error: String,
+}
+
+/// Why a contract's stored events could not be re-decoded
+#[derive(Debug, Display)]
+pub enum RedecodeEventsError {
+    #[display(fmt = "Contract {} is missing from the config", _0)]
+    UnknownContract(String),
+    RepoError(RepoError),
+}
+
+impl From<RepoError> for RedecodeEventsError {
+    fn from(repo_error: RepoError) -> Self {
+        RedecodeEventsError::RepoError(repo_error)
+    }
+}
+
 #[derive(Debug, Clone, Eq, Queryable, Insertable)]
 #[diesel(table_name = chaindexing_events)]
 pub struct Event {
@@ -30,25 +63,28 @@ pub struct Event {
     pub log_index: i64,
     removed: bool,
     inserted_at: chrono::NaiveDateTime,
+    /// Only fetched when `Config::fetch_transaction_statuses` is set
+    pub transaction_status: Option<i64>,
+    /// Set on events soft-deleted by the reorged block,
+    /// with `Config::soft_delete_removed_events`
+    pub reorged_block_id: Option<i32>,
+    /// Only stored with `Config::store_raw_logs`
+    raw_log: Option<serde_json::Value>,
+    /// Missing on events stored before content hashes were, see
+    /// `Event::get_content_hash`
+    content_hash: Option<String>,
 }
 
+/// Events are equal when their contents are, regardless of ids and positions
 impl PartialEq for Event {
     fn eq(&self, other: &Self) -> bool {
-        self.chain_id == other.chain_id
-            && self.contract_address == other.contract_address
-            && self.abi == other.abi
-            && self.log_params == other.log_params
-            && self.block_hash == other.block_hash
+        self.get_content_hash() == other.get_content_hash()
     }
 }
 
 impl Hash for Event {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.chain_id.hash(state);
-        self.contract_address.hash(state);
-        self.abi.hash(state);
-        self.log_params.to_string().hash(state);
-        self.block_hash.hash(state);
+        self.get_content_hash().hash(state);
     }
 }
 
@@ -59,31 +95,149 @@ impl Event {
         contract_address: &UnsavedContractAddress,
         block_timestamp: i64,
     ) -> Self {
-        let log_params = event.value.parse_log(log.clone().into()).unwrap().params;
+        Self::new_with_log_decoder(
+            log,
+            event,
+            contract_address,
+            block_timestamp,
+            &AbiLogDecoder,
+        )
+    }
+
+    pub fn new_with_log_decoder(
+        log: &Log,
+        event: &ContractEvent,
+        contract_address: &UnsavedContractAddress,
+        block_timestamp: i64,
+        log_decoder: &dyn LogDecoder,
+    ) -> Self {
+        Self::try_new_with_log_decoder(log, event, contract_address, block_timestamp, log_decoder)
+            .unwrap()
+    }
+
+    /// Err with the decoding error for logs the log decoder cannot decode
+    pub fn try_new_with_log_decoder(
+        log: &Log,
+        event: &ContractEvent,
+        contract_address: &UnsavedContractAddress,
+        block_timestamp: i64,
+        log_decoder: &dyn LogDecoder,
+    ) -> Result<Self, String> {
+        let log_params = log_decoder.try_decode(log, event)?;
         let parameters = Self::log_params_to_parameters(&log_params);
 
-        Self {
+        Ok(Self {
             id: uuid::Uuid::new_v4(),
             chain_id: contract_address.chain_id,
-            contract_address: ContractAddress::address_to_string(&log.address).to_lowercase(),
+            contract_address: ContractAddress::address_to_string(&log.address),
             contract_name: contract_address.contract_name.to_owned(),
             abi: event.abi.clone(),
             log_params: serde_json::to_value(log_params).unwrap(),
             parameters: serde_json::to_value(parameters).unwrap(),
             topics: serde_json::to_value(&log.topics).unwrap(),
             block_hash: Hashes::h256_to_string(&log.block_hash.unwrap()).to_lowercase(),
-            block_number: log.block_number.unwrap().as_u64() as i64,
+            block_number: i64::try_from(BlockNumber::from(log.block_number.unwrap())).unwrap(),
             block_timestamp,
             transaction_hash: Hashes::h256_to_string(&log.transaction_hash.unwrap()).to_lowercase(),
             transaction_index: log.transaction_index.unwrap().as_u64() as i64,
             log_index: log.log_index.unwrap().as_u64() as i64,
             removed: log.removed.unwrap(),
             inserted_at: chrono::Utc::now().naive_utc(),
+            transaction_status: None,
+            reorged_block_id: None,
+            raw_log: None,
+            content_hash: None,
         }
+        .with_content_hash())
+    }
+
+    /// For constructing synthetic events, e.g. to unit test handlers
+    /// without ingesting any logs
+    pub fn builder() -> EventBuilder {
+        EventBuilder::default()
+    }
+
+    pub fn get_inserted_at(&self) -> chrono::NaiveDateTime {
+        self.inserted_at
+    }
+
+    /// Keccak-256 of the event's chain, contract address, ABI, decoded params
+    /// and block hash, e.g. to tell whether a reingested event changed
+    /// without comparing all of its fields. Computed on the fly for events
+    /// stored before content hashes were.
+    pub fn get_content_hash(&self) -> String {
+        self.content_hash.clone().unwrap_or_else(|| self.compute_content_hash())
+    }
+
+    fn with_content_hash(mut self) -> Self {
+        self.content_hash = Some(self.compute_content_hash());
+
+        self
+    }
+
+    fn compute_content_hash(&self) -> String {
+        let content = serde_json::json!([
+            self.chain_id,
+            self.contract_address,
+            self.abi,
+            self.log_params,
+            self.block_hash
+        ]);
+
+        format!("0x{}", hex::encode(keccak256(content.to_string())))
+    }
+
+    /// The log's topics and data as fetched, e.g. to decode it again with a
+    /// fixed ABI without fetching it from the JSON RPC again
+    pub fn get_raw_log(&self) -> Option<RawLog> {
+        self.try_get_raw_log().unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    pub fn try_get_raw_log(&self) -> Result<Option<RawLog>, MalformedEventError> {
+        let Some(raw_log) = self.raw_log.as_ref() else {
+            return Ok(None);
+        };
+        let topics: Vec<H256> = self.deserialize_field("raw_log", &raw_log["topics"])?;
+        let data: Bytes = self.deserialize_field("raw_log", &raw_log["data"])?;
+
+        Ok(Some(RawLog {
+            topics,
+            data: data.to_vec(),
+        }))
     }
 
     pub fn get_params(&self) -> HashMap<String, Token> {
-        serde_json::from_value(self.parameters.clone()).unwrap()
+        self.try_get_params().unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    pub fn try_get_params(&self) -> Result<HashMap<String, Token>, MalformedEventError> {
+        self.deserialize_field("parameters", &self.parameters)
+    }
+
+    /// Checks every stored JSON field deserializes, e.g. before handing events
+    /// read back from the database to code that would panic on them
+    pub fn validate(&self) -> Result<(), MalformedEventError> {
+        self.deserialize_field::<Vec<LogParam>>("log_params", &self.log_params)?;
+        self.try_get_params()?;
+        self.deserialize_field::<Vec<H256>>("topics", &self.topics)?;
+        self.try_get_raw_log()?;
+
+        Ok(())
+    }
+
+    /// Params decoded from the log's topics, i.e. the ABI's `indexed` inputs.
+    /// Indexed dynamic types, e.g. strings, only hold their keccak256 hashes.
+    pub fn get_indexed_params(&self) -> HashMap<String, Token> {
+        self.get_params_by_indexed(true)
+    }
+
+    /// Params decoded from the log's data, i.e. the ABI's non-`indexed` inputs
+    pub fn get_non_indexed_params(&self) -> HashMap<String, Token> {
+        self.get_params_by_indexed(false)
+    }
+
+    pub fn transaction_reverted(&self) -> Option<bool> {
+        self.transaction_status.map(|status| status == 0)
     }
 
     pub fn not_removed(&self) -> bool {
@@ -91,7 +245,47 @@ impl Event {
     }
 
     pub fn match_contract_address(&self, contract_address: &String) -> bool {
-        self.contract_address.to_lowercase() == *contract_address.to_lowercase()
+        ContractAddress::normalize_address(&self.contract_address)
+            == ContractAddress::normalize_address(contract_address)
+    }
+
+    /// Same log, by its chain, transaction hash and log index, but with other
+    /// data, which usually points at an inconsistent provider rather than a
+    /// reorg, since reorgs mostly move logs to other blocks as they are
+    pub fn has_same_identity_but_different_data(&self, other: &Event) -> bool {
+        self.chain_id == other.chain_id
+            && self.transaction_hash == other.transaction_hash
+            && self.log_index == other.log_index
+            && (!self.match_contract_address(&other.contract_address)
+                || self.abi != other.abi
+                || self.log_params != other.log_params)
+    }
+
+    fn get_params_by_indexed(&self, indexed: bool) -> HashMap<String, Token> {
+        let input_names: HashSet<_> = HumanReadableParser::parse_event(&self.abi)
+            .unwrap()
+            .inputs
+            .into_iter()
+            .filter(|input| input.indexed == indexed)
+            .map(|input| input.name)
+            .collect();
+
+        self.get_params()
+            .into_iter()
+            .filter(|(name, _)| input_names.contains(name))
+            .collect()
+    }
+
+    fn deserialize_field<T: DeserializeOwned>(
+        &self,
+        field: &'static str,
+        value: &serde_json::Value,
+    ) -> Result<T, MalformedEventError> {
+        serde_json::from_value(value.clone()).map_err(|error| MalformedEventError {
+            event_id: self.id,
+            field,
+            error: error.to_string(),
+        })
     }
 
     fn log_params_to_parameters(log_params: &Vec<LogParam>) -> HashMap<String, Token> {
@@ -103,6 +297,118 @@ impl Event {
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct EventBuilder {
+    chain: Chain,
+    contract_address: String,
+    contract_name: String,
+    abi: String,
+    log_params: Vec<LogParam>,
+    block_number: i64,
+    block_timestamp: i64,
+    log_index: i64,
+}
+
+impl Default for EventBuilder {
+    fn default() -> Self {
+        Self {
+            chain: Chain::Mainnet,
+            contract_address: Hashes::h160_to_string(&H160::zero()),
+            contract_name: "".to_string(),
+            abi: "".to_string(),
+            log_params: vec![],
+            block_number: 0,
+            block_timestamp: 0,
+            log_index: 0,
+        }
+    }
+}
+
+impl EventBuilder {
+    pub fn with_chain(mut self, chain: &Chain) -> Self {
+        self.chain = *chain;
+
+        self
+    }
+
+    pub fn with_contract_address(mut self, contract_address: &str) -> Self {
+        self.contract_address = contract_address.to_string();
+
+        self
+    }
+
+    pub fn with_contract_name(mut self, contract_name: &str) -> Self {
+        self.contract_name = contract_name.to_string();
+
+        self
+    }
+
+    pub fn with_abi(mut self, abi: &str) -> Self {
+        self.abi = abi.to_string();
+
+        self
+    }
+
+    pub fn add_param(mut self, name: &str, value: Token) -> Self {
+        self.log_params.push(LogParam {
+            name: name.to_string(),
+            value,
+        });
+
+        self
+    }
+
+    pub fn with_block_number(mut self, block_number: i64) -> Self {
+        self.block_number = block_number;
+
+        self
+    }
+
+    pub fn with_block_timestamp(mut self, block_timestamp: i64) -> Self {
+        self.block_timestamp = block_timestamp;
+
+        self
+    }
+
+    pub fn with_log_index(mut self, log_index: i64) -> Self {
+        self.log_index = log_index;
+
+        self
+    }
+
+    pub fn build(self) -> Event {
+        let topics: Vec<H256> = HumanReadableParser::parse_event(&self.abi)
+            .map(|event| vec![event.signature()])
+            .unwrap_or_default();
+        let zero_hash = Hashes::h256_to_string(&H256::zero());
+
+        Event {
+            id: uuid::Uuid::new_v4(),
+            chain_id: self.chain as i32,
+            contract_address: ContractAddress::normalize_address(&self.contract_address),
+            contract_name: self.contract_name,
+            abi: self.abi,
+            parameters: serde_json::to_value(Event::log_params_to_parameters(&self.log_params))
+                .unwrap(),
+            log_params: serde_json::to_value(self.log_params).unwrap(),
+            topics: serde_json::to_value(topics).unwrap(),
+            block_hash: zero_hash.clone(),
+            block_number: self.block_number,
+            block_timestamp: self.block_timestamp,
+            transaction_hash: zero_hash,
+            transaction_index: 0,
+            log_index: self.log_index,
+            removed: false,
+            inserted_at: chrono::Utc::now().naive_utc(),
+            transaction_status: None,
+            reorged_block_id: None,
+            raw_log: None,
+            content_hash: None,
+        }
+        .with_content_hash()
+    }
+}
+
 pub struct Events;
 
 impl Events {
@@ -111,29 +417,279 @@ impl Events {
         contracts: &Vec<Contract>,
         blocks_by_tx_hash: &HashMap<TxHash, Block<TxHash>>,
     ) -> Vec<Event> {
-        let events_by_topics = Contracts::group_events_by_topics(contracts);
-        let contract_addresses_by_address =
-            Contracts::get_all_contract_addresses_grouped_by_address(contracts);
+        let contract_addresses: Vec<_> =
+            contracts.iter().flat_map(|contract| contract.addresses.clone()).collect();
+
+        Self::new_with_contract_addresses(logs, contracts, &contract_addresses, blocks_by_tx_hash)
+    }
+
+    /// For logs of contract addresses missing from their contracts' configured
+    /// addresses, e.g. the ones registered while indexing
+    pub fn new_with_contract_addresses(
+        logs: &Vec<Log>,
+        contracts: &Vec<Contract>,
+        contract_addresses: &Vec<UnsavedContractAddress>,
+        blocks_by_tx_hash: &HashMap<TxHash, Block<TxHash>>,
+    ) -> Vec<Event> {
+        Self::try_new_with_contract_addresses(
+            logs,
+            contracts,
+            contract_addresses,
+            blocks_by_tx_hash,
+        )
+        .into_iter()
+        .map(|(_log, event)| event.unwrap())
+        .collect()
+    }
+
+    /// Same as `new_with_contract_addresses`, but leaves out logs that cannot
+    /// be decoded, with a warning, instead of panicking on them
+    pub fn new_skipping_undecodable_logs(
+        logs: &Vec<Log>,
+        contracts: &Vec<Contract>,
+        contract_addresses: &Vec<UnsavedContractAddress>,
+        blocks_by_tx_hash: &HashMap<TxHash, Block<TxHash>>,
+    ) -> Vec<Event> {
+        Self::try_new_with_contract_addresses(
+            logs,
+            contracts,
+            contract_addresses,
+            blocks_by_tx_hash,
+        )
+        .into_iter()
+        .filter_map(|(log, event)| match event {
+            Ok(event) => Some(event),
+            Err(error) => {
+                eprintln!(
+                    "Undecodable Log: Skipping log {} of transaction {:?} from {:?}: {error}",
+                    log.log_index.unwrap_or_default(),
+                    log.transaction_hash.unwrap_or_default(),
+                    log.address
+                );
+
+                None
+            }
+        })
+        .collect()
+    }
+
+    /// Leaves out events read back with malformed JSON fields, with a warning,
+    /// instead of panicking on them later, see `Event::validate`
+    pub fn skip_malformed(events: Vec<Event>) -> Vec<Event> {
+        events
+            .into_iter()
+            .filter(|event| match event.validate() {
+                Ok(()) => true,
+                Err(error) => {
+                    eprintln!("Malformed Event: Skipping {error}");
+
+                    false
+                }
+            })
+            .collect()
+    }
+
+    /// Logs of implementation event topics outside of their implementation's
+    /// address and block range have no event to decode them with, see
+    /// `Contract::get_event_topics`, so get dropped
+    fn try_new_with_contract_addresses<'a>(
+        logs: &'a Vec<Log>,
+        contracts: &Vec<Contract>,
+        contract_addresses: &Vec<UnsavedContractAddress>,
+        blocks_by_tx_hash: &HashMap<TxHash, Block<TxHash>>,
+    ) -> Vec<(&'a Log, Result<Event, String>)> {
+        let events_by_names_and_topics = Contracts::group_events_by_names_and_topics(contracts);
+        let contract_addresses_by_address: HashMap<_, _> = contract_addresses
+            .iter()
+            .map(|contract_address| {
+                let address = Address::from_str(contract_address.get_address()).unwrap();
+
+                (address, contract_address)
+            })
+            .collect();
+        let contracts_by_name: HashMap<_, _> =
+            contracts.iter().map(|contract| (contract.name.as_str(), contract)).collect();
 
         logs.iter()
-            .map(
+            .filter_map(
                 |log @ Log {
                      topics,
                      address,
                      transaction_hash,
+                     block_number,
                      ..
                  }| {
                     let contract_address = contract_addresses_by_address.get(&address).unwrap();
                     let block = blocks_by_tx_hash.get(&transaction_hash.unwrap()).unwrap();
 
-                    Event::new(
+                    let contract =
+                        contracts_by_name.get(contract_address.contract_name.as_str()).unwrap();
+                    let event = contract
+                        .get_implementation_event(
+                            contract_address.get_address(),
+                            &topics[0],
+                            BlockNumber::from(block_number.unwrap()),
+                        )
+                        .or_else(|| events_by_names_and_topics[&contract.name].get(&topics[0]))?;
+
+                    Some((
                         log,
-                        &events_by_topics.get(&topics[0]).unwrap(),
-                        &contract_address,
-                        block.timestamp.as_u64() as i64,
-                    )
+                        Event::try_new_with_log_decoder(
+                            log,
+                            event,
+                            &contract_address,
+                            block.timestamp.as_u64() as i64,
+                            contract.log_decoder.as_ref(),
+                        ),
+                    ))
                 },
             )
             .collect()
     }
+
+    /// Decodes stored raw logs again with the contract's current ABI and log
+    /// decoder, e.g. after fixing a wrong ABI, without fetching them again.
+    /// Skips events stored without raw logs, see `Config::with_store_raw_logs`,
+    /// and the ones of events the contract no longer has.
+    pub fn redecode(events: &Vec<Event>, contract: &Contract) -> Vec<Event> {
+        let events_by_topics = contract.group_events_by_topics();
+
+        events
+            .iter()
+            .filter_map(|event| {
+                let RawLog { topics, data } = event.get_raw_log()?;
+                let contract_event = contract
+                    .get_implementation_event(
+                        &event.contract_address,
+                        topics.first()?,
+                        BlockNumber::try_from(event.block_number).ok()?,
+                    )
+                    .or_else(|| events_by_topics.get(topics.first()?))?;
+                let log = Log {
+                    address: Address::from_str(&event.contract_address).unwrap(),
+                    topics,
+                    data: data.into(),
+                    ..Default::default()
+                };
+
+                let log_params = contract.log_decoder.decode(&log, contract_event);
+                let parameters = Event::log_params_to_parameters(&log_params);
+
+                Some(
+                    Event {
+                        abi: contract_event.abi.clone(),
+                        log_params: serde_json::to_value(log_params).unwrap(),
+                        parameters: serde_json::to_value(parameters).unwrap(),
+                        ..event.clone()
+                    }
+                    .with_content_hash(),
+                )
+            })
+            .collect()
+    }
+
+    pub fn set_inserted_at(events: &mut Vec<Event>, inserted_at: chrono::NaiveDateTime) {
+        for event in events.iter_mut() {
+            event.inserted_at = inserted_at;
+        }
+    }
+
+    pub fn set_raw_logs(events: &mut Vec<Event>, logs: &Vec<Log>) {
+        let raw_logs_by_position: HashMap<_, _> = logs
+            .iter()
+            .map(|log| {
+                let position = (
+                    Hashes::h256_to_string(&log.transaction_hash.unwrap()).to_lowercase(),
+                    log.log_index.unwrap().as_u64() as i64,
+                );
+                let raw_log = serde_json::json!({ "topics": log.topics, "data": log.data });
+
+                (position, raw_log)
+            })
+            .collect();
+
+        for event in events.iter_mut() {
+            event.raw_log = raw_logs_by_position
+                .get(&(event.transaction_hash.clone(), event.log_index))
+                .cloned();
+        }
+    }
+
+    pub fn set_transaction_statuses(
+        events: &mut Vec<Event>,
+        receipts_by_tx_hash: &HashMap<TxHash, TransactionReceipt>,
+    ) {
+        let transaction_statuses_by_tx_hash: HashMap<_, _> = receipts_by_tx_hash
+            .iter()
+            .filter_map(|(tx_hash, TransactionReceipt { status, .. })| {
+                status.map(|status| {
+                    let tx_hash = Hashes::h256_to_string(tx_hash).to_lowercase();
+
+                    (tx_hash, status.as_u64() as i64)
+                })
+            })
+            .collect();
+
+        for event in events.iter_mut() {
+            event.transaction_status =
+                transaction_statuses_by_tx_hash.get(&event.transaction_hash).cloned();
+        }
+    }
+}
+
+/// Position after an event in its contract address's events, in on-chain
+/// order, i.e. by block number, transaction index and log index, for
+/// paginating them with `Repo::paginate_events`. Stays stable as newer events
+/// get ingested.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EventsCursor {
+    pub block_number: i64,
+    pub transaction_index: i64,
+    pub log_index: i64,
+}
+
+impl EventsCursor {
+    pub fn new(event: &Event) -> Self {
+        Self {
+            block_number: event.block_number,
+            transaction_index: event.transaction_index,
+            log_index: event.log_index,
+        }
+    }
+
+    /// Opaque and URL-safe, e.g. to hand over to API clients
+    pub fn encode(&self) -> String {
+        let bytes: Vec<u8> = [self.block_number, self.transaction_index, self.log_index]
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect();
+
+        hex::encode(bytes)
+    }
+
+    /// None for cursors not produced by `encode`. Cursors encoded before
+    /// events got ordered by transaction index, without one, still decode to
+    /// the start of their block's first transaction, so clients holding them
+    /// resume where they were, give or take a few events of that block.
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let bytes = hex::decode(cursor).ok()?;
+        let values: Vec<_> = bytes
+            .chunks_exact(8)
+            .map(|value| i64::from_be_bytes(value.try_into().unwrap()))
+            .collect();
+
+        match (bytes.len(), values.as_slice()) {
+            (24, [block_number, transaction_index, log_index]) => Some(Self {
+                block_number: *block_number,
+                transaction_index: *transaction_index,
+                log_index: *log_index,
+            }),
+            (16, [block_number, log_index]) => Some(Self {
+                block_number: *block_number,
+                transaction_index: 0,
+                log_index: *log_index,
+            }),
+            _ => None,
+        }
+    }
 }
@@ -1,7 +1,15 @@
+mod adaptive_blocks_per_batch;
+mod backfilled_events;
+mod blocks_per_batch_probe;
+mod blocks_per_tick_budget;
+mod http_json_rpc;
 mod ingest_events;
 mod ingested_events;
+mod ingestion_interval;
+mod write_pressure;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -11,27 +19,54 @@ use ethers::providers::{Http, Provider, ProviderError};
 use ethers::types::{Address, Filter as EthersFilter, Log};
 use futures_util::future::try_join_all;
 use futures_util::StreamExt;
-use std::cmp::min;
+use std::cmp::{max, min};
 use tokio::sync::Mutex;
-use tokio::time::{interval, sleep};
+use tokio::time::{sleep, timeout};
 
+pub use adaptive_blocks_per_batch::AdaptiveBlocksPerBatch;
+use backfilled_events::BackfillEvents;
+pub use blocks_per_batch_probe::{BlocksPerBatchError, BlocksPerBatchProbe};
+use blocks_per_tick_budget::BlocksPerTickBudget;
+pub use http_json_rpc::{HttpJsonRpc, HttpJsonRpcClients};
 use ingest_events::IngestEvents;
 use ingested_events::MaybeBacktrackIngestedEvents;
+pub use ingested_events::{InconsistentEvent, OnInconsistentEvent};
+use ingestion_interval::AdaptiveIngestionInterval;
+pub use write_pressure::WritePressure;
 
+use crate::block_filters::filter_logs_by_block;
 use crate::chain_reorg::Execution;
 use crate::contracts::Contract;
-use crate::contracts::{ContractEventTopic, Contracts};
+use crate::contracts::{ContractEventTopic, Contracts, UnsavedContractAddress};
+use crate::events::{Event, Events};
+use crate::lagging_nodes::LaggingNode;
+use crate::metrics::{record_metric, MetricKind};
+use crate::start_block_strategies::{StartBlockJsonRpc, StartBlockStrategy};
 use crate::{
-    ChaindexingRepo, ChaindexingRepoConn, Config, ContractAddress, MinConfirmationCount, Repo,
-    RepoError, Streamable,
+    BatchTimings, BlockNumber, BlockRanges, ChainCircuitState, ChaindexingRepo,
+    ChaindexingRepoConn, Config, ContractAddress, ReorgReport, Repo, RepoError, Streamable,
 };
 
 #[async_trait::async_trait]
 pub trait EventsIngesterJsonRpc: Clone + Sync + Send {
     async fn get_block_number(&self) -> Result<U64, ProviderError>;
     async fn get_logs(&self, filter: &EthersFilter) -> Result<Vec<Log>, ProviderError>;
+    /// Logs of each filter, in order. Fetched with concurrent `get_logs` calls
+    /// by default: override to multiplex them into a single call, e.g. a JSON
+    /// RPC batch request as `HttpJsonRpc` does.
+    async fn get_logs_batch(
+        &self,
+        filters: &Vec<&EthersFilter>,
+    ) -> Result<Vec<Vec<Log>>, ProviderError> {
+        try_join_all(filters.iter().map(|filter| self.get_logs(filter))).await
+    }
 
     async fn get_block(&self, block_number: U64) -> Result<Block<TxHash>, ProviderError>;
+    /// Hash of the canonical block at the given number, if the node knows of
+    /// one, e.g. to tell events of reorged-out blocks apart
+    async fn get_block_hash(&self, block_number: U64) -> Result<Option<H256>, ProviderError> {
+        Ok(self.get_block(block_number).await?.hash)
+    }
     async fn get_blocks_by_tx_hash(
         &self,
         logs: &Vec<Log>,
@@ -55,6 +90,32 @@ pub trait EventsIngesterJsonRpc: Clone + Sync + Send {
 
         Ok(blocks)
     }
+
+    /// Receipt of the transaction, if the node knows of it. Only needed with
+    /// `Config::with_fetch_transaction_statuses`, so none by default.
+    async fn get_transaction_receipt(
+        &self,
+        _tx_hash: TxHash,
+    ) -> Result<Option<TransactionReceipt>, ProviderError> {
+        Ok(None)
+    }
+    async fn get_receipts_by_tx_hash(
+        &self,
+        logs: &Vec<Log>,
+    ) -> Result<HashMap<TxHash, TransactionReceipt>, ProviderError> {
+        let tx_hashes: HashSet<_> = logs.iter().map(|log| log.transaction_hash.unwrap()).collect();
+
+        let receipts = try_join_all(
+            tx_hashes.into_iter().map(|tx_hash| self.get_transaction_receipt(tx_hash)),
+        )
+        .await?;
+
+        Ok(receipts
+            .into_iter()
+            .flatten()
+            .map(|receipt| (receipt.transaction_hash, receipt))
+            .collect())
+    }
 }
 
 #[async_trait::async_trait]
@@ -70,11 +131,24 @@ impl EventsIngesterJsonRpc for Provider<Http> {
     async fn get_block(&self, block_number: U64) -> Result<Block<TxHash>, ProviderError> {
         Ok(Middleware::get_block(&self, block_number).await?.unwrap())
     }
+
+    async fn get_block_hash(&self, block_number: U64) -> Result<Option<H256>, ProviderError> {
+        Ok(Middleware::get_block(&self, block_number).await?.and_then(|block| block.hash))
+    }
+
+    async fn get_transaction_receipt(
+        &self,
+        tx_hash: TxHash,
+    ) -> Result<Option<TransactionReceipt>, ProviderError> {
+        Middleware::get_transaction_receipt(&self, tx_hash).await
+    }
 }
 
 #[derive(Debug)]
 pub enum EventsIngesterError {
     RepoConnectionError,
+    /// JSON RPC request still failing after `Config::max_json_rpc_retries`
+    JsonRpcError(String),
     GenericError(String),
 }
 
@@ -82,7 +156,9 @@ impl From<RepoError> for EventsIngesterError {
     fn from(value: RepoError) -> Self {
         match value {
             RepoError::NotConnected => EventsIngesterError::RepoConnectionError,
-            RepoError::Unknown(error) => EventsIngesterError::GenericError(error),
+            RepoError::Conflict(error) | RepoError::Unknown(error) => {
+                EventsIngesterError::GenericError(error)
+            }
         }
     }
 }
@@ -97,43 +173,190 @@ impl EventsIngester {
             let pool = config.repo.get_pool(1).await;
             let conn = ChaindexingRepo::get_conn(&pool).await;
             let conn = Arc::new(Mutex::new(conn));
-            let contracts = config.contracts.clone();
-            let mut interval = interval(Duration::from_millis(config.ingestion_interval_ms));
-
-            loop {
-                interval.tick().await;
-
-                for (chain, json_rpc_url) in config.chains.clone() {
-                    let json_rpc = Arc::new(Provider::<Http>::try_from(json_rpc_url).unwrap());
-
-                    Self::ingest(
-                        conn.clone(),
-                        &contracts,
-                        config.blocks_per_batch,
-                        json_rpc,
-                        &chain,
-                        &config.min_confirmation_count,
-                    )
-                    .await
-                    .unwrap();
+            let mut ingestion_intervals: HashMap<Chain, AdaptiveIngestionInterval> = HashMap::new();
+
+            'ingestion: loop {
+                for chain in config.get_unpaused_chains().keys() {
+                    // Its circuit would be left open anyway, without fetching anything
+                    if config.get_chain_circuit_state(chain) == ChainCircuitState::Open {
+                        continue;
+                    }
+
+                    let json_rpc = Arc::new(config.get_json_rpc(chain));
+
+                    // Fetched once per tick, for both the interval and ingestion
+                    let current_block_number = if config.adaptive_ingestion_interval {
+                        fetch_current_block_number(&json_rpc, &config).await.map(
+                            |current_block_number| {
+                                ingestion_intervals
+                                    .entry(*chain)
+                                    .or_insert_with(|| {
+                                        AdaptiveIngestionInterval::new(config.ingestion_interval_ms)
+                                    })
+                                    .observe(current_block_number, config.clock.instant());
+
+                                Some(current_block_number)
+                            },
+                        )
+                    } else {
+                        Ok(None)
+                    };
+
+                    let ingestion = match current_block_number {
+                        Ok(current_block_number) => {
+                            Self::ingest_unless_circuit_open_at(
+                                conn.clone(),
+                                json_rpc,
+                                chain,
+                                current_block_number,
+                                &config,
+                            )
+                            .await
+                        }
+                        Err(error) => {
+                            Self::record_chain_circuit_outcome(chain, Err(error), &config)
+                        }
+                    };
+                    // Failed ticks open the chain's circuit instead of being fatal
+                    if let (None, Err(error)) = (&config.chain_circuit_breakers, ingestion) {
+                        match config.fatal_error_policy.apply(chain, error) {
+                            Some(retry_delay) => {
+                                sleep(retry_delay).await;
+
+                                continue 'ingestion;
+                            }
+                            None => return,
+                        }
+                    }
                 }
+
+                // Keeps up with the fastest chain
+                let ingestion_interval = ingestion_intervals
+                    .values()
+                    .map(|ingestion_interval| ingestion_interval.get())
+                    .min()
+                    .unwrap_or(Duration::from_millis(config.ingestion_interval_ms));
+
+                sleep(ingestion_interval).await;
             }
         });
     }
 
+    /// Records each tick's outcome with the chain's circuit breaker, if any,
+    /// skipping the chain altogether while its circuit is open
+    pub async fn ingest_unless_circuit_open<'a>(
+        conn: Arc<Mutex<ChaindexingRepoConn<'a>>>,
+        json_rpc: Arc<impl EventsIngesterJsonRpc + 'static>,
+        chain: &Chain,
+        config: &Config,
+    ) -> Result<(), EventsIngesterError> {
+        Self::ingest_unless_circuit_open_at(conn, json_rpc, chain, None, config).await
+    }
+
+    async fn ingest_unless_circuit_open_at<'a>(
+        conn: Arc<Mutex<ChaindexingRepoConn<'a>>>,
+        json_rpc: Arc<impl EventsIngesterJsonRpc + 'static>,
+        chain: &Chain,
+        current_block_number: Option<BlockNumber>,
+        config: &Config,
+    ) -> Result<(), EventsIngesterError> {
+        if config.get_chain_circuit_state(chain) == ChainCircuitState::Open {
+            return Ok(());
+        }
+
+        let ingestion = Self::ingest_at(conn, json_rpc, chain, current_block_number, config).await;
+
+        Self::record_chain_circuit_outcome(chain, ingestion, config)
+    }
+
+    fn record_chain_circuit_outcome(
+        chain: &Chain,
+        ingestion: Result<(), EventsIngesterError>,
+        config: &Config,
+    ) -> Result<(), EventsIngesterError> {
+        let Some(chain_circuit_breakers) = &config.chain_circuit_breakers else {
+            return ingestion;
+        };
+
+        match &ingestion {
+            Ok(()) => chain_circuit_breakers.succeed(chain),
+            Err(error) => {
+                eprintln!("Events Ingester Error: {chain}: {error:?}");
+
+                if chain_circuit_breakers.fail(chain, config.clock.instant()) {
+                    eprintln!("Chain Circuit Open: Skipping {chain} until its cooldown elapses");
+                }
+            }
+        }
+
+        ingestion
+    }
+
     pub async fn ingest<'a>(
         conn: Arc<Mutex<ChaindexingRepoConn<'a>>>,
-        contracts: &Vec<Contract>,
-        blocks_per_batch: u64,
         json_rpc: Arc<impl EventsIngesterJsonRpc + 'static>,
         chain: &Chain,
-        min_confirmation_count: &MinConfirmationCount,
+        config: &Config,
+    ) -> Result<(), EventsIngesterError> {
+        Self::ingest_at(conn, json_rpc, chain, None, config).await
+    }
+
+    /// Up to the tick's current block when already fetched, e.g. to pick the
+    /// adaptive ingestion interval, instead of fetching it again
+    async fn ingest_at<'a>(
+        conn: Arc<Mutex<ChaindexingRepoConn<'a>>>,
+        json_rpc: Arc<impl EventsIngesterJsonRpc + 'static>,
+        chain: &Chain,
+        current_block_number: Option<BlockNumber>,
+        config: &Config,
     ) -> Result<(), EventsIngesterError> {
-        let current_block_number = fetch_current_block_number(&json_rpc).await;
+        if !config.contracts.iter().any(|contract| contract.runs_on(chain)) {
+            return Ok(());
+        }
+
+        let current_block_number = match current_block_number {
+            Some(current_block_number) => current_block_number,
+            None => fetch_current_block_number(&json_rpc, config).await?,
+        };
+        // Ingesting up to the block before the current one, like at the head
+        let current_block_number = match config.end_block_number {
+            Some(end_block_number) => min(current_block_number, end_block_number.saturating_add(1)),
+            None => current_block_number,
+        };
         let mut contract_addresses_stream =
             ChaindexingRepo::get_contract_addresses_stream(conn.clone());
+        let mut max_next_block_number_to_ingest_from = None;
+        let mut blocks_per_tick_budget = BlocksPerTickBudget::new(config.max_blocks_per_tick);
 
         while let Some(contract_addresses) = contract_addresses_stream.next().await {
+            let contract_addresses = Self::filter_contract_addresses_on_chain(
+                &contract_addresses,
+                &config.contracts,
+                chain,
+            );
+
+            max_next_block_number_to_ingest_from = max(
+                max_next_block_number_to_ingest_from,
+                contract_addresses
+                    .iter()
+                    .map(|ca| ca.get_next_block_number_to_ingest_from())
+                    .max(),
+            );
+
+            // Covers contract addresses already at head, which get filtered out
+            for contract_address in contract_addresses.iter() {
+                notify_caught_up(contract_address, current_block_number, config);
+
+                record_metric(
+                    config,
+                    MetricKind::IngestionLag,
+                    current_block_number.value().saturating_sub(
+                        contract_address.get_next_block_number_to_ingest_from().value(),
+                    ),
+                    contract_address,
+                );
+            }
+
             let contract_addresses = Self::filter_uningested_contract_addresses(
                 &contract_addresses,
                 current_block_number,
@@ -144,103 +367,561 @@ impl EventsIngester {
             IngestEvents::run(
                 &mut conn,
                 contract_addresses.clone(),
-                contracts,
                 &json_rpc,
+                chain,
                 current_block_number,
-                blocks_per_batch,
+                &mut blocks_per_tick_budget,
+                config,
             )
             .await?;
 
             MaybeBacktrackIngestedEvents::run(
                 &mut conn,
                 contract_addresses.clone(),
-                contracts,
                 &json_rpc,
                 chain,
                 current_block_number,
-                blocks_per_batch,
-                min_confirmation_count,
+                config,
             )
             .await?;
         }
 
+        if let Some(lagging_node) = max_next_block_number_to_ingest_from
+            .and_then(|block_number| LaggingNode::detect(chain, current_block_number, block_number))
+        {
+            warn_lagging_node(&lagging_node, config);
+        }
+
+        let mut conn = conn.lock().await;
+        Self::maybe_report_reorgs(&mut conn, chain, config).await;
+        Self::maybe_prune_reorged_blocks(&mut conn, chain, config).await;
+
         Ok(())
     }
 
+    async fn maybe_report_reorgs<'a>(
+        conn: &mut ChaindexingRepoConn<'a>,
+        chain: &Chain,
+        config: &Config,
+    ) {
+        let Some(reorg_reporter) = &config.reorg_reporter else {
+            return;
+        };
+
+        if reorg_reporter.take_due(chain, config.clock.instant()) {
+            let interval = reorg_reporter.interval;
+            let since = config.clock.now() - chrono::Duration::from_std(interval).unwrap();
+            let reorged_blocks =
+                ChaindexingRepo::get_reorged_blocks_since(conn, chain, since).await;
+
+            reorg_reporter.report(&ReorgReport::new(chain, interval, &reorged_blocks));
+        }
+    }
+
+    async fn maybe_prune_reorged_blocks<'a>(
+        conn: &mut ChaindexingRepoConn<'a>,
+        chain: &Chain,
+        config: &Config,
+    ) {
+        if let Some(retention) = config.reorged_blocks_retention {
+            let before = config.clock.now() - chrono::Duration::from_std(retention).unwrap();
+
+            ChaindexingRepo::delete_handled_reorged_blocks_before(conn, chain, before).await;
+        }
+    }
+
+    /// Re-fetches logs of the chain's contract addresses over the given block
+    /// range, e.g. to force reconciling a window on demand. Events added or
+    /// removed since they got ingested are applied like a reorg's, recording
+    /// a reorged block for handlers to backtrack from. Only already ingested
+    /// blocks, from each contract address's start block, get reconciled.
+    pub async fn reconcile(
+        config: &Config,
+        chain: &Chain,
+        from_block_number: BlockNumber,
+        to_block_number: BlockNumber,
+    ) -> Result<(), EventsIngesterError> {
+        let pool = config.repo.get_pool(1).await;
+        let mut conn = ChaindexingRepo::get_conn(&pool).await;
+        let json_rpc = Arc::new(config.get_json_rpc(chain));
+
+        Self::reconcile_with_conn(
+            &mut conn,
+            json_rpc,
+            chain,
+            from_block_number,
+            to_block_number,
+            config,
+        )
+        .await
+    }
+
+    pub async fn reconcile_with_conn<'a>(
+        conn: &mut ChaindexingRepoConn<'a>,
+        json_rpc: Arc<impl EventsIngesterJsonRpc + 'static>,
+        chain: &Chain,
+        from_block_number: BlockNumber,
+        to_block_number: BlockNumber,
+        config: &Config,
+    ) -> Result<(), EventsIngesterError> {
+        let contract_addresses = ChaindexingRepo::get_all_contract_addresses(conn)
+            .await
+            .into_iter()
+            .filter(|contract_address| contract_address.get_chain_id() == *chain as i32)
+            .collect();
+
+        MaybeBacktrackIngestedEvents::run_with_execution(
+            conn,
+            contract_addresses,
+            &json_rpc,
+            chain,
+            to_block_number,
+            &Execution::Reconciliation(from_block_number, to_block_number),
+            config,
+        )
+        .await
+    }
+
+    /// Ingests logs of the chain's contract addresses over the exact block
+    /// range, regardless of their cursors, e.g. to ingest history again after
+    /// adding events to a contract. Already ingested events are left as is,
+    /// and so are cursors: backfilled events behind handling cursors do not
+    /// get handled.
+    pub async fn backfill(
+        config: &Config,
+        chain: &Chain,
+        from_block_number: BlockNumber,
+        to_block_number: BlockNumber,
+    ) -> Result<(), EventsIngesterError> {
+        let pool = config.repo.get_pool(1).await;
+        let mut conn = ChaindexingRepo::get_conn(&pool).await;
+        let json_rpc = Arc::new(config.get_json_rpc(chain));
+
+        Self::backfill_with_conn(
+            &mut conn,
+            json_rpc,
+            chain,
+            from_block_number,
+            to_block_number,
+            config,
+        )
+        .await
+    }
+
+    pub async fn backfill_with_conn<'a>(
+        conn: &mut ChaindexingRepoConn<'a>,
+        json_rpc: Arc<impl EventsIngesterJsonRpc + 'static>,
+        chain: &Chain,
+        from_block_number: BlockNumber,
+        to_block_number: BlockNumber,
+        config: &Config,
+    ) -> Result<(), EventsIngesterError> {
+        let contract_addresses = ChaindexingRepo::get_all_contract_addresses(conn)
+            .await
+            .into_iter()
+            .filter(|contract_address| contract_address.get_chain_id() == *chain as i32)
+            .collect();
+
+        BackfillEvents::run(
+            conn,
+            contract_addresses,
+            &json_rpc,
+            chain,
+            from_block_number,
+            to_block_number,
+            config,
+        )
+        .await
+    }
+
+    /// Leaves out contract addresses of other chains, along with those of
+    /// contracts restricted to other chains
+    fn filter_contract_addresses_on_chain(
+        contract_addresses: &Vec<ContractAddress>,
+        contracts: &Vec<Contract>,
+        chain: &Chain,
+    ) -> Vec<ContractAddress> {
+        let contract_names_on_chain: HashSet<_> = contracts
+            .iter()
+            .filter(|contract| contract.runs_on(chain))
+            .map(|contract| contract.name.as_str())
+            .collect();
+
+        contract_addresses
+            .iter()
+            .filter(|ca| {
+                ca.get_chain_id() == *chain as i32
+                    && contract_names_on_chain.contains(ca.contract_name.as_str())
+            })
+            .cloned()
+            .collect()
+    }
+
     fn filter_uningested_contract_addresses(
         contract_addresses: &Vec<ContractAddress>,
-        current_block_number: u64,
+        current_block_number: BlockNumber,
     ) -> Vec<ContractAddress> {
         contract_addresses
             .to_vec()
             .into_iter()
-            .filter(|ca| current_block_number > ca.next_block_number_to_ingest_from as u64)
+            .filter(|ca| current_block_number > ca.get_next_block_number_to_ingest_from())
             .collect()
     }
 }
 
-async fn fetch_current_block_number<'a>(json_rpc: &'a Arc<impl EventsIngesterJsonRpc>) -> u64 {
+async fn fetch_current_block_number<'a>(
+    json_rpc: &'a Arc<impl EventsIngesterJsonRpc>,
+    config: &Config,
+) -> Result<BlockNumber, EventsIngesterError> {
     let mut maybe_current_block_number = None;
     let mut retries_so_far = 0;
 
     while maybe_current_block_number.is_none() {
-        match json_rpc.get_block_number().await {
+        match with_json_rpc_timeout(json_rpc.get_block_number(), config).await {
             Ok(current_block_number) => {
-                maybe_current_block_number = Some(current_block_number.as_u64())
+                maybe_current_block_number = Some(BlockNumber::from(current_block_number))
             }
             Err(provider_error) => {
-                eprintln!("Provider Error: {}", provider_error);
+                backoff_or_fail(provider_error, &mut retries_so_far, config).await?
+            }
+        }
+    }
+
+    Ok(maybe_current_block_number.unwrap())
+}
+/// Re-runs the whole resolution on JSON RPC errors, since strategies may
+/// query the JSON RPC several times
+pub(crate) async fn fetch_start_block_number(
+    start_block_strategy: &dyn StartBlockStrategy,
+    contract_address: &UnsavedContractAddress,
+    json_rpc: &dyn StartBlockJsonRpc,
+    config: &Config,
+) -> Result<i64, EventsIngesterError> {
+    let mut maybe_start_block_number = None;
+    let mut retries_so_far = 0;
 
-                backoff(retries_so_far).await;
-                retries_so_far += 1;
+    while maybe_start_block_number.is_none() {
+        // Not timed out as a whole, since scanning for it may take a while
+        match start_block_strategy.resolve(contract_address, json_rpc).await {
+            Ok(start_block_number) => maybe_start_block_number = Some(start_block_number),
+            Err(provider_error) => {
+                backoff_or_fail(provider_error, &mut retries_so_far, config).await?
             }
         }
     }
 
-    maybe_current_block_number.unwrap()
+    Ok(maybe_start_block_number.unwrap())
 }
-async fn fetch_logs(filters: &Vec<Filter>, json_rpc: &Arc<impl EventsIngesterJsonRpc>) -> Vec<Log> {
+async fn fetch_logs(
+    filters: &Vec<Filter>,
+    json_rpc: &Arc<impl EventsIngesterJsonRpc>,
+    config: &Config,
+) -> Result<Vec<Log>, EventsIngesterError> {
+    let mut filter_values: Vec<_> =
+        filters.iter().flat_map(|f| f.values_within_block_ranges.clone()).collect();
+    if config.coalesce_log_filters {
+        filter_values = coalesce_filter_values(filter_values);
+    }
+    let adaptive_blocks_per_batch = config
+        .adaptive_blocks_per_batch
+        .as_ref()
+        .zip(filters.first().map(|filter| filter.chain_id));
+
     let mut maybe_logs = None;
     let mut retries_so_far = 0;
 
     while maybe_logs.is_none() {
-        match try_join_all(filters.iter().map(|f| json_rpc.get_logs(&f.value))).await {
+        let filter_values_refs = filter_values.iter().collect::<Vec<_>>();
+
+        match with_json_rpc_timeout(json_rpc.get_logs_batch(&filter_values_refs), config).await {
             Ok(logs_per_filter) => {
-                let logs = logs_per_filter.into_iter().flatten().collect();
+                let logs = logs_per_filter
+                    .into_iter()
+                    .zip(filter_values.iter())
+                    .flat_map(|(logs, value)| filter_logs_within_block_range(logs, value))
+                    .collect();
+
+                if let Some((adaptive_blocks_per_batch, chain_id)) = adaptive_blocks_per_batch {
+                    adaptive_blocks_per_batch.succeed(chain_id);
+                }
 
                 maybe_logs = Some(logs)
             }
             Err(provider_error) => {
-                eprintln!("Provider Error: {}", provider_error);
+                // Narrower ranges are retried right away, backing off only at the min
+                match adaptive_blocks_per_batch.and_then(|(adaptive_blocks_per_batch, chain_id)| {
+                    adaptive_blocks_per_batch.shrink(chain_id)
+                }) {
+                    Some(blocks_per_batch) => {
+                        eprintln!("Provider Error: {}", provider_error);
 
-                backoff(retries_so_far).await;
-                retries_so_far += 1;
+                        filter_values = split_filter_values(&filter_values, blocks_per_batch)
+                    }
+                    None => backoff_or_fail(provider_error, &mut retries_so_far, config).await?,
+                }
             }
         }
     }
 
-    maybe_logs.unwrap()
+    Ok(maybe_logs.unwrap())
+}
+fn split_filter_values(filter_values: &[EthersFilter], blocks_per_batch: u64) -> Vec<EthersFilter> {
+    filter_values
+        .iter()
+        .flat_map(|value| {
+            Filter::split_into_batches(
+                BlockNumber::from(value.get_from_block().unwrap()),
+                BlockNumber::from(value.get_to_block().unwrap()),
+                blocks_per_batch,
+            )
+            .into_iter()
+            .map(|(from, to)| value.clone().from_block(from.value()).to_block(to.value()))
+        })
+        .collect()
+}
+// Logs still get attributed to contract addresses by their own address
+fn coalesce_filter_values(filter_values: Vec<EthersFilter>) -> Vec<EthersFilter> {
+    filter_values
+        .into_iter()
+        .fold(vec![], |mut coalesced_values: Vec<EthersFilter>, value| {
+            let coalescable_value = coalesced_values.iter_mut().find(|coalesced_value| {
+                coalesced_value.address.is_some()
+                    && value.address.is_some()
+                    && coalesced_value.block_option == value.block_option
+                    && coalesced_value.topics == value.topics
+            });
+
+            match coalescable_value {
+                Some(coalesced_value) => {
+                    let mut addresses = get_filter_addresses(coalesced_value);
+                    addresses.extend(get_filter_addresses(&value));
+
+                    coalesced_value.address = Some(ValueOrArray::Array(addresses));
+                }
+                None => coalesced_values.push(value),
+            }
+
+            coalesced_values
+        })
+}
+fn get_filter_addresses(filter_value: &EthersFilter) -> Vec<Address> {
+    match &filter_value.address {
+        Some(ValueOrArray::Value(address)) => vec![*address],
+        Some(ValueOrArray::Array(addresses)) => addresses.clone(),
+        None => vec![],
+    }
+}
+// Some providers return logs slightly out of the requested range, which would
+// otherwise get ingested twice or show up as reorgs in the confirmation diff
+fn filter_logs_within_block_range(logs: Vec<Log>, filter_value: &EthersFilter) -> Vec<Log> {
+    let from_block_number = filter_value.get_from_block().unwrap_or_default();
+    let to_block_number = filter_value.get_to_block().unwrap_or(U64::MAX);
+
+    logs.into_iter()
+        .filter(|log| {
+            let is_within_block_range = log.block_number.is_some_and(|block_number| {
+                from_block_number <= block_number && block_number <= to_block_number
+            });
+
+            if !is_within_block_range {
+                eprintln!(
+                    "Provider Error: Dropping log at block {:?} outside of {from_block_number}-{to_block_number}",
+                    log.block_number
+                );
+            }
+
+            is_within_block_range
+        })
+        .collect()
 }
 async fn fetch_blocks_by_tx_hash(
     logs: &Vec<Log>,
     json_rpc: &Arc<impl EventsIngesterJsonRpc>,
-) -> HashMap<TxHash, Block<TxHash>> {
+    config: &Config,
+) -> Result<HashMap<TxHash, Block<TxHash>>, EventsIngesterError> {
     let mut maybe_blocks_by_tx_hash = None;
     let mut retries_so_far = 0;
 
     while maybe_blocks_by_tx_hash.is_none() {
-        match json_rpc.get_blocks_by_tx_hash(logs).await {
+        match with_json_rpc_timeout(json_rpc.get_blocks_by_tx_hash(logs), config).await {
             Ok(blocks_by_tx_hash) => maybe_blocks_by_tx_hash = Some(blocks_by_tx_hash),
             Err(provider_error) => {
-                eprintln!("Provider Error: {}", provider_error);
+                backoff_or_fail(provider_error, &mut retries_so_far, config).await?
+            }
+        }
+    }
+
+    Ok(maybe_blocks_by_tx_hash.unwrap())
+}
+async fn fetch_receipts_by_tx_hash(
+    logs: &Vec<Log>,
+    json_rpc: &Arc<impl EventsIngesterJsonRpc>,
+    config: &Config,
+) -> Result<HashMap<TxHash, TransactionReceipt>, EventsIngesterError> {
+    let mut maybe_receipts_by_tx_hash = None;
+    let mut retries_so_far = 0;
+
+    while maybe_receipts_by_tx_hash.is_none() {
+        match with_json_rpc_timeout(json_rpc.get_receipts_by_tx_hash(logs), config).await {
+            Ok(receipts_by_tx_hash) => maybe_receipts_by_tx_hash = Some(receipts_by_tx_hash),
+            Err(provider_error) => {
+                backoff_or_fail(provider_error, &mut retries_so_far, config).await?
+            }
+        }
+    }
+
+    Ok(maybe_receipts_by_tx_hash.unwrap())
+}
+async fn fetch_block_hashes(
+    block_numbers: &HashSet<i64>,
+    json_rpc: &Arc<impl EventsIngesterJsonRpc>,
+    config: &Config,
+) -> Result<HashMap<i64, Option<H256>>, EventsIngesterError> {
+    let mut block_hashes = HashMap::new();
 
-                backoff(retries_so_far).await;
-                retries_so_far += 1;
+    for block_number in block_numbers {
+        let mut retries_so_far = 0;
+
+        // Unknown blocks are left as None rather than retried
+        loop {
+            let block_number_value = U64::from(*block_number as u64);
+            match with_json_rpc_timeout(json_rpc.get_block_hash(block_number_value), config).await {
+                Ok(block_hash) => {
+                    block_hashes.insert(*block_number, block_hash);
+                    break;
+                }
+                Err(provider_error) => {
+                    backoff_or_fail(provider_error, &mut retries_so_far, config).await?
+                }
             }
         }
     }
 
-    maybe_blocks_by_tx_hash.unwrap()
+    Ok(block_hashes)
+}
+async fn fetch_events(
+    filters: &Vec<Filter>,
+    json_rpc: &Arc<impl EventsIngesterJsonRpc>,
+    config: &Config,
+) -> Result<Vec<Event>, EventsIngesterError> {
+    fetch_events_with_timings(filters, json_rpc, config, &mut BatchTimings::default()).await
+}
+async fn fetch_events_with_timings(
+    filters: &Vec<Filter>,
+    json_rpc: &Arc<impl EventsIngesterJsonRpc>,
+    config: &Config,
+    timings: &mut BatchTimings,
+) -> Result<Vec<Event>, EventsIngesterError> {
+    let started_at = config.clock.instant();
+    let logs = fetch_logs(filters, json_rpc, config).await?;
+    let fetched_logs_at = config.clock.instant();
+    timings.get_logs = fetched_logs_at.saturating_duration_since(started_at);
+
+    let blocks_by_tx_hash = fetch_blocks_by_tx_hash(&logs, json_rpc, config).await?;
+    let logs = match &config.block_filter {
+        Some(block_filter) => filter_logs_by_block(logs, &blocks_by_tx_hash, block_filter),
+        None => logs,
+    };
+    let fetched_blocks_at = config.clock.instant();
+    timings.get_blocks = fetched_blocks_at.saturating_duration_since(fetched_logs_at);
+
+    // Saved contract addresses include the ones registered while indexing
+    let contract_addresses: Vec<_> = filters.iter().map(|f| f.contract_address.clone()).collect();
+    let mut events = if config.skip_undecodable_logs {
+        Events::new_skipping_undecodable_logs(
+            &logs,
+            &config.contracts,
+            &contract_addresses,
+            &blocks_by_tx_hash,
+        )
+    } else {
+        Events::new_with_contract_addresses(
+            &logs,
+            &config.contracts,
+            &contract_addresses,
+            &blocks_by_tx_hash,
+        )
+    };
+    Events::set_inserted_at(&mut events, config.clock.now());
+
+    if config.store_raw_logs {
+        Events::set_raw_logs(&mut events, &logs);
+    }
+    let decoded_at = config.clock.instant();
+    timings.decode = decoded_at.saturating_duration_since(fetched_blocks_at);
+
+    if config.fetch_transaction_statuses {
+        let receipts_by_tx_hash = fetch_receipts_by_tx_hash(&logs, json_rpc, config).await?;
+        Events::set_transaction_statuses(&mut events, &receipts_by_tx_hash);
+        timings.get_transaction_receipts =
+            config.clock.instant().saturating_duration_since(decoded_at);
+    }
+
+    Ok(events)
+}
+fn notify_caught_up(
+    contract_address: &ContractAddress,
+    current_block_number: BlockNumber,
+    config: &Config,
+) {
+    if let Some(on_caught_up) = &config.on_caught_up {
+        if config.caught_up_contract_addresses.catch_up(
+            contract_address.id,
+            contract_address.get_next_block_number_to_ingest_from(),
+            current_block_number,
+            config.caught_up_window,
+        ) {
+            on_caught_up(contract_address);
+        }
+    }
+}
+fn warn_lagging_node(lagging_node: &LaggingNode, config: &Config) {
+    eprintln!(
+        "Lagging Node: {} is at block {}, behind the already ingested block {}",
+        lagging_node.chain,
+        lagging_node.current_block_number,
+        lagging_node.last_ingested_block_number
+    );
+
+    if let Some(on_lagging_node) = &config.on_lagging_node {
+        on_lagging_node(lagging_node);
+    }
+}
+async fn with_json_rpc_timeout<T>(
+    request: impl Future<Output = Result<T, ProviderError>>,
+    config: &Config,
+) -> Result<T, ProviderError> {
+    match config.json_rpc_timeout {
+        Some(json_rpc_timeout) => timeout(json_rpc_timeout, request).await.unwrap_or_else(|_| {
+            Err(ProviderError::CustomError(format!(
+                "JSON RPC request timed out after {json_rpc_timeout:?}"
+            )))
+        }),
+        None => request.await,
+    }
+}
+/// Backs off before retrying a failed JSON RPC request, unless it already got
+/// retried `Config::max_json_rpc_retries` times
+async fn backoff_or_fail(
+    provider_error: ProviderError,
+    retries_so_far: &mut u32,
+    config: &Config,
+) -> Result<(), EventsIngesterError> {
+    eprintln!("Provider Error: {}", provider_error);
+
+    if config
+        .max_json_rpc_retries
+        .is_some_and(|max_json_rpc_retries| *retries_so_far >= max_json_rpc_retries)
+    {
+        return Err(EventsIngesterError::JsonRpcError(
+            provider_error.to_string(),
+        ));
+    }
+
+    backoff(*retries_so_far).await;
+    *retries_so_far += 1;
+
+    Ok(())
 }
 async fn backoff(retries_so_far: u32) {
     sleep(Duration::from_secs(2u64.pow(retries_so_far))).await;
@@ -252,27 +933,73 @@ impl Filters {
     fn new(
         contract_addresses: &Vec<ContractAddress>,
         contracts: &Vec<Contract>,
-        current_block_number: u64,
+        current_block_number: BlockNumber,
         blocks_per_batch: u64,
+        parallelism: u64,
         execution: &Execution,
     ) -> Vec<Filter> {
         let topics_by_contract_name = Contracts::group_event_topics_by_names(contracts);
+        let block_ranges_by_contract_name: HashMap<_, _> =
+            contracts.iter().map(|c| (c.name.as_str(), &c.block_ranges)).collect();
+        let min_confirmation_counts_by_contract_name: HashMap<_, _> = contracts
+            .iter()
+            .filter_map(|c| Some((c.name.as_str(), c.min_confirmation_count.as_ref()?)))
+            .collect();
 
         contract_addresses
             .iter()
-            .map(|contract_address| {
-                let topics_by_contract_name =
-                    topics_by_contract_name.get(contract_address.contract_name.as_str()).unwrap();
+            .filter_map(|contract_address| {
+                let contract_name = contract_address.contract_name.as_str();
+                let topics_by_contract_name = topics_by_contract_name.get(contract_name).unwrap();
+                let block_ranges = block_ranges_by_contract_name.get(contract_name).unwrap();
 
-                Filter::new(
+                let execution = match execution {
+                    Execution::Main => Execution::Main,
+                    Execution::Confirmation(min_confirmation_count)
+                    | Execution::FinalityCheck(min_confirmation_count) => {
+                        let min_confirmation_count = min_confirmation_counts_by_contract_name
+                            .get(contract_name)
+                            .copied()
+                            .unwrap_or(*min_confirmation_count);
+
+                        // Would otherwise confirm blocks ahead of the ingested ones
+                        if min_confirmation_count.is_instant_finality() {
+                            return None;
+                        }
+
+                        match execution {
+                            Execution::Confirmation(_) => {
+                                Execution::Confirmation(min_confirmation_count)
+                            }
+                            _ => Execution::FinalityCheck(min_confirmation_count),
+                        }
+                    }
+                    Execution::Reconciliation(from_block_number, to_block_number) => {
+                        Execution::Reconciliation(*from_block_number, *to_block_number)
+                    }
+                    Execution::Backfill { from, to } => Execution::Backfill {
+                        from: *from,
+                        to: *to,
+                    },
+                };
+
+                Some(Filter::new(
                     contract_address,
                     topics_by_contract_name,
+                    block_ranges,
                     current_block_number,
                     blocks_per_batch,
-                    execution,
-                )
+                    parallelism,
+                    &execution,
+                ))
+            })
+            .filter(|f| match execution {
+                // Exact block ranges can start and end at the same block
+                Execution::Reconciliation(..) | Execution::Backfill { .. } => {
+                    f.value.get_from_block() <= f.value.get_to_block()
+                }
+                _ => f.value.get_from_block() < f.value.get_to_block(),
             })
-            .filter(|f| !f.value.get_from_block().eq(&f.value.get_to_block()))
             .collect()
     }
 
@@ -296,6 +1023,25 @@ impl Filters {
         )
     }
 
+    /// Stops filters at the (inclusive) end block, dropping the ones starting
+    /// past it, see `Config::with_end_block`
+    fn end_at(filters: Vec<Filter>, end_block_number: Option<BlockNumber>) -> Vec<Filter> {
+        let Some(end_block_number) = end_block_number else {
+            return filters;
+        };
+
+        filters
+            .into_iter()
+            .filter_map(|filter| {
+                let from_block_number = BlockNumber::from(filter.value.get_from_block().unwrap());
+
+                (from_block_number <= end_block_number).then(|| {
+                    filter.truncate(end_block_number.value() - from_block_number.value() + 1)
+                })
+            })
+            .collect()
+    }
+
     fn get_latest(filters: &Vec<Filter>) -> Option<Filter> {
         let mut filters = filters.clone();
         filters.sort_by_key(|f| f.value.get_to_block());
@@ -308,46 +1054,154 @@ impl Filters {
 struct Filter {
     contract_address_id: i32,
     address: String,
+    chain_id: i32,
+    contract_address: UnsavedContractAddress,
+    /// Spans the whole batch, which ingestion cursors advance by
     value: EthersFilter,
+    /// The batch split into parallel batches and around the contract's
+    /// allowed/blocked block ranges, which logs get fetched with concurrently
+    values_within_block_ranges: Vec<EthersFilter>,
 }
 
 impl Filter {
     fn new(
         contract_address: &ContractAddress,
         topics: &Vec<ContractEventTopic>,
-        current_block_number: u64,
+        block_ranges: &BlockRanges,
+        current_block_number: BlockNumber,
         blocks_per_batch: u64,
+        parallelism: u64,
         execution: &Execution,
     ) -> Filter {
         let ContractAddress {
             id: contract_address_id,
-            next_block_number_to_ingest_from,
-            start_block_number,
             address,
             ..
         } = contract_address;
+        let next_block_number_to_ingest_from =
+            contract_address.get_next_block_number_to_ingest_from();
 
         let from_block_number = match execution {
-            Execution::Main => *next_block_number_to_ingest_from as u64,
+            Execution::Main => next_block_number_to_ingest_from,
             Execution::Confirmation(min_confirmation_count) => min_confirmation_count.deduct_from(
-                *next_block_number_to_ingest_from as u64,
-                *start_block_number as u64,
+                next_block_number_to_ingest_from,
+                contract_address.get_start_block_number(),
+            ),
+            Execution::FinalityCheck(min_confirmation_count) => min_confirmation_count.deduct_from(
+                min_confirmation_count.deduct_from(
+                    next_block_number_to_ingest_from,
+                    contract_address.get_start_block_number(),
+                ),
+                contract_address.get_start_block_number(),
+            ),
+            Execution::Reconciliation(from_block_number, _) => max(
+                *from_block_number,
+                contract_address.get_start_block_number(),
             ),
+            Execution::Backfill { from, .. } => *from,
         };
 
+        // Far behind contract addresses get several batches fetched at once,
+        // converging to a single batch as they catch up with the current block
         let to_block_number = match execution {
-            Execution::Main => min(from_block_number + blocks_per_batch, current_block_number),
-            Execution::Confirmation(_mcc) => from_block_number + blocks_per_batch,
+            Execution::Main => min(
+                from_block_number.saturating_add(
+                    blocks_per_batch.saturating_add(1).saturating_mul(parallelism.max(1)) - 1,
+                ),
+                current_block_number,
+            ),
+            // Chains younger than their confirmation window, e.g. right after
+            // genesis, have no blocks past their current one to confirm
+            Execution::Confirmation(_mcc) => min(
+                from_block_number.saturating_add(blocks_per_batch),
+                current_block_number,
+            ),
+            // Up to the block before the confirmation window
+            Execution::FinalityCheck(min_confirmation_count) => min_confirmation_count
+                .deduct_from(
+                    next_block_number_to_ingest_from,
+                    contract_address.get_start_block_number(),
+                )
+                .saturating_sub(1),
+            // Blocks yet to be ingested have nothing to reconcile
+            Execution::Reconciliation(_, to_block_number) => min(
+                *to_block_number,
+                next_block_number_to_ingest_from.saturating_sub(1),
+            ),
+            Execution::Backfill { to, .. } => *to,
         };
 
+        let value = EthersFilter::new()
+            .address(address.parse::<Address>().unwrap())
+            .topic0(topics.to_vec());
+
         Filter {
             contract_address_id: *contract_address_id,
-            address: address.to_string(),
-            value: EthersFilter::new()
-                .address(address.parse::<Address>().unwrap())
-                .topic0(topics.to_vec())
-                .from_block(from_block_number)
-                .to_block(to_block_number),
+            address: ContractAddress::normalize_address(address),
+            chain_id: contract_address.get_chain_id(),
+            contract_address: contract_address.into(),
+            values_within_block_ranges: Self::split_into_batches(
+                from_block_number,
+                to_block_number,
+                blocks_per_batch,
+            )
+            .into_iter()
+            .flat_map(|(from, to)| block_ranges.split(from, to))
+            .map(|(from, to)| value.clone().from_block(from.value()).to_block(to.value()))
+            .collect(),
+            value: value.from_block(from_block_number.value()).to_block(to_block_number.value()),
         }
     }
+
+    fn get_blocks_count(&self) -> u64 {
+        let from_block_number = self.value.get_from_block().unwrap();
+        let to_block_number = self.value.get_to_block().unwrap();
+
+        (to_block_number - from_block_number).as_u64() + 1
+    }
+
+    /// Same address, topics and start block, but only over the first
+    /// `blocks_count` blocks
+    fn truncate(&self, blocks_count: u64) -> Filter {
+        let from_block_number = self.value.get_from_block().unwrap();
+        let to_block_number = min(
+            from_block_number + blocks_count.max(1) - 1,
+            self.value.get_to_block().unwrap(),
+        );
+
+        Filter {
+            value: self.value.clone().to_block(to_block_number),
+            values_within_block_ranges: self
+                .values_within_block_ranges
+                .iter()
+                .filter(|value| value.get_from_block().unwrap() <= to_block_number)
+                .map(|value| {
+                    value.clone().to_block(min(value.get_to_block().unwrap(), to_block_number))
+                })
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    fn split_into_batches(
+        from: BlockNumber,
+        to: BlockNumber,
+        blocks_per_batch: u64,
+    ) -> Vec<(BlockNumber, BlockNumber)> {
+        let mut batches = vec![];
+        let mut batch_from = from;
+
+        while batch_from <= to {
+            let batch_to = min(batch_from.saturating_add(blocks_per_batch), to);
+            batches.push((batch_from, batch_to));
+
+            if batch_to == to {
+                break;
+            }
+
+            batch_from = batch_to.saturating_add(1);
+        }
+
+        batches
+    }
 }
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// Successful log fetches in a row before a chain's `blocks_per_batch` grows back
+pub const BLOCKS_PER_BATCH_GROWTH_STREAK: u64 = 5;
+
+#[derive(Clone, Copy, Debug)]
+struct ChainBlocksPerBatch {
+    value: u64,
+    successes_in_a_row: u64,
+}
+
+/// Adapts each chain's `blocks_per_batch` to its JSON RPC's limits, AIMD-style:
+/// halved whenever fetching logs fails, e.g. on rate limit or range limit
+/// errors, then grown back by a tenth of the max after sustained success.
+/// Clones share the same sizes, so they persist across ingestion passes.
+#[derive(Clone, Debug)]
+pub struct AdaptiveBlocksPerBatch {
+    min: u64,
+    max: u64,
+    by_chain_id: Arc<RwLock<HashMap<i32, ChainBlocksPerBatch>>>,
+}
+
+impl AdaptiveBlocksPerBatch {
+    pub fn new(min: u64, max: u64) -> Self {
+        Self {
+            min: min.max(1),
+            max: max.max(min.max(1)),
+            by_chain_id: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Starts chains without any fetches so far at the configured `blocks_per_batch`
+    pub fn get(&self, chain_id: i32, configured_blocks_per_batch: u64) -> u64 {
+        self.by_chain_id
+            .write()
+            .unwrap()
+            .entry(chain_id)
+            .or_insert(ChainBlocksPerBatch {
+                value: configured_blocks_per_batch.clamp(self.min, self.max),
+                successes_in_a_row: 0,
+            })
+            .value
+    }
+
+    /// Returns the halved `blocks_per_batch`, unless it cannot shrink any further
+    pub fn shrink(&self, chain_id: i32) -> Option<u64> {
+        let mut by_chain_id = self.by_chain_id.write().unwrap();
+        let blocks_per_batch = by_chain_id.get_mut(&chain_id)?;
+
+        blocks_per_batch.successes_in_a_row = 0;
+
+        if blocks_per_batch.value > self.min {
+            blocks_per_batch.value = (blocks_per_batch.value / 2).max(self.min);
+
+            Some(blocks_per_batch.value)
+        } else {
+            None
+        }
+    }
+
+    pub fn succeed(&self, chain_id: i32) {
+        let mut by_chain_id = self.by_chain_id.write().unwrap();
+
+        if let Some(blocks_per_batch) = by_chain_id.get_mut(&chain_id) {
+            blocks_per_batch.successes_in_a_row += 1;
+
+            if blocks_per_batch.successes_in_a_row >= BLOCKS_PER_BATCH_GROWTH_STREAK {
+                blocks_per_batch.value =
+                    blocks_per_batch.value.saturating_add((self.max / 10).max(1)).min(self.max);
+                blocks_per_batch.successes_in_a_row = 0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod adaptive_blocks_per_batch_test {
+    use super::*;
+
+    #[test]
+    fn starts_at_the_configured_blocks_per_batch_within_bounds() {
+        let adaptive_blocks_per_batch = AdaptiveBlocksPerBatch::new(10, 1000);
+
+        assert_eq!(adaptive_blocks_per_batch.get(1, 500), 500);
+        assert_eq!(adaptive_blocks_per_batch.get(137, 5000), 1000);
+    }
+
+    #[test]
+    fn halves_down_to_the_min() {
+        let adaptive_blocks_per_batch = AdaptiveBlocksPerBatch::new(10, 1000);
+        adaptive_blocks_per_batch.get(1, 40);
+
+        assert_eq!(adaptive_blocks_per_batch.shrink(1), Some(20));
+        assert_eq!(adaptive_blocks_per_batch.clone().shrink(1), Some(10));
+        assert_eq!(adaptive_blocks_per_batch.shrink(1), None);
+        assert_eq!(adaptive_blocks_per_batch.get(1, 40), 10);
+    }
+
+    #[test]
+    fn grows_back_after_sustained_success_up_to_the_max() {
+        let adaptive_blocks_per_batch = AdaptiveBlocksPerBatch::new(10, 1000);
+        adaptive_blocks_per_batch.get(1, 950);
+
+        (1..BLOCKS_PER_BATCH_GROWTH_STREAK).for_each(|_| adaptive_blocks_per_batch.succeed(1));
+        assert_eq!(adaptive_blocks_per_batch.get(1, 950), 950);
+
+        adaptive_blocks_per_batch.succeed(1);
+        assert_eq!(adaptive_blocks_per_batch.get(1, 950), 1000);
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use futures_util::FutureExt;
+
+use crate::chain_reorg::Execution;
+use crate::{
+    BlockNumber, Chain, ChaindexingRepo, ChaindexingRepoConn, Config, ContractAddress,
+    EventsIngesterJsonRpc, Repo,
+};
+
+use super::{fetch_events, EventsIngesterError, Filters, MaybeBacktrackIngestedEvents};
+
+pub struct BackfillEvents;
+
+impl BackfillEvents {
+    pub async fn run<'a>(
+        conn: &mut ChaindexingRepoConn<'a>,
+        contract_addresses: Vec<ContractAddress>,
+        json_rpc: &Arc<impl EventsIngesterJsonRpc + 'static>,
+        chain: &Chain,
+        from_block_number: BlockNumber,
+        to_block_number: BlockNumber,
+        config: &Config,
+    ) -> Result<(), EventsIngesterError> {
+        let filters = Filters::new(
+            &contract_addresses,
+            &config.contracts,
+            to_block_number,
+            config.get_blocks_per_batch(chain),
+            1,
+            &Execution::Backfill {
+                from: from_block_number,
+                to: to_block_number,
+            },
+        );
+
+        if !filters.is_empty() {
+            let already_ingested_events =
+                MaybeBacktrackIngestedEvents::get_already_ingested_events(conn, &filters).await;
+            // Matches the events' unique index
+            let already_ingested_positions: HashSet<_> = already_ingested_events
+                .iter()
+                .map(|event| (event.transaction_hash.clone(), event.log_index))
+                .collect();
+
+            let mut events: Vec<_> = fetch_events(&filters, json_rpc, config)
+                .await?
+                .into_iter()
+                .filter(|event| {
+                    !already_ingested_positions
+                        .contains(&(event.transaction_hash.clone(), event.log_index))
+                })
+                .collect();
+            if let Some(on_events_ingested) = &config.on_events_ingested {
+                on_events_ingested(&mut events);
+            }
+
+            if let Some(events_partitioning) = &config.events_partitioning {
+                ChaindexingRepo::create_events_partitions(conn, events_partitioning, &events).await;
+            }
+
+            ChaindexingRepo::run_in_transaction(conn, move |conn| {
+                async move {
+                    ChaindexingRepo::create_events(conn, &events.clone()).await;
+
+                    Ok(())
+                }
+                .boxed()
+            })
+            .await?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+use derive_more::Display;
+use ethers::types::{Address, Filter as EthersFilter};
+
+use crate::{BlockNumber, EventsIngesterJsonRpc};
+
+#[derive(Debug, Display, PartialEq)]
+pub enum BlocksPerBatchError {
+    #[display(
+        fmt = "blocks_per_batch of {} is wider than the JSON RPC's eth_getLogs range limit. Try {} instead",
+        blocks_per_batch,
+        safe_blocks_per_batch
+    )]
+    TooWide {
+        blocks_per_batch: u64,
+        safe_blocks_per_batch: u64,
+    },
+    #[display(fmt = "Provider Error: {}", _0)]
+    ProviderError(String),
+}
+
+/// Probes the JSON RPC with an `eth_getLogs` as wide as ingestion batches,
+/// so that range limits surface upfront instead of as endless backoffs.
+/// Rejected widths are halved to find a safe `blocks_per_batch`.
+pub struct BlocksPerBatchProbe;
+
+impl BlocksPerBatchProbe {
+    pub async fn run(
+        json_rpc: &impl EventsIngesterJsonRpc,
+        blocks_per_batch: u64,
+    ) -> Result<(), BlocksPerBatchError> {
+        let current_block_number = json_rpc.get_block_number().await.map_err(|provider_error| {
+            BlocksPerBatchError::ProviderError(provider_error.to_string())
+        })?;
+        let current_block_number = BlockNumber::from(current_block_number);
+
+        let mut probed_blocks_per_batch = blocks_per_batch;
+
+        loop {
+            let filter = Self::get_filter(current_block_number, probed_blocks_per_batch);
+
+            match json_rpc.get_logs(&filter).await {
+                Ok(_logs) if probed_blocks_per_batch == blocks_per_batch => return Ok(()),
+                Ok(_logs) => {
+                    return Err(BlocksPerBatchError::TooWide {
+                        blocks_per_batch,
+                        safe_blocks_per_batch: probed_blocks_per_batch,
+                    })
+                }
+                // Even a single block got rejected, so the range is not the problem
+                Err(provider_error) if probed_blocks_per_batch == 0 => {
+                    return Err(BlocksPerBatchError::ProviderError(
+                        provider_error.to_string(),
+                    ))
+                }
+                Err(_provider_error) => probed_blocks_per_batch /= 2,
+            }
+        }
+    }
+
+    // Mirrors ingestion filters, which span `blocks_per_batch + 1` blocks. The zero
+    // address emits no logs, which keeps successful probes cheap.
+    fn get_filter(current_block_number: BlockNumber, blocks_per_batch: u64) -> EthersFilter {
+        EthersFilter::new()
+            .address(Address::zero())
+            .from_block(current_block_number.saturating_sub(blocks_per_batch).value())
+            .to_block(current_block_number.value())
+    }
+}
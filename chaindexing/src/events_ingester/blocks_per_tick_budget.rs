@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+use super::Filter;
+
+/// Blocks left to ingest across all contract addresses within a single
+/// ingestion tick. Unlimited without `max_blocks_per_tick`. Split fairly
+/// between the contract addresses spending it at once, so that none of them
+/// starves the others, as long as it covers a block per contract address.
+pub struct BlocksPerTickBudget {
+    remaining_blocks_count: Option<u64>,
+}
+
+impl BlocksPerTickBudget {
+    pub fn new(max_blocks_per_tick: u64) -> Self {
+        Self {
+            remaining_blocks_count: (max_blocks_per_tick > 0).then_some(max_blocks_per_tick),
+        }
+    }
+
+    /// Keeps each contract address's filters in order while they fit its
+    /// share, truncating the one exceeding it, so its cursor only advances
+    /// over the blocks actually fetched
+    pub fn spend(&mut self, filters: Vec<Filter>) -> Vec<Filter> {
+        let Some(remaining_blocks_count) = self.remaining_blocks_count.as_mut() else {
+            return filters;
+        };
+
+        let mut blocks_counts_by_contract_address: Vec<(i32, u64)> = vec![];
+        for filter in filters.iter() {
+            match blocks_counts_by_contract_address
+                .iter_mut()
+                .find(|(contract_address_id, _)| *contract_address_id == filter.contract_address_id)
+            {
+                Some((_, blocks_count)) => *blocks_count += filter.get_blocks_count(),
+                None => blocks_counts_by_contract_address
+                    .push((filter.contract_address_id, filter.get_blocks_count())),
+            }
+        }
+
+        let mut shares =
+            Self::split_fairly(*remaining_blocks_count, blocks_counts_by_contract_address);
+
+        filters
+            .into_iter()
+            .filter_map(|filter| {
+                let share = shares.get_mut(&filter.contract_address_id).unwrap();
+
+                if *share == 0 {
+                    return None;
+                }
+
+                let blocks_count = filter.get_blocks_count();
+
+                if blocks_count <= *share {
+                    *share -= blocks_count;
+                    *remaining_blocks_count -= blocks_count;
+
+                    Some(filter)
+                } else {
+                    let truncated_filter = filter.truncate(*share);
+                    *remaining_blocks_count -= *share;
+                    *share = 0;
+
+                    Some(truncated_filter)
+                }
+            })
+            .collect()
+    }
+
+    /// Max-min fair shares: contract addresses needing less than an even
+    /// share get all they need, leaving the rest to be split evenly between
+    /// the others
+    fn split_fairly(
+        blocks_count: u64,
+        mut blocks_counts_by_contract_address: Vec<(i32, u64)>,
+    ) -> HashMap<i32, u64> {
+        blocks_counts_by_contract_address
+            .sort_by_key(|(_, needed_blocks_count)| *needed_blocks_count);
+
+        let mut remaining_blocks_count = blocks_count;
+        let mut remaining_contract_addresses_count = blocks_counts_by_contract_address.len() as u64;
+
+        blocks_counts_by_contract_address
+            .into_iter()
+            .map(|(contract_address_id, needed_blocks_count)| {
+                let even_share = remaining_blocks_count / remaining_contract_addresses_count;
+                let share = needed_blocks_count.min(even_share);
+
+                remaining_blocks_count -= share;
+                remaining_contract_addresses_count -= 1;
+
+                (contract_address_id, share)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod blocks_per_tick_budget_test {
+    use super::*;
+
+    #[test]
+    fn splits_blocks_evenly_between_contract_addresses_needing_more() {
+        let shares = BlocksPerTickBudget::split_fairly(25, vec![(1, 44), (2, 44)]);
+
+        assert_eq!(shares, HashMap::from([(1, 12), (2, 13)]));
+    }
+
+    #[test]
+    fn leaves_blocks_unneeded_by_a_contract_address_to_the_others() {
+        let shares = BlocksPerTickBudget::split_fairly(25, vec![(1, 44), (2, 5), (3, 44)]);
+
+        assert_eq!(shares, HashMap::from([(1, 10), (2, 5), (3, 10)]));
+    }
+}
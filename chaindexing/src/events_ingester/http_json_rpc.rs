@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use ethers::prelude::Middleware;
+use ethers::providers::{Http, Provider, ProviderError};
+use ethers::types::{Block, Filter as EthersFilter, Log, TransactionReceipt, TxHash, H256, U64};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use reqwest::{Client, Url};
+use serde_json::{json, Value};
+
+use super::EventsIngesterJsonRpc;
+
+/// JSON RPC over HTTP, sending the filters of each `get_logs_batch` call as a
+/// single JSON RPC batch request rather than one request per filter.
+#[derive(Clone, Debug)]
+pub struct HttpJsonRpc {
+    url: Url,
+    client: Client,
+    provider: Provider<Http>,
+}
+
+impl HttpJsonRpc {
+    pub fn new(url: Url, client: Client) -> Self {
+        let provider = Provider::new(Http::new_with_client(url.clone(), client.clone()));
+
+        Self {
+            url,
+            client,
+            provider,
+        }
+    }
+
+    async fn send_batch_request(&self, requests: &Vec<Value>) -> Result<Vec<Value>, ProviderError> {
+        let response = self
+            .client
+            .post(self.url.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(requests)?)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+
+        let mut responses: Vec<Value> = serde_json::from_slice(&response)?;
+        // Batch responses may come back in any order
+        responses.sort_by_key(|response| response["id"].as_u64());
+
+        Ok(responses)
+    }
+}
+
+#[async_trait::async_trait]
+impl EventsIngesterJsonRpc for HttpJsonRpc {
+    async fn get_block_number(&self) -> Result<U64, ProviderError> {
+        Middleware::get_block_number(&self.provider).await
+    }
+
+    async fn get_logs(&self, filter: &EthersFilter) -> Result<Vec<Log>, ProviderError> {
+        Middleware::get_logs(&self.provider, filter).await
+    }
+
+    async fn get_logs_batch(
+        &self,
+        filters: &Vec<&EthersFilter>,
+    ) -> Result<Vec<Vec<Log>>, ProviderError> {
+        if filters.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let requests: Vec<_> = filters
+            .iter()
+            .enumerate()
+            .map(|(id, filter)| {
+                json!({ "jsonrpc": "2.0", "id": id, "method": "eth_getLogs", "params": [filter] })
+            })
+            .collect();
+
+        let responses = self.send_batch_request(&requests).await?;
+
+        if responses.len() != requests.len() {
+            return Err(ProviderError::CustomError(format!(
+                "Expected {} eth_getLogs responses in batch, got {}",
+                requests.len(),
+                responses.len()
+            )));
+        }
+
+        responses
+            .into_iter()
+            .map(|mut response| match response.get_mut("result") {
+                Some(result) => Ok(serde_json::from_value(result.take())?),
+                None => Err(ProviderError::CustomError(format!(
+                    "eth_getLogs failed in batch: {}",
+                    response["error"]
+                ))),
+            })
+            .collect()
+    }
+
+    async fn get_block(&self, block_number: U64) -> Result<Block<TxHash>, ProviderError> {
+        Ok(Middleware::get_block(&self.provider, block_number).await?.unwrap())
+    }
+
+    async fn get_block_hash(&self, block_number: U64) -> Result<Option<H256>, ProviderError> {
+        Ok(Middleware::get_block(&self.provider, block_number)
+            .await?
+            .and_then(|block| block.hash))
+    }
+
+    async fn get_transaction_receipt(
+        &self,
+        tx_hash: TxHash,
+    ) -> Result<Option<TransactionReceipt>, ProviderError> {
+        Middleware::get_transaction_receipt(&self.provider, tx_hash).await
+    }
+}
+
+/// HTTP clients by JSON RPC endpoint, i.e. origin and headers. Chains sharing
+/// an endpoint share its client, so their requests reuse the same pooled
+/// connections instead of each opening their own.
+#[derive(Clone, Default)]
+pub struct HttpJsonRpcClients {
+    clients: Arc<Mutex<HashMap<(String, Vec<(String, String)>), Client>>>,
+}
+
+impl HttpJsonRpcClients {
+    pub fn get_or_create(&self, url: &Url, headers: &HashMap<String, String>) -> Client {
+        let mut endpoint_headers: Vec<_> = headers
+            .iter()
+            .map(|(name, value)| (name.to_lowercase(), value.clone()))
+            .collect();
+        endpoint_headers.sort();
+        let endpoint = (url.origin().ascii_serialization(), endpoint_headers);
+
+        let mut clients = self.clients.lock().unwrap();
+
+        clients
+            .entry(endpoint)
+            .or_insert_with(|| {
+                let headers: HeaderMap = headers
+                    .iter()
+                    .map(|(name, value)| {
+                        let mut value = HeaderValue::from_str(value).unwrap();
+                        value.set_sensitive(true);
+
+                        (HeaderName::from_str(name).unwrap(), value)
+                    })
+                    .collect();
+
+                Client::builder().default_headers(headers).build().unwrap()
+            })
+            .clone()
+    }
+}
@@ -1,13 +1,23 @@
+use std::cmp::min;
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use futures_util::FutureExt;
 
 use crate::chain_reorg::Execution;
-use crate::contracts::Contract;
-use crate::events::Events;
-use crate::{ChaindexingRepo, ChaindexingRepoConn, ContractAddress, EventsIngesterJsonRpc, Repo};
+use crate::events::Event;
+use crate::metrics::{record_metric, MetricKind};
+#[cfg(feature = "pipeline-spans")]
+use crate::pipeline_spans::{record_pipeline_span, PipelineSpanKind};
+use crate::{
+    BatchTimings, BlockNumber, Chain, ChaindexingRepo, ChaindexingRepoConn, Config,
+    ContractAddress, EventsIngesterJsonRpc, Repo,
+};
 
-use super::{fetch_blocks_by_tx_hash, fetch_logs, EventsIngesterError, Filter, Filters};
+use super::{
+    fetch_events_with_timings, notify_caught_up, BlocksPerTickBudget, EventsIngesterError, Filter,
+    Filters,
+};
 
 pub struct IngestEvents;
 
@@ -15,23 +25,149 @@ impl IngestEvents {
     pub async fn run<'a>(
         conn: &mut ChaindexingRepoConn<'a>,
         contract_addresses: Vec<ContractAddress>,
-        contracts: &Vec<Contract>,
         json_rpc: &Arc<impl EventsIngesterJsonRpc + 'static>,
-        current_block_number: u64,
-        blocks_per_batch: u64,
+        chain: &Chain,
+        current_block_number: BlockNumber,
+        blocks_per_tick_budget: &mut BlocksPerTickBudget,
+        config: &Config,
     ) -> Result<(), EventsIngesterError> {
+        if Self::is_under_write_pressure(config) {
+            return Ok(());
+        }
+
+        let empty_contract_addresses = Self::run_checkpointed_batches(
+            conn,
+            contract_addresses,
+            json_rpc,
+            chain,
+            current_block_number,
+            config.get_blocks_per_batch(chain),
+            blocks_per_tick_budget,
+            config,
+        )
+        .await?;
+
+        // Fetched through the head in one go, so nothing gets skipped unfetched
+        // and the confirmation window behind the cursors stays the same
+        if config.fast_forward_window > 0 && !Self::is_under_write_pressure(config) {
+            let fast_forwarded_contract_addresses = Self::filter_near_head(
+                empty_contract_addresses,
+                current_block_number,
+                config.fast_forward_window,
+            );
+
+            Self::run_batch(
+                conn,
+                fast_forwarded_contract_addresses,
+                json_rpc,
+                chain,
+                current_block_number,
+                config.fast_forward_window,
+                1,
+                blocks_per_tick_budget,
+                config,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Commits concurrently fetched batches every `ingestion_checkpoint_interval`
+    /// blocks, so that crashing midway resumes from the last checkpoint rather
+    /// than from the first batch. Returns contract addresses whose last
+    /// batches had no events.
+    async fn run_checkpointed_batches<'a>(
+        conn: &mut ChaindexingRepoConn<'a>,
+        contract_addresses: Vec<ContractAddress>,
+        json_rpc: &Arc<impl EventsIngesterJsonRpc + 'static>,
+        chain: &Chain,
+        current_block_number: BlockNumber,
+        blocks_per_batch: u64,
+        blocks_per_tick_budget: &mut BlocksPerTickBudget,
+        config: &Config,
+    ) -> Result<Vec<ContractAddress>, EventsIngesterError> {
+        let parallelism = config.initial_sync_parallelism.max(1);
+        let checkpoint_parallelism = match config.ingestion_checkpoint_interval {
+            0 => parallelism,
+            interval => (interval / blocks_per_batch.saturating_add(1)).clamp(1, parallelism),
+        };
+
+        let mut contract_addresses = contract_addresses;
+        let mut empty_contract_addresses = vec![];
+        let mut remaining_parallelism = parallelism;
+
+        while remaining_parallelism > 0 && !contract_addresses.is_empty() {
+            let batch_parallelism = min(checkpoint_parallelism, remaining_parallelism);
+
+            (contract_addresses, empty_contract_addresses) = Self::run_batch(
+                conn,
+                contract_addresses,
+                json_rpc,
+                chain,
+                current_block_number,
+                blocks_per_batch,
+                batch_parallelism,
+                blocks_per_tick_budget,
+                config,
+            )
+            .await?;
+
+            remaining_parallelism -= batch_parallelism;
+
+            if Self::is_under_write_pressure(config) {
+                break;
+            }
+        }
+
+        Ok(empty_contract_addresses)
+    }
+
+    /// Returns ingested contract addresses with their advanced cursors, along
+    /// with the ones whose batches had no events
+    async fn run_batch<'a>(
+        conn: &mut ChaindexingRepoConn<'a>,
+        contract_addresses: Vec<ContractAddress>,
+        json_rpc: &Arc<impl EventsIngesterJsonRpc + 'static>,
+        chain: &Chain,
+        current_block_number: BlockNumber,
+        blocks_per_batch: u64,
+        parallelism: u64,
+        blocks_per_tick_budget: &mut BlocksPerTickBudget,
+        config: &Config,
+    ) -> Result<(Vec<ContractAddress>, Vec<ContractAddress>), EventsIngesterError> {
         let filters = Filters::new(
             &contract_addresses,
-            &contracts,
+            &config.contracts,
             current_block_number,
             blocks_per_batch,
+            parallelism,
             &Execution::Main,
         );
+        let filters =
+            blocks_per_tick_budget.spend(Filters::end_at(filters, config.end_block_number));
+        let mut ingested_contract_addresses = vec![];
+        let mut empty_contract_addresses = vec![];
 
         if !filters.is_empty() {
-            let logs = fetch_logs(&filters, json_rpc).await;
-            let blocks_by_tx_hash = fetch_blocks_by_tx_hash(&logs, json_rpc).await;
-            let events = Events::new(&logs, &contracts, &blocks_by_tx_hash);
+            let started_at = config.clock.instant();
+            let mut timings = BatchTimings::new(*chain as i32);
+            let mut events =
+                fetch_events_with_timings(&filters, json_rpc, config, &mut timings).await?;
+            if let Some(on_events_ingested) = &config.on_events_ingested {
+                on_events_ingested(&mut events);
+            }
+            let events_counts_by_address = Self::count_events_by_address(&events);
+            ingested_contract_addresses =
+                Self::get_ingested_contract_addresses(&contract_addresses, &filters);
+            let contract_addresses_to_update = ingested_contract_addresses.clone();
+            #[cfg(feature = "pipeline-spans")]
+            let event_ids: Vec<_> = events.iter().map(|e| e.id).collect();
+
+            let writing_started_at = config.clock.instant();
+            if let Some(events_partitioning) = &config.events_partitioning {
+                ChaindexingRepo::create_events_partitions(conn, events_partitioning, &events).await;
+            }
 
             ChaindexingRepo::run_in_transaction(conn, move |conn| {
                 async move {
@@ -39,8 +175,7 @@ impl IngestEvents {
 
                     Self::update_next_block_numbers_to_ingest_from(
                         conn,
-                        &contract_addresses,
-                        &filters,
+                        &contract_addresses_to_update,
                     )
                     .await;
 
@@ -49,32 +184,121 @@ impl IngestEvents {
                 .boxed()
             })
             .await?;
+
+            let written_at = config.clock.instant();
+            timings.db_writes = written_at.saturating_duration_since(writing_started_at);
+            timings.total = written_at.saturating_duration_since(started_at);
+            if let Some(write_pressure) = &config.write_pressure {
+                write_pressure.observe(timings.db_writes, written_at);
+            }
+            if let Some(on_batch_timings) = &config.on_batch_timings {
+                on_batch_timings(&timings);
+            }
+            #[cfg(feature = "pipeline-spans")]
+            record_pipeline_span(
+                config,
+                PipelineSpanKind::IngestBatch,
+                *chain as i32,
+                event_ids,
+                timings.total,
+            );
+
+            for contract_address in ingested_contract_addresses.iter() {
+                notify_caught_up(contract_address, current_block_number, config);
+
+                let events_count = events_counts_by_address
+                    .get(&ContractAddress::normalize_address(
+                        &contract_address.address,
+                    ))
+                    .cloned()
+                    .unwrap_or(0);
+                record_metric(
+                    config,
+                    MetricKind::EventsIngested,
+                    events_count,
+                    contract_address,
+                );
+
+                if events_count == 0 {
+                    empty_contract_addresses.push(contract_address.clone());
+                }
+            }
         }
 
-        Ok(())
+        Ok((ingested_contract_addresses, empty_contract_addresses))
     }
 
-    async fn update_next_block_numbers_to_ingest_from<'a>(
-        conn: &mut ChaindexingRepoConn<'a>,
+    fn is_under_write_pressure(config: &Config) -> bool {
+        config
+            .write_pressure
+            .as_ref()
+            .is_some_and(|write_pressure| write_pressure.is_paused(config.clock.instant()))
+    }
+
+    fn filter_near_head(
+        contract_addresses: Vec<ContractAddress>,
+        current_block_number: BlockNumber,
+        fast_forward_window: u64,
+    ) -> Vec<ContractAddress> {
+        contract_addresses
+            .into_iter()
+            .filter(|contract_address| {
+                let next_block_number_to_ingest_from =
+                    contract_address.get_next_block_number_to_ingest_from();
+
+                current_block_number > next_block_number_to_ingest_from
+                    && next_block_number_to_ingest_from.saturating_add(fast_forward_window)
+                        >= current_block_number
+            })
+            .collect()
+    }
+
+    /// Contract addresses with their ingestion cursors advanced past their filters
+    fn get_ingested_contract_addresses(
         contract_addresses: &Vec<ContractAddress>,
         filters: &Vec<Filter>,
-    ) {
+    ) -> Vec<ContractAddress> {
         let filters_by_contract_address_id = Filters::group_by_contract_address_id(filters);
 
-        for contract_address in contract_addresses {
-            let filters = filters_by_contract_address_id.get(&contract_address.id).unwrap();
-
-            if let Some(latest_filter) = Filters::get_latest(filters) {
+        contract_addresses
+            .iter()
+            .filter_map(|contract_address| {
+                let filters = filters_by_contract_address_id.get(&contract_address.id)?;
+                let latest_filter = Filters::get_latest(filters)?;
                 let next_block_number_to_ingest_from =
-                    latest_filter.value.get_to_block().unwrap() + 1;
-
-                ChaindexingRepo::update_next_block_number_to_ingest_from(
-                    conn,
-                    &contract_address,
-                    next_block_number_to_ingest_from.as_u64() as i64,
-                )
-                .await
-            }
+                    BlockNumber::from(latest_filter.value.get_to_block().unwrap())
+                        .saturating_add(1);
+
+                let mut contract_address = contract_address.clone();
+                contract_address.next_block_number_to_ingest_from =
+                    i64::try_from(next_block_number_to_ingest_from).unwrap();
+
+                Some(contract_address)
+            })
+            .collect()
+    }
+
+    fn count_events_by_address(events: &Vec<Event>) -> HashMap<String, u64> {
+        events.iter().fold(HashMap::new(), |mut events_counts, event| {
+            *events_counts
+                .entry(ContractAddress::normalize_address(&event.contract_address))
+                .or_insert(0) += 1;
+
+            events_counts
+        })
+    }
+
+    async fn update_next_block_numbers_to_ingest_from<'a>(
+        conn: &mut ChaindexingRepoConn<'a>,
+        contract_addresses: &Vec<ContractAddress>,
+    ) {
+        for contract_address in contract_addresses {
+            ChaindexingRepo::update_next_block_number_to_ingest_from(
+                conn,
+                contract_address,
+                contract_address.next_block_number_to_ingest_from,
+            )
+            .await
         }
     }
 }
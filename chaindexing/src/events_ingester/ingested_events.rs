@@ -1,19 +1,31 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use ethers::prelude::*;
 use futures_util::FutureExt;
-use std::cmp::min;
 
-use crate::chain_reorg::{Execution, UnsavedReorgedBlock};
-use crate::contracts::Contract;
-use crate::events::{Event, Events};
+use crate::chain_reorg::{Execution, MinConfirmationCount, UnsavedReorgedBlock};
+use crate::events::Event;
+use crate::hashes::Hashes;
 use crate::{
-    ChaindexingRepo, ChaindexingRepoConn, ContractAddress, EventsIngesterJsonRpc,
-    MinConfirmationCount, Repo,
+    BlockNumber, ChaindexingRepo, ChaindexingRepoConn, Config, ContractAddress,
+    EventsIngesterJsonRpc, FinalityViolation, Repo,
 };
 
-use super::{fetch_blocks_by_tx_hash, fetch_logs, EventsIngesterError, Filter, Filters};
+use super::{fetch_block_hashes, fetch_events, EventsIngesterError, Filter, Filters};
+
+/// Called whenever a JSON RPC serves a log with the same identity as an
+/// already ingested one, but different data. See `InconsistentEvent`.
+pub type OnInconsistentEvent = Arc<dyn Fn(&InconsistentEvent) + Send + Sync>;
+
+/// Already ingested event a JSON RPC served again with the same transaction
+/// hash and log index but different data. Still backtracked like a reorg,
+/// though it often points at a provider bug rather than a branch switch.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InconsistentEvent {
+    pub already_ingested_event: Event,
+    pub json_rpc_event: Event,
+}
 
 pub struct MaybeBacktrackIngestedEvents;
 
@@ -21,89 +33,245 @@ impl MaybeBacktrackIngestedEvents {
     pub async fn run<'a>(
         conn: &mut ChaindexingRepoConn<'a>,
         contract_addresses: Vec<ContractAddress>,
-        contracts: &Vec<Contract>,
         json_rpc: &Arc<impl EventsIngesterJsonRpc + 'static>,
         chain: &Chain,
-        current_block_number: u64,
-        blocks_per_batch: u64,
+        current_block_number: BlockNumber,
+        config: &Config,
+    ) -> Result<(), EventsIngesterError> {
+        let min_confirmation_count = config.get_min_confirmation_count(chain);
+        let execution = Execution::Confirmation(min_confirmation_count);
+
+        Self::run_with_execution(
+            conn,
+            contract_addresses.clone(),
+            json_rpc,
+            chain,
+            current_block_number,
+            &execution,
+            config,
+        )
+        .await?;
+
+        if config.on_finality_violation.is_some() {
+            Self::check_finality(
+                conn,
+                &contract_addresses,
+                json_rpc,
+                chain,
+                current_block_number,
+                min_confirmation_count,
+                config,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Reports blocks right past the confirmation window whose hashes diverged
+    /// since their events got ingested, without backtracking them
+    async fn check_finality<'a>(
+        conn: &mut ChaindexingRepoConn<'a>,
+        contract_addresses: &Vec<ContractAddress>,
+        json_rpc: &Arc<impl EventsIngesterJsonRpc + 'static>,
+        chain: &Chain,
+        current_block_number: BlockNumber,
         min_confirmation_count: &MinConfirmationCount,
+        config: &Config,
+    ) -> Result<(), EventsIngesterError> {
+        let filters = Filters::new(
+            contract_addresses,
+            &config.contracts,
+            current_block_number,
+            config.get_blocks_per_batch(chain),
+            1,
+            &Execution::FinalityCheck(min_confirmation_count),
+        );
+        let filters = Filters::end_at(filters, config.end_block_number);
+
+        if !filters.is_empty() {
+            let already_ingested_events = Self::get_already_ingested_events(conn, &filters).await;
+            let json_rpc_events = fetch_events(&filters, json_rpc, config).await?;
+
+            for finality_violation in
+                FinalityViolation::detect(chain, &already_ingested_events, &json_rpc_events)
+            {
+                warn_finality_violation(&finality_violation, config);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn run_with_execution<'a>(
+        conn: &mut ChaindexingRepoConn<'a>,
+        contract_addresses: Vec<ContractAddress>,
+        json_rpc: &Arc<impl EventsIngesterJsonRpc + 'static>,
+        chain: &Chain,
+        current_block_number: BlockNumber,
+        execution: &Execution<'_>,
+        config: &Config,
     ) -> Result<(), EventsIngesterError> {
         let filters = Filters::new(
             &contract_addresses,
-            &contracts,
+            &config.contracts,
             current_block_number,
-            blocks_per_batch,
-            &Execution::Confirmation(min_confirmation_count),
+            config.get_blocks_per_batch(chain),
+            1,
+            execution,
         );
+        // Nothing past the end block gets ingested, nor confirmed or reconciled
+        let filters = Filters::end_at(filters, config.end_block_number);
 
         if !filters.is_empty() {
             let already_ingested_events = Self::get_already_ingested_events(conn, &filters).await;
-            let json_rpc_events = Self::get_json_rpc_events(&filters, json_rpc, contracts).await;
+            let json_rpc_events = if config.confirm_by_block_hash {
+                Self::fetch_canonical_events(&filters, &already_ingested_events, json_rpc, config)
+                    .await?
+            } else {
+                fetch_events(&filters, json_rpc, config).await?
+            };
 
-            Self::maybe_handle_chain_reorg(conn, chain, &already_ingested_events, &json_rpc_events)
-                .await?;
+            // Reconciled block ranges say nothing about how deep reorgs get
+            let detected_at_block_number = match execution {
+                Execution::Confirmation(_) => Some(current_block_number),
+                _ => None,
+            };
+
+            Self::maybe_handle_chain_reorg(
+                conn,
+                chain,
+                detected_at_block_number,
+                &already_ingested_events,
+                &json_rpc_events,
+                config,
+            )
+            .await?;
         }
 
         Ok(())
     }
 
-    async fn get_already_ingested_events<'a>(
+    pub async fn get_already_ingested_events<'a>(
         conn: &mut ChaindexingRepoConn<'a>,
         filters: &Vec<Filter>,
     ) -> Vec<Event> {
         let mut already_ingested_events = vec![];
         for filter in filters {
-            let from_block = filter.value.get_from_block().unwrap().as_u64();
-            let to_block = filter.value.get_to_block().unwrap().as_u64();
+            let from_block = BlockNumber::from(filter.value.get_from_block().unwrap());
+            let to_block = BlockNumber::from(filter.value.get_to_block().unwrap());
 
-            let mut events =
-                ChaindexingRepo::get_events(conn, filter.address.to_owned(), from_block, to_block)
-                    .await;
+            let mut events = ChaindexingRepo::get_events(
+                conn,
+                filter.address.to_owned(),
+                filter.chain_id,
+                from_block,
+                to_block,
+            )
+            .await;
             already_ingested_events.append(&mut events);
         }
 
         already_ingested_events
     }
 
-    async fn get_json_rpc_events(
+    // Load-balanced providers can serve a block number range from a node still
+    // on a reorged-out branch, returning its stale events as if confirmed.
+    // Only events of the canonical blocks at their numbers are kept instead,
+    // so events of blocks the node does not know of at all count as removed.
+    async fn fetch_canonical_events(
         filters: &Vec<Filter>,
+        already_ingested_events: &Vec<Event>,
         json_rpc: &Arc<impl EventsIngesterJsonRpc + 'static>,
-        contracts: &Vec<Contract>,
-    ) -> Vec<Event> {
-        let logs = fetch_logs(&filters, json_rpc).await;
-        let blocks_by_tx_hash = fetch_blocks_by_tx_hash(&logs, json_rpc).await;
+        config: &Config,
+    ) -> Result<Vec<Event>, EventsIngesterError> {
+        let json_rpc_events = fetch_events(filters, json_rpc, config).await?;
+
+        let block_numbers: HashSet<_> = already_ingested_events
+            .iter()
+            .chain(json_rpc_events.iter())
+            .map(|e| e.block_number)
+            .collect();
+        let canonical_block_hashes = fetch_block_hashes(&block_numbers, json_rpc, config).await?;
 
-        Events::new(&logs, contracts, &blocks_by_tx_hash)
+        let canonical_block_hashes: HashMap<_, _> = canonical_block_hashes
+            .into_iter()
+            .filter_map(|(block_number, block_hash)| {
+                Some((
+                    block_number,
+                    Hashes::h256_to_string(&block_hash?).to_lowercase(),
+                ))
+            })
+            .collect();
+
+        Ok(json_rpc_events
+            .into_iter()
+            .filter(|e| canonical_block_hashes.get(&e.block_number) == Some(&e.block_hash))
+            .collect())
     }
 
     async fn maybe_handle_chain_reorg<'a>(
         conn: &mut ChaindexingRepoConn<'a>,
         chain: &Chain,
+        detected_at_block_number: Option<BlockNumber>,
         already_ingested_events: &Vec<Event>,
         json_rpc_events: &Vec<Event>,
+        config: &Config,
     ) -> Result<(), EventsIngesterError> {
-        if let Some((added_events, removed_events)) =
-            Self::get_json_rpc_added_and_removed_events(&already_ingested_events, &json_rpc_events)
-        {
-            let earliest_block_number =
-                Self::get_earliest_block_number((&added_events, &removed_events));
-            let new_reorged_block = UnsavedReorgedBlock::new(earliest_block_number, chain);
-
-            ChaindexingRepo::run_in_transaction(conn, move |conn| {
-                async move {
+        // The same contract address can be deployed on several chains
+        let chain_id = *chain as i32;
+        let already_ingested_events: Vec<_> = already_ingested_events
+            .iter()
+            .filter(|e| e.chain_id == chain_id)
+            .cloned()
+            .collect();
+        let json_rpc_events: Vec<_> =
+            json_rpc_events.iter().filter(|e| e.chain_id == chain_id).cloned().collect();
+
+        let Some((added_events, removed_events)) = Self::get_json_rpc_added_and_removed_events(
+            &already_ingested_events,
+            &json_rpc_events,
+            |inconsistent_event| warn_inconsistent_event(&inconsistent_event, config),
+        ) else {
+            return Ok(());
+        };
+        // Nothing got added or removed, so there is no reorg to record
+        let Some(earliest_block_number) =
+            Self::get_earliest_block_number((&added_events, &removed_events))
+        else {
+            return Ok(());
+        };
+
+        let new_reorged_block =
+            UnsavedReorgedBlock::new(earliest_block_number, chain, config.clock.now())
+                .with_current_block_number(detected_at_block_number);
+        let soft_delete_removed_events = config.soft_delete_removed_events;
+
+        if let Some(events_partitioning) = &config.events_partitioning {
+            ChaindexingRepo::create_events_partitions(conn, events_partitioning, &added_events)
+                .await;
+        }
+
+        ChaindexingRepo::run_in_transaction(conn, move |conn| {
+            async move {
+                let reorged_block =
                     ChaindexingRepo::create_reorged_block(conn, &new_reorged_block).await;
 
-                    let event_ids = removed_events.iter().map(|e| e.id).collect();
+                let event_ids = removed_events.iter().map(|e| e.id).collect();
+                if soft_delete_removed_events {
+                    ChaindexingRepo::soft_delete_events_by_ids(conn, &event_ids, reorged_block.id)
+                        .await;
+                } else {
                     ChaindexingRepo::delete_events_by_ids(conn, &event_ids).await;
+                }
 
-                    ChaindexingRepo::create_events(conn, &added_events).await;
+                ChaindexingRepo::create_events(conn, &added_events).await;
 
-                    Ok(())
-                }
-                .boxed()
-            })
-            .await?;
-        }
+                Ok(())
+            }
+            .boxed()
+        })
+        .await?;
 
         Ok(())
     }
@@ -111,22 +279,39 @@ impl MaybeBacktrackIngestedEvents {
     fn get_json_rpc_added_and_removed_events(
         already_ingested_events: &Vec<Event>,
         json_rpc_events: &Vec<Event>,
+        on_inconsistent_event: impl Fn(InconsistentEvent),
     ) -> Option<(Vec<Event>, Vec<Event>)> {
-        let already_ingested_events_set: HashSet<_> =
-            already_ingested_events.clone().into_iter().collect();
-        let json_rpc_events_set: HashSet<_> = json_rpc_events.clone().into_iter().collect();
+        let already_ingested_content_hashes: HashSet<_> =
+            already_ingested_events.iter().map(|e| e.get_content_hash()).collect();
+        let json_rpc_content_hashes: HashSet<_> =
+            json_rpc_events.iter().map(|e| e.get_content_hash()).collect();
 
-        let added_events: Vec<_> = json_rpc_events
+        let mut added_events: Vec<_> = json_rpc_events
             .clone()
             .into_iter()
-            .filter(|e| !already_ingested_events_set.contains(e))
+            .filter(|e| !already_ingested_content_hashes.contains(&e.get_content_hash()))
             .collect();
+        added_events.sort_by_key(|e| (e.block_number, e.transaction_index, e.log_index));
 
-        let removed_events: Vec<_> = already_ingested_events
+        let mut removed_events: Vec<_> = already_ingested_events
             .clone()
             .into_iter()
-            .filter(|e| !json_rpc_events_set.contains(e))
+            .filter(|e| !json_rpc_content_hashes.contains(&e.get_content_hash()))
             .collect();
+        removed_events.sort_by_key(|e| (e.block_number, e.transaction_index, e.log_index));
+
+        // Still surface as added and removed events, like any reorg's
+        for removed_event in removed_events.iter() {
+            if let Some(added_event) = added_events
+                .iter()
+                .find(|e| e.has_same_identity_but_different_data(removed_event))
+            {
+                on_inconsistent_event(InconsistentEvent {
+                    already_ingested_event: removed_event.clone(),
+                    json_rpc_event: added_event.clone(),
+                });
+            }
+        }
 
         if added_events.is_empty() && removed_events.is_empty() {
             None
@@ -137,17 +322,177 @@ impl MaybeBacktrackIngestedEvents {
 
     fn get_earliest_block_number(
         (added_events, removed_events): (&Vec<Event>, &Vec<Event>),
-    ) -> i64 {
-        let earliest_added_event = added_events.iter().min_by_key(|e| e.block_number);
-        let earliest_removed_event = removed_events.iter().min_by_key(|e| e.block_number);
-
-        match (earliest_added_event, earliest_removed_event) {
-            (None, Some(event)) => event.block_number,
-            (Some(event), None) => event.block_number,
-            (Some(earliest_added), Some(earliest_removed)) => {
-                min(earliest_added.block_number, earliest_removed.block_number)
-            }
-            _ => unreachable!("Added Events or Removed Events must have at least one entry"),
+    ) -> Option<i64> {
+        added_events.iter().chain(removed_events.iter()).map(|e| e.block_number).min()
+    }
+}
+
+fn warn_finality_violation(finality_violation: &FinalityViolation, config: &Config) {
+    eprintln!(
+        "Finality Violation: Chain {} reorged block {} beyond its confirmation window, from {} to {:?}",
+        finality_violation.chain,
+        finality_violation.block_number,
+        finality_violation.ingested_block_hash,
+        finality_violation.json_rpc_block_hash
+    );
+
+    if let Some(on_finality_violation) = &config.on_finality_violation {
+        on_finality_violation(finality_violation);
+    }
+}
+
+fn warn_inconsistent_event(inconsistent_event: &InconsistentEvent, config: &Config) {
+    let InconsistentEvent { json_rpc_event, .. } = inconsistent_event;
+
+    eprintln!(
+        "Inconsistent Event: Chain {} served log {} of transaction {} with other data than ingested",
+        json_rpc_event.chain_id, json_rpc_event.log_index, json_rpc_event.transaction_hash
+    );
+
+    if let Some(on_inconsistent_event) = &config.on_inconsistent_event {
+        on_inconsistent_event(inconsistent_event);
+    }
+}
+
+#[cfg(test)]
+mod get_json_rpc_added_and_removed_events_test {
+    use super::*;
+
+    use crate::contracts::UnsavedContractAddress;
+    use crate::ContractEvent;
+
+    const BAYC_CONTRACT_ADDRESS: &str = "0xBC4CA0EdA7647A8aB7C2061c2E118A18a936f13D";
+    const TRANSFER_EVENT_ABI: &str =
+        "event Transfer(address indexed from, address indexed to, uint256 indexed tokenId)";
+
+    #[test]
+    fn sorts_added_and_removed_events_by_block_number_and_log_index() {
+        let already_ingested_events = vec![
+            transfer_event(9, 2),
+            transfer_event(7, 1),
+            transfer_event(5, 1),
+            transfer_event(9, 1),
+        ];
+        let json_rpc_events = vec![
+            transfer_event(8, 3),
+            transfer_event(5, 1),
+            transfer_event(8, 1),
+            transfer_event(6, 4),
+        ];
+
+        let (added_events, removed_events) =
+            MaybeBacktrackIngestedEvents::get_json_rpc_added_and_removed_events(
+                &already_ingested_events,
+                &json_rpc_events,
+                |_| {},
+            )
+            .unwrap();
+
+        assert_eq!(get_positions(&added_events), vec![(6, 4), (8, 1), (8, 3)]);
+        assert_eq!(get_positions(&removed_events), vec![(7, 1), (9, 1), (9, 2)]);
+    }
+
+    #[test]
+    fn reports_logs_with_the_same_identity_but_different_data() {
+        let already_ingested_event = transfer_event(9, 2);
+        let mut log = transfer_log(9, 2);
+        // Same transaction hash and log index, other token id
+        log.topics[3] = H256::from_low_u64_be(1661);
+        let inconsistent_event = transfer_event_from_log(&log);
+        let inconsistent_events = std::cell::RefCell::new(vec![]);
+
+        MaybeBacktrackIngestedEvents::get_json_rpc_added_and_removed_events(
+            &vec![already_ingested_event.clone(), transfer_event(5, 1)],
+            &vec![inconsistent_event.clone(), transfer_event(5, 1)],
+            |e| inconsistent_events.borrow_mut().push(e),
+        )
+        .unwrap();
+
+        assert_eq!(
+            inconsistent_events.into_inner(),
+            vec![InconsistentEvent {
+                already_ingested_event,
+                json_rpc_event: inconsistent_event,
+            }]
+        );
+    }
+
+    #[test]
+    fn does_not_report_logs_moved_to_other_blocks() {
+        let mut log = transfer_log(9, 2);
+        log.block_hash = Some(H256::from_low_u64_be(10));
+        log.block_number = Some(10.into());
+        let inconsistent_events = std::cell::RefCell::new(vec![]);
+
+        MaybeBacktrackIngestedEvents::get_json_rpc_added_and_removed_events(
+            &vec![transfer_event(9, 2)],
+            &vec![transfer_event_from_log(&log)],
+            |e| inconsistent_events.borrow_mut().push(e),
+        )
+        .unwrap();
+
+        assert!(inconsistent_events.into_inner().is_empty());
+    }
+
+    fn get_positions(events: &Vec<Event>) -> Vec<(i64, i64)> {
+        events.iter().map(|e| (e.block_number, e.log_index)).collect()
+    }
+
+    fn transfer_event(block_number: u64, log_index: u64) -> Event {
+        transfer_event_from_log(&transfer_log(block_number, log_index))
+    }
+
+    fn transfer_log(block_number: u64, log_index: u64) -> Log {
+        let contract_event = ContractEvent::new(TRANSFER_EVENT_ABI);
+
+        Log {
+            address: BAYC_CONTRACT_ADDRESS.parse().unwrap(),
+            topics: vec![
+                contract_event.value.signature(),
+                H256::zero(),
+                H256::zero(),
+                H256::from_low_u64_be(log_index),
+            ],
+            block_hash: Some(H256::from_low_u64_be(block_number)),
+            block_number: Some(block_number.into()),
+            transaction_hash: Some(H256::from_low_u64_be(block_number * 100 + log_index)),
+            transaction_index: Some(0.into()),
+            log_index: Some(log_index.into()),
+            removed: Some(false),
+            ..Default::default()
         }
     }
+
+    fn transfer_event_from_log(log: &Log) -> Event {
+        let contract_event = ContractEvent::new(TRANSFER_EVENT_ABI);
+        let contract_address = UnsavedContractAddress::new(
+            "BoredApeYachtClub",
+            BAYC_CONTRACT_ADDRESS,
+            &Chain::Mainnet,
+            0,
+        );
+
+        Event::new(log, &contract_event, &contract_address, 0)
+    }
+}
+
+#[cfg(test)]
+mod get_earliest_block_number_test {
+    use super::*;
+
+    #[test]
+    fn returns_none_without_added_or_removed_events() {
+        assert_eq!(
+            MaybeBacktrackIngestedEvents::get_json_rpc_added_and_removed_events(
+                &vec![],
+                &vec![],
+                |_| {}
+            ),
+            None
+        );
+        assert_eq!(
+            MaybeBacktrackIngestedEvents::get_earliest_block_number((&vec![], &vec![])),
+            None
+        );
+    }
 }
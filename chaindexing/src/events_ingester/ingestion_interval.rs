@@ -0,0 +1,100 @@
+use std::time::{Duration, Instant};
+
+use crate::BlockNumber;
+
+/// Polling any faster than this gains nothing, even on chains with sub-second blocks
+pub const MIN_INGESTION_INTERVAL_MS: u64 = 250;
+
+/// Tracks a chain's observed block production rate to poll its JSON RPC about
+/// once per block, never slower than the configured ingestion interval.
+#[derive(Clone, Debug)]
+pub struct AdaptiveIngestionInterval {
+    max_interval: Duration,
+    last_observation: Option<(BlockNumber, Instant)>,
+    block_time: Option<Duration>,
+}
+
+impl AdaptiveIngestionInterval {
+    pub fn new(max_interval_ms: u64) -> Self {
+        Self {
+            max_interval: Duration::from_millis(max_interval_ms),
+            last_observation: None,
+            block_time: None,
+        }
+    }
+
+    pub fn observe(&mut self, current_block_number: BlockNumber, observed_at: Instant) {
+        match self.last_observation {
+            Some((last_block_number, _)) if current_block_number <= last_block_number => {
+                // Keep measuring from the last observed block change
+            }
+            Some((last_block_number, last_observed_at)) => {
+                let blocks_produced = current_block_number.value() - last_block_number.value();
+                let elapsed = observed_at.saturating_duration_since(last_observed_at);
+                let block_time = elapsed / blocks_produced as u32;
+
+                // Smooths out jittery observations
+                self.block_time = Some(match self.block_time {
+                    Some(previous_block_time) => (previous_block_time + block_time) / 2,
+                    None => block_time,
+                });
+                self.last_observation = Some((current_block_number, observed_at));
+            }
+            None => self.last_observation = Some((current_block_number, observed_at)),
+        }
+    }
+
+    pub fn get(&self) -> Duration {
+        let min_interval = Duration::from_millis(MIN_INGESTION_INTERVAL_MS).min(self.max_interval);
+
+        self.block_time
+            .map(|block_time| block_time.clamp(min_interval, self.max_interval))
+            .unwrap_or(self.max_interval)
+    }
+}
+
+#[cfg(test)]
+mod adaptive_ingestion_interval_test {
+    use super::*;
+
+    #[test]
+    fn starts_at_the_configured_interval() {
+        let ingestion_interval = AdaptiveIngestionInterval::new(4000);
+
+        assert_eq!(ingestion_interval.get(), Duration::from_millis(4000));
+    }
+
+    #[test]
+    fn adapts_to_the_observed_block_time_within_bounds() {
+        let started_at = Instant::now();
+        // Mocks a chain head advancing by one block every 2 seconds, polled every second
+        let observe_head = |ingestion_interval: &mut AdaptiveIngestionInterval, seconds: u64| {
+            ingestion_interval.observe(
+                BlockNumber::new(100 + seconds / 2),
+                started_at + Duration::from_secs(seconds),
+            )
+        };
+
+        let mut ingestion_interval = AdaptiveIngestionInterval::new(4000);
+        (0..=10).for_each(|seconds| observe_head(&mut ingestion_interval, seconds));
+        assert_eq!(ingestion_interval.get(), Duration::from_secs(2));
+
+        let mut ingestion_interval = AdaptiveIngestionInterval::new(1500);
+        (0..=10).for_each(|seconds| observe_head(&mut ingestion_interval, seconds));
+        assert_eq!(ingestion_interval.get(), Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn never_polls_faster_than_the_min_interval() {
+        let started_at = Instant::now();
+        let mut ingestion_interval = AdaptiveIngestionInterval::new(4000);
+
+        ingestion_interval.observe(BlockNumber::new(100), started_at);
+        ingestion_interval.observe(BlockNumber::new(200), started_at + Duration::from_secs(1));
+
+        assert_eq!(
+            ingestion_interval.get(),
+            Duration::from_millis(MIN_INGESTION_INTERVAL_MS)
+        );
+    }
+}
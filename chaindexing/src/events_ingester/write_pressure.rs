@@ -0,0 +1,71 @@
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// Pauses ingestion for `backoff` whenever writing a batch's events took
+/// longer than `max_write_latency`, so a struggling database gets to recover
+/// instead of getting piled onto. Ingestion resumes at full rate once writes
+/// get fast again. Shared by every chain, along with its clones.
+#[derive(Clone, Debug)]
+pub struct WritePressure {
+    pub max_write_latency: Duration,
+    pub backoff: Duration,
+    paused_until: Arc<RwLock<Option<Instant>>>,
+}
+
+impl WritePressure {
+    pub fn new(max_write_latency: Duration, backoff: Duration) -> Self {
+        Self {
+            max_write_latency,
+            backoff,
+            paused_until: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    pub fn observe(&self, write_latency: Duration, written_at: Instant) {
+        if write_latency > self.max_write_latency {
+            eprintln!(
+                "Write Pressure: writing events took {write_latency:?}, pausing ingestion for {backoff:?}",
+                backoff = self.backoff
+            );
+
+            *self.paused_until.write().unwrap() = Some(written_at + self.backoff);
+        }
+    }
+
+    pub fn is_paused(&self, now: Instant) -> bool {
+        self.paused_until.read().unwrap().is_some_and(|paused_until| now < paused_until)
+    }
+}
+
+#[cfg(test)]
+mod write_pressure_test {
+    use super::*;
+
+    #[test]
+    fn pauses_on_slow_writes_until_the_backoff_elapses() {
+        let write_pressure =
+            WritePressure::new(Duration::from_millis(500), Duration::from_secs(10));
+        let now = Instant::now();
+
+        write_pressure.observe(Duration::from_millis(500), now);
+        assert!(!write_pressure.is_paused(now));
+
+        write_pressure.observe(Duration::from_millis(501), now);
+        assert!(write_pressure.clone().is_paused(now));
+        assert!(write_pressure.is_paused(now + Duration::from_secs(9)));
+        assert!(!write_pressure.is_paused(now + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn keeps_pausing_while_writes_stay_slow() {
+        let write_pressure =
+            WritePressure::new(Duration::from_millis(500), Duration::from_secs(10));
+        let now = Instant::now();
+
+        write_pressure.observe(Duration::from_secs(2), now);
+        let later = now + Duration::from_secs(10);
+        write_pressure.observe(Duration::from_secs(2), later);
+
+        assert!(write_pressure.is_paused(later + Duration::from_secs(9)));
+    }
+}
@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{Chain, EventsIngesterError};
+
+/// Ingestion tick that failed without any chain circuit breaker to absorb it
+#[derive(Debug)]
+pub struct FatalError {
+    pub chain: Chain,
+    pub error: EventsIngesterError,
+}
+
+/// What the events ingester, or the coupled pipeline, does on fatal errors,
+/// see `FatalError`
+#[derive(Clone, Debug, Default)]
+pub enum FatalErrorPolicy {
+    /// Panics, stopping the events ingester's background task
+    #[default]
+    Panic,
+    /// Logs the error, then restarts the ingestion loop after the delay
+    Retry(Duration),
+    /// Sends the error to the channel, then stops the events ingester, so the
+    /// application can decide how to shut down, e.g. by crashing the process
+    Shutdown(UnboundedSender<FatalError>),
+}
+
+impl FatalErrorPolicy {
+    /// Returns the delay to restart the ingestion loop after, if it should
+    pub fn apply(&self, chain: &Chain, error: EventsIngesterError) -> Option<Duration> {
+        match self {
+            Self::Panic => panic!("Events Ingester Error: {chain}: {error:?}"),
+            Self::Retry(delay) => {
+                eprintln!("Events Ingester Error: {chain}: {error:?}. Retrying in {delay:?}");
+
+                Some(*delay)
+            }
+            Self::Shutdown(fatal_errors) => {
+                eprintln!("Events Ingester Error: {chain}: {error:?}. Shutting down");
+
+                // The application might have stopped listening already
+                let _ = fatal_errors.send(FatalError {
+                    chain: *chain,
+                    error,
+                });
+
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod fatal_error_policy_test {
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use super::*;
+
+    fn fatal_error() -> EventsIngesterError {
+        EventsIngesterError::GenericError("relation does not exist".to_string())
+    }
+
+    #[test]
+    #[should_panic(expected = "Events Ingester Error")]
+    fn panics_by_default() {
+        FatalErrorPolicy::default().apply(&Chain::Mainnet, fatal_error());
+    }
+
+    #[test]
+    fn retries_after_the_delay() {
+        let policy = FatalErrorPolicy::Retry(Duration::from_secs(5));
+
+        assert_eq!(
+            policy.apply(&Chain::Mainnet, fatal_error()),
+            Some(Duration::from_secs(5))
+        );
+    }
+
+    #[test]
+    fn propagates_fatal_errors_to_the_shutdown_channel() {
+        let (sender, mut receiver) = unbounded_channel();
+        let policy = FatalErrorPolicy::Shutdown(sender);
+
+        assert_eq!(policy.apply(&Chain::Polygon, fatal_error()), None);
+
+        let fatal_error = receiver.try_recv().unwrap();
+        assert_eq!(fatal_error.chain, Chain::Polygon);
+        assert!(matches!(
+            fatal_error.error,
+            EventsIngesterError::GenericError(_)
+        ));
+    }
+}
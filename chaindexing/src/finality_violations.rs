@@ -0,0 +1,58 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::{Chain, Event};
+
+/// Called whenever a chain reorgs deeper than its confirmation window. See
+/// `FinalityViolation`.
+pub type OnFinalityViolation = Arc<dyn Fn(&FinalityViolation) + Send + Sync>;
+
+/// Block right past the confirmation window whose hash diverged from the
+/// ingested one, i.e. a reorg deeper than `MinConfirmationCount`. Its events
+/// got confirmed, and maybe handled, already, so they do not get backtracked
+/// automatically: operators are expected to reconcile the chain from the
+/// block on, e.g. with `EventsIngester::reconcile`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FinalityViolation {
+    pub chain: Chain,
+    pub block_number: i64,
+    pub ingested_block_hash: String,
+    /// None when the JSON RPC serves none of the block's ingested logs anymore
+    pub json_rpc_block_hash: Option<String>,
+}
+
+impl FinalityViolation {
+    /// Compares the block hashes of events already ingested with the ones of
+    /// the events served for the same block range, by block number
+    pub fn detect(
+        chain: &Chain,
+        already_ingested_events: &Vec<Event>,
+        json_rpc_events: &Vec<Event>,
+    ) -> Vec<Self> {
+        let chain_id = *chain as i32;
+        let json_rpc_block_hashes: HashMap<_, _> = json_rpc_events
+            .iter()
+            .filter(|e| e.chain_id == chain_id)
+            .map(|e| (e.block_number, e.block_hash.as_str()))
+            .collect();
+        let ingested_block_hashes: BTreeMap<_, _> = already_ingested_events
+            .iter()
+            .filter(|e| e.chain_id == chain_id && e.not_removed())
+            .map(|e| (e.block_number, e.block_hash.as_str()))
+            .collect();
+
+        ingested_block_hashes
+            .into_iter()
+            .filter_map(|(block_number, ingested_block_hash)| {
+                let json_rpc_block_hash = json_rpc_block_hashes.get(&block_number).copied();
+
+                (json_rpc_block_hash != Some(ingested_block_hash)).then(|| Self {
+                    chain: *chain,
+                    block_number,
+                    ingested_block_hash: ingested_block_hash.to_string(),
+                    json_rpc_block_hash: json_rpc_block_hash.map(|hash| hash.to_string()),
+                })
+            })
+            .collect()
+    }
+}
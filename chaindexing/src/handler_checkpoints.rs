@@ -0,0 +1,18 @@
+use crate::diesels::schema::chaindexing_handler_checkpoints;
+use diesel::Queryable;
+
+/// Last event handled for a contract address, committed atomically with
+/// the handlers' writes, e.g. to query how far handling got across tables.
+/// Blocks are always handled whole, so it is the last event of the block
+/// right before the contract address's handling cursor.
+#[derive(Debug, Clone, PartialEq, Queryable)]
+#[diesel(table_name = chaindexing_handler_checkpoints)]
+pub struct HandlerCheckpoint {
+    pub contract_address_id: i32,
+    pub block_number: i64,
+    pub log_index: i64,
+    updated_at: chrono::NaiveDateTime,
+    /// Missing from checkpoints saved before events got handled in
+    /// transaction order within blocks
+    pub transaction_index: Option<i64>,
+}
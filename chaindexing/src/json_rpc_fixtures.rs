@@ -0,0 +1,234 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use derive_more::Display;
+use ethers::providers::ProviderError;
+use ethers::types::{
+    Address, Block, Filter as EthersFilter, FilteredParams, Log, Topic, TxHash, ValueOrArray, H256,
+    U64,
+};
+use serde_json::json;
+
+use crate::{Chain, Contract, EventsIngesterJsonRpc};
+
+#[derive(Debug, Display, PartialEq)]
+pub enum JsonRpcFixtureError {
+    #[display(fmt = "JSON RPC fixture cannot be read: {}", _0)]
+    Unreadable(String),
+    #[display(fmt = "JSON RPC fixture cannot be written: {}", _0)]
+    Unwritable(String),
+    #[display(fmt = "JSON RPC fixture is invalid: {}", _0)]
+    Invalid(String),
+}
+
+/// Blocks and logs captured from a chain's JSON RPC, e.g. to replay ingestion
+/// offline in reproducible tests. Stored as JSON, in the JSON RPC's own
+/// encoding of blocks and logs:
+///
+/// ```json
+/// { "block_number": "0x10f3a12", "blocks": [...], "logs": [...] }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct JsonRpcFixture {
+    /// Current block number at capture time
+    pub block_number: U64,
+    pub blocks: Vec<Block<TxHash>>,
+    pub logs: Vec<Log>,
+}
+
+impl JsonRpcFixture {
+    /// Captures the logs of the contracts' events, for their addresses on the
+    /// given chain, over the given block range, along with their blocks
+    pub async fn capture(
+        json_rpc: &impl EventsIngesterJsonRpc,
+        contracts: &Vec<Contract>,
+        chain: &Chain,
+        from_block_number: u64,
+        to_block_number: u64,
+    ) -> Result<Self, ProviderError> {
+        let current_block_number = json_rpc.get_block_number().await?;
+        let to_block_number = to_block_number.min(current_block_number.as_u64());
+
+        let mut logs = vec![];
+
+        for contract in contracts {
+            for contract_address in contract.addresses.iter() {
+                if contract_address.chain_id != *chain as i32 {
+                    continue;
+                }
+
+                let filter = EthersFilter::new()
+                    .address(contract_address.get_address().parse::<Address>().unwrap())
+                    .topic0(contract.get_event_topics())
+                    .from_block(from_block_number)
+                    .to_block(to_block_number);
+
+                logs.extend(json_rpc.get_logs(&filter).await?);
+            }
+        }
+
+        let block_numbers: BTreeSet<_> = logs.iter().filter_map(|log| log.block_number).collect();
+        let mut blocks = vec![];
+        for block_number in block_numbers {
+            blocks.push(json_rpc.get_block(block_number).await?);
+        }
+
+        Ok(Self {
+            block_number: to_block_number.into(),
+            blocks,
+            logs,
+        })
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, JsonRpcFixtureError> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|error| JsonRpcFixtureError::Unreadable(error.to_string()))?;
+
+        Self::from_json(&json)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, JsonRpcFixtureError> {
+        let fixture: serde_json::Value = serde_json::from_str(json)
+            .map_err(|error| JsonRpcFixtureError::Invalid(error.to_string()))?;
+
+        let get_field = |name: &str| {
+            fixture
+                .get(name)
+                .cloned()
+                .ok_or_else(|| JsonRpcFixtureError::Invalid(format!("Expected {name}")))
+        };
+        let invalid = |error: serde_json::Error| JsonRpcFixtureError::Invalid(error.to_string());
+
+        Ok(Self {
+            block_number: serde_json::from_value(get_field("block_number")?).map_err(invalid)?,
+            blocks: serde_json::from_value(get_field("blocks")?).map_err(invalid)?,
+            logs: serde_json::from_value(get_field("logs")?).map_err(invalid)?,
+        })
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), JsonRpcFixtureError> {
+        std::fs::write(path, self.to_json())
+            .map_err(|error| JsonRpcFixtureError::Unwritable(error.to_string()))
+    }
+
+    pub fn to_json(&self) -> String {
+        json!({
+            "block_number": self.block_number,
+            "blocks": self.blocks,
+            "logs": self.logs,
+        })
+        .to_string()
+    }
+
+    fn get_logs(&self, filter: &EthersFilter, current_block_number: u64) -> Vec<Log> {
+        let filtered_params = FilteredParams::new(Some(filter.clone()));
+
+        self.logs
+            .iter()
+            .filter(|log| {
+                log.block_number.is_some_and(|block_number| {
+                    block_number.as_u64() <= current_block_number
+                        && filtered_params.filter_block_range(block_number.as_u64())
+                })
+            })
+            .filter(|log| filtered_params.filter_address(log))
+            .filter(|log| {
+                filter
+                    .topics
+                    .iter()
+                    .enumerate()
+                    .all(|(index, topic)| matches_topic(topic, log.topics.get(index)))
+            })
+            .cloned()
+            .collect()
+    }
+
+    fn get_block(&self, block_number: U64) -> Option<Block<TxHash>> {
+        self.blocks.iter().find(|block| block.number == Some(block_number)).cloned()
+    }
+}
+
+fn matches_topic(topic: &Option<Topic>, log_topic: Option<&H256>) -> bool {
+    match topic {
+        None | Some(ValueOrArray::Value(None)) => true,
+        Some(ValueOrArray::Value(Some(topic))) => log_topic == Some(topic),
+        Some(ValueOrArray::Array(topics)) => {
+            topics.is_empty()
+                || topics.iter().any(|topic| topic.is_none() || topic.as_ref() == log_topic)
+        }
+    }
+}
+
+/// Serves a captured `JsonRpcFixture` in place of a live node, e.g. for
+/// reproducible end-to-end tests. The current block number starts at the
+/// fixture's and can be moved with `set_block_number` to replay the chain's
+/// progress: logs beyond it are not served. Transaction receipts are not
+/// captured, so their statuses are unknown. Clones share the same current
+/// block number.
+#[derive(Clone, Debug)]
+pub struct FileBackedJsonRpc {
+    fixture: Arc<JsonRpcFixture>,
+    reorg: Option<(u64, Arc<JsonRpcFixture>)>,
+    block_number: Arc<AtomicU64>,
+}
+
+impl FileBackedJsonRpc {
+    pub fn new(fixture: JsonRpcFixture) -> Self {
+        Self {
+            block_number: Arc::new(AtomicU64::new(fixture.block_number.as_u64())),
+            fixture: Arc::new(fixture),
+            reorg: None,
+        }
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, JsonRpcFixtureError> {
+        Ok(Self::new(JsonRpcFixture::read(path)?))
+    }
+
+    /// Simulates a reorg by serving `reorged_fixture` instead, e.g. captured
+    /// from another branch of the chain, once the current block number
+    /// reaches `block_number`
+    pub fn with_reorg(mut self, block_number: u64, reorged_fixture: JsonRpcFixture) -> Self {
+        self.reorg = Some((block_number, Arc::new(reorged_fixture)));
+
+        self
+    }
+
+    pub fn set_block_number(&self, block_number: u64) {
+        self.block_number.store(block_number, Ordering::SeqCst);
+    }
+
+    fn get_current_block_number(&self) -> u64 {
+        self.block_number.load(Ordering::SeqCst)
+    }
+
+    fn get_fixture(&self) -> &JsonRpcFixture {
+        match &self.reorg {
+            Some((block_number, reorged_fixture))
+                if self.get_current_block_number() >= *block_number =>
+            {
+                reorged_fixture
+            }
+            _ => &self.fixture,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EventsIngesterJsonRpc for FileBackedJsonRpc {
+    async fn get_block_number(&self) -> Result<U64, ProviderError> {
+        Ok(self.get_current_block_number().into())
+    }
+
+    async fn get_logs(&self, filter: &EthersFilter) -> Result<Vec<Log>, ProviderError> {
+        Ok(self.get_fixture().get_logs(filter, self.get_current_block_number()))
+    }
+
+    async fn get_block(&self, block_number: U64) -> Result<Block<TxHash>, ProviderError> {
+        self.get_fixture().get_block(block_number).ok_or_else(|| {
+            ProviderError::CustomError(format!("Block {block_number} is missing from the fixture"))
+        })
+    }
+}
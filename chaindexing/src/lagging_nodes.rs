@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use crate::{BlockNumber, Chain};
+
+/// Called whenever a chain's JSON RPC reports a current block behind blocks
+/// already ingested, e.g. when served from a lagging or forked node.
+pub type OnLaggingNode = Arc<dyn Fn(&LaggingNode) + Send + Sync>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LaggingNode {
+    pub chain: Chain,
+    /// Current block reported by the chain's JSON RPC
+    pub current_block_number: BlockNumber,
+    /// Latest block already ingested for any of the chain's contract addresses
+    pub last_ingested_block_number: BlockNumber,
+}
+
+impl LaggingNode {
+    /// Detects from the furthest ingestion cursor of the chain's contract addresses
+    pub fn detect(
+        chain: &Chain,
+        current_block_number: BlockNumber,
+        max_next_block_number_to_ingest_from: BlockNumber,
+    ) -> Option<Self> {
+        // Fully ingested contract addresses resume from the block after the head
+        (current_block_number.saturating_add(1) < max_next_block_number_to_ingest_from).then(|| {
+            Self {
+                chain: *chain,
+                current_block_number,
+                last_ingested_block_number: max_next_block_number_to_ingest_from.saturating_sub(1),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod lagging_node_test {
+    use super::*;
+
+    #[test]
+    fn does_not_detect_nodes_at_or_ahead_of_ingested_blocks() {
+        let chain = Chain::Mainnet;
+
+        assert_eq!(
+            LaggingNode::detect(&chain, BlockNumber::new(100), BlockNumber::new(101)),
+            None
+        );
+        assert_eq!(
+            LaggingNode::detect(&chain, BlockNumber::new(150), BlockNumber::new(101)),
+            None
+        );
+    }
+
+    #[test]
+    fn detects_nodes_behind_ingested_blocks() {
+        assert_eq!(
+            LaggingNode::detect(&Chain::Mainnet, BlockNumber::new(90), BlockNumber::new(101)),
+            Some(LaggingNode {
+                chain: Chain::Mainnet,
+                current_block_number: BlockNumber::new(90),
+                last_ingested_block_number: BlockNumber::new(100),
+            })
+        );
+    }
+}
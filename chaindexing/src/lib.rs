@@ -1,29 +1,117 @@
+mod batch_timings;
+mod block_filters;
+mod block_numbers;
+mod caught_up;
 mod chain_reorg;
 mod chains;
+mod circuit_breakers;
+mod clocks;
 mod config;
 mod contract_states;
 mod contracts;
+mod deployment_manifests;
 mod diesels;
+mod env_configs;
 mod event_handlers;
+#[cfg(feature = "sinks")]
+mod event_sinks;
+mod event_subscriptions;
 mod events;
 mod events_ingester;
+mod fatal_errors;
+mod finality_violations;
+mod handler_checkpoints;
 mod hashes;
+mod json_rpc_fixtures;
+mod lagging_nodes;
+mod log_decoders;
+mod metrics;
+#[cfg(feature = "pipeline-spans")]
+mod pipeline_spans;
+mod pipelines;
+mod reorg_reports;
 mod repos;
 mod reset_counts;
+mod start_block_strategies;
+#[cfg(feature = "tokens")]
+mod tokens;
+mod topic_hashers;
 
-pub use chain_reorg::{MinConfirmationCount, ReorgedBlock, ReorgedBlocks, UnsavedReorgedBlock};
-pub use chains::Chains;
+use std::collections::{HashMap, HashSet};
+
+use futures_util::FutureExt;
+
+use events_ingester::fetch_start_block_number;
+
+pub use batch_timings::{BatchTimings, OnBatchTimings};
+pub use block_filters::BlockFilter;
+pub use block_numbers::{BlockNumber, BlockNumberError, BlockRanges};
+pub use caught_up::{CaughtUpContractAddresses, OnCaughtUp};
+pub use chain_reorg::{
+    MinConfirmationCount, ReorgDepthStats, ReorgedBlock, ReorgedBlocks, UnsavedReorgedBlock,
+};
+pub use chains::{Chains, PausedChains};
+pub use circuit_breakers::{ChainCircuitBreakers, ChainCircuitState};
+pub use clocks::{Clock, MockClock, SystemClock};
 pub use config::Config;
-pub use contract_states::{ContractState, ContractStateMigrations, ContractStates};
-pub use contracts::{Contract, ContractAddress, ContractEvent, Contracts};
+pub use contract_states::{
+    ContractState, ContractStateMigrations, ContractStates, StateDrift, StateDriftKind,
+    StateSnapshot, StateVersionsPrimaryKey, SHADOW_STATE_SCHEMA, STATE_VERIFICATION_SCHEMA,
+};
+pub use contracts::{
+    Contract, ContractAddress, ContractAddressID, ContractAddressStatus, ContractEvent,
+    ContractEventTopic, ContractStatus, Contracts, FromEventSignature, HandlerMismatch,
+    ImplementationEvent, UnsavedContractAddress,
+};
+pub use deployment_manifests::{Deployment, DeploymentManifest, DeploymentManifestError};
 pub use diesel;
 pub use diesel::prelude::QueryableByName;
+pub use env_configs::{
+    EnvConfigError, BLOCKS_PER_BATCH_ENV_VAR, CHAINS_ENV_VAR,
+    CHAIN_MIN_CONFIRMATION_COUNTS_ENV_VAR, DATABASE_URL_ENV_VAR, HANDLER_INTERVAL_MS_ENV_VAR,
+    INGESTION_INTERVAL_MS_ENV_VAR, MIN_CONFIRMATION_COUNT_ENV_VAR,
+};
 pub use ethers::prelude::Chain;
-pub use event_handlers::{EventHandler, EventHandlerContext as EventContext, EventHandlers};
-pub use events::{Event, Events};
-pub use events_ingester::{EventsIngester, EventsIngesterJsonRpc};
+pub use event_handlers::{
+    ContractStateError, EventHandler, EventHandlerContext as EventContext, EventHandlers,
+    HandleEvents, HandlingThrottle,
+};
+#[cfg(feature = "sinks")]
+pub use event_sinks::{EventSink, EventSinkError, EventSinkHandler};
+pub use event_subscriptions::EventSubscriptions;
+pub use events::{
+    Event, EventBuilder, Events, EventsCursor, MalformedEventError, OnEventsIngested,
+    RedecodeEventsError,
+};
+pub use events_ingester::{
+    AdaptiveBlocksPerBatch, BlocksPerBatchError, BlocksPerBatchProbe, EventsIngester,
+    EventsIngesterError, EventsIngesterJsonRpc, HttpJsonRpc, HttpJsonRpcClients, InconsistentEvent,
+    OnInconsistentEvent, WritePressure,
+};
+pub use fatal_errors::{FatalError, FatalErrorPolicy};
+pub use finality_violations::{FinalityViolation, OnFinalityViolation};
+pub use handler_checkpoints::HandlerCheckpoint;
+pub use json_rpc_fixtures::{FileBackedJsonRpc, JsonRpcFixture, JsonRpcFixtureError};
+pub use lagging_nodes::{LaggingNode, OnLaggingNode};
+pub use log_decoders::{AbiLogDecoder, LogDecoder};
+pub use metrics::{Metric, MetricKind, MetricLabels, OnMetric};
+#[cfg(feature = "pipeline-spans")]
+pub use pipeline_spans::{OnPipelineSpan, PipelineSpan, PipelineSpanKind};
+pub use pipelines::{CoupledPipeline, PipelineMode};
+pub use reorg_reports::{OnReorgReport, ReorgReport, ReorgReporter};
 pub use repos::*;
 pub use reset_counts::ResetCount;
+pub use start_block_strategies::{
+    AutoDetectStartBlock, FixedStartBlock, FromLatestStartBlock, StartBlockJsonRpc,
+    StartBlockStrategy,
+};
+#[cfg(feature = "tokens")]
+pub use tokens::{
+    Erc20Balance, Erc20BalancesMigrations, Erc20TransferHandler, Erc721Token,
+    Erc721TokensMigrations, Erc721TransferHandler, ERC20_TRANSFER_EVENT_ABI,
+    ERC721_TRANSFER_EVENT_ABI,
+};
+pub use topic_hashers::{KeccakTopicHasher, TopicHasher};
 
 pub use ethers::prelude::{Address, U256, U64};
 
@@ -48,21 +136,55 @@ pub type ChaindexingRepoRawQueryTxnClient<'a> = PostgresRepoRawQueryTxnClient<'a
 #[cfg(feature = "postgres")]
 pub use repos::PostgresRepoAsyncConnection as ChaindexingRepoAsyncConnection;
 
+/// Events re-decoded per transaction by `Chaindexing::redecode_events`
+pub const REDECODED_EVENTS_PAGE_SIZE: i64 = 1_000;
+
 pub struct Chaindexing;
 
 impl Chaindexing {
-    pub async fn index_states(config: &Config) -> Result<(), ()> {
+    pub async fn index_states(config: &Config) -> Result<(), EventsIngesterError> {
         Self::setup(config).await?;
-        EventsIngester::start(config);
-        EventHandlers::start(config);
+
+        match config.pipeline_mode {
+            PipelineMode::Decoupled => {
+                EventsIngester::start(config);
+                EventHandlers::start(config);
+            }
+            PipelineMode::Coupled => CoupledPipeline::start(config),
+        }
+
+        Ok(())
+    }
+
+    /// Pool over the database chaindexing indexes into, for consumer queries,
+    /// e.g. joining their own tables with `chaindexing_events`. Connections
+    /// borrowed with `ChaindexingRepo::get_conn` cannot outlive the pool and
+    /// are independent of handlers' transactions: within a handler, use
+    /// `EventContext::get_raw_query_txn_client` instead.
+    pub async fn get_pool(config: &Config, max_size: u32) -> ChaindexingRepoPool {
+        config.repo.get_pool(max_size).await
+    }
+
+    /// Checks every chain's JSON RPC accepts `eth_getLogs` over the configured
+    /// `blocks_per_batch`, suggesting a safe value otherwise.
+    pub async fn probe_blocks_per_batch(config: &Config) -> Result<(), BlocksPerBatchError> {
+        for chain in config.chains.keys() {
+            let json_rpc = config.get_json_rpc(chain);
+
+            BlocksPerBatchProbe::run(&json_rpc, config.blocks_per_batch).await?;
+        }
+
         Ok(())
     }
 
-    pub async fn setup(config: &Config) -> Result<(), ()> {
+    /// Fails once resolving a start block exhausts `max_json_rpc_retries`
+    pub async fn setup(config: &Config) -> Result<(), EventsIngesterError> {
         let Config {
             repo,
             contracts,
             reset_count,
+            events_partitioning,
+            chains,
             ..
         } = config;
 
@@ -72,9 +194,18 @@ impl Chaindexing {
 
         Self::run_migrations_for_resets(&client).await;
         Self::maybe_reset(reset_count, contracts, &client, &mut conn).await;
+        if let Some(events_partitioning) = events_partitioning {
+            let chain_ids: Vec<_> = chains.keys().map(|chain| *chain as i32).collect();
+            Self::run_migrations_for_events_partitioning(&client, events_partitioning, &chain_ids)
+                .await;
+        }
         Self::run_internal_migrations(&client).await;
         Self::run_migrations_for_contract_states(&client, contracts).await;
-        Self::create_initial_contract_addresses(&mut conn, contracts).await;
+        let json_rpcs: HashMap<_, _> =
+            chains.keys().map(|chain| (*chain, config.get_json_rpc(chain))).collect();
+        let contracts =
+            Self::resolve_start_block_numbers(&mut conn, contracts, &json_rpcs, config).await?;
+        Self::create_initial_contract_addresses(&mut conn, &contracts).await;
 
         Ok(())
     }
@@ -105,6 +236,28 @@ impl Chaindexing {
         )
         .await;
     }
+    /// Not checksummed, unlike other migrations, since the internal migrations
+    /// checksum the same events table and unique index
+    pub async fn run_migrations_for_events_partitioning(
+        client: &ChaindexingRepoRawQueryClient,
+        events_partitioning: &EventsPartitioning,
+        chain_ids: &[i32],
+    ) {
+        let events_tables: Vec<HashMap<String, String>> =
+            ChaindexingRepo::load_data_list_from_raw_query(
+                client,
+                "SELECT relkind::TEXT FROM pg_class WHERE relname = 'chaindexing_events'",
+            )
+            .await;
+
+        if events_tables.first().is_some_and(|events_table| events_table["relkind"] != "p") {
+            panic!("Events Partitioning: chaindexing_events already exists unpartitioned, reset to partition it");
+        }
+
+        for migration in events_partitioning.get_create_events_migrations(chain_ids) {
+            ChaindexingRepo::execute_raw_query(client, &migration).await;
+        }
+    }
     pub async fn run_internal_migrations(client: &ChaindexingRepoRawQueryClient) {
         ChaindexingRepo::migrate(&client, ChaindexingRepo::get_internal_migrations()).await;
     }
@@ -129,6 +282,173 @@ impl Chaindexing {
         }
     }
 
+    /// Re-decodes the contract's stored events with its current ABI, e.g. after
+    /// fixing a wrong one, from their raw logs instead of the JSON RPC. Only
+    /// covers events ingested with `Config::with_store_raw_logs`. Already
+    /// handled events do not get handled again.
+    pub async fn redecode_events(
+        config: &Config,
+        contract_name: &str,
+    ) -> Result<(), RedecodeEventsError> {
+        let contract = config
+            .contracts
+            .iter()
+            .find(|c| c.name == contract_name)
+            .ok_or_else(|| RedecodeEventsError::UnknownContract(contract_name.to_string()))?;
+        let pool = config.repo.get_pool(1).await;
+        let mut conn = ChaindexingRepo::get_conn(&pool).await;
+
+        Self::redecode_events_with_conn(&mut conn, contract, REDECODED_EVENTS_PAGE_SIZE).await
+    }
+
+    /// Pages through the events, so they never all get loaded at once. Each
+    /// page gets replaced in its own transaction: re-running it after a
+    /// failure re-decodes already re-decoded events to the same ones.
+    pub async fn redecode_events_with_conn<'a>(
+        conn: &mut ChaindexingRepoConn<'a>,
+        contract: &Contract,
+        page_size: i64,
+    ) -> Result<(), RedecodeEventsError> {
+        let mut last_event_id = None;
+
+        loop {
+            let events = ChaindexingRepo::get_events_page_by_contract_name(
+                conn,
+                &contract.name,
+                last_event_id,
+                page_size,
+            )
+            .await;
+            let Some(last_event) = events.last() else {
+                return Ok(());
+            };
+            last_event_id = Some(last_event.id);
+
+            let redecoded_events = Events::redecode(&events, contract);
+
+            // Replaced as a whole, keeping their ids and positions
+            ChaindexingRepo::run_in_transaction(conn, move |conn| {
+                async move {
+                    let event_ids = redecoded_events.iter().map(|e| e.id).collect();
+                    ChaindexingRepo::delete_events_by_ids(conn, &event_ids).await;
+                    ChaindexingRepo::create_events(conn, &redecoded_events).await;
+
+                    Ok(())
+                }
+                .boxed()
+            })
+            .await?;
+        }
+    }
+
+    /// Registers addresses for an already configured contract while indexing,
+    /// e.g. pools as their factory deploys them. Ingestion picks them up on
+    /// its next tick. Addresses already registered are left untouched, while
+    /// new ones get their start blocks resolved like on setup.
+    pub async fn register_contract_addresses<'a>(
+        conn: &mut ChaindexingRepoConn<'a>,
+        config: &Config,
+        contract: &Contract,
+        addresses: &[&str],
+        chain: &Chain,
+        start_block_number: i64,
+    ) -> Result<(), EventsIngesterError> {
+        let json_rpc = config.get_json_rpc(chain);
+
+        Self::register_contract_addresses_with_json_rpc(
+            conn,
+            config,
+            contract,
+            addresses,
+            chain,
+            start_block_number,
+            json_rpc,
+        )
+        .await
+    }
+
+    pub async fn register_contract_addresses_with_json_rpc<'a>(
+        conn: &mut ChaindexingRepoConn<'a>,
+        config: &Config,
+        contract: &Contract,
+        addresses: &[&str],
+        chain: &Chain,
+        start_block_number: i64,
+        json_rpc: impl StartBlockJsonRpc,
+    ) -> Result<(), EventsIngesterError> {
+        let mut addresses = addresses.to_vec();
+        // A single upsert cannot affect the same row twice
+        addresses.sort_unstable_by_key(|address| ContractAddress::normalize_address(address));
+        addresses.dedup_by_key(|address| ContractAddress::normalize_address(address));
+
+        let contract = Contract {
+            addresses: addresses
+                .iter()
+                .map(|address| {
+                    UnsavedContractAddress::new(&contract.name, address, chain, start_block_number)
+                })
+                .collect(),
+            ..contract.clone()
+        };
+        let json_rpcs = HashMap::from([(*chain, json_rpc)]);
+        let contracts =
+            Self::resolve_start_block_numbers(conn, &vec![contract], &json_rpcs, config).await?;
+
+        ChaindexingRepo::create_contract_addresses(conn, &contracts[0].addresses).await;
+
+        Ok(())
+    }
+
+    /// Resolves the start blocks of contract addresses yet to be registered
+    /// with their contracts' `StartBlockStrategy`, on chains with a JSON RPC,
+    /// including the ones added from deployment manifests. JSON RPC errors
+    /// get retried with backoff, up to `max_json_rpc_retries`.
+    pub async fn resolve_start_block_numbers<'a>(
+        conn: &mut ChaindexingRepoConn<'a>,
+        contracts: &Vec<Contract>,
+        json_rpcs: &HashMap<Chain, impl StartBlockJsonRpc>,
+        config: &Config,
+    ) -> Result<Vec<Contract>, EventsIngesterError> {
+        let registered_addresses: HashSet<_> = ChaindexingRepo::get_all_contract_addresses(conn)
+            .await
+            .into_iter()
+            .map(|contract_address| contract_address.address)
+            .collect();
+        let mut contracts = contracts.clone();
+
+        for contract in contracts.iter_mut() {
+            let start_block_strategy = contract.start_block_strategy.clone();
+
+            for contract_address in contract.addresses.iter_mut() {
+                let address = contract_address.get_address().to_string();
+                let chain = Chain::try_from(contract_address.chain_id as u64).unwrap();
+                let Some(json_rpc) = json_rpcs.get(&chain) else {
+                    continue;
+                };
+                if registered_addresses.contains(&address) {
+                    continue;
+                }
+
+                let start_block_number = fetch_start_block_number(
+                    start_block_strategy.as_ref(),
+                    contract_address,
+                    json_rpc,
+                    config,
+                )
+                .await?;
+
+                *contract_address = UnsavedContractAddress::new(
+                    &contract_address.contract_name,
+                    &address,
+                    &chain,
+                    start_block_number,
+                );
+            }
+        }
+
+        Ok(contracts)
+    }
+
     pub async fn create_initial_contract_addresses<'a>(
         conn: &mut ChaindexingRepoConn<'a>,
         contracts: &Vec<Contract>,
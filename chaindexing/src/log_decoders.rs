@@ -0,0 +1,106 @@
+use ethers::abi::{LogParam, RawLog};
+use ethers::types::Log;
+
+use crate::ContractEvent;
+
+/// Decodes a contract's logs into their events' params, e.g. for protocols
+/// packing them into `data` instead of ABI-encoding them. The params make up
+/// the ingested event's `log_params` and `parameters`.
+pub trait LogDecoder: Send + Sync {
+    fn decode(&self, log: &Log, event: &ContractEvent) -> Vec<LogParam>;
+
+    /// Err for logs that cannot be decoded, e.g. with corrupt data or an
+    /// unexpected ABI, instead of panicking. Override along with `decode` to
+    /// let ingestion skip them, see `Config::with_skip_undecodable_logs`.
+    fn try_decode(&self, log: &Log, event: &ContractEvent) -> Result<Vec<LogParam>, String> {
+        Ok(self.decode(log, event))
+    }
+}
+
+/// Decodes logs with their events' ABI, for contracts without a custom decoder
+#[derive(Clone, Debug, Default)]
+pub struct AbiLogDecoder;
+
+impl LogDecoder for AbiLogDecoder {
+    fn decode(&self, log: &Log, event: &ContractEvent) -> Vec<LogParam> {
+        self.try_decode(log, event).unwrap()
+    }
+
+    fn try_decode(&self, log: &Log, event: &ContractEvent) -> Result<Vec<LogParam>, String> {
+        let mut raw_log: RawLog = log.clone().into();
+        // Logs get matched to their events by topic already, possibly hashed
+        // with a custom `TopicHasher`, whereas ethers checks its own topic
+        if !event.value.anonymous {
+            if let Some(topic) = raw_log.topics.first_mut() {
+                *topic = event.value.signature();
+            }
+        }
+
+        event
+            .value
+            .parse_log(raw_log)
+            .map(|log| log.params)
+            .map_err(|error| error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod log_decoder_test {
+    use std::collections::HashMap;
+
+    use ethers::abi::Token;
+    use ethers::types::{Block, Bytes, Chain, H160, H256, U256};
+
+    use super::*;
+    use crate::{Contract, Events};
+
+    const DEPOSIT_EVENT_ABI: &str = "event Deposit(uint256 amount)";
+    const CONTRACT_ADDRESS: &str = "0xBC4CA0EdA7647A8aB7C2061c2E118A18a936f13D";
+
+    struct PackedAmountLogDecoder;
+
+    impl LogDecoder for PackedAmountLogDecoder {
+        fn decode(&self, log: &Log, _event: &ContractEvent) -> Vec<LogParam> {
+            // Packed into 16 bytes, whereas the ABI pads uint256 to 32
+            let amount = U256::from_big_endian(&log.data[..16]);
+
+            vec![LogParam {
+                name: "amount".to_string(),
+                value: Token::Uint(amount),
+            }]
+        }
+    }
+
+    #[test]
+    fn decodes_logs_with_the_contract_log_decoder() {
+        let deposit_event = ContractEvent::new(DEPOSIT_EVENT_ABI);
+        let mut contract = Contract::new("PackedVault")
+            .add_address(CONTRACT_ADDRESS, &Chain::Mainnet, 0)
+            .with_log_decoder(PackedAmountLogDecoder);
+        contract.events.push(deposit_event.clone());
+
+        let log = packed_deposit_log(42);
+        assert!(deposit_event.value.parse_log(log.clone().into()).is_err());
+
+        let blocks_by_tx_hash = HashMap::from([(log.transaction_hash.unwrap(), Block::default())]);
+        let events = Events::new(&vec![log], &vec![contract], &blocks_by_tx_hash);
+
+        let params = events.first().unwrap().get_params();
+        assert_eq!(params.get("amount"), Some(&Token::Uint(U256::from(42))));
+    }
+
+    fn packed_deposit_log(amount: u128) -> Log {
+        Log {
+            address: CONTRACT_ADDRESS.parse::<H160>().unwrap(),
+            topics: vec![ContractEvent::new(DEPOSIT_EVENT_ABI).value.signature()],
+            data: Bytes::from(amount.to_be_bytes().to_vec()),
+            block_hash: Some(H256::from_low_u64_be(1)),
+            block_number: Some(1.into()),
+            transaction_hash: Some(H256::from_low_u64_be(2)),
+            transaction_index: Some(0.into()),
+            log_index: Some(0.into()),
+            removed: Some(false),
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::sync::Arc;
+
+use crate::{Config, ContractAddress};
+
+/// Called with every metric as it gets recorded, e.g. to forward it to
+/// Prometheus or StatsD with its labels.
+pub type OnMetric = Arc<dyn Fn(&Metric) + Send + Sync>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MetricKind {
+    /// Count of events ingested for a contract address in one batch
+    EventsIngested,
+    /// Blocks between a contract address's ingestion cursor and its chain's current block
+    IngestionLag,
+    /// Count of events handled for a contract address in one transaction
+    EventsHandled,
+    /// Failed attempts at handling a contract address's events, e.g. timed
+    /// out handlers or conflicting transactions, one at a time
+    HandlerErrors,
+    /// Blocks between a contract address's handling cursor and its ingestion
+    /// cursor, as of each handling run
+    HandlingLag,
+}
+
+/// Labels identifying the contract address a metric was recorded for.
+/// Their cardinality is bounded by the configured contract addresses: labels
+/// never carry per-event values such as block numbers or transaction hashes.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MetricLabels {
+    pub chain_id: i32,
+    pub contract_name: String,
+    pub address: String,
+}
+
+impl MetricLabels {
+    pub fn new(contract_address: &ContractAddress) -> Self {
+        Self {
+            chain_id: contract_address.get_chain_id(),
+            contract_name: contract_address.contract_name.clone(),
+            address: ContractAddress::normalize_address(&contract_address.address),
+        }
+    }
+
+    pub fn to_pairs(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("chain_id", self.chain_id.to_string()),
+            ("contract_name", self.contract_name.clone()),
+            ("address", self.address.clone()),
+        ]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Metric {
+    pub kind: MetricKind,
+    pub value: u64,
+    pub labels: MetricLabels,
+}
+
+impl Metric {
+    pub fn new(kind: MetricKind, value: u64, contract_address: &ContractAddress) -> Self {
+        Self {
+            kind,
+            value,
+            labels: MetricLabels::new(contract_address),
+        }
+    }
+}
+
+pub fn record_metric(
+    config: &Config,
+    kind: MetricKind,
+    value: u64,
+    contract_address: &ContractAddress,
+) {
+    if let Some(on_metric) = &config.on_metric {
+        on_metric(&Metric::new(kind, value, contract_address));
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use uuid::Uuid;
+
+use crate::Config;
+
+/// Called with every span of the ingest→handle pipeline once what it covers
+/// got committed. Only a hook: chaindexing does not depend on OpenTelemetry,
+/// so exporting spans, e.g. as OpenTelemetry ones, is up to it. See
+/// `PipelineSpan`.
+pub type OnPipelineSpan = Arc<dyn Fn(&PipelineSpan) + Send + Sync>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PipelineSpanKind {
+    /// Fetching a batch of a chain's logs through persisting their events
+    IngestBatch,
+    /// Running an event's handler, within its handling transaction. Only
+    /// reported once the transaction commits, so handlers re-run on conflicts
+    /// or timeouts leave no spans behind for their rolled back runs.
+    HandleEvent,
+}
+
+/// Events' ids identify the traces of their journeys through the pipeline,
+/// being 128 bits like OpenTelemetry trace ids, and persisted with the events
+/// so handlers pick them up without any other context. A batch's span belongs
+/// to the traces of all of its events, e.g. as span links, whereas an event's
+/// handling span belongs to its own trace only.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PipelineSpan {
+    pub id: Uuid,
+    pub kind: PipelineSpanKind,
+    pub chain_id: i32,
+    /// Ids of the batch's events, or of the handled event
+    pub event_ids: Vec<Uuid>,
+    pub started_at: NaiveDateTime,
+    pub duration: Duration,
+}
+
+impl PipelineSpan {
+    pub fn new(
+        kind: PipelineSpanKind,
+        chain_id: i32,
+        event_ids: Vec<Uuid>,
+        ended_at: NaiveDateTime,
+        duration: Duration,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            kind,
+            chain_id,
+            event_ids,
+            started_at: ended_at - chrono::Duration::from_std(duration).unwrap(),
+            duration,
+        }
+    }
+}
+
+pub fn record_pipeline_span(
+    config: &Config,
+    kind: PipelineSpanKind,
+    chain_id: i32,
+    event_ids: Vec<Uuid>,
+    duration: Duration,
+) {
+    if let Some(pipeline_span) = new_pipeline_span(config, kind, chain_id, event_ids, duration) {
+        record_pipeline_spans(config, &[pipeline_span]);
+    }
+}
+
+/// Ends a span to be recorded later, e.g. once its transaction commits. None
+/// without any `on_pipeline_span` to record it with.
+pub fn new_pipeline_span(
+    config: &Config,
+    kind: PipelineSpanKind,
+    chain_id: i32,
+    event_ids: Vec<Uuid>,
+    duration: Duration,
+) -> Option<PipelineSpan> {
+    config.on_pipeline_span.as_ref()?;
+
+    Some(PipelineSpan::new(
+        kind,
+        chain_id,
+        event_ids,
+        config.clock.now(),
+        duration,
+    ))
+}
+
+pub fn record_pipeline_spans(config: &Config, pipeline_spans: &[PipelineSpan]) {
+    if let Some(on_pipeline_span) = &config.on_pipeline_span {
+        for pipeline_span in pipeline_spans {
+            on_pipeline_span(pipeline_span);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+
+use crate::{
+    Chain, ChaindexingRepo, ChaindexingRepoConn, ChaindexingRepoRawQueryClient, Config,
+    EventHandlers, EventsIngester, EventsIngesterError, EventsIngesterJsonRpc, HandleEvents,
+    HasRawQueryClient, Repo,
+};
+
+/// How ingesting and handling events are scheduled relative to each other
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum PipelineMode {
+    /// Ingestion and handling run in independent loops, each on its own interval
+    #[default]
+    Decoupled,
+    /// A single loop handles each chain's ingested events right after ingesting
+    /// them, before ingesting any further, so that handlers never lag behind
+    /// ingestion, at the cost of throughput. Ticks every `ingestion_interval_ms`,
+    /// leaving `handler_interval_ms` and the adaptive ingestion interval unused.
+    Coupled,
+}
+
+pub struct CoupledPipeline;
+
+impl CoupledPipeline {
+    pub fn start(config: &Config) {
+        let config = config.clone();
+        tokio::spawn(async move {
+            let pool = config.repo.get_pool(1).await;
+            let conn = ChaindexingRepo::get_conn(&pool).await;
+            let conn = Arc::new(Mutex::new(conn));
+            let mut raw_query_client = config.repo.get_raw_query_client().await;
+            let mut shard_raw_query_clients =
+                HandleEvents::get_shard_raw_query_clients(&config).await;
+
+            Self::run(
+                conn,
+                &mut raw_query_client,
+                &mut shard_raw_query_clients,
+                |chain| Arc::new(config.get_json_rpc(chain)),
+                &config,
+            )
+            .await;
+        });
+    }
+
+    /// Ticks through the unpaused chains until the fatal error policy stops
+    /// the pipeline, if ever. See `FatalErrorPolicy`.
+    pub async fn run<'a, JsonRpc: EventsIngesterJsonRpc + 'static>(
+        conn: Arc<Mutex<ChaindexingRepoConn<'a>>>,
+        raw_query_client: &mut ChaindexingRepoRawQueryClient,
+        shard_raw_query_clients: &mut [ChaindexingRepoRawQueryClient],
+        get_json_rpc: impl Fn(&Chain) -> Arc<JsonRpc>,
+        config: &Config,
+    ) {
+        'pipeline: loop {
+            for chain in config.get_unpaused_chains().keys() {
+                let json_rpc = get_json_rpc(chain);
+
+                let tick = Self::tick(
+                    conn.clone(),
+                    raw_query_client,
+                    shard_raw_query_clients,
+                    json_rpc,
+                    chain,
+                    config,
+                )
+                .await;
+                if let Err(error) = tick {
+                    match config.fatal_error_policy.apply(chain, error) {
+                        Some(retry_delay) => {
+                            sleep(retry_delay).await;
+
+                            continue 'pipeline;
+                        }
+                        None => return,
+                    }
+                }
+            }
+
+            sleep(Duration::from_millis(config.ingestion_interval_ms)).await;
+        }
+    }
+
+    /// Fails, without handling anything, when ingestion failed without any
+    /// chain circuit breaker to absorb it
+    pub async fn tick<'a>(
+        conn: Arc<Mutex<ChaindexingRepoConn<'a>>>,
+        raw_query_client: &mut ChaindexingRepoRawQueryClient,
+        shard_raw_query_clients: &mut [ChaindexingRepoRawQueryClient],
+        json_rpc: Arc<impl EventsIngesterJsonRpc + 'static>,
+        chain: &Chain,
+        config: &Config,
+    ) -> Result<(), EventsIngesterError> {
+        let ingestion =
+            EventsIngester::ingest_unless_circuit_open(conn.clone(), json_rpc, chain, config).await;
+        // Failed ticks open the chain's circuit instead of stopping the pipeline
+        if config.chain_circuit_breakers.is_none() {
+            ingestion?;
+        }
+
+        EventHandlers::run_with_shard_clients(
+            conn,
+            config,
+            raw_query_client,
+            shard_raw_query_clients,
+        )
+        .await;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::{Chain, ReorgDepthStats, ReorgedBlock};
+
+/// Called with every chain's `ReorgReport`, once per reporting interval, e.g.
+/// to forward reorg frequency and depth to Prometheus or StatsD.
+pub type OnReorgReport = Arc<dyn Fn(&ReorgReport) + Send + Sync>;
+
+/// Reorgs a chain had over the last reporting interval
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReorgReport {
+    pub chain: Chain,
+    pub interval: Duration,
+    /// Including reconciled block ranges, which have no depth
+    pub count: u64,
+    pub max_depth: u64,
+}
+
+impl ReorgReport {
+    pub fn new(chain: &Chain, interval: Duration, reorged_blocks: &[ReorgedBlock]) -> Self {
+        Self {
+            chain: *chain,
+            interval,
+            count: reorged_blocks.len() as u64,
+            max_depth: ReorgDepthStats::new(reorged_blocks).max,
+        }
+    }
+}
+
+/// Reports each chain's reorgs once per `interval`, from the confirmation
+/// pass. Clones share the same reporting times, so they persist across ticks.
+#[derive(Clone)]
+pub struct ReorgReporter {
+    pub interval: Duration,
+    on_reorg_report: OnReorgReport,
+    last_reported_at: Arc<RwLock<HashMap<Chain, Instant>>>,
+}
+
+impl ReorgReporter {
+    pub fn new(interval: Duration, on_reorg_report: OnReorgReport) -> Self {
+        Self {
+            interval,
+            on_reorg_report,
+            last_reported_at: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Returns true on the chain's first tick, then whenever the interval
+    /// elapsed since its last report, which the chain is then due for
+    pub fn take_due(&self, chain: &Chain, now: Instant) -> bool {
+        let mut last_reported_at = self.last_reported_at.write().unwrap();
+
+        match last_reported_at.get(chain) {
+            Some(reported_at) if now.saturating_duration_since(*reported_at) < self.interval => {
+                false
+            }
+            _ => {
+                last_reported_at.insert(*chain, now);
+
+                true
+            }
+        }
+    }
+
+    pub fn report(&self, reorg_report: &ReorgReport) {
+        let ReorgReport {
+            chain,
+            interval,
+            count,
+            max_depth,
+        } = reorg_report;
+
+        if *count > 0 {
+            eprintln!(
+                "Chain Reorgs: {chain} had {count} reorgs up to depth {max_depth} in the last {interval:?}"
+            );
+        }
+
+        (self.on_reorg_report)(reorg_report);
+    }
+}
+
+#[cfg(test)]
+mod reorg_reporter_test {
+    use super::*;
+
+    #[test]
+    fn is_due_once_per_interval_and_chain() {
+        let reorg_reporter = ReorgReporter::new(Duration::from_secs(60), Arc::new(|_| {}));
+        let now = Instant::now();
+
+        assert!(reorg_reporter.take_due(&Chain::Mainnet, now));
+        assert!(!reorg_reporter.take_due(&Chain::Mainnet, now));
+        assert!(reorg_reporter.take_due(&Chain::Polygon, now));
+
+        let later = now + Duration::from_secs(59);
+        assert!(!reorg_reporter.clone().take_due(&Chain::Mainnet, later));
+
+        let later = now + Duration::from_secs(60);
+        assert!(reorg_reporter.take_due(&Chain::Mainnet, later));
+        assert!(!reorg_reporter.take_due(&Chain::Mainnet, later));
+    }
+}
@@ -1,6 +1,9 @@
+mod events_partitioning;
+mod migration_checksums;
 mod postgres_repo;
 mod repo;
 
+pub use events_partitioning::EventsPartitioning;
 pub use postgres_repo::{
     Conn as PostgresRepoConn, Pool as PostgresRepoPool, PostgresRepo, PostgresRepoAsyncConnection,
     PostgresRepoRawQueryClient, PostgresRepoRawQueryTxnClient,
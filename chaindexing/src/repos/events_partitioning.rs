@@ -0,0 +1,135 @@
+use std::collections::BTreeSet;
+
+use crate::{Event, SQLikeMigrations};
+
+/// Partitions `chaindexing_events`, e.g. for very large indexers, where a
+/// single table degrades queries and vacuuming. Inserts get routed to their
+/// partition by Postgres. Only applies to an events table created along with
+/// it, i.e. on a fresh database or with a reset.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventsPartitioning {
+    /// A partition per configured chain, created on setup
+    ByChain,
+    /// Partitions of `blocks_count` consecutive blocks each, created on
+    /// ingestion, right before inserting their first events
+    ByBlockRange(u64),
+}
+
+impl EventsPartitioning {
+    /// Run before the internal migrations, which then skip the events table
+    /// and its unique index as they already exist. Partitioned tables need
+    /// their partition key in their primary key and unique indexes.
+    pub fn get_create_events_migrations(&self, chain_ids: &[i32]) -> Vec<String> {
+        let partition_key = self.get_partition_key();
+        let partition_method = match self {
+            Self::ByChain => "LIST",
+            Self::ByBlockRange(_) => "RANGE",
+        };
+
+        let create_events = SQLikeMigrations::create_events()[0].replacen(
+            "id uuid PRIMARY KEY",
+            "id uuid NOT NULL",
+            1,
+        );
+        let create_events = create_events.trim_end().strip_suffix(')').unwrap().trim_end();
+
+        let mut migrations = vec![
+            format!(
+                "{create_events},
+                PRIMARY KEY (id, {partition_key})
+            ) PARTITION BY {partition_method} ({partition_key})"
+            ),
+            format!(
+                "CREATE UNIQUE INDEX IF NOT EXISTS chaindexing_events_unremoved_transaction_hash_log_index
+            ON chaindexing_events(transaction_hash,log_index,{partition_key}) WHERE removed = false"
+            ),
+        ];
+
+        if let Self::ByChain = self {
+            migrations.extend(chain_ids.iter().map(|chain_id| {
+                format!(
+                    "CREATE TABLE IF NOT EXISTS chaindexing_events_chain_{chain_id}
+                    PARTITION OF chaindexing_events FOR VALUES IN ({chain_id})"
+                )
+            }));
+        }
+
+        migrations
+    }
+
+    /// Creates the block range partitions the events fall in, if missing
+    pub fn get_create_partitions_migrations(&self, events: &Vec<Event>) -> Vec<String> {
+        let Self::ByBlockRange(blocks_count) = self else {
+            return vec![];
+        };
+        let blocks_count = (*blocks_count).max(1) as i64;
+
+        let from_block_numbers: BTreeSet<_> = events
+            .iter()
+            .map(|event| event.block_number - event.block_number % blocks_count)
+            .collect();
+
+        from_block_numbers
+            .into_iter()
+            .map(|from_block_number| {
+                let to_block_number = from_block_number + blocks_count;
+
+                format!(
+                    "CREATE TABLE IF NOT EXISTS chaindexing_events_blocks_{from_block_number}
+                    PARTITION OF chaindexing_events
+                    FOR VALUES FROM ({from_block_number}) TO ({to_block_number})"
+                )
+            })
+            .collect()
+    }
+
+    fn get_partition_key(&self) -> &'static str {
+        match self {
+            Self::ByChain => "chain_id",
+            Self::ByBlockRange(_) => "block_number",
+        }
+    }
+}
+
+#[cfg(test)]
+mod events_partitioning_test {
+    use crate::EventBuilder;
+
+    use super::*;
+
+    #[test]
+    fn includes_the_partition_key_in_the_primary_key_and_unique_index() {
+        let migrations = EventsPartitioning::ByChain.get_create_events_migrations(&[1, 137]);
+
+        assert!(migrations[0].starts_with("CREATE TABLE IF NOT EXISTS chaindexing_events ("));
+        assert!(migrations[0].contains("id uuid NOT NULL"));
+        assert!(migrations[0].contains("PRIMARY KEY (id, chain_id)"));
+        assert!(migrations[0].ends_with("PARTITION BY LIST (chain_id)"));
+        assert!(migrations[1].contains("(transaction_hash,log_index,chain_id)"));
+        assert_eq!(migrations.len(), 4);
+        assert!(migrations[3].contains("chaindexing_events_chain_137"));
+        assert!(migrations[3].ends_with("FOR VALUES IN (137)"));
+    }
+
+    #[test]
+    fn creates_block_range_partitions_on_ingestion_only() {
+        let events_partitioning = EventsPartitioning::ByBlockRange(1000);
+
+        assert_eq!(
+            events_partitioning.get_create_events_migrations(&[1]).len(),
+            2
+        );
+
+        let events: Vec<_> = [1500, 1999, 3000]
+            .into_iter()
+            .map(|block_number| EventBuilder::default().with_block_number(block_number).build())
+            .collect();
+        let migrations = events_partitioning.get_create_partitions_migrations(&events);
+
+        assert_eq!(migrations.len(), 2);
+        assert!(migrations[0].contains("chaindexing_events_blocks_1000"));
+        assert!(migrations[0].ends_with("FROM (1000) TO (2000)"));
+        assert!(migrations[1].ends_with("FROM (3000) TO (4000)"));
+        assert!(EventsPartitioning::ByChain.get_create_partitions_migrations(&events).is_empty());
+    }
+}
@@ -0,0 +1,153 @@
+use ethers::utils::{hex, keccak256};
+
+/// Checksum of an applied `IF NOT EXISTS` migration, which would otherwise be
+/// silently skipped after getting edited, since its object already exists.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationChecksum {
+    /// Name of the table, index or schema the migration creates, or of the
+    /// column it adds, qualified by its table
+    pub name: String,
+    /// Table the created object belongs to, for checksums to be dropped with it
+    pub table_name: String,
+    pub checksum: String,
+    /// Replaced rather than skipped when already applied, e.g. views, so edits
+    /// get applied right away instead of drifting
+    pub replaced: bool,
+}
+
+impl MigrationChecksum {
+    pub fn new(migration: &str) -> Option<Self> {
+        let migration = normalize(migration);
+
+        if let Some(view) = migration.strip_prefix("CREATE OR REPLACE VIEW ") {
+            let name = get_name(view);
+
+            return Some(Self {
+                table_name: name.clone(),
+                name,
+                checksum: hex::encode(keccak256(migration.as_bytes())),
+                replaced: true,
+            });
+        }
+
+        let (statement, object) = migration.split_once("IF NOT EXISTS ")?;
+        let name = get_name(object);
+        let (name, table_name) = match statement.strip_prefix("ALTER TABLE ") {
+            // Columns are only unique within their tables
+            Some(altered_table) => {
+                let table_name = get_name(altered_table);
+
+                (format!("{table_name}.{name}"), table_name)
+            }
+            None => match object.split_once(" ON ") {
+                Some((_, table)) => (name, get_name(table)),
+                None => (name.clone(), name),
+            },
+        };
+
+        Some(Self {
+            name,
+            table_name,
+            checksum: hex::encode(keccak256(migration.as_bytes())),
+            replaced: false,
+        })
+    }
+
+    /// Name of the dropped table or view
+    pub fn get_dropped_table_name(migration: &str) -> Option<String> {
+        let migration = normalize(migration);
+        let (_, table) = migration
+            .split_once("DROP TABLE IF EXISTS ")
+            .or_else(|| migration.split_once("DROP VIEW IF EXISTS "))?;
+
+        Some(get_name(table))
+    }
+}
+
+// Formatting changes are not schema changes
+fn normalize(migration: &str) -> String {
+    migration.split_ascii_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn get_name(object: &str) -> String {
+    object
+        .split(|c: char| c == '(' || c.is_ascii_whitespace())
+        .next()
+        .unwrap()
+        .to_string()
+}
+
+#[cfg(test)]
+mod migration_checksum_test {
+    use super::*;
+
+    #[test]
+    fn names_created_tables_and_indexes_after_their_tables() {
+        let table_checksum =
+            MigrationChecksum::new("CREATE TABLE IF NOT EXISTS nft_states(token_id INTEGER)")
+                .unwrap();
+        assert_eq!(table_checksum.name, "nft_states");
+        assert_eq!(table_checksum.table_name, "nft_states");
+
+        let index_checksum = MigrationChecksum::new(
+            "CREATE UNIQUE INDEX IF NOT EXISTS unique_nft_states ON nft_states(token_id)",
+        )
+        .unwrap();
+        assert_eq!(index_checksum.name, "unique_nft_states");
+        assert_eq!(index_checksum.table_name, "nft_states");
+    }
+
+    #[test]
+    fn names_added_columns_after_their_tables() {
+        let column_checksum = MigrationChecksum::new(
+            "ALTER TABLE chaindexing_events ADD COLUMN IF NOT EXISTS raw_log JSON NULL",
+        )
+        .unwrap();
+        assert_eq!(column_checksum.name, "chaindexing_events.raw_log");
+        assert_eq!(column_checksum.table_name, "chaindexing_events");
+    }
+
+    #[test]
+    fn ignores_formatting_but_not_edits() {
+        let checksum =
+            MigrationChecksum::new("CREATE TABLE IF NOT EXISTS nft_states (token_id INTEGER)");
+        let reformatted_checksum = MigrationChecksum::new(
+            "CREATE TABLE IF NOT EXISTS nft_states (
+                token_id INTEGER
+            )",
+        );
+        let edited_checksum =
+            MigrationChecksum::new("CREATE TABLE IF NOT EXISTS nft_states (token_id BIGINT)");
+
+        assert_eq!(checksum, reformatted_checksum);
+        assert_ne!(checksum, edited_checksum);
+    }
+
+    #[test]
+    fn replaces_views_instead_of_drifting() {
+        let view_checksum = MigrationChecksum::new(
+            "CREATE OR REPLACE VIEW nft_balances AS SELECT token_id FROM nft_states",
+        )
+        .unwrap();
+        assert_eq!(view_checksum.name, "nft_balances");
+        assert_eq!(view_checksum.table_name, "nft_balances");
+        assert!(view_checksum.replaced);
+
+        assert_eq!(
+            MigrationChecksum::get_dropped_table_name("DROP VIEW IF EXISTS nft_balances"),
+            Some("nft_balances".to_string())
+        );
+    }
+
+    #[test]
+    fn skips_migrations_without_if_not_exists() {
+        assert_eq!(
+            MigrationChecksum::new("UPDATE nft_states SET token_id = 1"),
+            None
+        );
+        assert_eq!(
+            MigrationChecksum::get_dropped_table_name("DROP TABLE IF EXISTS nft_states"),
+            Some("nft_states".to_string())
+        );
+    }
+}
@@ -5,20 +5,24 @@ mod raw_queries;
 
 use crate::{
     contracts::{ContractAddress, ContractAddressID, UnsavedContractAddress},
-    events::Event,
-    ReorgedBlock, ResetCount, Streamable, UnsavedReorgedBlock,
+    events::{Event, EventsCursor},
+    BlockNumber, Chain, EventsPartitioning, HandlerCheckpoint, ReorgDepthStats, ReorgedBlock,
+    ResetCount, Streamable, UnsavedReorgedBlock,
 };
 use diesel_async::RunQueryDsl;
 
 use diesel::{
     delete,
+    dsl::exists,
     result::{DatabaseErrorKind, Error as DieselError},
     upsert::excluded,
-    ExpressionMethods, QueryDsl,
+    BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl,
 };
 use diesel_async::{pooled_connection::AsyncDieselConnectionManager, AsyncPgConnection};
 use diesel_streamer::get_serial_table_async_stream;
 use futures_core::{future::BoxFuture, Stream};
+use futures_util::stream;
+use serde::Serialize;
 use tokio::sync::Mutex;
 use uuid::Uuid;
 
@@ -40,6 +44,9 @@ impl From<DieselError> for RepoError {
             DieselError::DatabaseError(DatabaseErrorKind::ClosedConnection, _info) => {
                 RepoError::NotConnected
             }
+            DieselError::DatabaseError(DatabaseErrorKind::SerializationFailure, info) => {
+                RepoError::Conflict(info.message().to_string())
+            }
             any_other_error => RepoError::Unknown(any_other_error.to_string()),
         }
     }
@@ -50,6 +57,10 @@ pub struct PostgresRepo {
     url: String,
 }
 
+// Postgres caps each statement's bind parameters, one per inserted column value
+const MAX_BIND_PARAMETERS: usize = u16::MAX as usize;
+const CONTRACT_ADDRESS_COLUMNS_COUNT: usize = 6;
+
 type PgPooledConn<'a> = bb8::PooledConnection<'a, AsyncDieselConnectionManager<AsyncPgConnection>>;
 
 #[async_trait::async_trait]
@@ -94,14 +105,18 @@ impl Repo for PostgresRepo {
             address, chaindexing_contract_addresses,
         };
 
-        diesel::insert_into(chaindexing_contract_addresses)
-            .values(contract_addresses)
-            .on_conflict(address)
-            .do_update()
-            .set(address.eq(excluded(address)))
-            .execute(conn)
-            .await
-            .unwrap();
+        for contract_addresses in
+            contract_addresses.chunks(MAX_BIND_PARAMETERS / CONTRACT_ADDRESS_COLUMNS_COUNT)
+        {
+            diesel::insert_into(chaindexing_contract_addresses)
+                .values(contract_addresses)
+                .on_conflict(address)
+                .do_update()
+                .set(address.eq(excluded(address)))
+                .execute(conn)
+                .await
+                .unwrap();
+        }
     }
 
     async fn get_all_contract_addresses<'a>(conn: &mut Conn<'a>) -> Vec<ContractAddress> {
@@ -110,6 +125,20 @@ impl Repo for PostgresRepo {
         chaindexing_contract_addresses.load(conn).await.unwrap()
     }
 
+    async fn get_contract_address_by_id<'a>(
+        conn: &mut Conn<'a>,
+        ContractAddressID(contract_address_id): ContractAddressID,
+    ) -> Option<ContractAddress> {
+        use crate::diesels::schema::chaindexing_contract_addresses::dsl::*;
+
+        chaindexing_contract_addresses
+            .find(contract_address_id)
+            .first(conn)
+            .await
+            .optional()
+            .unwrap()
+    }
+
     async fn create_events<'a>(conn: &mut Conn<'a>, events: &Vec<Event>) {
         use crate::diesels::schema::chaindexing_events::dsl::*;
 
@@ -119,6 +148,15 @@ impl Repo for PostgresRepo {
             .await
             .unwrap();
     }
+    async fn create_events_partitions<'a>(
+        conn: &mut Conn<'a>,
+        events_partitioning: &EventsPartitioning,
+        events: &Vec<Event>,
+    ) {
+        for migration in events_partitioning.get_create_partitions_migrations(events) {
+            diesel::sql_query(migration).execute(conn).await.unwrap();
+        }
+    }
     async fn get_all_events<'a>(conn: &mut Conn<'a>) -> Vec<Event> {
         use crate::diesels::schema::chaindexing_events::dsl::*;
 
@@ -127,14 +165,170 @@ impl Repo for PostgresRepo {
     async fn get_events<'a>(
         conn: &mut Self::Conn<'a>,
         address: String,
-        from: u64,
-        to: u64,
+        address_chain_id: i32,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> Vec<Event> {
+        use crate::diesels::schema::chaindexing_events::dsl::*;
+
+        let from = i64::try_from(from).unwrap();
+        let to = i64::try_from(to).unwrap();
+
+        chaindexing_events
+            .filter(contract_address.eq(ContractAddress::normalize_address(&address)))
+            .filter(chain_id.eq(address_chain_id))
+            .filter(block_number.between(from, to))
+            .filter(removed.eq(false))
+            .load(conn)
+            .await
+            .unwrap()
+    }
+    async fn has_events_for_abis<'a>(
+        conn: &mut Self::Conn<'a>,
+        address: &str,
+        address_chain_id: i32,
+        abis: &Vec<String>,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> bool {
+        use crate::diesels::schema::chaindexing_events::dsl::*;
+
+        let from = i64::try_from(from).unwrap();
+        let to = i64::try_from(to).unwrap();
+
+        diesel::select(exists(
+            chaindexing_events
+                .filter(contract_address.eq(ContractAddress::normalize_address(address)))
+                .filter(chain_id.eq(address_chain_id))
+                .filter(abi.eq_any(abis))
+                .filter(block_number.between(from, to))
+                .filter(removed.eq(false)),
+        ))
+        .get_result(conn)
+        .await
+        .unwrap()
+    }
+    /// Events of every contract emitted by the transaction, ordered by log index
+    async fn get_events_by_tx_hash<'a>(conn: &mut Self::Conn<'a>, tx_hash: &str) -> Vec<Event> {
+        use crate::diesels::schema::chaindexing_events::dsl::*;
+
+        chaindexing_events
+            .filter(transaction_hash.eq(tx_hash.to_lowercase()))
+            .filter(removed.eq(false))
+            .order(log_index.asc())
+            .load(conn)
+            .await
+            .unwrap()
+    }
+    async fn get_events_page_by_contract_name<'a>(
+        conn: &mut Self::Conn<'a>,
+        name: &str,
+        after_event_id: Option<Uuid>,
+        limit: i64,
+    ) -> Vec<Event> {
+        use crate::diesels::schema::chaindexing_events::dsl::*;
+
+        // Starts before the first event, without an id, since ids are never nil
+        let after_event_id = after_event_id.unwrap_or(Uuid::nil());
+
+        chaindexing_events
+            .filter(contract_name.eq(name))
+            .filter(id.gt(after_event_id))
+            .order(id.asc())
+            .limit(limit)
+            .load(conn)
+            .await
+            .unwrap()
+    }
+    async fn get_events_where<'a>(
+        conn: &mut Self::Conn<'a>,
+        address: &str,
+        json_path: &[&str],
+        value: impl Serialize + Send,
+    ) -> Vec<Event> {
+        use crate::diesels::schema::chaindexing_events::dsl::*;
+        use diesel::sql_types::{Array, Bool, Jsonb, Text};
+
+        let json_path: Vec<_> = json_path.iter().map(|key| key.to_string()).collect();
+        let has_value_at_json_path = diesel::dsl::sql::<Bool>("parameters::jsonb #> ")
+            .bind::<Array<Text>, _>(json_path)
+            .sql(" = ")
+            .bind::<Jsonb, _>(serde_json::to_value(value).unwrap());
+
+        chaindexing_events
+            .filter(contract_address.eq(ContractAddress::normalize_address(address)))
+            .filter(removed.eq(false))
+            .filter(has_value_at_json_path)
+            .order((block_number.asc(), log_index.asc()))
+            .load(conn)
+            .await
+            .unwrap()
+    }
+    async fn paginate_events<'a>(
+        conn: &mut Self::Conn<'a>,
+        address: &str,
+        cursor: Option<EventsCursor>,
+        limit: i64,
+    ) -> (Vec<Event>, Option<EventsCursor>) {
+        use crate::diesels::schema::chaindexing_events::dsl::*;
+
+        // Starts before the first event, without a cursor
+        let EventsCursor {
+            block_number: cursor_block_number,
+            transaction_index: cursor_transaction_index,
+            log_index: cursor_log_index,
+        } = cursor.unwrap_or(EventsCursor {
+            block_number: -1,
+            transaction_index: -1,
+            log_index: -1,
+        });
+
+        // The extra event tells whether there is a next page
+        let mut events: Vec<Event> = chaindexing_events
+            .filter(contract_address.eq(ContractAddress::normalize_address(&address)))
+            .filter(removed.eq(false))
+            .filter(
+                block_number
+                    .gt(cursor_block_number)
+                    .or(block_number.eq(cursor_block_number).and(
+                        transaction_index.gt(cursor_transaction_index).or(transaction_index
+                            .eq(cursor_transaction_index)
+                            .and(log_index.gt(cursor_log_index))),
+                    )),
+            )
+            .order((block_number.asc(), transaction_index.asc(), log_index.asc()))
+            .limit(limit + 1)
+            .load(conn)
+            .await
+            .unwrap();
+
+        if events.len() as i64 > limit {
+            events.truncate(limit as usize);
+            let next_cursor = events.last().map(EventsCursor::new);
+
+            (events, next_cursor)
+        } else {
+            (events, None)
+        }
+    }
+    async fn get_events_since<'a>(
+        conn: &mut Self::Conn<'a>,
+        timestamp: i64,
+        limit: i64,
     ) -> Vec<Event> {
         use crate::diesels::schema::chaindexing_events::dsl::*;
 
         chaindexing_events
-            .filter(contract_address.eq(address.to_lowercase()))
-            .filter(block_number.between(from as i64, to as i64))
+            .filter(block_timestamp.ge(timestamp))
+            .filter(removed.eq(false))
+            .order((
+                block_timestamp.asc(),
+                chain_id.asc(),
+                block_number.asc(),
+                transaction_index.asc(),
+                log_index.asc(),
+            ))
+            .limit(limit)
             .load(conn)
             .await
             .unwrap()
@@ -144,6 +338,20 @@ impl Repo for PostgresRepo {
 
         delete(chaindexing_events).filter(id.eq_any(ids)).execute(conn).await.unwrap();
     }
+    async fn soft_delete_events_by_ids<'a>(
+        conn: &mut Self::Conn<'a>,
+        ids: &Vec<Uuid>,
+        by_reorged_block_id: i32,
+    ) {
+        use crate::diesels::schema::chaindexing_events::dsl::*;
+
+        diesel::update(chaindexing_events)
+            .filter(id.eq_any(ids))
+            .set((removed.eq(true), reorged_block_id.eq(by_reorged_block_id)))
+            .execute(conn)
+            .await
+            .unwrap();
+    }
 
     async fn update_next_block_number_to_ingest_from<'a>(
         conn: &mut Self::Conn<'a>,
@@ -175,17 +383,62 @@ impl Repo for PostgresRepo {
             .unwrap();
     }
 
+    async fn update_handling_cursors<'a>(
+        conn: &mut Conn<'a>,
+        handling_cursors: Vec<(ContractAddressID, i64)>,
+    ) {
+        use crate::diesels::schema::chaindexing_contract_addresses::dsl::*;
+        use crate::diesels::schema::chaindexing_handler_checkpoints::dsl as checkpoints;
+
+        conn.transaction::<(), DieselError, _>(|conn| {
+            async move {
+                for (ContractAddressID(contract_address_id), block_number) in handling_cursors {
+                    diesel::update(chaindexing_contract_addresses)
+                        .filter(id.eq(contract_address_id))
+                        .set(next_block_number_to_handle_from.eq(block_number))
+                        .execute(conn)
+                        .await?;
+
+                    delete(checkpoints::chaindexing_handler_checkpoints)
+                        .filter(checkpoints::contract_address_id.eq(contract_address_id))
+                        .filter(checkpoints::block_number.ge(block_number))
+                        .execute(conn)
+                        .await?;
+                }
+
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+        .unwrap();
+    }
+
+    async fn get_handler_checkpoint<'a>(
+        conn: &mut Self::Conn<'a>,
+        ContractAddressID(checkpointed_contract_address_id): ContractAddressID,
+    ) -> Option<HandlerCheckpoint> {
+        use crate::diesels::schema::chaindexing_handler_checkpoints::dsl::*;
+
+        chaindexing_handler_checkpoints
+            .filter(contract_address_id.eq(checkpointed_contract_address_id))
+            .first(conn)
+            .await
+            .optional()
+            .unwrap()
+    }
+
     async fn create_reorged_block<'a>(
         conn: &mut Self::Conn<'a>,
         reorged_block: &UnsavedReorgedBlock,
-    ) {
+    ) -> ReorgedBlock {
         use crate::diesels::schema::chaindexing_reorged_blocks::dsl::*;
 
         diesel::insert_into(chaindexing_reorged_blocks)
             .values(reorged_block)
-            .execute(conn)
+            .get_result(conn)
             .await
-            .unwrap();
+            .unwrap()
     }
 
     async fn get_unhandled_reorged_blocks<'a>(conn: &mut Self::Conn<'a>) -> Vec<ReorgedBlock> {
@@ -198,6 +451,56 @@ impl Repo for PostgresRepo {
             .unwrap()
     }
 
+    async fn reorg_depth_stats<'a>(
+        conn: &mut Self::Conn<'a>,
+        chain: &Chain,
+        window: u64,
+    ) -> ReorgDepthStats {
+        use crate::diesels::schema::chaindexing_reorged_blocks::dsl::*;
+
+        let reorged_blocks: Vec<ReorgedBlock> = chaindexing_reorged_blocks
+            .filter(chain_id.eq(*chain as i32))
+            .filter(current_block_number.is_not_null())
+            .order(id.desc())
+            .limit(i64::try_from(window).unwrap())
+            .load(conn)
+            .await
+            .unwrap();
+
+        ReorgDepthStats::new(&reorged_blocks)
+    }
+
+    async fn get_reorged_blocks_since<'a>(
+        conn: &mut Self::Conn<'a>,
+        chain: &Chain,
+        since: chrono::NaiveDateTime,
+    ) -> Vec<ReorgedBlock> {
+        use crate::diesels::schema::chaindexing_reorged_blocks::dsl::*;
+
+        chaindexing_reorged_blocks
+            .filter(chain_id.eq(*chain as i32))
+            .filter(inserted_at.ge(since))
+            .load(conn)
+            .await
+            .unwrap()
+    }
+
+    async fn delete_handled_reorged_blocks_before<'a>(
+        conn: &mut Self::Conn<'a>,
+        chain: &Chain,
+        before: chrono::NaiveDateTime,
+    ) {
+        use crate::diesels::schema::chaindexing_reorged_blocks::dsl::*;
+
+        diesel::delete(chaindexing_reorged_blocks)
+            .filter(chain_id.eq(*chain as i32))
+            .filter(handled_at.is_not_null())
+            .filter(inserted_at.lt(before))
+            .execute(conn)
+            .await
+            .unwrap();
+    }
+
     async fn create_reset_count<'a>(conn: &mut Self::Conn<'a>) {
         use crate::diesels::schema::chaindexing_reset_counts::dsl::*;
 
@@ -252,4 +555,57 @@ impl Streamable for PostgresRepo {
             Some(from)
         )
     }
+
+    /// Like `get_events_stream`, but only streams events with the given ABIs,
+    /// in chunks of at most `chunk_size` events ordered by block and log index.
+    fn get_events_stream_for_abis<'a>(
+        conn: Arc<Mutex<Self::StreamConn<'a>>>,
+        from: i64,
+        abis: Vec<String>,
+        chunk_size: i64,
+    ) -> Box<dyn Stream<Item = Vec<Event>> + Send + Unpin + 'a> {
+        use crate::diesels::schema::chaindexing_events::dsl::*;
+
+        // Paginates in on-chain order, by (block_number, transaction_index,
+        // log_index), so that chunks can split a block's events without
+        // skipping or repeating any of them
+        let events_stream = stream::unfold(
+            (from, -1, -1),
+            move |(last_block_number, last_transaction_index, last_log_index)| {
+                let conn = conn.clone();
+                let abis = abis.clone();
+
+                async move {
+                    let mut conn = conn.lock().await;
+
+                    let events: Vec<Event> = chaindexing_events
+                        .filter(abi.eq_any(&abis))
+                        .filter(
+                            block_number.gt(last_block_number).or(block_number
+                                .eq(last_block_number)
+                                .and(
+                                    transaction_index.gt(last_transaction_index).or(
+                                        transaction_index
+                                            .eq(last_transaction_index)
+                                            .and(log_index.gt(last_log_index)),
+                                    ),
+                                )),
+                        )
+                        .order((block_number.asc(), transaction_index.asc(), log_index.asc()))
+                        .limit(chunk_size)
+                        .load(&mut *conn)
+                        .await
+                        .unwrap();
+
+                    let last_event = events
+                        .last()
+                        .map(|e| (e.block_number, e.transaction_index, e.log_index))?;
+
+                    Some((events, last_event))
+                }
+            },
+        );
+
+        Box::new(Box::pin(events_stream))
+    }
 }
@@ -22,6 +22,13 @@ impl RepoMigrations for PostgresRepo {
         SQLikeMigrations::drop_reorged_blocks()
     }
 
+    fn create_handler_checkpoints_migration() -> &'static [&'static str] {
+        SQLikeMigrations::create_handler_checkpoints()
+    }
+    fn drop_handler_checkpoints_migration() -> &'static [&'static str] {
+        SQLikeMigrations::drop_handler_checkpoints()
+    }
+
     fn create_reset_counts_migration() -> &'static [&'static str] {
         SQLikeMigrations::create_reset_counts()
     }
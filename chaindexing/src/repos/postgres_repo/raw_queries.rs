@@ -1,16 +1,57 @@
-use tokio_postgres::{types::ToSql, Client, NoTls, Transaction};
+use std::ops::Deref;
+use std::sync::Mutex;
+
+use tokio_postgres::{error::SqlState, types::ToSql, Client, NoTls, Transaction};
 
 use crate::contracts::ContractAddressID;
-use crate::{ExecutesWithRawQuery, HasRawQueryClient, LoadsDataWithRawQuery, PostgresRepo};
+use crate::{
+    ExecutesWithRawQuery, HasRawQueryClient, LoadsDataWithRawQuery, PostgresRepo, RepoError,
+};
 use serde::de::DeserializeOwned;
 
 pub type PostgresRepoRawQueryClient = Client;
-pub type PostgresRepoRawQueryTxnClient<'a> = Transaction<'a>;
+
+/// Transaction keeping hold of the first conflict its statements ran into.
+/// Statements report conflicts as soon as they run, not only on commit, e.g.
+/// within handlers, which cannot return them. Statements are skipped from
+/// then on, and committing fails with the conflict instead.
+pub struct PostgresRepoRawQueryTxnClient<'a> {
+    txn: Transaction<'a>,
+    conflict: Mutex<Option<String>>,
+}
+
+impl<'a> PostgresRepoRawQueryTxnClient<'a> {
+    fn check_conflict(&self) -> Result<(), RepoError> {
+        match self.conflict.lock().unwrap().clone() {
+            Some(conflict) => Err(RepoError::Conflict(conflict)),
+            None => Ok(()),
+        }
+    }
+
+    fn keep_conflict<T>(&self, result: Result<T, tokio_postgres::Error>) -> Result<T, RepoError> {
+        result.map_err(|error| match to_repo_error(error) {
+            RepoError::Conflict(conflict) => {
+                let mut kept_conflict = self.conflict.lock().unwrap();
+
+                RepoError::Conflict(kept_conflict.get_or_insert(conflict).clone())
+            }
+            repo_error => repo_error,
+        })
+    }
+}
+
+impl<'a> Deref for PostgresRepoRawQueryTxnClient<'a> {
+    type Target = Transaction<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.txn
+    }
+}
 
 #[async_trait::async_trait]
 impl HasRawQueryClient for PostgresRepo {
     type RawQueryClient = Client;
-    type RawQueryTxnClient<'a> = Transaction<'a>;
+    type RawQueryTxnClient<'a> = PostgresRepoRawQueryTxnClient<'a>;
 
     async fn get_raw_query_client(&self) -> Self::RawQueryClient {
         let (client, conn) = tokio_postgres::connect(&self.url, NoTls).await.unwrap();
@@ -22,7 +63,10 @@ impl HasRawQueryClient for PostgresRepo {
     async fn get_raw_query_txn_client<'a>(
         client: &'a mut Self::RawQueryClient,
     ) -> Self::RawQueryTxnClient<'a> {
-        client.transaction().await.unwrap()
+        PostgresRepoRawQueryTxnClient {
+            txn: client.transaction().await.unwrap(),
+            conflict: Mutex::new(None),
+        }
     }
 }
 
@@ -32,26 +76,78 @@ impl ExecutesWithRawQuery for PostgresRepo {
         client.execute(query, &[] as &[&(dyn ToSql + Sync)]).await.unwrap();
     }
     async fn execute_raw_query_in_txn<'a>(txn_client: &Self::RawQueryTxnClient<'a>, query: &str) {
-        txn_client.execute(query, &[] as &[&(dyn ToSql + Sync)]).await.unwrap();
+        match Self::try_execute_raw_query_in_txn(txn_client, query).await {
+            Ok(()) | Err(RepoError::Conflict(_)) => {}
+            Err(repo_error) => panic!("{repo_error:?}"),
+        }
+    }
+    async fn try_execute_raw_query_in_txn<'a>(
+        txn_client: &Self::RawQueryTxnClient<'a>,
+        query: &str,
+    ) -> Result<(), RepoError> {
+        txn_client.check_conflict()?;
+
+        let execution = txn_client.execute(query, &[] as &[&(dyn ToSql + Sync)]).await;
+
+        txn_client.keep_conflict(execution).map(|_| ())
+    }
+    async fn get_raw_query_txn_conflict<'a>(
+        txn_client: &Self::RawQueryTxnClient<'a>,
+    ) -> Result<(), RepoError> {
+        txn_client.check_conflict()
     }
     async fn commit_raw_query_txns<'a>(client: Self::RawQueryTxnClient<'a>) {
-        client.commit().await.unwrap();
+        Self::try_commit_raw_query_txns(client).await.unwrap();
+    }
+    async fn try_commit_raw_query_txns<'a>(
+        client: Self::RawQueryTxnClient<'a>,
+    ) -> Result<(), RepoError> {
+        if let Err(conflict) = client.check_conflict() {
+            client.txn.rollback().await.map_err(to_repo_error)?;
+
+            return Err(conflict);
+        }
+
+        client.txn.commit().await.map_err(to_repo_error)
+    }
+    async fn rollback_raw_query_txns<'a>(client: Self::RawQueryTxnClient<'a>) {
+        client.txn.rollback().await.unwrap();
     }
 
     async fn update_next_block_number_to_handle_from_in_txn<'a>(
         client: &Self::RawQueryTxnClient<'a>,
         ContractAddressID(contract_address_id): ContractAddressID,
         block_number: i64,
-    ) {
+    ) -> Result<(), RepoError> {
         let query = format!(
             "UPDATE chaindexing_contract_addresses 
         SET next_block_number_to_handle_from = {block_number}
         WHERE id = {contract_address_id}"
         );
 
-        Self::execute_raw_query_in_txn(client, &query).await;
+        Self::try_execute_raw_query_in_txn(client, &query).await
+    }
+
+    async fn update_handler_checkpoint_in_txn<'a>(
+        client: &Self::RawQueryTxnClient<'a>,
+        ContractAddressID(contract_address_id): ContractAddressID,
+        block_number: i64,
+        transaction_index: i64,
+        log_index: i64,
+    ) -> Result<(), RepoError> {
+        let query = format!(
+            "INSERT INTO chaindexing_handler_checkpoints (contract_address_id, block_number, transaction_index, log_index)
+        VALUES ({contract_address_id}, {block_number}, {transaction_index}, {log_index})
+        ON CONFLICT (contract_address_id) DO UPDATE
+        SET block_number = excluded.block_number, transaction_index = excluded.transaction_index,
+        log_index = excluded.log_index, updated_at = NOW()"
+        );
+
+        Self::try_execute_raw_query_in_txn(client, &query).await
     }
 
+    /// Rewound handling cursors drop their checkpoints, which would otherwise
+    /// point past them.
     async fn update_every_next_block_number_to_handle_from_in_txn<'a>(
         client: &Self::RawQueryTxnClient<'a>,
         chain_id: i32,
@@ -64,11 +160,55 @@ impl ExecutesWithRawQuery for PostgresRepo {
         );
 
         Self::execute_raw_query_in_txn(client, &query).await;
+
+        let query = format!(
+            "DELETE FROM chaindexing_handler_checkpoints
+        WHERE contract_address_id IN (
+            SELECT id FROM chaindexing_contract_addresses WHERE chain_id = {chain_id}
+        )"
+        );
+
+        Self::execute_raw_query_in_txn(client, &query).await;
+    }
+
+    /// Rewinds handling to the given block, but never behind an address' start
+    /// block nor ahead of what has been ingested so far.
+    async fn update_every_next_block_number_to_handle_from_for_contracts_in_txn<'a>(
+        client: &Self::RawQueryTxnClient<'a>,
+        contract_names: &Vec<String>,
+        block_number: i64,
+    ) {
+        let contract_names = contract_names
+            .iter()
+            .map(|name| format!("'{name}'"))
+            .collect::<Vec<String>>()
+            .join(",");
+
+        let query = format!(
+            "UPDATE chaindexing_contract_addresses 
+        SET next_block_number_to_handle_from = LEAST(
+            next_block_number_to_ingest_from,
+            GREATEST(start_block_number, {block_number})
+        )
+        WHERE contract_name IN ({contract_names})"
+        );
+
+        Self::execute_raw_query_in_txn(client, &query).await;
+
+        let query = format!(
+            "DELETE FROM chaindexing_handler_checkpoints
+        WHERE contract_address_id IN (
+            SELECT id FROM chaindexing_contract_addresses WHERE contract_name IN ({contract_names})
+        )"
+        );
+
+        Self::execute_raw_query_in_txn(client, &query).await;
     }
 
     async fn update_reorged_blocks_as_handled_in_txn<'a>(
         client: &Self::RawQueryTxnClient<'a>,
         reorged_block_ids: &Vec<i32>,
+        handled_at: chrono::NaiveDateTime,
     ) {
         let query = format!(
             "UPDATE chaindexing_reorged_blocks
@@ -79,7 +219,7 @@ impl ExecutesWithRawQuery for PostgresRepo {
                 .map(|id| id.to_string())
                 .collect::<Vec<String>>()
                 .join(","),
-            handled_at = chrono::Utc::now().naive_utc().to_string(),
+            handled_at = handled_at.to_string(),
         );
 
         Self::execute_raw_query_in_txn(client, &query).await;
@@ -123,16 +263,31 @@ impl LoadsDataWithRawQuery for PostgresRepo {
         }
     }
 
+    /// Loads nothing once the transaction ran into a conflict, since it
+    /// will not commit anyway
     async fn load_data_list_from_raw_query_with_txn_client<'a, Data: Send + DeserializeOwned>(
         txn_client: &Self::RawQueryTxnClient<'a>,
         query: &str,
     ) -> Vec<Data> {
-        let json_aggregate = get_json_aggregate_in_txn(txn_client, query).await;
+        match Self::try_load_data_list_from_raw_query_with_txn_client(txn_client, query).await {
+            Ok(data_list) => data_list,
+            Err(RepoError::Conflict(_)) => vec![],
+            Err(repo_error) => panic!("{repo_error:?}"),
+        }
+    }
+    async fn try_load_data_list_from_raw_query_with_txn_client<
+        'a,
+        Data: Send + DeserializeOwned,
+    >(
+        txn_client: &Self::RawQueryTxnClient<'a>,
+        query: &str,
+    ) -> Result<Vec<Data>, RepoError> {
+        let json_aggregate = get_json_aggregate_in_txn(txn_client, query).await?;
 
         if json_aggregate.is_object() || json_aggregate.is_array() {
-            serde_json::from_value(json_aggregate).unwrap()
+            Ok(serde_json::from_value(json_aggregate).unwrap())
         } else {
-            vec![]
+            Ok(vec![])
         }
     }
 }
@@ -145,9 +300,25 @@ async fn get_json_aggregate(client: &PostgresRepoRawQueryClient, query: &str) ->
 async fn get_json_aggregate_in_txn<'a>(
     txn_client: &PostgresRepoRawQueryTxnClient<'a>,
     query: &str,
-) -> serde_json::Value {
-    let rows = txn_client.query(json_aggregate_query(query).as_str(), &[]).await.unwrap();
-    rows.first().unwrap().get(0)
+) -> Result<serde_json::Value, RepoError> {
+    txn_client.check_conflict()?;
+
+    let rows = txn_client.query(json_aggregate_query(query).as_str(), &[]).await;
+    let rows = txn_client.keep_conflict(rows)?;
+
+    Ok(rows.first().unwrap().get(0))
+}
+
+fn to_repo_error(error: tokio_postgres::Error) -> RepoError {
+    let is_conflict = error.code().is_some_and(|code| {
+        *code == SqlState::T_R_SERIALIZATION_FAILURE || *code == SqlState::T_R_DEADLOCK_DETECTED
+    });
+
+    if is_conflict {
+        RepoError::Conflict(error.to_string())
+    } else {
+        RepoError::Unknown(error.to_string())
+    }
 }
 
 fn json_aggregate_query(query: &str) -> String {
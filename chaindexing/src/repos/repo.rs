@@ -1,20 +1,27 @@
 use derive_more::Display;
+use std::collections::HashMap;
 use std::sync::Arc;
 use uuid::Uuid;
 
 use futures_core::{future::BoxFuture, Stream};
 use serde::de::DeserializeOwned;
+use serde::Serialize;
 use tokio::sync::Mutex;
 
+use super::migration_checksums::MigrationChecksum;
+
 use crate::{
     contracts::{ContractAddressID, UnsavedContractAddress},
-    events::Event,
-    ContractAddress, ReorgedBlock, ResetCount, UnsavedReorgedBlock,
+    events::{Event, EventsCursor},
+    BlockNumber, Chain, ContractAddress, EventsPartitioning, HandlerCheckpoint, ReorgDepthStats,
+    ReorgedBlock, ResetCount, UnsavedReorgedBlock,
 };
 
 #[derive(Debug, Display)]
 pub enum RepoError {
     NotConnected,
+    /// Serialization failure or deadlock, which can succeed when retried
+    Conflict(String),
     Unknown(String),
 }
 
@@ -44,16 +51,79 @@ pub trait Repo:
         contract_addresses: &Vec<UnsavedContractAddress>,
     );
     async fn get_all_contract_addresses<'a>(conn: &mut Self::Conn<'a>) -> Vec<ContractAddress>;
+    async fn get_contract_address_by_id<'a>(
+        conn: &mut Self::Conn<'a>,
+        contract_address_id: ContractAddressID,
+    ) -> Option<ContractAddress>;
 
     async fn create_events<'a>(conn: &mut Self::Conn<'a>, events: &Vec<Event>);
     async fn get_all_events<'a>(conn: &mut Self::Conn<'a>) -> Vec<Event>;
     async fn get_events<'a>(
         conn: &mut Self::Conn<'a>,
         address: String,
-        from: u64,
-        to: u64,
+        chain_id: i32,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> Vec<Event>;
+    /// Whether the contract address has events of the given ABIs within the
+    /// (inclusive) block range, leaving out removed ones
+    async fn has_events_for_abis<'a>(
+        conn: &mut Self::Conn<'a>,
+        address: &str,
+        chain_id: i32,
+        abis: &Vec<String>,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> bool;
+    async fn get_events_by_tx_hash<'a>(conn: &mut Self::Conn<'a>, tx_hash: &str) -> Vec<Event>;
+    /// Returns up to `limit` events of the contract after the event of the
+    /// given id, ordered by id, including removed ones, to page through them
+    async fn get_events_page_by_contract_name<'a>(
+        conn: &mut Self::Conn<'a>,
+        contract_name: &str,
+        after_event_id: Option<Uuid>,
+        limit: i64,
+    ) -> Vec<Event>;
+    /// Events of the contract address, on any chain, whose decoded parameters
+    /// hold the value, serialized to JSON, at the path, filtered by the
+    /// database and leaving out removed ones. Parameters are stored as tokens,
+    /// e.g. `Transfer`s to an address are at `["to"]` with the value
+    /// `Token::Address(to)`, or at `["to", "Address"]` with its hex string.
+    async fn get_events_where<'a>(
+        conn: &mut Self::Conn<'a>,
+        address: &str,
+        json_path: &[&str],
+        value: impl Serialize + Send,
+    ) -> Vec<Event>;
+    /// Returns up to `limit` events of the contract address after the cursor,
+    /// from its first one without a cursor, along with the cursor of the next
+    /// page, unless this one is the last.
+    async fn paginate_events<'a>(
+        conn: &mut Self::Conn<'a>,
+        address: &str,
+        cursor: Option<EventsCursor>,
+        limit: i64,
+    ) -> (Vec<Event>, Option<EventsCursor>);
+    /// Returns up to `limit` events of blocks from the timestamp onwards, e.g.
+    /// the last hour's, across chains and contracts, oldest first
+    async fn get_events_since<'a>(
+        conn: &mut Self::Conn<'a>,
+        timestamp: i64,
+        limit: i64,
     ) -> Vec<Event>;
+    /// Creates the missing partitions of the events, with `EventsPartitioning`
+    /// creating them on ingestion
+    async fn create_events_partitions<'a>(
+        conn: &mut Self::Conn<'a>,
+        events_partitioning: &EventsPartitioning,
+        events: &Vec<Event>,
+    );
     async fn delete_events_by_ids<'a>(conn: &mut Self::Conn<'a>, ids: &Vec<Uuid>);
+    async fn soft_delete_events_by_ids<'a>(
+        conn: &mut Self::Conn<'a>,
+        ids: &Vec<Uuid>,
+        reorged_block_id: i32,
+    );
 
     async fn update_next_block_number_to_ingest_from<'a>(
         conn: &mut Self::Conn<'a>,
@@ -65,12 +135,44 @@ pub trait Repo:
         contract_address_id: ContractAddressID,
         block_number: i64,
     );
+    /// Moves many contract addresses' handling cursors at once, e.g. for a
+    /// coordinated replay, within a single transaction: either all of them
+    /// move or none does. Their checkpoints from the new cursors on get
+    /// dropped too, so none points past its cursor
+    async fn update_handling_cursors<'a>(
+        conn: &mut Self::Conn<'a>,
+        handling_cursors: Vec<(ContractAddressID, i64)>,
+    );
+
+    async fn get_handler_checkpoint<'a>(
+        conn: &mut Self::Conn<'a>,
+        contract_address_id: ContractAddressID,
+    ) -> Option<HandlerCheckpoint>;
 
     async fn create_reorged_block<'a>(
         conn: &mut Self::Conn<'a>,
         reorged_block: &UnsavedReorgedBlock,
-    );
+    ) -> ReorgedBlock;
     async fn get_unhandled_reorged_blocks<'a>(conn: &mut Self::Conn<'a>) -> Vec<ReorgedBlock>;
+    /// Depths of the chain's latest `window` reorged blocks
+    async fn reorg_depth_stats<'a>(
+        conn: &mut Self::Conn<'a>,
+        chain: &Chain,
+        window: u64,
+    ) -> ReorgDepthStats;
+    /// The chain's reorged blocks recorded since the given time
+    async fn get_reorged_blocks_since<'a>(
+        conn: &mut Self::Conn<'a>,
+        chain: &Chain,
+        since: chrono::NaiveDateTime,
+    ) -> Vec<ReorgedBlock>;
+    /// Deletes the chain's handled reorged blocks recorded before the given
+    /// time. Unhandled ones are kept for handlers to backtrack from.
+    async fn delete_handled_reorged_blocks_before<'a>(
+        conn: &mut Self::Conn<'a>,
+        chain: &Chain,
+        before: chrono::NaiveDateTime,
+    );
 
     async fn create_reset_count<'a>(conn: &mut Self::Conn<'a>);
     async fn get_reset_counts<'a>(conn: &mut Self::Conn<'a>) -> Vec<ResetCount>;
@@ -90,14 +192,40 @@ pub trait HasRawQueryClient {
 #[async_trait::async_trait]
 pub trait ExecutesWithRawQuery: HasRawQueryClient {
     async fn execute_raw_query(client: &Self::RawQueryClient, query: &str);
+    /// Leaves conflicts with concurrent transactions to be reported by
+    /// `get_raw_query_txn_conflict` and `try_commit_raw_query_txns`, e.g. for
+    /// handlers, which cannot return them
     async fn execute_raw_query_in_txn<'a>(client: &Self::RawQueryTxnClient<'a>, query: &str);
+    async fn try_execute_raw_query_in_txn<'a>(
+        client: &Self::RawQueryTxnClient<'a>,
+        query: &str,
+    ) -> Result<(), RepoError>;
+    /// Fails with the first conflict the transaction's statements ran into,
+    /// if any, after which the transaction can only be re-run
+    async fn get_raw_query_txn_conflict<'a>(
+        client: &Self::RawQueryTxnClient<'a>,
+    ) -> Result<(), RepoError>;
     async fn commit_raw_query_txns<'a>(client: Self::RawQueryTxnClient<'a>);
+    /// Fails with `RepoError::Conflict` instead of panicking on conflicts with
+    /// concurrent transactions, so the transaction can be re-run
+    async fn try_commit_raw_query_txns<'a>(
+        client: Self::RawQueryTxnClient<'a>,
+    ) -> Result<(), RepoError>;
+    async fn rollback_raw_query_txns<'a>(client: Self::RawQueryTxnClient<'a>);
 
     async fn update_next_block_number_to_handle_from_in_txn<'a>(
         client: &Self::RawQueryTxnClient<'a>,
         contract_address_id: ContractAddressID,
         block_number: i64,
-    );
+    ) -> Result<(), RepoError>;
+
+    async fn update_handler_checkpoint_in_txn<'a>(
+        client: &Self::RawQueryTxnClient<'a>,
+        contract_address_id: ContractAddressID,
+        block_number: i64,
+        transaction_index: i64,
+        log_index: i64,
+    ) -> Result<(), RepoError>;
 
     async fn update_every_next_block_number_to_handle_from_in_txn<'a>(
         client: &Self::RawQueryTxnClient<'a>,
@@ -105,9 +233,16 @@ pub trait ExecutesWithRawQuery: HasRawQueryClient {
         block_number: i64,
     );
 
+    async fn update_every_next_block_number_to_handle_from_for_contracts_in_txn<'a>(
+        client: &Self::RawQueryTxnClient<'a>,
+        contract_names: &Vec<String>,
+        block_number: i64,
+    );
+
     async fn update_reorged_blocks_as_handled_in_txn<'a>(
         client: &Self::RawQueryTxnClient<'a>,
         reorged_block_ids: &Vec<i32>,
+        handled_at: chrono::NaiveDateTime,
     );
 }
 
@@ -129,6 +264,10 @@ pub trait LoadsDataWithRawQuery: HasRawQueryClient {
         conn: &Self::RawQueryTxnClient<'a>,
         query: &str,
     ) -> Vec<Data>;
+    async fn try_load_data_list_from_raw_query_with_txn_client<'a, Data: Send + DeserializeOwned>(
+        conn: &Self::RawQueryTxnClient<'a>,
+        query: &str,
+    ) -> Result<Vec<Data>, RepoError>;
 }
 
 pub trait Streamable {
@@ -140,8 +279,15 @@ pub trait Streamable {
         conn: Arc<Mutex<Self::StreamConn<'a>>>,
         from: i64,
     ) -> Box<dyn Stream<Item = Vec<Event>> + Send + Unpin + 'a>;
+    fn get_events_stream_for_abis<'a>(
+        conn: Arc<Mutex<Self::StreamConn<'a>>>,
+        from: i64,
+        abis: Vec<String>,
+        chunk_size: i64,
+    ) -> Box<dyn Stream<Item = Vec<Event>> + Send + Unpin + 'a>;
 }
 
+#[async_trait::async_trait]
 pub trait RepoMigrations: Migratable {
     fn create_contract_addresses_migration() -> &'static [&'static str];
     fn drop_contract_addresses_migration() -> &'static [&'static str];
@@ -150,36 +296,126 @@ pub trait RepoMigrations: Migratable {
     fn create_reset_counts_migration() -> &'static [&'static str];
     fn create_reorged_blocks_migration() -> &'static [&'static str];
     fn drop_reorged_blocks_migration() -> &'static [&'static str];
+    fn create_handler_checkpoints_migration() -> &'static [&'static str];
+    fn drop_handler_checkpoints_migration() -> &'static [&'static str];
 
     fn get_internal_migrations() -> Vec<&'static str> {
         [
             Self::create_contract_addresses_migration(),
             Self::create_events_migration(),
             Self::create_reorged_blocks_migration(),
+            Self::create_handler_checkpoints_migration(),
         ]
         .concat()
     }
 
+    /// Idempotently creates, or upgrades, chaindexing's own tables and their
+    /// indexes, independently of any contract's state migrations.
+    async fn setup(client: &Self::RawQueryClient)
+    where
+        Self: Sized,
+    {
+        Self::migrate(client, Self::create_reset_counts_migration().to_vec()).await;
+        Self::migrate(client, Self::get_internal_migrations()).await;
+    }
+
     fn get_reset_internal_migrations() -> Vec<&'static str> {
         [
             Self::drop_contract_addresses_migration(),
             Self::drop_events_migration(),
             Self::drop_reorged_blocks_migration(),
+            Self::drop_handler_checkpoints_migration(),
         ]
         .concat()
     }
 }
 
 #[async_trait::async_trait]
-pub trait Migratable: ExecutesWithRawQuery + Sync + Send {
+pub trait Migratable: ExecutesWithRawQuery + LoadsDataWithRawQuery + Sync + Send {
+    /// Warns about previously applied migrations whose text changed since,
+    /// which `IF NOT EXISTS` would otherwise skip silently.
     async fn migrate(client: &Self::RawQueryClient, migrations: Vec<impl AsRef<str> + Send + Sync>)
     where
         Self: Sized,
     {
+        for drifted_migration in Self::get_drifted_migrations(client, &migrations).await {
+            eprintln!(
+                "Migration Drift: Edited migration won't be applied until reset: {drifted_migration}"
+            );
+        }
+
         for migration in migrations {
-            Self::execute_raw_query(client, migration.as_ref()).await;
+            let migration = migration.as_ref();
+
+            Self::execute_raw_query(client, migration).await;
+
+            if let Some(MigrationChecksum {
+                name,
+                table_name,
+                checksum,
+                replaced,
+            }) = MigrationChecksum::new(migration)
+            {
+                let on_conflict = if replaced {
+                    "UPDATE SET checksum = EXCLUDED.checksum"
+                } else {
+                    "NOTHING"
+                };
+                let query = format!(
+                    "INSERT INTO chaindexing_migration_checksums (name, table_name, checksum)
+                VALUES ('{name}', '{table_name}', '{checksum}')
+                ON CONFLICT (name) DO {on_conflict}"
+                );
+
+                Self::execute_raw_query(client, &query).await;
+            }
+
+            // Re-created tables get their current migrations applied
+            if let Some(table_name) = MigrationChecksum::get_dropped_table_name(migration) {
+                let query = format!(
+                    "DELETE FROM chaindexing_migration_checksums WHERE table_name = '{table_name}'"
+                );
+
+                Self::execute_raw_query(client, &query).await;
+            }
         }
     }
+
+    async fn get_drifted_migrations<'a>(
+        client: &Self::RawQueryClient,
+        migrations: &'a Vec<impl AsRef<str> + Send + Sync>,
+    ) -> Vec<&'a str>
+    where
+        Self: Sized,
+    {
+        for migration in SQLikeMigrations::create_migration_checksums() {
+            Self::execute_raw_query(client, migration).await;
+        }
+
+        let mut drifted_migrations = vec![];
+
+        for migration in migrations {
+            if let Some(MigrationChecksum {
+                name,
+                checksum,
+                replaced: false,
+                ..
+            }) = MigrationChecksum::new(migration.as_ref())
+            {
+                let query = format!(
+                    "SELECT checksum FROM chaindexing_migration_checksums WHERE name = '{name}'"
+                );
+                let applied_checksum: Option<HashMap<String, String>> =
+                    Self::load_data_from_raw_query(client, &query).await;
+
+                if applied_checksum.is_some_and(|applied| applied["checksum"] != checksum) {
+                    drifted_migrations.push(migration.as_ref());
+                }
+            }
+        }
+
+        drifted_migrations
+    }
 }
 
 pub struct SQLikeMigrations;
@@ -198,6 +434,9 @@ impl SQLikeMigrations {
         )",
             "CREATE UNIQUE INDEX IF NOT EXISTS chaindexing_contract_addresses_address_index
         ON chaindexing_contract_addresses(address)",
+            // Addresses used to be saved as configured, e.g. EIP-55 checksummed
+            "UPDATE chaindexing_contract_addresses SET address = LOWER(address)
+        WHERE address <> LOWER(address)",
         ]
     }
     pub fn drop_contract_addresses() -> &'static [&'static str] {
@@ -224,10 +463,18 @@ impl SQLikeMigrations {
                 removed BOOLEAN NOT NULL,
                 inserted_at TIMESTAMPTZ NOT NULL DEFAULT NOW() 
             )",
-            "CREATE UNIQUE INDEX IF NOT EXISTS chaindexing_events_transaction_hash_log_index
-            ON chaindexing_events(transaction_hash,log_index)",
+            // Soft-deleted events must not keep their logs from getting ingested again
+            "DROP INDEX IF EXISTS chaindexing_events_transaction_hash_log_index",
+            "CREATE UNIQUE INDEX IF NOT EXISTS chaindexing_events_unremoved_transaction_hash_log_index
+            ON chaindexing_events(transaction_hash,log_index) WHERE removed = false",
             "CREATE INDEX IF NOT EXISTS chaindexing_events_abi
             ON chaindexing_events(abi)",
+            "ALTER TABLE chaindexing_events ADD COLUMN IF NOT EXISTS transaction_status BIGINT NULL",
+            "ALTER TABLE chaindexing_events ADD COLUMN IF NOT EXISTS reorged_block_id INTEGER NULL",
+            "ALTER TABLE chaindexing_events ADD COLUMN IF NOT EXISTS raw_log JSON NULL",
+            "ALTER TABLE chaindexing_events ADD COLUMN IF NOT EXISTS content_hash TEXT NULL",
+            "CREATE INDEX IF NOT EXISTS chaindexing_events_block_timestamp
+            ON chaindexing_events(block_timestamp)",
         ]
     }
     pub fn drop_events() -> &'static [&'static str] {
@@ -235,18 +482,49 @@ impl SQLikeMigrations {
     }
 
     pub fn create_reorged_blocks() -> &'static [&'static str] {
-        &["CREATE TABLE IF NOT EXISTS chaindexing_reorged_blocks (
+        &[
+            "CREATE TABLE IF NOT EXISTS chaindexing_reorged_blocks (
                 id SERIAL PRIMARY KEY,
                 chain_id INTEGER NOT NULL,
                 block_number BIGINT NOT NULL,
                 handled_at TIMESTAMPTZ,
                 inserted_at TIMESTAMPTZ NOT NULL DEFAULT NOW() 
-            )"]
+            )",
+            "ALTER TABLE chaindexing_reorged_blocks
+            ADD COLUMN IF NOT EXISTS current_block_number BIGINT NULL",
+        ]
     }
     pub fn drop_reorged_blocks() -> &'static [&'static str] {
         &["DROP TABLE IF EXISTS chaindexing_reorged_blocks"]
     }
 
+    pub fn create_handler_checkpoints() -> &'static [&'static str] {
+        &[
+            "CREATE TABLE IF NOT EXISTS chaindexing_handler_checkpoints (
+                contract_address_id INTEGER PRIMARY KEY,
+                block_number BIGINT NOT NULL,
+                log_index BIGINT NOT NULL,
+                updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+            )",
+            "ALTER TABLE chaindexing_handler_checkpoints
+            ADD COLUMN IF NOT EXISTS transaction_index BIGINT NULL",
+        ]
+    }
+    pub fn drop_handler_checkpoints() -> &'static [&'static str] {
+        &["DROP TABLE IF EXISTS chaindexing_handler_checkpoints"]
+    }
+
+    pub fn create_migration_checksums() -> &'static [&'static str] {
+        &[
+            "CREATE TABLE IF NOT EXISTS chaindexing_migration_checksums (
+                name TEXT PRIMARY KEY,
+                table_name TEXT NOT NULL,
+                checksum TEXT NOT NULL,
+                inserted_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+            )",
+        ]
+    }
+
     pub fn create_reset_counts() -> &'static [&'static str] {
         &["CREATE TABLE IF NOT EXISTS chaindexing_reset_counts (
                 id SERIAL PRIMARY KEY,
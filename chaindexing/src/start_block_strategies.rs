@@ -0,0 +1,130 @@
+use std::cmp::min;
+
+use ethers::providers::ProviderError;
+use ethers::types::{Address, Filter, Log, U64};
+
+use crate::{EventsIngesterJsonRpc, UnsavedContractAddress};
+
+/// The part of a chain's JSON RPC start block strategies get to query, which
+/// every `EventsIngesterJsonRpc` provides
+#[async_trait::async_trait]
+pub trait StartBlockJsonRpc: Send + Sync {
+    async fn get_block_number(&self) -> Result<U64, ProviderError>;
+    async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, ProviderError>;
+}
+
+#[async_trait::async_trait]
+impl<T: EventsIngesterJsonRpc> StartBlockJsonRpc for T {
+    async fn get_block_number(&self) -> Result<U64, ProviderError> {
+        EventsIngesterJsonRpc::get_block_number(self).await
+    }
+
+    async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, ProviderError> {
+        EventsIngesterJsonRpc::get_logs(self, filter).await
+    }
+}
+
+/// Resolves the block a contract address gets ingested from, once, when it
+/// gets registered, on setup or with `Chaindexing::register_contract_addresses`.
+/// Addresses already registered keep their cursors.
+#[async_trait::async_trait]
+pub trait StartBlockStrategy: Send + Sync {
+    async fn resolve(
+        &self,
+        contract_address: &UnsavedContractAddress,
+        json_rpc: &dyn StartBlockJsonRpc,
+    ) -> Result<i64, ProviderError>;
+}
+
+/// Starts from the configured start block, e.g. a known deployment block
+#[derive(Clone, Debug, Default)]
+pub struct FixedStartBlock;
+
+#[async_trait::async_trait]
+impl StartBlockStrategy for FixedStartBlock {
+    async fn resolve(
+        &self,
+        contract_address: &UnsavedContractAddress,
+        _json_rpc: &dyn StartBlockJsonRpc,
+    ) -> Result<i64, ProviderError> {
+        Ok(contract_address.get_start_block_number())
+    }
+}
+
+/// Starts from the chain's current block, skipping history altogether
+#[derive(Clone, Debug, Default)]
+pub struct FromLatestStartBlock;
+
+#[async_trait::async_trait]
+impl StartBlockStrategy for FromLatestStartBlock {
+    async fn resolve(
+        &self,
+        _contract_address: &UnsavedContractAddress,
+        json_rpc: &dyn StartBlockJsonRpc,
+    ) -> Result<i64, ProviderError> {
+        let current_block_number = json_rpc.get_block_number().await?;
+
+        Ok(current_block_number.as_u64() as i64)
+    }
+}
+
+/// Starts from the address's first log, scanning from the configured start
+/// block, e.g. 0, through the current block, `blocks_per_window` blocks at a
+/// time. Addresses without any log yet start from the current block.
+#[derive(Clone, Debug)]
+pub struct AutoDetectStartBlock {
+    pub blocks_per_window: u64,
+}
+
+impl AutoDetectStartBlock {
+    pub fn new(blocks_per_window: u64) -> Self {
+        Self {
+            blocks_per_window: blocks_per_window.max(1),
+        }
+    }
+}
+
+impl Default for AutoDetectStartBlock {
+    fn default() -> Self {
+        Self::new(10_000)
+    }
+}
+
+#[async_trait::async_trait]
+impl StartBlockStrategy for AutoDetectStartBlock {
+    async fn resolve(
+        &self,
+        contract_address: &UnsavedContractAddress,
+        json_rpc: &dyn StartBlockJsonRpc,
+    ) -> Result<i64, ProviderError> {
+        let address = contract_address.get_address().parse::<Address>().unwrap();
+        let current_block_number = json_rpc.get_block_number().await?.as_u64();
+        let mut from_block_number = contract_address.get_start_block_number().max(0) as u64;
+
+        while from_block_number <= current_block_number {
+            let to_block_number = min(
+                from_block_number.saturating_add(self.blocks_per_window - 1),
+                current_block_number,
+            );
+            let filter = Filter::new()
+                .address(address)
+                .from_block(from_block_number)
+                .to_block(to_block_number);
+
+            let first_log_block_number = json_rpc
+                .get_logs(&filter)
+                .await?
+                .iter()
+                .filter_map(|log| log.block_number)
+                .min();
+
+            if let Some(first_log_block_number) = first_log_block_number {
+                return Ok(first_log_block_number.as_u64() as i64);
+            }
+
+            from_block_number = to_block_number + 1;
+        }
+
+        Ok(current_block_number as i64)
+    }
+}
@@ -0,0 +1,246 @@
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+
+use crate::{ContractAddress, ContractState, ContractStateMigrations, EventContext, EventHandler};
+use crate::{Event, U256};
+
+pub const ERC20_TRANSFER_EVENT_ABI: &str =
+    "event Transfer(address indexed from, address indexed to, uint256 value)";
+pub const ERC721_TRANSFER_EVENT_ABI: &str =
+    "event Transfer(address indexed from, address indexed to, uint256 indexed tokenId)";
+
+struct Transfer {
+    from: Address,
+    to: Address,
+    /// Value for ERC-20 transfers, token id for ERC-721 ones
+    amount: U256,
+}
+
+impl Transfer {
+    fn new(event: &Event, amount_param: &str) -> Self {
+        let params = event.get_params();
+        let get_param = |name: &str| params.get(name).unwrap().clone();
+
+        Self {
+            from: get_param("from").into_address().unwrap(),
+            to: get_param("to").into_address().unwrap(),
+            amount: get_param(amount_param).into_uint().unwrap(),
+        }
+    }
+}
+
+/// An ERC-20 holder's balance, in the token's smallest unit. Stored as a
+/// decimal string since balances overflow any SQL integer type. Holders
+/// without any balance left are deleted.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Erc20Balance {
+    pub contract_address: String,
+    pub chain_id: i32,
+    pub holder_address: String,
+    pub balance: String,
+}
+
+impl ContractState for Erc20Balance {
+    fn table_name() -> &'static str {
+        "erc20_balances"
+    }
+}
+
+impl Erc20Balance {
+    pub fn get_balance(&self) -> U256 {
+        U256::from_dec_str(&self.balance).unwrap()
+    }
+
+    async fn read<'a>(holder_address: &str, context: &EventContext<'a>) -> Option<Self> {
+        Self::read_one_on_chain(
+            [
+                (
+                    "contract_address".to_owned(),
+                    context.event.contract_address.clone(),
+                ),
+                ("holder_address".to_owned(), holder_address.to_owned()),
+            ]
+            .into(),
+            context,
+        )
+        .await
+    }
+
+    async fn credit<'a>(holder_address: String, value: U256, context: &EventContext<'a>) {
+        match Self::read(&holder_address, context).await {
+            Some(erc20_balance) => {
+                let balance = erc20_balance.get_balance().saturating_add(value);
+                let updates = [("balance".to_owned(), balance.to_string())];
+
+                erc20_balance.update(updates.into(), context).await;
+            }
+            None => {
+                Self {
+                    contract_address: context.event.contract_address.clone(),
+                    chain_id: context.get_chain_id(),
+                    holder_address,
+                    balance: value.to_string(),
+                }
+                .create(context)
+                .await
+            }
+        }
+    }
+
+    // Balances credited before the contract's start block are unknown, so
+    // debiting them is skipped
+    async fn debit<'a>(holder_address: String, value: U256, context: &EventContext<'a>) {
+        if let Some(erc20_balance) = Self::read(&holder_address, context).await {
+            let balance = erc20_balance.get_balance().saturating_sub(value);
+
+            if balance.is_zero() {
+                erc20_balance.delete(context).await;
+            } else {
+                let updates = [("balance".to_owned(), balance.to_string())];
+
+                erc20_balance.update(updates.into(), context).await;
+            }
+        }
+    }
+}
+
+/// States of every ERC-20 contract share the same table, told apart by their
+/// `contract_address` and `chain_id`, so rebuilding a single ERC-20 contract's
+/// states drops the other ones' too
+pub struct Erc20BalancesMigrations;
+
+impl ContractStateMigrations for Erc20BalancesMigrations {
+    fn migrations(&self) -> Vec<&'static str> {
+        vec![
+            "CREATE TABLE IF NOT EXISTS erc20_balances (
+                contract_address TEXT NOT NULL,
+                chain_id INTEGER NOT NULL,
+                holder_address TEXT NOT NULL,
+                balance TEXT NOT NULL
+            )",
+        ]
+    }
+}
+
+/// Tracks `Erc20Balance`s of `ERC20_TRANSFER_EVENT_ABI` events, minting from
+/// and burning to the zero address, e.g.
+///
+/// ```ignore
+/// Contract::new("USDC")
+///     .add_event(ERC20_TRANSFER_EVENT_ABI, Erc20TransferHandler)
+///     .add_state_migrations(Erc20BalancesMigrations)
+/// ```
+pub struct Erc20TransferHandler;
+
+#[async_trait::async_trait]
+impl EventHandler for Erc20TransferHandler {
+    async fn handle_event<'a>(&self, event_context: EventContext<'a>) {
+        let Transfer { from, to, amount } = Transfer::new(&event_context.event, "value");
+
+        if amount.is_zero() {
+            return;
+        }
+
+        if !from.is_zero() {
+            let from = ContractAddress::address_to_string(&from);
+            Erc20Balance::debit(from, amount, &event_context).await;
+        }
+
+        if !to.is_zero() {
+            let to = ContractAddress::address_to_string(&to);
+            Erc20Balance::credit(to, amount, &event_context).await;
+        }
+    }
+}
+
+/// An ERC-721 token's current owner. Token ids are stored as decimal strings
+/// since they overflow any SQL integer type. Burnt tokens are deleted.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Erc721Token {
+    pub contract_address: String,
+    pub chain_id: i32,
+    pub token_id: String,
+    pub owner_address: String,
+}
+
+impl ContractState for Erc721Token {
+    fn table_name() -> &'static str {
+        "erc721_tokens"
+    }
+}
+
+impl Erc721Token {
+    pub fn get_token_id(&self) -> U256 {
+        U256::from_dec_str(&self.token_id).unwrap()
+    }
+
+    async fn read<'a>(token_id: &str, context: &EventContext<'a>) -> Option<Self> {
+        Self::read_one_on_chain(
+            [
+                (
+                    "contract_address".to_owned(),
+                    context.event.contract_address.clone(),
+                ),
+                ("token_id".to_owned(), token_id.to_owned()),
+            ]
+            .into(),
+            context,
+        )
+        .await
+    }
+}
+
+/// Shared by every ERC-721 contract, like `Erc20BalancesMigrations`
+pub struct Erc721TokensMigrations;
+
+impl ContractStateMigrations for Erc721TokensMigrations {
+    fn migrations(&self) -> Vec<&'static str> {
+        vec![
+            "CREATE TABLE IF NOT EXISTS erc721_tokens (
+                contract_address TEXT NOT NULL,
+                chain_id INTEGER NOT NULL,
+                token_id TEXT NOT NULL,
+                owner_address TEXT NOT NULL
+            )",
+        ]
+    }
+}
+
+/// Tracks `Erc721Token`s of `ERC721_TRANSFER_EVENT_ABI` events, minting from
+/// and burning to the zero address
+pub struct Erc721TransferHandler;
+
+#[async_trait::async_trait]
+impl EventHandler for Erc721TransferHandler {
+    async fn handle_event<'a>(&self, event_context: EventContext<'a>) {
+        let Transfer {
+            to,
+            amount: token_id,
+            ..
+        } = Transfer::new(&event_context.event, "tokenId");
+        let token_id = token_id.to_string();
+
+        match Erc721Token::read(&token_id, &event_context).await {
+            Some(erc721_token) if to.is_zero() => erc721_token.delete(&event_context).await,
+            Some(erc721_token) => {
+                let updates = [(
+                    "owner_address".to_owned(),
+                    ContractAddress::address_to_string(&to),
+                )];
+
+                erc721_token.update(updates.into(), &event_context).await;
+            }
+            None if to.is_zero() => {}
+            None => {
+                Erc721Token {
+                    contract_address: event_context.event.contract_address.clone(),
+                    chain_id: event_context.get_chain_id(),
+                    token_id,
+                    owner_address: ContractAddress::address_to_string(&to),
+                }
+                .create(&event_context)
+                .await
+            }
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use ethers::abi::Event;
+use ethers::types::H256;
+use ethers::utils::keccak256;
+
+use crate::ContractEventTopic;
+
+/// Computes events' topic0 from their canonical signatures, e.g. for crypto
+/// backends other than ethers'. Logs get matched to their contracts' events
+/// by these topics, and fetched by them too.
+pub trait TopicHasher: Send + Sync {
+    /// Hashes a canonical signature, e.g. `Transfer(address,address,uint256)`
+    fn hash(&self, signature: &str) -> ContractEventTopic;
+
+    fn hash_event(&self, event: &Event) -> ContractEventTopic {
+        self.hash(&get_canonical_signature(event))
+    }
+}
+
+/// Hashes signatures with ethers' Keccak-256, for contracts without a custom
+/// topic hasher
+#[derive(Clone, Debug, Default)]
+pub struct KeccakTopicHasher;
+
+impl TopicHasher for KeccakTopicHasher {
+    fn hash(&self, signature: &str) -> ContractEventTopic {
+        H256::from(keccak256(signature.as_bytes()))
+    }
+}
+
+fn get_canonical_signature(Event { name, inputs, .. }: &Event) -> String {
+    let kinds: Vec<_> = inputs.iter().map(|input| input.kind.to_string()).collect();
+
+    format!("{name}({kinds})", kinds = kinds.join(","))
+}
+
+#[cfg(test)]
+mod topic_hasher_test {
+    use std::collections::HashMap;
+
+    use ethers::abi::Token;
+    use ethers::types::{Block, Chain, Log, H160, U256};
+
+    use super::*;
+    use crate::{Contract, ContractEvent, EventContext, EventHandler, Events};
+
+    const TRANSFER_EVENT_ABI: &str =
+        "event Transfer(address indexed from, address indexed to, uint256 indexed tokenId)";
+    const CONTRACT_ADDRESS: &str = "0xBC4CA0EdA7647A8aB7C2061c2E118A18a936f13D";
+
+    struct SignatureLengthTopicHasher;
+
+    impl TopicHasher for SignatureLengthTopicHasher {
+        fn hash(&self, signature: &str) -> ContractEventTopic {
+            H256::from_low_u64_be(signature.len() as u64)
+        }
+    }
+
+    struct NoopEventHandler;
+
+    #[async_trait::async_trait]
+    impl EventHandler for NoopEventHandler {
+        async fn handle_event<'a>(&self, _event_context: EventContext<'a>) {}
+    }
+
+    #[test]
+    fn hashes_canonical_signatures_like_ethers() {
+        let transfer_event = ContractEvent::new(TRANSFER_EVENT_ABI);
+
+        assert_eq!(
+            KeccakTopicHasher.hash_event(&transfer_event.value),
+            transfer_event.value.signature()
+        );
+        assert_eq!(transfer_event.topic, transfer_event.value.signature());
+    }
+
+    #[test]
+    fn matches_and_decodes_logs_with_the_contract_topic_hasher() {
+        let contract = Contract::new("BoredApeYachtClub")
+            .add_address(CONTRACT_ADDRESS, &Chain::Mainnet, 0)
+            .add_event(TRANSFER_EVENT_ABI, NoopEventHandler)
+            .with_topic_hasher(SignatureLengthTopicHasher);
+
+        let topic = H256::from_low_u64_be("Transfer(address,address,uint256)".len() as u64);
+        assert_eq!(contract.get_event_topics(), vec![topic]);
+
+        let log = transfer_log(topic, 1661);
+        let blocks_by_tx_hash = HashMap::from([(log.transaction_hash.unwrap(), Block::default())]);
+        let events = Events::new(&vec![log], &vec![contract], &blocks_by_tx_hash);
+
+        let params = events.first().unwrap().get_params();
+        assert_eq!(params.get("tokenId"), Some(&Token::Uint(U256::from(1661))));
+    }
+
+    fn transfer_log(topic: ContractEventTopic, token_id: u64) -> Log {
+        Log {
+            address: CONTRACT_ADDRESS.parse::<H160>().unwrap(),
+            topics: vec![
+                topic,
+                H256::zero(),
+                H256::zero(),
+                H256::from_low_u64_be(token_id),
+            ],
+            block_hash: Some(H256::from_low_u64_be(1)),
+            block_number: Some(1.into()),
+            transaction_hash: Some(H256::from_low_u64_be(2)),
+            transaction_index: Some(0.into()),
+            log_index: Some(0.into()),
+            removed: Some(false),
+            ..Default::default()
+        }
+    }
+}